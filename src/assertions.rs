@@ -0,0 +1,122 @@
+use std::fmt;
+
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderName},
+    StatusCode,
+};
+
+use crate::graphql;
+
+/// A check a successful response has to pass, failing it counts the request as failed
+#[derive(Debug, Clone)]
+pub enum Assertion {
+    /// The status is one of these
+    Status(Vec<StatusCode>),
+    /// The header is present, and its value matches when a pattern is given
+    Header {
+        name: HeaderName,
+        value: Option<Regex>,
+    },
+    BodyContains(String),
+    BodyMatches(Regex),
+    /// The body has no GraphQL `errors`, with --graphql
+    NoGraphqlErrors,
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Assertion::Status(statuses) => {
+                let statuses: Vec<_> = statuses.iter().map(|s| s.as_str()).collect();
+                write!(f, "status in {}", statuses.join(","))
+            }
+            Assertion::Header { name, value: None } => write!(f, "header {} present", name),
+            Assertion::Header {
+                name,
+                value: Some(value),
+            } => write!(f, "header {} matches /{}/", name, value),
+            Assertion::BodyContains(text) => write!(f, "body contains {:?}", text),
+            Assertion::BodyMatches(pattern) => write!(f, "body matches /{}/", pattern),
+            Assertion::NoGraphqlErrors => write!(f, "no GraphQL errors"),
+        }
+    }
+}
+
+/// Parses `Name` or `Name: <regex>`
+pub fn parse_header_assertion(spec: &str) -> Result<Assertion, String> {
+    let (name, value) = match spec.split_once(':') {
+        Some((name, value)) => (name, Some(parse_regex(value.trim())?)),
+        None => (spec, None),
+    };
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| format!("invalid header name `{}`", name))?;
+    Ok(Assertion::Header { name, value })
+}
+
+pub fn parse_status(status: &str) -> Result<StatusCode, String> {
+    status
+        .trim()
+        .parse::<u16>()
+        .ok()
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .ok_or_else(|| format!("invalid status code `{}`", status))
+}
+
+pub fn parse_regex(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| format!("invalid regex `{}`: {}", pattern, e))
+}
+
+/// Every assertion of a test, identified in results by their index
+#[derive(Debug, Default)]
+pub struct Assertions(Vec<Assertion>);
+
+impl Assertions {
+    pub fn new(assertions: Vec<Assertion>) -> Self {
+        Assertions(assertions)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> &Assertion {
+        &self.0[index]
+    }
+
+    /// Index of the first status or header assertion the response fails
+    pub fn check_head(&self, status: StatusCode, headers: &HeaderMap) -> Option<usize> {
+        self.0.iter().position(|assertion| match assertion {
+            Assertion::Status(statuses) => !statuses.contains(&status),
+            Assertion::Header { name, value } => {
+                let Some(actual) = headers.get(name) else {
+                    return true;
+                };
+                value.as_ref().is_some_and(|value| {
+                    !actual.to_str().is_ok_and(|actual| value.is_match(actual))
+                })
+            }
+            _ => false,
+        })
+    }
+
+    /// Index of the first body assertion the response fails. Bodies that aren't UTF-8 are
+    /// compared lossily.
+    pub fn check_body(&self, body: &[u8]) -> Option<usize> {
+        if !self.0.iter().any(|assertion| {
+            matches!(
+                assertion,
+                Assertion::BodyContains(_) | Assertion::BodyMatches(_) | Assertion::NoGraphqlErrors
+            )
+        }) {
+            return None;
+        }
+        let text = String::from_utf8_lossy(body);
+        self.0.iter().position(|assertion| match assertion {
+            Assertion::BodyContains(expected) => !text.contains(expected.as_str()),
+            Assertion::BodyMatches(pattern) => !pattern.is_match(&text),
+            Assertion::NoGraphqlErrors => graphql::has_errors(body),
+            _ => false,
+        })
+    }
+}
@@ -0,0 +1,322 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::ErrorCategory,
+    latency::{Corrected, Latencies, LatencyHistogram, Throughput},
+    report::Sections,
+    samples::SampleFile,
+    stages::{StageStats, Stages},
+    timeline::Timeline,
+    trend::{Series, Slice},
+    BackendStats, ConnectionStats, LoadResult,
+};
+
+/// Aggregated state of a test, flushed periodically so a crash doesn't lose what was measured
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub url: String,
+    /// Number of requests the test was asked to make
+    pub requested: u64,
+    /// How long the test was asked to run for with --duration
+    #[serde(default)]
+    pub duration: Option<Duration>,
+    /// Seconds since the test started
+    pub elapsed: f32,
+    pub sections: Sections,
+    pub successful: u64,
+    pub failed: u64,
+    pub range_violations: u64,
+    pub overload_signals: u64,
+    pub pin_mismatches: u64,
+    pub retries: u64,
+    #[serde(default)]
+    pub retried_requests: u64,
+    #[serde(default)]
+    pub recovered_requests: u64,
+    pub retry_delay: Duration,
+    pub backoff_delay: Duration,
+    #[serde(default)]
+    pub iterations: u64,
+    /// Outcomes per load stage, with --stages
+    #[serde(default)]
+    pub stages: Vec<StageStats>,
+    #[serde(default)]
+    pub dropped_arrivals: u64,
+    /// TTFB percentiles per window, with --trend-window
+    #[serde(default)]
+    pub trend: Vec<Option<Slice>>,
+    /// TTFB from when requests were meant to go out, with --correct-omission
+    #[serde(default)]
+    pub corrected: Option<Corrected>,
+    /// Outcomes per backend address when requests were spread across several
+    #[serde(default)]
+    pub backends: BTreeMap<SocketAddr, BackendStats>,
+    /// Outcomes per URL when the test has several
+    #[serde(default)]
+    pub targets: BTreeMap<String, BackendStats>,
+    #[serde(default)]
+    pub assertion_failures: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub errors: BTreeMap<ErrorCategory, u64>,
+    #[serde(default)]
+    pub bytes_received: u64,
+    #[serde(default)]
+    pub bytes_decoded: u64,
+    #[serde(default)]
+    pub decode_failures: u64,
+    #[serde(default)]
+    pub connections: ConnectionStats,
+    #[serde(default)]
+    pub statuses: BTreeMap<u16, u64>,
+    /// Timings of the successful requests
+    #[serde(default)]
+    pub latencies: Latencies,
+    #[serde(default)]
+    pub throughput: Throughput,
+    #[serde(default)]
+    pub versions: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub bytes_successful: u64,
+    #[serde(default)]
+    pub timeline: Option<Timeline>,
+    /// File the test appends its samples to with --samples-file, reopened on resume
+    #[serde(default)]
+    pub samples_file: Option<PathBuf>,
+}
+
+impl Checkpoint {
+    pub fn capture(
+        url: &str,
+        requested: u64,
+        sections: Sections,
+        result: &LoadResult,
+        test_start: Instant,
+    ) -> Self {
+        Checkpoint {
+            url: url.to_string(),
+            requested,
+            duration: None,
+            elapsed: test_start.elapsed().as_secs_f32(),
+            sections,
+            successful: result.number_of_successful_calls,
+            failed: result.number_of_failed_calls,
+            range_violations: result.number_of_range_violations,
+            overload_signals: result.number_of_overload_signals,
+            pin_mismatches: result.number_of_pin_mismatches,
+            retries: result.number_of_retries,
+            retried_requests: result.number_of_retried_requests,
+            recovered_requests: result.number_of_recovered_requests,
+            retry_delay: result.retry_delay,
+            backoff_delay: result.backoff_delay,
+            iterations: result.number_of_iterations,
+            stages: result
+                .stages
+                .as_ref()
+                .map_or_else(Vec::new, |stages| stages.stats().to_vec()),
+            dropped_arrivals: result.number_of_dropped_arrivals,
+            trend: result.trend.as_ref().map_or_else(Vec::new, Series::slices),
+            corrected: result.corrected.clone(),
+            backends: result.backends.clone(),
+            targets: result.targets.clone(),
+            assertion_failures: result.assertion_failures.clone(),
+            errors: result.errors.clone(),
+            bytes_received: result.bytes_received,
+            bytes_decoded: result.bytes_decoded,
+            decode_failures: result.decode_failures,
+            connections: result.connections.clone(),
+            statuses: result.statuses.clone(),
+            latencies: result.latencies.clone(),
+            throughput: result.throughput.clone(),
+            versions: result.versions.clone(),
+            bytes_successful: result.bytes_successful,
+            timeline: Some(result.timeline.clone()),
+            samples_file: result
+                .samples
+                .as_ref()
+                .map(|samples| samples.path().to_path_buf()),
+        }
+    }
+
+    /// Rebuilds the aggregated result, continuing the timeline from `test_start`. A samples file
+    /// is reopened for appending.
+    pub fn restore(&self, test_start: Instant) -> io::Result<LoadResult> {
+        let timeline = match &self.timeline {
+            Some(timeline) => timeline.clone().resumed(test_start),
+            None => Timeline::new(test_start, self.sections.resolution),
+        };
+        let samples = match &self.samples_file {
+            Some(path) => Some(SampleFile::reopen(path, test_start)?),
+            None => None,
+        };
+        Ok(LoadResult {
+            number_of_successful_calls: self.successful,
+            number_of_failed_calls: self.failed,
+            number_of_range_violations: self.range_violations,
+            number_of_overload_signals: self.overload_signals,
+            number_of_pin_mismatches: self.pin_mismatches,
+            retry_after: None,
+            number_of_retries: self.retries,
+            number_of_retried_requests: self.retried_requests,
+            number_of_recovered_requests: self.recovered_requests,
+            retry_delay: self.retry_delay,
+            backoff_delay: self.backoff_delay,
+            number_of_iterations: self.iterations,
+            number_of_dropped_arrivals: self.dropped_arrivals,
+            backends: self.backends.clone(),
+            targets: self.targets.clone(),
+            assertion_failures: self.assertion_failures.clone(),
+            errors: self.errors.clone(),
+            bytes_received: self.bytes_received,
+            bytes_decoded: self.bytes_decoded,
+            decode_failures: self.decode_failures,
+            connections: self.connections.clone(),
+            statuses: self.statuses.clone(),
+            versions: self.versions.clone(),
+            bytes_successful: self.bytes_successful,
+            throughput: self.throughput.clone(),
+            timeline,
+            latencies: self.latencies.clone(),
+            interval: LatencyHistogram::default(),
+            // Connection timings aren't kept across a resume
+            phases: None,
+            samples,
+            stages: (!self.sections.stages.is_empty()).then(|| {
+                Stages::with_stats(
+                    test_start,
+                    self.sections.stages.clone(),
+                    self.stages.clone(),
+                )
+            }),
+            // Searches and spike tests can't be resumed, they judge loads run back to back
+            search: None,
+            spikes: None,
+            trend: self.sections.trend.map(|trend| {
+                Series::with_slices(
+                    test_start,
+                    trend.window,
+                    self.sections.percentiles.clone(),
+                    self.trend.clone(),
+                )
+            }),
+            corrected: self.corrected.clone(),
+            abort: None,
+        })
+    }
+
+    /// Folds in the checkpoint of a test that ran alongside this one, e.g. on another worker
+    pub fn merge(&mut self, other: Checkpoint) {
+        self.requested = self.requested.saturating_add(other.requested);
+        self.elapsed = self.elapsed.max(other.elapsed);
+        self.successful = self.successful.saturating_add(other.successful);
+        self.failed = self.failed.saturating_add(other.failed);
+        self.range_violations = self.range_violations.saturating_add(other.range_violations);
+        self.overload_signals = self.overload_signals.saturating_add(other.overload_signals);
+        self.pin_mismatches = self.pin_mismatches.saturating_add(other.pin_mismatches);
+        self.retries = self.retries.saturating_add(other.retries);
+        self.retried_requests = self.retried_requests.saturating_add(other.retried_requests);
+        self.recovered_requests = self
+            .recovered_requests
+            .saturating_add(other.recovered_requests);
+        self.retry_delay = self.retry_delay.saturating_add(other.retry_delay);
+        self.backoff_delay = self.backoff_delay.saturating_add(other.backoff_delay);
+        self.iterations = self.iterations.saturating_add(other.iterations);
+        if self.stages.len() < other.stages.len() {
+            self.stages
+                .resize(other.stages.len(), StageStats::default());
+        }
+        for (stage, other) in self.stages.iter_mut().zip(&other.stages) {
+            stage.merge(other);
+        }
+        self.dropped_arrivals = self.dropped_arrivals.saturating_add(other.dropped_arrivals);
+        match (&mut self.corrected, other.corrected) {
+            (Some(ours), Some(theirs)) => ours.merge(&theirs),
+            (ours @ None, theirs) => *ours = theirs,
+            (Some(_), None) => {}
+        }
+        if self.trend.len() < other.trend.len() {
+            self.trend.resize(other.trend.len(), None);
+        }
+        for (ours, theirs) in self.trend.iter_mut().zip(other.trend) {
+            match (ours, theirs) {
+                (Some(ours), Some(theirs)) => ours.merge(&theirs),
+                (ours @ None, theirs) => *ours = theirs,
+                (Some(_), None) => {}
+            }
+        }
+        self.connections.attempts = self
+            .connections
+            .attempts
+            .saturating_add(other.connections.attempts);
+        self.connections.failed = self
+            .connections
+            .failed
+            .saturating_add(other.connections.failed);
+        self.connections.fallbacks = self
+            .connections
+            .fallbacks
+            .saturating_add(other.connections.fallbacks);
+        self.connections.recovered = self
+            .connections
+            .recovered
+            .saturating_add(other.connections.recovered);
+        self.connections.ipv4 = self.connections.ipv4.saturating_add(other.connections.ipv4);
+        self.connections.ipv6 = self.connections.ipv6.saturating_add(other.connections.ipv6);
+        for (status, count) in other.statuses {
+            let ours = self.statuses.entry(status).or_default();
+            *ours = ours.saturating_add(count);
+        }
+        for (addr, theirs) in other.backends {
+            self.backends.entry(addr).or_default().merge(&theirs);
+        }
+        for (url, theirs) in other.targets {
+            self.targets.entry(url).or_default().merge(&theirs);
+        }
+        for (assertion, count) in other.assertion_failures {
+            let ours = self.assertion_failures.entry(assertion).or_default();
+            *ours = ours.saturating_add(count);
+        }
+        self.bytes_received = self.bytes_received.saturating_add(other.bytes_received);
+        self.bytes_decoded = self.bytes_decoded.saturating_add(other.bytes_decoded);
+        self.decode_failures = self.decode_failures.saturating_add(other.decode_failures);
+        for (category, count) in other.errors {
+            let ours = self.errors.entry(category).or_default();
+            *ours = ours.saturating_add(count);
+        }
+        self.latencies.merge(&other.latencies);
+        self.throughput.merge(&other.throughput);
+        for (version, count) in other.versions {
+            let ours = self.versions.entry(version).or_default();
+            *ours = ours.saturating_add(count);
+        }
+        self.bytes_successful = self.bytes_successful.saturating_add(other.bytes_successful);
+        match (&mut self.timeline, other.timeline) {
+            (Some(ours), Some(theirs)) => ours.merge(&theirs),
+            (ours @ None, theirs) => *ours = theirs,
+            (Some(_), None) => {}
+        }
+        self.samples_file = self.samples_file.take().or(other.samples_file);
+    }
+
+    /// Writes to a temporary file first and renames it over `path`, so a crash mid-write
+    /// leaves the previous checkpoint intact
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let checkpoint = serde_json::from_slice(&fs::read(path)?)?;
+        Ok(checkpoint)
+    }
+}
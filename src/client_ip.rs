@@ -0,0 +1,72 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use rand::Rng;
+
+/// Where synthetic client addresses for X-Forwarded-For/Forwarded are drawn from
+#[derive(Debug, Clone, Copy)]
+pub enum ClientIps {
+    /// Any IPv4 address
+    Random,
+    /// Addresses inside a network
+    Cidr { network: IpAddr, prefix: u8 },
+}
+
+/// Parses `random` or `cidr:<network>/<prefix>`, e.g. cidr:10.0.0.0/16
+pub fn parse_client_ips(spec: &str) -> Result<ClientIps, String> {
+    if spec == "random" {
+        return Ok(ClientIps::Random);
+    }
+    let cidr = spec.strip_prefix("cidr:").ok_or_else(|| {
+        format!(
+            "unsupported client IP spec `{}`, expected random or cidr:<network>/<prefix>",
+            spec
+        )
+    })?;
+    let (network, prefix) = cidr
+        .split_once('/')
+        .ok_or_else(|| format!("`{}` is missing the /<prefix>", cidr))?;
+    let network: IpAddr = network
+        .parse()
+        .map_err(|_| format!("invalid network address `{}`", network))?;
+    let prefix: u8 = prefix
+        .parse()
+        .map_err(|_| format!("invalid prefix length `{}`", prefix))?;
+    let max = if network.is_ipv4() { 32 } else { 128 };
+    if prefix > max {
+        return Err(format!("prefix length {} is longer than {}", prefix, max));
+    }
+    Ok(ClientIps::Cidr { network, prefix })
+}
+
+impl ClientIps {
+    pub fn generate(&self) -> IpAddr {
+        let mut rng = rand::thread_rng();
+        match *self {
+            ClientIps::Random => IpAddr::V4(Ipv4Addr::from(rng.gen::<u32>())),
+            ClientIps::Cidr {
+                network: IpAddr::V4(network),
+                prefix,
+            } => {
+                let host_bits = u32::MAX.checked_shr(prefix as u32).unwrap_or(0);
+                let network = u32::from(network) & !host_bits;
+                IpAddr::V4(Ipv4Addr::from(network | (rng.gen::<u32>() & host_bits)))
+            }
+            ClientIps::Cidr {
+                network: IpAddr::V6(network),
+                prefix,
+            } => {
+                let host_bits = u128::MAX.checked_shr(prefix as u32).unwrap_or(0);
+                let network = u128::from(network) & !host_bits;
+                IpAddr::V6(Ipv6Addr::from(network | (rng.gen::<u128>() & host_bits)))
+            }
+        }
+    }
+}
+
+/// Value of the standard Forwarded header for `ip`, quoting IPv6 as RFC 7239 requires
+pub fn forwarded_for(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => format!("for={}", ip),
+        IpAddr::V6(ip) => format!("for=\"[{}]\"", ip),
+    }
+}
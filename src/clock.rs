@@ -0,0 +1,38 @@
+use std::{
+    error::Error,
+    time::{Duration, SystemTime},
+};
+
+use reqwest::header::DATE;
+
+/// Estimates how far the local clock is ahead of the server's (negative when behind), using
+/// the `Date` header of a single request. `Date` only has one-second precision, so the
+/// estimate assumes the server's clock sits halfway through the second it reported.
+pub async fn measure_skew(client: &reqwest::Client, url: &str) -> Result<f64, Box<dyn Error>> {
+    let sent = SystemTime::now();
+    let res = client.head(url).send().await?;
+    let received = SystemTime::now();
+
+    let date = res
+        .headers()
+        .get(DATE)
+        .ok_or_else(|| format!("{} did not send a Date header", url))?
+        .to_str()?;
+    let server = httpdate::parse_http_date(date)? + Duration::from_millis(500);
+
+    // The server stamped the response somewhere between sending and receiving
+    let local = sent + received.duration_since(sent).unwrap_or_default() / 2;
+    Ok(match local.duration_since(server) {
+        Ok(ahead) => ahead.as_secs_f64(),
+        Err(behind) => -behind.duration().as_secs_f64(),
+    })
+}
+
+/// Sleeps until the wall clock reaches `start`
+pub async fn wait_until(start: SystemTime) -> Result<(), Box<dyn Error>> {
+    let wait = start
+        .duration_since(SystemTime::now())
+        .map_err(|_| "--start-at is in the past")?;
+    tokio::time::sleep(wait).await;
+    Ok(())
+}
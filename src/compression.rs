@@ -0,0 +1,130 @@
+use std::io::{self, Write};
+
+use brotli_decompressor::DecompressorWriter;
+use bytes::Bytes;
+use flate2::write::{GzDecoder, ZlibDecoder};
+use reqwest::header::HeaderValue;
+
+/// Content coding the client accepts and decodes itself once the body is read, so
+/// decompression stays out of the time to last byte. Downloads are decoded as they stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Asks for uncompressed bodies explicitly
+    Identity,
+    Gzip,
+    Deflate,
+    Brotli,
+}
+
+impl Encoding {
+    fn token(self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Brotli => "br",
+        }
+    }
+}
+
+/// Parses gzip, deflate, br, or none for uncompressed bodies only
+pub fn parse_encoding(encoding: &str) -> Result<Encoding, String> {
+    match encoding.trim() {
+        "none" | "identity" => Ok(Encoding::Identity),
+        "gzip" => Ok(Encoding::Gzip),
+        "deflate" => Ok(Encoding::Deflate),
+        "br" => Ok(Encoding::Brotli),
+        _ => Err(format!(
+            "unsupported encoding `{}`, expected gzip, deflate, br or none",
+            encoding
+        )),
+    }
+}
+
+/// Accept-Encoding header offering the encodings in order, None when there are none
+pub fn accept_encoding(encodings: &[Encoding]) -> Option<HeaderValue> {
+    let mut tokens: Vec<_> = encodings.iter().map(|e| e.token()).collect();
+    tokens.dedup();
+    (!tokens.is_empty()).then(|| HeaderValue::from_str(&tokens.join(", ")).unwrap())
+}
+
+/// Which of the accepted encodings the response's Content-Encoding names, if any
+pub fn negotiated(
+    accepted: &[Encoding],
+    content_encoding: Option<&HeaderValue>,
+) -> Option<Encoding> {
+    let value = content_encoding?.to_str().ok()?.trim();
+    accepted
+        .iter()
+        .copied()
+        .filter(|&e| e != Encoding::Identity)
+        .find(|e| value.eq_ignore_ascii_case(e.token()))
+}
+
+/// Decodes a whole body, handing back bodies in no accepted encoding as they are
+pub fn decode(encoding: Option<Encoding>, body: Bytes) -> io::Result<Bytes> {
+    if encoding.is_none() {
+        return Ok(body);
+    }
+    let mut decoder = Decoder::new(encoding, Vec::new());
+    decoder.write(&body)?;
+    decoder.finish().map(Bytes::from)
+}
+
+/// Counts what goes through it, for downloads whose decoded body isn't kept
+#[derive(Default)]
+pub struct Counter(pub u64);
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Decodes a body fed chunk by chunk into `W`, bodies in no accepted encoding pass through
+/// unchanged
+pub enum Decoder<W: Write> {
+    Identity(W),
+    Gzip(GzDecoder<W>),
+    Deflate(ZlibDecoder<W>),
+    Brotli(Box<DecompressorWriter<W>>),
+}
+
+impl<W: Write> Decoder<W> {
+    pub fn new(encoding: Option<Encoding>, sink: W) -> Self {
+        match encoding {
+            Some(Encoding::Gzip) => Decoder::Gzip(GzDecoder::new(sink)),
+            Some(Encoding::Deflate) => Decoder::Deflate(ZlibDecoder::new(sink)),
+            Some(Encoding::Brotli) => {
+                Decoder::Brotli(Box::new(DecompressorWriter::new(sink, 4096)))
+            }
+            Some(Encoding::Identity) | None => Decoder::Identity(sink),
+        }
+    }
+
+    pub fn write(&mut self, chunk: &[u8]) -> io::Result<()> {
+        match self {
+            Decoder::Identity(sink) => sink.write_all(chunk),
+            Decoder::Gzip(decoder) => decoder.write_all(chunk),
+            Decoder::Deflate(decoder) => decoder.write_all(chunk),
+            Decoder::Brotli(decoder) => decoder.write_all(chunk),
+        }
+    }
+
+    /// Fails when the body ended before its compressed stream did
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Decoder::Identity(sink) => Ok(sink),
+            Decoder::Gzip(decoder) => decoder.finish(),
+            Decoder::Deflate(decoder) => decoder.finish(),
+            Decoder::Brotli(decoder) => decoder
+                .into_inner()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "truncated brotli stream")),
+        }
+    }
+}
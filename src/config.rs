@@ -0,0 +1,314 @@
+use std::{error::Error, ffi::OsString, fs, path::Path};
+
+use serde_json::Value;
+
+/// Subcommands whose options don't come from a test plan
+const OTHER_COMMANDS: [&str; 6] = ["convert", "record", "report", "doctor", "fleet", "serve"];
+
+/// Expands `--config <FILE>` into the options the file describes, placed before the ones on
+/// the command line so those override it. Repeatable options such as headers are merged.
+/// `${KEY}` in its values is filled in from --env or the environment.
+pub fn expand_args(args: Vec<OsString>) -> Result<Vec<OsString>, Box<dyn Error>> {
+    let Some(insert_at) = options_start(&args) else {
+        return Ok(args);
+    };
+    let Some(path) = config_path(&args) else {
+        return Ok(args);
+    };
+
+    let env = env_overrides(&args[insert_at..]);
+    let options = read_options(Path::new(&path), &env)
+        .map_err(|e| format!("failed to load config {}: {}", path.to_string_lossy(), e))?;
+    let mut expanded = args;
+    expanded.splice(
+        insert_at..insert_at,
+        options.into_iter().map(OsString::from),
+    );
+    Ok(expanded)
+}
+
+/// Where the options of a test start, after the program name and `run`; None for the
+/// subcommands that don't run one
+fn options_start(args: &[OsString]) -> Option<usize> {
+    match args.get(1).and_then(|arg| arg.to_str()) {
+        Some(arg) if OTHER_COMMANDS.contains(&arg) => None,
+        Some("run") => Some(2),
+        _ => Some(1),
+    }
+}
+
+/// The --env options among `args`, read ahead of clap since the config is expanded first.
+/// Malformed ones are left for clap to report.
+fn env_overrides(args: &[OsString]) -> Vec<(String, String)> {
+    let mut overrides = vec![];
+    let mut args = args.iter().take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        let value = match arg.to_str() {
+            Some("--env") => args.next().and_then(|value| value.to_str()),
+            Some(arg) => arg.strip_prefix("--env="),
+            None => None,
+        };
+        if let Some(Ok(pair)) = value.map(parse_env) {
+            overrides.push(pair);
+        }
+    }
+    overrides
+}
+
+/// Parses a `KEY=VALUE` override of --env
+pub fn parse_env(pair: &str) -> Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got `{}`", pair)),
+    }
+}
+
+/// Fills in the `${KEY}` references of a config or scenario value, from the last --env override of KEY
+/// or else the environment. `${KEY:-default}` falls back to the default when neither has
+/// it, and `$${` is a literal `${`.
+pub fn interpolate(value: &str, env: &[(String, String)]) -> Result<String, String> {
+    let mut interpolated = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(at) = rest.find('$') {
+        interpolated.push_str(&rest[..at]);
+        rest = &rest[at..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            interpolated.push_str("${");
+            rest = after;
+            continue;
+        }
+        let Some(reference) = rest.strip_prefix("${") else {
+            interpolated.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+        let end = reference
+            .find('}')
+            .ok_or_else(|| format!("unclosed `${{` in `{}`", value))?;
+        let (key, default) = match reference[..end].split_once(":-") {
+            Some((key, default)) => (key, Some(default)),
+            None => (&reference[..end], None),
+        };
+        let resolved = env
+            .iter()
+            .rev()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.clone())
+            .or_else(|| std::env::var(key).ok())
+            .or_else(|| default.map(str::to_string))
+            .ok_or_else(|| {
+                format!(
+                    "environment variable `{}` is not set, pass it with --env {}=<VALUE>",
+                    key, key
+                )
+            })?;
+        interpolated.push_str(&resolved);
+        rest = &reference[end + 1..];
+    }
+    interpolated.push_str(rest);
+    Ok(interpolated)
+}
+
+/// Interpolates every string of a parsed config or scenario file, see interpolate
+pub fn interpolate_value(value: &mut Value, env: &[(String, String)]) -> Result<(), String> {
+    match value {
+        Value::String(s) => *s = interpolate(s, env)?,
+        Value::Array(values) => {
+            for value in values {
+                interpolate_value(value, env)?;
+            }
+        }
+        Value::Object(fields) => {
+            for value in fields.values_mut() {
+                interpolate_value(value, env)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn config_path(args: &[OsString]) -> Option<OsString> {
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            return None;
+        }
+        if arg == "--config" {
+            return args.next().cloned();
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(path.into());
+        }
+    }
+    None
+}
+
+/// Reads a TOML or YAML test plan whose keys are the long options, e.g. `url`, `concurrency`
+/// or `retry-budget`, turning it into command-line arguments
+fn read_options(path: &Path, env: &[(String, String)]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut plan = read_file(path)?;
+    interpolate_value(&mut plan, env)?;
+    let Value::Object(plan) = plan else {
+        return Err("expected a table of options".into());
+    };
+
+    let mut options = vec![];
+    for (key, value) in plan {
+        let flag = match key.replace('_', "-").as_str() {
+            // Nested configs would make precedence hard to follow
+            "config" => return Err("config files can't include other config files".into()),
+            "headers" => "--header".to_string(),
+            name => format!("--{}", name),
+        };
+        match value {
+            // Headers may also be written as a table of name to value
+            Value::Object(headers) if flag == "--header" => {
+                for (name, value) in headers {
+                    options.push(flag.clone());
+                    options.push(format!("{}: {}", name, scalar(&key, &value)?));
+                }
+            }
+            Value::Array(values) => {
+                for value in values {
+                    options.push(flag.clone());
+                    options.push(scalar(&key, &value)?);
+                }
+            }
+            Value::Bool(true) => options.push(flag),
+            Value::Bool(false) | Value::Null => {}
+            value => {
+                options.push(flag);
+                options.push(scalar(&key, &value)?);
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Parses a TOML or YAML file, told apart by its extension
+pub fn read_file(path: &Path) -> Result<Value, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => Ok(toml::from_str(&contents)?),
+        Some("yaml" | "yml") => Ok(serde_yaml::from_str(&contents)?),
+        _ => Err("expected a .toml, .yaml or .yml file".into()),
+    }
+}
+
+fn scalar(key: &str, value: &Value) -> Result<String, Box<dyn Error>> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(format!("unsupported value for `{}`", key).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    /// Writes a config file of this test's own, so tests running at once don't share one
+    fn config(name: &str, contents: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("load-tester-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn interpolates_references() {
+        let env = env(&[("HOST", "a.test"), ("HOST", "b.test"), ("NAME", "zoë")]);
+        assert_eq!(
+            interpolate("https://${HOST}/${NAME}", &env).unwrap(),
+            "https://b.test/zoë"
+        );
+        assert_eq!(
+            interpolate("${LOAD_TESTER_TEST_UNSET:-8080}", &env).unwrap(),
+            "8080"
+        );
+        assert_eq!(
+            interpolate("${LOAD_TESTER_TEST_UNSET:-}", &env).unwrap(),
+            ""
+        );
+        assert_eq!(interpolate("$${HOST} $5 $", &env).unwrap(), "${HOST} $5 $");
+        assert_eq!(interpolate("", &env).unwrap(), "");
+        assert!(interpolate("${LOAD_TESTER_TEST_UNSET}", &env).is_err());
+        assert!(interpolate("${HOST", &env).is_err());
+    }
+
+    #[test]
+    fn parses_env_overrides() {
+        assert_eq!(
+            parse_env("KEY=a=b").unwrap(),
+            ("KEY".to_string(), "a=b".to_string())
+        );
+        assert_eq!(
+            parse_env("KEY=").unwrap(),
+            ("KEY".to_string(), String::new())
+        );
+        assert!(parse_env("=value").is_err());
+        assert!(parse_env("KEY").is_err());
+        assert_eq!(
+            env_overrides(&args(&["--env", "A=1", "--env=B=2", "--", "--env", "C=3"])),
+            env(&[("A", "1"), ("B", "2")])
+        );
+    }
+
+    #[test]
+    fn expands_a_config_file() {
+        let path = config(
+            "expand.toml",
+            "url = \"https://${HOST}/\"\nconcurrency = 5\ndownload = true\nadaptive = false\n\
+             [headers]\nx-name = \"zoë\"\n",
+        );
+        let expanded = expand_args(args(&[
+            "load-tester",
+            "run",
+            "--config",
+            path.to_str().unwrap(),
+            "--env",
+            "HOST=a.test",
+        ]))
+        .unwrap();
+        assert_eq!(
+            expanded[2..9],
+            args(&[
+                "--concurrency",
+                "5",
+                "--download",
+                "--header",
+                "x-name: zoë",
+                "--url",
+                "https://a.test/",
+            ])
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_nested_configs() {
+        let path = config("nested.yaml", "config: other.yaml\n");
+        assert!(read_options(&path, &[]).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn leaves_other_commands_alone() {
+        assert_eq!(options_start(&args(&["load-tester", "report", "x"])), None);
+        assert_eq!(options_start(&args(&["load-tester", "run"])), Some(2));
+        assert_eq!(options_start(&args(&["load-tester"])), Some(1));
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use serde_json::json;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+};
+use tracing::{debug, info};
+
+use crate::{events::Event, stop::StopSignal};
+
+/// Requests started per second, adjustable while the test runs. Zero sends batches as fast
+/// as they complete.
+#[derive(Clone, Default)]
+pub struct RateLimit {
+    /// Bits of the f64 rate
+    rps: Arc<AtomicU64>,
+}
+
+impl RateLimit {
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.rps.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, rps: f64) {
+        self.rps.store(rps.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// State shared with the control API
+pub struct Control {
+    pub requested: u64,
+    pub test_start: Instant,
+    pub stop: StopSignal,
+    pub rate: RateLimit,
+}
+
+#[derive(Default)]
+struct Progress {
+    responses: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// Serves a small HTTP API on `addr` for the rest of the process:
+///
+/// - `GET /status` reports progress as JSON
+/// - `POST /stop` ends the test cleanly
+/// - `GET /rate` shows and `POST /rate?rps=<N>` changes the request rate (0 goes back to
+///   batches sent as fast as they complete)
+pub async fn serve(
+    addr: SocketAddr,
+    control: Control,
+    mut events: broadcast::Receiver<Event>,
+) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+    info!(addr = %local_addr, "control API listening");
+
+    let control = Arc::new(control);
+    let progress = Arc::new(Progress::default());
+    let counted = progress.clone();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(Event::BodyDone { .. }) => counted.responses.fetch_add(1, Ordering::Relaxed),
+                Ok(Event::Error { .. }) => counted.errors.fetch_add(1, Ordering::Relaxed),
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+        }
+    });
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let control = control.clone();
+            let progress = progress.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream, &control, &progress).await {
+                    debug!("control API connection failed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(local_addr)
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    control: &Control,
+    progress: &Progress,
+) -> std::io::Result<()> {
+    // Requests are a single line with no body worth reading
+    let mut buffer = [0; 1024];
+    let read = stream.read(&mut buffer).await?;
+    let request = String::from_utf8_lossy(&buffer[..read]);
+    let mut parts = request.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let (status, body) = match (method, path) {
+        ("GET", "/status") => (
+            "200 OK",
+            json!({
+                "elapsed": control.test_start.elapsed().as_secs_f64(),
+                "requested": control.requested,
+                "responses": progress.responses.load(Ordering::Relaxed),
+                "errors": progress.errors.load(Ordering::Relaxed),
+                "rate": control.rate.get(),
+                "stopped": control.stop.reason(),
+            }),
+        ),
+        ("POST", "/stop") => {
+            control
+                .stop
+                .stop("stopped through the control API".to_string());
+            ("200 OK", json!({ "stopped": true }))
+        }
+        ("GET", "/rate") => ("200 OK", json!({ "rate": control.rate.get() })),
+        ("POST", "/rate") => {
+            let rps = query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("rps="))
+                .and_then(|rps| rps.parse::<f64>().ok())
+                .filter(|rps| rps.is_finite() && *rps >= 0.0);
+            match rps {
+                Some(rps) => {
+                    info!(rps, "rate changed through the control API");
+                    control.rate.set(rps);
+                    ("200 OK", json!({ "rate": rps }))
+                }
+                None => (
+                    "400 Bad Request",
+                    json!({ "error": "expected ?rps=<non-negative number>" }),
+                ),
+            }
+        }
+        _ => ("404 Not Found", json!({ "error": "not found" })),
+    };
+
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -0,0 +1,737 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use base64::Engine;
+use reqwest::{header::COOKIE, Url};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::{
+    curl, har,
+    scenario::{ScenarioFile, StepFile},
+};
+
+/// Formats `convert` reads
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// HAR file exported from browser devtools
+    Har,
+    /// Postman collection, v2.1
+    Postman,
+    /// curl command lines, one per line as devtools' "Copy all as cURL" writes them
+    Curl,
+}
+
+/// A file to turn into a scenario and where to write it
+pub struct Conversion {
+    pub input: PathBuf,
+    /// Guessed from the extension of the input when missing
+    pub from: Option<Format>,
+    /// YAML or TOML by its extension, YAML on stdout when missing
+    pub output: Option<PathBuf>,
+    /// Write the recorded Authorization and Cookie values instead of placeholders
+    pub keep_credentials: bool,
+}
+
+/// Headers replaced by a `{{placeholder}}` so that credentials stay out of the scenario
+const CREDENTIAL_HEADERS: [&str; 3] = ["authorization", "proxy-authorization", "cookie"];
+
+/// A request read from the input, its URL absolute unless a placeholder stands for the host
+pub struct Request {
+    pub name: Option<String>,
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    /// Whether `{{...}}` in its text is meant as a placeholder, as in Postman
+    pub templated: bool,
+    /// Pause before it since the previous request ended, when the input recorded timings
+    pub gap: Option<Duration>,
+}
+
+/// Writes the requests of a HAR file, a Postman collection or curl commands as steps of a
+/// scenario, with paths relative to the first request's origin so that --url picks the host
+pub fn run(conversion: Conversion) -> Result<(), Box<dyn Error>> {
+    let input = &conversion.input;
+    let from = conversion
+        .from
+        .or_else(|| guess(input))
+        .ok_or_else(|| format!("can't tell the format of {}, pass --from", input.display()))?;
+    let requests = match from {
+        Format::Har => from_har(input)?,
+        Format::Postman => from_postman(&read(input)?).map_err(|e| {
+            format!(
+                "failed to read Postman collection {}: {}",
+                input.display(),
+                e
+            )
+        })?,
+        Format::Curl => from_curl(&read(input)?)
+            .map_err(|e| format!("failed to read curl commands {}: {}", input.display(), e))?,
+    };
+    if requests.is_empty() {
+        return Err(format!("no requests in {}", input.display()).into());
+    }
+    write(
+        requests,
+        conversion.output.as_deref(),
+        conversion.keep_credentials,
+    )
+}
+
+/// Writes `requests` as the steps of a scenario, to stdout without `output`, and says how to
+/// run it
+pub fn write(
+    requests: Vec<Request>,
+    output: Option<&Path>,
+    keep_credentials: bool,
+) -> Result<(), Box<dyn Error>> {
+    let origin = requests
+        .first()
+        .and_then(|request| Url::parse(&request.url).ok())
+        .map(|url| url.origin());
+    let mut credentials = vec![];
+    let steps: Vec<StepFile> = requests
+        .into_iter()
+        .map(|request| {
+            let url = match (Url::parse(&request.url), &origin) {
+                (Ok(url), Some(origin)) if url.origin() == *origin => match url.query() {
+                    Some(query) => format!("{}?{}", url.path(), query),
+                    None => url.path().to_string(),
+                },
+                _ => request.url,
+            };
+            let mut headers = BTreeMap::new();
+            for (name, value) in request.headers {
+                let name = name.to_ascii_lowercase();
+                // A value that is already a placeholder, e.g. Postman's {{token}}, is kept
+                let value = if !keep_credentials
+                    && CREDENTIAL_HEADERS.contains(&name.as_str())
+                    && !value.contains("{{")
+                {
+                    let placeholder = name.replace('-', "_");
+                    if !credentials.contains(&placeholder) {
+                        credentials.push(placeholder.clone());
+                    }
+                    format!("{{{{{}}}}}", placeholder)
+                } else {
+                    escape(&value, request.templated)
+                };
+                let joined = headers.entry(name.clone()).or_insert_with(String::new);
+                if joined.is_empty() {
+                    *joined = value;
+                } else if !joined.starts_with("{{") {
+                    // Repeated headers, e.g. HTTP/2 cookies, are folded into one
+                    joined.push_str(if name == COOKIE.as_str() { "; " } else { ", " });
+                    joined.push_str(&value);
+                }
+            }
+            StepFile {
+                name: request.name,
+                method: (request.method != "GET").then_some(request.method),
+                url: escape(&url, request.templated),
+                body: request.body.map(|body| escape(&body, request.templated)),
+                headers,
+                pause: request
+                    .gap
+                    .map(|gap| Duration::from_millis(gap.as_millis() as u64))
+                    .filter(|gap| !gap.is_zero())
+                    .map(|gap| humantime::format_duration(gap).to_string()),
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    let count = steps.len();
+    let file = ScenarioFile { steps };
+    let text = match output_extension(output)? {
+        "toml" => toml::to_string(&file)?,
+        _ => serde_yaml::to_string(&file)?,
+    };
+    let destination = match output {
+        Some(path) => {
+            fs::write(path, text)
+                .map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+            path.display().to_string()
+        }
+        None => {
+            print!("{}", text);
+            "<FILE>".to_string()
+        }
+    };
+    eprintln!(
+        "Converted {} request{}. Run them with:",
+        count,
+        if count == 1 { "" } else { "s" }
+    );
+    let url = origin.map_or("<URL>".to_string(), |origin| origin.ascii_serialization());
+    let secrets: String = credentials
+        .iter()
+        .map(|name| format!(" --secret {}=env:<VAR>", name))
+        .collect();
+    eprintln!(
+        "  load-tester --url {} --scenario {}{}",
+        url, destination, secrets
+    );
+    Ok(())
+}
+
+fn guess(input: &Path) -> Option<Format> {
+    match input.extension()?.to_str()? {
+        "har" => Some(Format::Har),
+        "json" => Some(Format::Postman),
+        "sh" | "curl" | "txt" => Some(Format::Curl),
+        _ => None,
+    }
+}
+
+fn output_extension(output: Option<&Path>) -> Result<&str, String> {
+    let Some(output) = output else {
+        return Ok("yaml");
+    };
+    match output.extension().and_then(|ext| ext.to_str()) {
+        Some(ext @ ("toml" | "yaml" | "yml")) => Ok(ext),
+        _ => Err(format!(
+            "expected a .toml, .yaml or .yml file to write, got {}",
+            output.display()
+        )),
+    }
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))
+}
+
+/// Recorded text as the scenario would send it: `$${` keeps a `${` from being interpolated,
+/// and a `{{` that isn't meant as a placeholder is pointed out, since there is no escaping it
+fn escape(text: &str, templated: bool) -> String {
+    if !templated && text.contains("{{") {
+        warn!(
+            "`{}` has {{{{ in it, which the scenario fills in as a placeholder",
+            text
+        );
+    }
+    text.replace("${", "$${")
+}
+
+fn from_har(path: &Path) -> Result<Vec<Request>, Box<dyn Error>> {
+    let recording = har::read(path)?;
+    Ok(recording
+        .requests
+        .into_iter()
+        .map(|request| Request {
+            name: Some(format!("{} {}", request.method, request.path)),
+            method: request.method.to_string(),
+            url: format!("{}{}", recording.origin, request.path),
+            headers: request
+                .headers
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            body: request.body,
+            templated: false,
+            gap: request.gap,
+        })
+        .collect())
+}
+
+/// Splits the input at the lines starting a curl command, each ended by a newline, `;` or `&&`
+fn from_curl(text: &str) -> Result<Vec<Request>, String> {
+    let mut commands: Vec<String> = vec![];
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("curl ") || commands.is_empty() && !trimmed.is_empty() {
+            commands.push(String::new());
+        }
+        if let Some(command) = commands.last_mut() {
+            command.push_str(line);
+            command.push('\n');
+        }
+    }
+    commands
+        .iter()
+        .map(|command| {
+            let command = command.trim_end();
+            let command = command
+                .strip_suffix("&&")
+                .or_else(|| command.strip_suffix(';'))
+                .unwrap_or(command);
+            curl::request(command)
+        })
+        .collect()
+}
+
+/// The parts of a Postman v2.1 collection that make up its requests
+#[derive(Deserialize)]
+struct Collection {
+    #[serde(default)]
+    item: Vec<Item>,
+    #[serde(default)]
+    variable: Vec<Variable>,
+    auth: Option<Auth>,
+}
+
+/// A request, or a folder of them
+#[derive(Deserialize)]
+struct Item {
+    name: Option<String>,
+    request: Option<PostmanRequest>,
+    #[serde(default)]
+    item: Vec<Item>,
+    auth: Option<Auth>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PostmanRequest {
+    Url(String),
+    Request(Box<FullRequest>),
+}
+
+#[derive(Deserialize)]
+struct FullRequest {
+    method: Option<String>,
+    #[serde(default)]
+    header: Vec<Pair>,
+    url: Option<PostmanUrl>,
+    body: Option<Body>,
+    auth: Option<Auth>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PostmanUrl {
+    Raw(String),
+    Parts { raw: String },
+}
+
+#[derive(Deserialize)]
+struct Pair {
+    key: String,
+    #[serde(default)]
+    value: Value,
+    #[serde(default)]
+    disabled: bool,
+}
+
+#[derive(Deserialize)]
+struct Body {
+    mode: Option<String>,
+    raw: Option<String>,
+    #[serde(default)]
+    urlencoded: Vec<Pair>,
+    options: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct Auth {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    bearer: Vec<Pair>,
+    #[serde(default)]
+    basic: Vec<Pair>,
+}
+
+#[derive(Deserialize)]
+struct Variable {
+    key: String,
+    #[serde(default)]
+    value: Value,
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+fn from_postman(json: &str) -> Result<Vec<Request>, Box<dyn Error>> {
+    let collection: Collection = serde_json::from_str(json)?;
+    let variables: HashMap<String, String> = collection
+        .variable
+        .iter()
+        .map(|variable| (variable.key.clone(), text(&variable.value)))
+        .collect();
+    let mut requests = vec![];
+    let mut hosts = BTreeSet::new();
+    collect(
+        &collection.item,
+        collection.auth.as_ref(),
+        &variables,
+        &mut requests,
+        &mut hosts,
+    )?;
+    for variable in hosts {
+        warn!(
+            "{{{{{}}}}} isn't defined in the collection, pass its origin with --url",
+            variable
+        );
+    }
+    Ok(requests)
+}
+
+/// The requests of `items` and their folders, in order, each with the auth it inherits.
+/// `hosts` gets the undefined variables standing for the scheme and host of a URL.
+fn collect(
+    items: &[Item],
+    auth: Option<&Auth>,
+    variables: &HashMap<String, String>,
+    requests: &mut Vec<Request>,
+    hosts: &mut BTreeSet<String>,
+) -> Result<(), String> {
+    for item in items {
+        let inherited = item.auth.as_ref().or(auth);
+        collect(&item.item, inherited, variables, requests, hosts)?;
+        let Some(request) = &item.request else {
+            continue;
+        };
+        let fill_in = |text: &str| fill(text, variables);
+        let (method, header, url, body, own_auth) = match request {
+            PostmanRequest::Url(url) => (None, &[][..], Some(url.clone()), None, None),
+            PostmanRequest::Request(request) => (
+                request.method.clone(),
+                request.header.as_slice(),
+                request.url.as_ref().map(|url| match url {
+                    PostmanUrl::Raw(raw) | PostmanUrl::Parts { raw } => raw.clone(),
+                }),
+                request.body.as_ref(),
+                request.auth.as_ref(),
+            ),
+        };
+        let name = item.name.clone();
+        let url = fill_in(
+            &url.ok_or_else(|| format!("{}: no URL", name.as_deref().unwrap_or("request")))?,
+        );
+        let mut headers: Vec<(String, String)> = header
+            .iter()
+            .filter(|pair| !pair.disabled)
+            .map(|pair| (pair.key.clone(), fill_in(&text(&pair.value))))
+            .collect();
+        let has_header = |headers: &[(String, String)], name: &str| {
+            headers
+                .iter()
+                .any(|(header, _)| header.eq_ignore_ascii_case(name))
+        };
+        if let Some(auth) = own_auth.or(inherited) {
+            if let Some(value) = authorization(auth, &fill_in) {
+                if !has_header(&headers, "authorization") {
+                    headers.push(("Authorization".to_string(), value));
+                }
+            }
+        }
+        let body = match body {
+            Some(Body {
+                mode: Some(mode),
+                raw,
+                urlencoded,
+                options,
+            }) => match mode.as_str() {
+                "raw" => {
+                    let language = options
+                        .as_ref()
+                        .and_then(|options| options.pointer("/raw/language"))
+                        .and_then(Value::as_str);
+                    if language == Some("json") && !has_header(&headers, "content-type") {
+                        headers.push(("Content-Type".to_string(), "application/json".to_string()));
+                    }
+                    raw.as_deref().map(fill_in)
+                }
+                "urlencoded" => {
+                    if !has_header(&headers, "content-type") {
+                        headers.push((
+                            "Content-Type".to_string(),
+                            "application/x-www-form-urlencoded".to_string(),
+                        ));
+                    }
+                    let form: Vec<String> = urlencoded
+                        .iter()
+                        .filter(|pair| !pair.disabled)
+                        .map(|pair| {
+                            let value = fill_in(&text(&pair.value));
+                            format!(
+                                "{}={}",
+                                form_encode(&fill_in(&pair.key)),
+                                form_encode(&value)
+                            )
+                        })
+                        .collect();
+                    Some(form.join("&"))
+                }
+                mode => {
+                    warn!(
+                        "skipping the {} body of {}, only raw and urlencoded bodies are converted",
+                        mode,
+                        name.as_deref().unwrap_or(&url)
+                    );
+                    None
+                }
+            },
+            _ => None,
+        };
+        let url = match url
+            .strip_prefix("{{")
+            .and_then(|rest| rest.split_once("}}"))
+        {
+            // A variable nobody defined stands for the scheme and host, which --url gives
+            Some((variable, path)) if !path.contains("://") => {
+                hosts.insert(variable.to_string());
+                if path.starts_with('/') {
+                    path.to_string()
+                } else {
+                    format!("/{}", path)
+                }
+            }
+            _ => url,
+        };
+        requests.push(Request {
+            name,
+            method: method.unwrap_or_else(|| "GET".to_string()).to_uppercase(),
+            url,
+            headers,
+            body,
+            templated: true,
+            gap: None,
+        });
+    }
+    Ok(())
+}
+
+/// The Authorization header of a bearer or basic auth, warning about other kinds
+fn authorization(auth: &Auth, fill: &impl Fn(&str) -> String) -> Option<String> {
+    let get = |pairs: &[Pair], key: &str| {
+        pairs
+            .iter()
+            .find(|pair| pair.key == key)
+            .map(|pair| fill(&text(&pair.value)))
+            .unwrap_or_default()
+    };
+    match auth.kind.as_str() {
+        "noauth" => None,
+        "bearer" => Some(format!("Bearer {}", get(&auth.bearer, "token"))),
+        "basic" => {
+            let credentials = format!(
+                "{}:{}",
+                get(&auth.basic, "username"),
+                get(&auth.basic, "password")
+            );
+            let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+            Some(format!("Basic {}", encoded))
+        }
+        kind => {
+            warn!(
+                "skipping {} auth, only bearer and basic are converted",
+                kind
+            );
+            None
+        }
+    }
+}
+
+/// Percent-encodes a form field, leaving its placeholders to be filled in
+fn form_encode(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some((open, close)) = rest
+        .find("{{")
+        .and_then(|open| Some((open, open + rest[open..].find("}}")? + 2)))
+    {
+        out.push_str(&curl::urlencode(&rest[..open]));
+        out.push_str(&rest[open..close]);
+        rest = &rest[close..];
+    }
+    out.push_str(&curl::urlencode(rest));
+    out
+}
+
+/// Fills in the collection's variables. Other `{{name}}` placeholders are left for the
+/// scenario to fill in from the columns of --data.
+fn fill(text: &str, variables: &HashMap<String, String>) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(open) = rest.find("{{") {
+        let Some(close) = rest[open..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..open]);
+        let name = rest[open + 2..open + close].trim();
+        match variables.get(name) {
+            Some(value) => out.push_str(value),
+            None => {
+                if name.starts_with('$') {
+                    warn!("Postman's {{{{{}}}}} has no equivalent", name);
+                }
+                out.push_str(&rest[open..open + close + 2]);
+            }
+        }
+        rest = &rest[open + close + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_in_variables() {
+        let variables = HashMap::from([("host".to_string(), "https://x.test".to_string())]);
+        assert_eq!(
+            fill(
+                "{{host}}/a?id={{$guid}}&n={{ host }}&u={{user}}",
+                &variables
+            ),
+            "https://x.test/a?id={{$guid}}&n=https://x.test&u={{user}}"
+        );
+        assert_eq!(fill("héllo {{host", &variables), "héllo {{host");
+        assert_eq!(fill("{{$randomColor}}", &variables), "{{$randomColor}}");
+        assert_eq!(fill("", &variables), "");
+    }
+
+    #[test]
+    fn form_encodes_around_placeholders() {
+        assert_eq!(
+            form_encode("a b&{{tök en}}=é"),
+            "a%20b%26{{tök en}}%3D%C3%A9"
+        );
+        assert_eq!(form_encode("{{open"), "%7B%7Bopen");
+        assert_eq!(form_encode(""), "");
+    }
+
+    #[test]
+    fn escapes_interpolation() {
+        assert_eq!(escape("${HOST} $${x} $5", true), "$${HOST} $$${x} $5");
+    }
+
+    #[test]
+    fn writes_credentials_as_placeholders() {
+        let requests = || {
+            vec![Request {
+                name: None,
+                method: "GET".to_string(),
+                url: "https://x.test/a".to_string(),
+                headers: [
+                    ("Cookie", "a=1"),
+                    ("cookie", "b=2"),
+                    ("Proxy-Authorization", "Basic eDp5"),
+                    ("Authorization", "Bearer {{token}}"),
+                ]
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .to_vec(),
+                body: None,
+                templated: true,
+                gap: None,
+            }]
+        };
+        let path = std::env::temp_dir().join(format!("convert-{}.yaml", std::process::id()));
+        let written = |keep_credentials| {
+            write(requests(), Some(&path), keep_credentials).unwrap();
+            let file: ScenarioFile = serde_yaml::from_str(&read(&path).unwrap()).unwrap();
+            file.steps.into_iter().next().unwrap().headers
+        };
+        let headers = written(false);
+        assert_eq!(headers["cookie"], "{{cookie}}");
+        assert_eq!(headers["proxy-authorization"], "{{proxy_authorization}}");
+        assert_eq!(headers["authorization"], "Bearer {{token}}");
+        assert_eq!(written(true)["cookie"], "a=1; b=2");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn writes_recorded_pauses() {
+        let requests = [
+            None,
+            Some(Duration::from_micros(1_250_400)),
+            Some(Duration::ZERO),
+        ]
+        .into_iter()
+        .map(|gap| Request {
+            name: None,
+            method: "GET".to_string(),
+            url: "https://x.test/".to_string(),
+            headers: vec![],
+            body: None,
+            templated: false,
+            gap,
+        })
+        .collect();
+        let path = std::env::temp_dir().join(format!("pauses-{}.toml", std::process::id()));
+        write(requests, Some(&path), false).unwrap();
+        let file: ScenarioFile = toml::from_str(&read(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        let pauses: Vec<_> = file.steps.into_iter().map(|step| step.pause).collect();
+        assert_eq!(pauses, [None, Some("1s 250ms".to_string()), None]);
+    }
+
+    #[test]
+    fn reads_one_request_per_curl_command() {
+        let requests = from_curl(
+            "curl 'https://x.test/a' -H 'Accept: */*' \\\n  --compressed ;\n\n\
+             curl https://x.test/b -d 'q=1' -b 'a=1; b=2' &&\n",
+        )
+        .unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].name.as_deref(), Some("GET /a"));
+        assert_eq!(
+            requests[0].headers,
+            [("Accept".to_string(), "*/*".to_string())]
+        );
+        assert_eq!(requests[1].method, "POST");
+        assert_eq!(requests[1].body.as_deref(), Some("q=1"));
+        assert_eq!(
+            requests[1].headers.last().unwrap(),
+            &("Cookie".to_string(), "a=1; b=2".to_string())
+        );
+        assert!(from_curl("").unwrap().is_empty());
+        assert!(from_curl("curl 'https://x.test").is_err());
+    }
+
+    #[test]
+    fn reads_a_postman_collection() {
+        let collection = r#"{
+            "auth": {"type": "bearer", "bearer": [{"key": "token", "value": "{{token}}"}]},
+            "variable": [{"key": "base", "value": "https://x.test"}],
+            "item": [
+                {"name": "folder", "item": [
+                    {"name": "list", "request": "{{base}}/items"}
+                ]},
+                {"name": "create", "request": {
+                    "method": "POST",
+                    "header": [{"key": "X-Off", "value": "1", "disabled": true}],
+                    "url": {"raw": "{{base}}/items"},
+                    "body": {"mode": "urlencoded", "urlencoded": [
+                        {"key": "name", "value": "zoë {{$timestamp}}"}
+                    ]},
+                    "auth": {"type": "noauth"}
+                }}
+            ]
+        }"#;
+        let requests = from_postman(collection).unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].url, "https://x.test/items");
+        assert_eq!(
+            requests[0].headers,
+            [("Authorization".to_string(), "Bearer {{token}}".to_string())]
+        );
+        assert_eq!(requests[1].method, "POST");
+        assert!(requests[1]
+            .headers
+            .iter()
+            .all(|(name, _)| name != "X-Off" && name != "Authorization"));
+        assert_eq!(
+            requests[1].body.as_deref(),
+            Some("name=zo%C3%AB%20{{$timestamp}}")
+        );
+        assert!(from_postman("{}").unwrap().is_empty());
+        assert!(from_postman("[]").is_err());
+    }
+}
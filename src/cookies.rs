@@ -0,0 +1,63 @@
+use reqwest::{
+    cookie::{CookieStore, Jar},
+    header::{HeaderMap, HeaderValue, SET_COOKIE},
+    Url,
+};
+
+/// Parses `name=value`
+pub fn parse_cookie(cookie: &str) -> Result<(String, String), String> {
+    let (name, value) = cookie
+        .split_once('=')
+        .ok_or_else(|| format!("invalid cookie `{}`, expected name=value", cookie))?;
+    let name = name.trim();
+    if name.is_empty() || name.contains([';', ' ']) || value.contains(';') {
+        return Err(format!("invalid cookie `{}`, expected name=value", cookie));
+    }
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+/// Cookies sent with every request, and with --cookies a jar per concurrent user that
+/// replays what the server sets, so every user keeps its own session
+pub struct Cookies {
+    fixed: Option<String>,
+    jars: Vec<Jar>,
+}
+
+impl Cookies {
+    pub fn new(fixed: &[(String, String)], jars: usize) -> Self {
+        let fixed = fixed
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Cookies {
+            fixed: (!fixed.is_empty()).then_some(fixed),
+            jars: (0..jars).map(|_| Jar::default()).collect(),
+        }
+    }
+
+    /// Cookie header of a request `user` sends to `url`, if it has any cookies
+    pub fn header(&self, user: usize, url: &str) -> Option<HeaderValue> {
+        let stored = self
+            .jars
+            .get(user)
+            .zip(Url::parse(url).ok())
+            .and_then(|(jar, url)| jar.cookies(&url))
+            .and_then(|cookies| cookies.to_str().ok().map(str::to_string));
+        let cookies = match (&self.fixed, stored) {
+            (Some(fixed), Some(stored)) => format!("{}; {}", fixed, stored),
+            (Some(fixed), None) => fixed.clone(),
+            (None, Some(stored)) => stored,
+            (None, None) => return None,
+        };
+        HeaderValue::from_str(&cookies).ok()
+    }
+
+    /// Keeps the cookies a response to `user`'s request to `url` sets. Only the final
+    /// response of a redirect chain is seen.
+    pub fn store(&self, user: usize, url: &str, headers: &HeaderMap) {
+        if let Some((jar, url)) = self.jars.get(user).zip(Url::parse(url).ok()) {
+            jar.set_cookies(&mut headers.get_all(SET_COOKIE).iter(), &url);
+        }
+    }
+}
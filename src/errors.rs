@@ -0,0 +1,106 @@
+use std::{error::Error, fmt, io};
+
+use serde::{Deserialize, Serialize};
+
+/// Why a request failed without getting a response, to tell network trouble from the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// The host name didn't resolve
+    Dns,
+    ConnectionRefused,
+    /// The peer reset or closed the connection before the response headers
+    ConnectionReset,
+    /// Handshake or certificate failure
+    Tls,
+    Timeout,
+    /// The response headers arrived but reading the body failed
+    Body,
+    /// Any other failure to connect
+    Connect,
+    Redirect,
+    Request,
+}
+
+impl ErrorCategory {
+    /// Name used in the raw CSV output
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCategory::Dns => "dns",
+            ErrorCategory::ConnectionRefused => "connection_refused",
+            ErrorCategory::ConnectionReset => "connection_reset",
+            ErrorCategory::Tls => "tls",
+            ErrorCategory::Timeout => "timeout",
+            ErrorCategory::Body => "body",
+            ErrorCategory::Connect => "connect",
+            ErrorCategory::Redirect => "redirect",
+            ErrorCategory::Request => "request",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorCategory::Dns => "DNS Failure",
+            ErrorCategory::ConnectionRefused => "Connection Refused",
+            ErrorCategory::ConnectionReset => "Connection Reset",
+            ErrorCategory::Tls => "TLS Error",
+            ErrorCategory::Timeout => "Timeout",
+            ErrorCategory::Body => "Body Read Error",
+            ErrorCategory::Connect => "Other Connect Error",
+            ErrorCategory::Redirect => "Redirect Error",
+            ErrorCategory::Request => "Other Request Error",
+        })
+    }
+}
+
+/// Looks through the error's sources for the underlying cause, reqwest only says whether
+/// it happened while connecting, sending or reading the body
+pub fn categorize(e: &reqwest::Error) -> ErrorCategory {
+    if e.is_timeout() {
+        return ErrorCategory::Timeout;
+    }
+    if e.is_body() || e.is_decode() {
+        return ErrorCategory::Body;
+    }
+    let mut source = e.source();
+    while let Some(cause) = source {
+        if cause.is::<rustls::Error>() {
+            return ErrorCategory::Tls;
+        }
+        if cause
+            .downcast_ref::<hyper::Error>()
+            .is_some_and(|e| e.is_incomplete_message())
+        {
+            return ErrorCategory::ConnectionReset;
+        }
+        // hyper's resolver error type is private, its message is all there is to go on
+        if cause.to_string().starts_with("dns error") {
+            return ErrorCategory::Dns;
+        }
+        source = cause.source();
+        if let Some(io) = cause.downcast_ref::<io::Error>() {
+            match io.kind() {
+                io::ErrorKind::ConnectionRefused => return ErrorCategory::ConnectionRefused,
+                io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::UnexpectedEof => return ErrorCategory::ConnectionReset,
+                io::ErrorKind::TimedOut => return ErrorCategory::Timeout,
+                _ => {}
+            }
+            // An io::Error hands out the source of the error it wraps rather than that error
+            if let Some(inner) = io.get_ref() {
+                source = Some(inner);
+            }
+        }
+    }
+    if e.is_connect() {
+        ErrorCategory::Connect
+    } else if e.is_redirect() {
+        ErrorCategory::Redirect
+    } else {
+        ErrorCategory::Request
+    }
+}
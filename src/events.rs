@@ -0,0 +1,200 @@
+use std::{
+    net::SocketAddr,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use reqwest::StatusCode;
+use tokio::{
+    fs::File,
+    io::{AsyncWriteExt, BufWriter},
+    sync::broadcast,
+};
+
+/// Number of events a slow subscriber may fall behind before it starts missing them
+const CAPACITY: usize = 4096;
+
+/// Something that happened in the engine. Ids tie together events of the same request,
+/// retries included.
+#[derive(Debug, Clone)]
+pub enum Event {
+    RequestScheduled {
+        id: u64,
+        attempt: u8,
+        at: Instant,
+    },
+    /// The request got a connection to `addr`, a new one or one from the pool
+    Connect {
+        id: u64,
+        addr: SocketAddr,
+    },
+    Headers {
+        id: u64,
+        status: StatusCode,
+        ttfb: f32,
+    },
+    /// The body was read and the response judged: `succeeded` folds in --expect-status, the
+    /// assertions, scenario checks and script
+    BodyDone {
+        id: u64,
+        bytes: u64,
+        ttlb: f32,
+        succeeded: bool,
+    },
+    Error {
+        id: u64,
+        message: String,
+    },
+    IntervalSummary {
+        elapsed: Duration,
+        successful: u64,
+        failed: u64,
+    },
+    /// Last event of a test, subscribers should flush and stop
+    Finished {
+        elapsed: Duration,
+    },
+}
+
+/// Broadcasts engine events to reporters, exporters and plugins. Publishing never blocks
+/// and is a no-op when nobody is subscribed.
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+    next_id: AtomicU64,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        EventBus {
+            sender,
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    pub fn publish(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn next_request_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+/// Writes every event as a line of `key=value` pairs until the test finishes.
+/// Timestamps are seconds since `test_start`.
+pub async fn write_events(
+    mut events: broadcast::Receiver<Event>,
+    path: &Path,
+    test_start: Instant,
+) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path).await?);
+    loop {
+        let (line, finished) = match events.recv().await {
+            Ok(event) => (
+                line(&event, test_start),
+                matches!(event, Event::Finished { .. }),
+            ),
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                (format!("lagged missed={}", missed), false)
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        out.write_all(line.as_bytes()).await?;
+        out.write_all(b"\n").await?;
+        if finished {
+            break;
+        }
+    }
+    out.flush().await
+}
+
+fn line(event: &Event, test_start: Instant) -> String {
+    match event {
+        Event::RequestScheduled { id, attempt, at } => format!(
+            "request_scheduled id={} attempt={} at={:.6}",
+            id,
+            attempt,
+            at.duration_since(test_start).as_secs_f32()
+        ),
+        Event::Connect { id, addr } => format!("connect id={} addr={}", id, addr),
+        Event::Headers { id, status, ttfb } => format!(
+            "headers id={} status={} ttfb={:.6}",
+            id,
+            status.as_u16(),
+            ttfb
+        ),
+        Event::BodyDone {
+            id,
+            bytes,
+            ttlb,
+            succeeded,
+        } => format!(
+            "body_done id={} bytes={} ttlb={:.6} succeeded={}",
+            id, bytes, ttlb, succeeded
+        ),
+        Event::Error { id, message } => format!("error id={} message={:?}", id, message),
+        Event::IntervalSummary {
+            elapsed,
+            successful,
+            failed,
+        } => format!(
+            "interval_summary elapsed={:.6} successful={} failed={}",
+            elapsed.as_secs_f32(),
+            successful,
+            failed
+        ),
+        Event::Finished { elapsed } => format!("finished elapsed={:.6}", elapsed.as_secs_f32()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_one_line_per_event() {
+        let start = Instant::now();
+        let events = [
+            Event::RequestScheduled {
+                id: 7,
+                attempt: 0,
+                at: start,
+            },
+            Event::Connect {
+                id: 7,
+                addr: "10.0.0.1:443".parse().unwrap(),
+            },
+            Event::Headers {
+                id: 7,
+                status: StatusCode::OK,
+                ttfb: 0.25,
+            },
+            Event::BodyDone {
+                id: 7,
+                bytes: 512,
+                ttlb: 0.5,
+                succeeded: true,
+            },
+            Event::Finished {
+                elapsed: Duration::from_secs(1),
+            },
+        ];
+        let lines: Vec<String> = events.iter().map(|event| line(event, start)).collect();
+        assert_eq!(
+            lines,
+            [
+                "request_scheduled id=7 attempt=0 at=0.000000",
+                "connect id=7 addr=10.0.0.1:443",
+                "headers id=7 status=200 ttfb=0.250000",
+                "body_done id=7 bytes=512 ttlb=0.500000 succeeded=true",
+                "finished elapsed=1.000000",
+            ]
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    error::Error,
+    ffi::OsStr,
+    path::PathBuf,
+    process::Stdio,
+    time::{Duration, Instant, SystemTime},
+};
+
+use futures::future::join_all;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::{checkpoint::Checkpoint, report};
+
+/// Hosts to run workers on and the test each of them runs
+pub struct Fleet {
+    pub hosts: Vec<String>,
+    /// Binary copied to every host, usually the running executable
+    pub binary: PathBuf,
+    /// How far in the future the synchronized start is scheduled
+    pub lead_time: Duration,
+    /// Arguments passed to `run` on every worker
+    pub args: Vec<String>,
+}
+
+/// Copies the binary to every host over SSH, runs the test on all of them with a shared
+/// --start-at, collects their checkpoints, removes what was deployed and prints one report
+pub async fn run(fleet: Fleet) -> Result<(), Box<dyn Error>> {
+    let remote_dir = format!("/tmp/load-tester-{}", std::process::id());
+    let start_at = SystemTime::now() + fleet.lead_time;
+    let local_dir = std::env::temp_dir().join(format!("load-tester-fleet-{}", std::process::id()));
+    std::fs::create_dir_all(&local_dir)?;
+
+    info!(
+        "running on {} workers, starting at {}",
+        fleet.hosts.len(),
+        humantime::format_rfc3339_seconds(start_at)
+    );
+    let workers = fleet.hosts.iter().map(|host| {
+        let checkpoint = local_dir.join(format!("{}.json", host.replace(['/', ':', '@'], "_")));
+        run_worker(host, &fleet, &remote_dir, start_at, checkpoint)
+    });
+    let results = join_all(workers).await;
+    let _ = std::fs::remove_dir_all(&local_dir);
+    print_combined(&fleet.hosts, results)
+}
+
+/// Merges the checkpoints of the workers that succeeded and prints one report, logging the
+/// ones that failed
+pub fn print_combined(
+    hosts: &[String],
+    results: Vec<Result<Checkpoint, Box<dyn Error>>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut merged: Option<Checkpoint> = None;
+    let (mut succeeded, mut failed) = (0, 0);
+    for (host, result) in hosts.iter().zip(results) {
+        match result {
+            Ok(checkpoint) => {
+                succeeded += 1;
+                match &mut merged {
+                    Some(merged) => merged.merge(checkpoint),
+                    None => merged = Some(checkpoint),
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                warn!(host, "worker failed: {}", e);
+            }
+        }
+    }
+
+    let merged = merged.ok_or("every worker failed")?;
+    let test_start = Instant::now();
+    match failed {
+        0 => println!(
+            "Combined results of {} workers against {}",
+            succeeded, merged.url
+        ),
+        _ => println!(
+            "Combined results of {} workers against {} ({} failed)",
+            succeeded, merged.url, failed
+        ),
+    }
+    report::print_report(
+        &merged.restore(test_start)?,
+        &merged.sections,
+        merged.elapsed,
+    );
+    Ok(())
+}
+
+/// Deploys, runs and tears down one worker, returning the checkpoint it wrote
+async fn run_worker(
+    host: &str,
+    fleet: &Fleet,
+    remote_dir: &str,
+    start_at: SystemTime,
+    checkpoint: PathBuf,
+) -> Result<Checkpoint, Box<dyn Error>> {
+    let remote_binary = format!("{}/load-tester", remote_dir);
+    let remote_checkpoint = format!("{}/checkpoint.json", remote_dir);
+
+    info!(host, "deploying worker");
+    ssh(host, &["mkdir", "-p", remote_dir]).await?;
+    scp(&fleet.binary, &format!("{}:{}", host, remote_binary)).await?;
+
+    info!(host, "starting worker");
+    let start_at = humantime::format_rfc3339_seconds(start_at).to_string();
+    let mut command = vec![remote_binary.as_str(), "run"];
+    command.extend(fleet.args.iter().map(String::as_str));
+    command.extend(["--start-at", &start_at, "--checkpoint", &remote_checkpoint]);
+    // A test that missed a threshold or aborted still wrote its results, so the checkpoint
+    // is fetched whatever the exit status and the worker only failed when there is none
+    let ran = ssh(host, &command).await;
+    let outcome = scp(format!("{}:{}", host, remote_checkpoint), &checkpoint)
+        .await
+        .and_then(|()| Checkpoint::read(&checkpoint))
+        .map(|checkpoint| Checkpoint {
+            // Raw samples stay on the worker, the aggregates hold what the report needs
+            samples_file: None,
+            ..checkpoint
+        })
+        .map_err(|e| ran.err().unwrap_or(e));
+
+    info!(host, "tearing down worker");
+    if let Err(e) = ssh(host, &["rm", "-rf", remote_dir]).await {
+        warn!(host, "failed to clean up {}: {}", remote_dir, e);
+    }
+    outcome
+}
+
+async fn ssh(host: &str, command: &[&str]) -> Result<(), Box<dyn Error>> {
+    // ssh joins its arguments into one shell command, so quote them to keep URLs intact
+    let command: Vec<String> = command
+        .iter()
+        .map(|arg| format!("'{}'", arg.replace('\'', "'\\''")))
+        .collect();
+    let mut ssh = Command::new("ssh");
+    ssh.args(["-o", "BatchMode=yes", host]).args(command);
+    output(ssh, host).await
+}
+
+async fn scp(from: impl AsRef<OsStr>, to: impl AsRef<OsStr>) -> Result<(), Box<dyn Error>> {
+    let mut scp = Command::new("scp");
+    scp.args(["-q", "-o", "BatchMode=yes"])
+        .arg(from)
+        .arg(to.as_ref());
+    output(scp, &to.as_ref().to_string_lossy()).await
+}
+
+async fn output(mut command: Command, host: &str) -> Result<(), Box<dyn Error>> {
+    let output = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .await?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} failed on {}: {}",
+            command.as_std().get_program().to_string_lossy(),
+            host,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into())
+    }
+}
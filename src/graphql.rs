@@ -0,0 +1,84 @@
+use std::{error::Error, fs, path::Path};
+
+use serde_json::{json, Value};
+
+/// The JSON body of a GraphQL POST: the operation read from `query`, with the `variables`
+/// file's object if there is one
+pub fn payload(query: &Path, variables: Option<&Path>) -> Result<String, Box<dyn Error>> {
+    let text = fs::read_to_string(query)
+        .map_err(|e| format!("failed to read query {}: {}", query.display(), e))?;
+    if text.trim().is_empty() {
+        return Err(format!("query {} is empty", query.display()).into());
+    }
+    let mut payload = json!({ "query": text });
+    if let Some(path) = variables {
+        let read = || -> Result<Value, Box<dyn Error>> {
+            let variables: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+            if !variables.is_object() {
+                return Err("expected a JSON object".into());
+            }
+            Ok(variables)
+        };
+        payload["variables"] =
+            read().map_err(|e| format!("failed to read variables {}: {}", path.display(), e))?;
+    }
+    Ok(payload.to_string())
+}
+
+/// Whether a response body carries a non-empty `errors` array, which GraphQL servers send
+/// with a 200 when the operation failed. Bodies that aren't JSON have none.
+pub fn has_errors(body: &[u8]) -> bool {
+    serde_json::from_slice::<Value>(body).is_ok_and(|body| {
+        body.get("errors")
+            .and_then(Value::as_array)
+            .is_some_and(|errors| !errors.is_empty())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn write(name: &str, text: &str) -> std::path::PathBuf {
+        let path = env::temp_dir().join(format!("graphql-{}-{}", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn builds_the_payload() {
+        let query = write(
+            "user.graphql",
+            "query User($id: ID!) { user(id: $id) { name } }\n",
+        );
+        let body: Value = serde_json::from_str(&payload(&query, None).unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json!({"query": "query User($id: ID!) { user(id: $id) { name } }\n"})
+        );
+        let variables = write("vars.json", r#"{"id": "7"}"#);
+        let body: Value =
+            serde_json::from_str(&payload(&query, Some(&variables)).unwrap()).unwrap();
+        assert_eq!(body["variables"], json!({"id": "7"}));
+
+        let list = write("list.json", "[1]");
+        assert!(payload(&query, Some(&list)).is_err());
+        let empty = write("empty.graphql", " \n");
+        assert!(payload(&empty, None).is_err());
+        for path in [query, variables, list, empty] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn spots_errors() {
+        assert!(has_errors(
+            br#"{"data": null, "errors": [{"message": "boom"}]}"#
+        ));
+        assert!(!has_errors(br#"{"data": {"user": null}, "errors": []}"#));
+        assert!(!has_errors(br#"{"data": {"user": {"name": "a"}}}"#));
+        assert!(!has_errors(b"<html>errors</html>"));
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use futures::future::join_all;
+use prost::Message;
+use prost_reflect::{
+    prost_types::FileDescriptorProto, DescriptorPool, DynamicMessage, MessageDescriptor,
+    MethodDescriptor,
+};
+use tonic::{
+    codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder},
+    codegen::http::uri::PathAndQuery,
+    transport::{Channel, ClientTlsConfig, Endpoint},
+    Code, Request, Status,
+};
+use tonic_reflection::pb::{
+    server_reflection_client::ServerReflectionClient, server_reflection_request::MessageRequest,
+    server_reflection_response::MessageResponse, ServerReflectionRequest,
+};
+use tracing::{debug, info};
+
+use crate::latency::LatencyHistogram;
+
+/// A gRPC test: unary calls of one method with the same request message, --concurrency at
+/// a time
+pub struct GrpcTest {
+    /// Server to call, e.g. http://localhost:50051
+    pub url: String,
+    /// Method to call, as package.Service/Method
+    pub method: String,
+    /// .proto file describing the method, or None to ask the server's reflection service
+    pub proto: Option<PathBuf>,
+    /// Directories the imports of `proto` are resolved from, besides its own
+    pub import_paths: Vec<PathBuf>,
+    /// Request message as JSON
+    pub data: String,
+    pub number: u64,
+    pub concurrency: usize,
+    /// Keep calling until this much time has passed instead of making `number` calls
+    pub duration: Option<Duration>,
+}
+
+/// What the calls of a test got back
+#[derive(Default)]
+struct Summary {
+    /// Latency of the calls that got a status, whatever it was
+    latency: LatencyHistogram,
+    codes: BTreeMap<i32, u64>,
+}
+
+pub async fn run(test: GrpcTest) -> Result<(), Box<dyn Error>> {
+    let (service, method) = test.method.rsplit_once('/').ok_or_else(|| {
+        format!(
+            "invalid method `{}`, expected package.Service/Method",
+            test.method
+        )
+    })?;
+    if test.concurrency == 0 {
+        return Err("concurrency must be at least 1".into());
+    }
+    let mut endpoint = Endpoint::from_shared(test.url.clone())?;
+    if test.url.starts_with("https://") {
+        endpoint = endpoint.tls_config(ClientTlsConfig::new())?;
+    }
+    let channel = endpoint
+        .connect()
+        .await
+        .map_err(|e| format!("failed to connect to {}: {}", test.url, e))?;
+    let pool = match &test.proto {
+        Some(proto) => compile(proto, &test.import_paths)?,
+        None => reflect(channel.clone(), service).await?,
+    };
+    let descriptor = pool
+        .get_service_by_name(service)
+        .ok_or_else(|| format!("no service {}", service))?
+        .methods()
+        .find(|m| m.name() == method)
+        .ok_or_else(|| format!("{} has no method {}", service, method))?;
+    if descriptor.is_client_streaming() || descriptor.is_server_streaming() {
+        return Err(format!(
+            "{} is a streaming method, only unary ones are called",
+            test.method
+        )
+        .into());
+    }
+    let request = parse_request(&descriptor, &test.data)?;
+    let path = PathAndQuery::from_str(&format!("/{}/{}", service, method))?;
+
+    let calls = AtomicU64::new(0);
+    let summary = Mutex::new(Summary::default());
+    let started = Instant::now();
+    let deadline = test.duration.map(|duration| started + duration);
+    let workers = (0..test.concurrency).map(|_| async {
+        let mut client = tonic::client::Grpc::new(channel.clone());
+        loop {
+            let more = match deadline {
+                Some(deadline) => Instant::now() < deadline,
+                None => calls.fetch_add(1, Ordering::Relaxed) < test.number,
+            };
+            if !more {
+                break;
+            }
+            let start = Instant::now();
+            let code = match call(&mut client, &request, &path, descriptor.output()).await {
+                Ok(()) => Code::Ok,
+                Err(status) => {
+                    debug!("call failed: {}", status);
+                    status.code()
+                }
+            };
+            let elapsed = start.elapsed().as_secs_f32();
+            let mut summary = summary.lock().unwrap();
+            summary.latency.record(elapsed);
+            *summary.codes.entry(code as i32).or_default() += 1;
+        }
+    });
+    join_all(workers).await;
+    print_summary(&test, &summary.into_inner().unwrap(), started.elapsed());
+    Ok(())
+}
+
+async fn call(
+    client: &mut tonic::client::Grpc<Channel>,
+    request: &DynamicMessage,
+    path: &PathAndQuery,
+    output: MessageDescriptor,
+) -> Result<(), Status> {
+    client
+        .ready()
+        .await
+        .map_err(|e| Status::unavailable(e.to_string()))?;
+    client
+        .unary(
+            Request::new(request.clone()),
+            path.clone(),
+            DynamicCodec(output),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Descriptors of the .proto file and what it imports
+fn compile(proto: &Path, import_paths: &[PathBuf]) -> Result<DescriptorPool, Box<dyn Error>> {
+    let own = proto
+        .parent()
+        .map(|dir| dir.to_path_buf())
+        .unwrap_or_default();
+    let mut includes = vec![own];
+    includes.extend(import_paths.iter().cloned());
+    let mut compiler = protox::Compiler::new(includes)?;
+    compiler
+        .open_file(proto)
+        .map_err(|e| format!("failed to compile {}: {}", proto.display(), e))?;
+    Ok(compiler.descriptor_pool())
+}
+
+/// Descriptors of the file defining `service` and its imports, from the server's reflection
+/// service
+async fn reflect(channel: Channel, service: &str) -> Result<DescriptorPool, Box<dyn Error>> {
+    info!(service, "describing the service through server reflection");
+    let mut client = ServerReflectionClient::new(channel);
+    let mut files: HashMap<String, FileDescriptorProto> = HashMap::new();
+    let mut asked = HashSet::new();
+    let mut wanted = vec![MessageRequest::FileContainingSymbol(service.to_string())];
+    while let Some(message_request) = wanted.pop() {
+        let request = ServerReflectionRequest {
+            host: String::new(),
+            message_request: Some(message_request),
+        };
+        let mut responses = client
+            .server_reflection_info(futures::stream::iter([request]))
+            .await
+            .map_err(|e| format!("server reflection failed: {}", e.message()))?
+            .into_inner();
+        while let Some(response) = responses.message().await? {
+            match response.message_response {
+                Some(MessageResponse::FileDescriptorResponse(response)) => {
+                    for bytes in response.file_descriptor_proto {
+                        let file = FileDescriptorProto::decode(bytes.as_slice())?;
+                        files.insert(file.name().to_string(), file);
+                    }
+                }
+                Some(MessageResponse::ErrorResponse(error)) => {
+                    return Err(format!("server reflection failed: {}", error.error_message).into())
+                }
+                _ => {}
+            }
+        }
+        // Servers usually send the imports along, only those they didn't are asked for
+        let missing: Vec<String> = files
+            .values()
+            .flat_map(|file| &file.dependency)
+            .filter(|dependency| !files.contains_key(*dependency))
+            .cloned()
+            .collect();
+        for dependency in missing {
+            if asked.insert(dependency.clone()) {
+                wanted.push(MessageRequest::FileByFilename(dependency));
+            }
+        }
+    }
+    let mut pool = DescriptorPool::new();
+    pool.add_file_descriptor_protos(files.into_values())?;
+    Ok(pool)
+}
+
+fn parse_request(method: &MethodDescriptor, json: &str) -> Result<DynamicMessage, String> {
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    let message = DynamicMessage::deserialize(method.input(), &mut deserializer)
+        .and_then(|message| deserializer.end().map(|_| message))
+        .map_err(|e| format!("invalid {} request: {}", method.input().full_name(), e))?;
+    Ok(message)
+}
+
+/// Encodes and decodes messages by their descriptors rather than generated types
+struct DynamicCodec(MessageDescriptor);
+
+struct DynamicDecoder(MessageDescriptor);
+
+struct DynamicEncoder;
+
+impl Codec for DynamicCodec {
+    type Encode = DynamicMessage;
+    type Decode = DynamicMessage;
+    type Encoder = DynamicEncoder;
+    type Decoder = DynamicDecoder;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        DynamicEncoder
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        DynamicDecoder(self.0.clone())
+    }
+}
+
+impl Encoder for DynamicEncoder {
+    type Item = DynamicMessage;
+    type Error = Status;
+
+    fn encode(&mut self, item: DynamicMessage, dst: &mut EncodeBuf<'_>) -> Result<(), Status> {
+        item.encode(dst)
+            .map_err(|e| Status::internal(format!("failed to encode the request: {}", e)))
+    }
+}
+
+impl Decoder for DynamicDecoder {
+    type Item = DynamicMessage;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<DynamicMessage>, Status> {
+        DynamicMessage::decode(self.0.clone(), src)
+            .map(Some)
+            .map_err(|e| Status::internal(format!("failed to decode the response: {}", e)))
+    }
+}
+
+fn print_summary(test: &GrpcTest, summary: &Summary, elapsed: Duration) {
+    let calls: u64 = summary.codes.values().sum();
+    let ok = summary.codes.get(&(Code::Ok as i32)).copied().unwrap_or(0);
+    println!("gRPC test of {} at {}", test.method, test.url);
+    println!("Results:");
+    println!("{:.<44}: {}", "Successful Calls", ok);
+    println!("{:.<44}: {}", "Failed Calls", calls - ok);
+    println!(
+        "{:.<44}: {}",
+        "Calls Per Sec",
+        ok as f32 / elapsed.as_secs_f32()
+    );
+    if !summary.latency.is_empty() {
+        let ms = |secs: f32| format!("{:.2}", secs * 1000.0);
+        println!();
+        println!();
+        println!(
+            "{:.<44}: {}, {}, {}, {}",
+            "Call Latency (ms) (P50, P95, P99, Max)",
+            ms(summary.latency.percentile(50.0)),
+            ms(summary.latency.percentile(95.0)),
+            ms(summary.latency.percentile(99.0)),
+            ms(summary.latency.max())
+        );
+    }
+    if !summary.codes.is_empty() {
+        println!();
+        println!();
+        println!("Status Codes:");
+        for (&code, count) in &summary.codes {
+            let label = format!("{} {:?}", code, Code::from_i32(code));
+            println!("  {:.<42}: {}", label, count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    #[test]
+    fn builds_requests_from_a_proto_file() {
+        let dir = env::temp_dir().join(format!("grpc-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let proto = dir.join("greeter.proto");
+        fs::write(
+            &proto,
+            "syntax = \"proto3\";\npackage hello;\n\
+             message HelloRequest { string name = 1; int32 times = 2; }\n\
+             message HelloReply { string message = 1; }\n\
+             service Greeter { rpc SayHello (HelloRequest) returns (HelloReply); }\n",
+        )
+        .unwrap();
+        let pool = compile(&proto, &[]).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let method = pool
+            .get_service_by_name("hello.Greeter")
+            .unwrap()
+            .methods()
+            .next()
+            .unwrap();
+        let request = parse_request(&method, r#"{"name": "zoë", "times": 2}"#).unwrap();
+        assert_eq!(
+            request.get_field_by_name("name").unwrap().as_str(),
+            Some("zoë")
+        );
+        assert_eq!(
+            DynamicMessage::decode(method.input(), request.encode_to_vec().as_slice()).unwrap(),
+            request
+        );
+        let error = parse_request(&method, r#"{"nome": "x"}"#).unwrap_err();
+        assert!(
+            error.starts_with("invalid hello.HelloRequest request"),
+            "{}",
+            error
+        );
+        assert!(parse_request(&method, "{} {}").is_err());
+    }
+}
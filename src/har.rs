@@ -0,0 +1,302 @@
+use std::{
+    error::Error,
+    fs,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    Method, Url,
+};
+use serde::Deserialize;
+use tracing::warn;
+
+/// The parts of a HAR file, as browser devtools export them, that are replayed
+#[derive(Deserialize)]
+struct HarFile {
+    log: Log,
+}
+
+#[derive(Deserialize)]
+struct Log {
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    request: RequestFile,
+    /// When the request started, e.g. 2024-05-01T10:00:00.123Z
+    started_date_time: Option<String>,
+    /// Milliseconds the request took, to its last byte
+    time: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestFile {
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: Vec<HeaderFile>,
+    post_data: Option<PostData>,
+}
+
+#[derive(Deserialize)]
+struct HeaderFile {
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct PostData {
+    text: Option<String>,
+}
+
+/// Headers the client sets itself, or that the recording's connection needed: HTTP/2
+/// pseudo-headers, Host, lengths and encodings
+pub const SKIPPED_HEADERS: [&str; 7] = [
+    "host",
+    "content-length",
+    "connection",
+    "keep-alive",
+    "transfer-encoding",
+    "upgrade",
+    "accept-encoding",
+];
+
+/// A recorded request, with the path and query it was sent to on the recording's origin
+pub struct Recorded {
+    pub method: Method,
+    pub path: String,
+    pub headers: Vec<(HeaderName, String)>,
+    pub body: Option<String>,
+    /// Pause between the end of the previous recorded request and the start of this one,
+    /// none for the first or when the recording has no timings
+    pub gap: Option<Duration>,
+}
+
+/// The requests of a HAR file to the origin of its first one, in the order they were made
+pub struct Recording {
+    /// Scheme, host and port of the first request, e.g. https://shop.example.com
+    pub origin: String,
+    pub requests: Vec<Recorded>,
+}
+
+pub fn read(path: &Path) -> Result<Recording, Box<dyn Error>> {
+    let read = || -> Result<Recording, Box<dyn Error>> {
+        let file: HarFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let mut entries = file.log.entries.into_iter().filter_map(|entry| {
+            let url = Url::parse(&entry.request.url).ok()?;
+            matches!(url.scheme(), "http" | "https").then_some((url, entry))
+        });
+        let (first, entry) = entries.next().ok_or("no http or https requests recorded")?;
+        let origin = first.origin();
+        let mut previous_end = end(&entry);
+        let mut requests = vec![recorded(&first, entry.request)?];
+        let mut skipped = 0;
+        for (url, entry) in entries {
+            if url.origin() != origin {
+                skipped += 1;
+                continue;
+            }
+            let start = entry.started_date_time.as_deref().and_then(started);
+            let gap = start.zip(previous_end).map(|(start, previous_end)| {
+                // Requests the browser made at once overlap and follow with no pause
+                start.duration_since(previous_end).unwrap_or_default()
+            });
+            previous_end = end(&entry);
+            requests.push(Recorded {
+                gap,
+                ..recorded(&url, entry.request)?
+            });
+        }
+        if skipped > 0 {
+            warn!(
+                "skipping {} recorded requests to other origins than {}",
+                skipped,
+                origin.ascii_serialization()
+            );
+        }
+        Ok(Recording {
+            origin: origin.ascii_serialization(),
+            requests,
+        })
+    };
+    read().map_err(|e| format!("failed to read HAR {}: {}", path.display(), e).into())
+}
+
+fn recorded(url: &Url, request: RequestFile) -> Result<Recorded, String> {
+    let method = Method::from_bytes(request.method.to_ascii_uppercase().as_bytes())
+        .map_err(|_| format!("invalid method `{}` in {}", request.method, url))?;
+    let mut headers = vec![];
+    for header in request.headers {
+        let name = header.name.to_ascii_lowercase();
+        if name.starts_with(':') || SKIPPED_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| format!("invalid header name `{}` in {}", header.name, url))?;
+        HeaderValue::from_str(&header.value)
+            .map_err(|_| format!("invalid value for header {} in {}", name, url))?;
+        headers.push((name, header.value));
+    }
+    let mut path = url.path().to_string();
+    if let Some(query) = url.query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    Ok(Recorded {
+        method,
+        path,
+        headers,
+        body: request.post_data.and_then(|data| data.text),
+        gap: None,
+    })
+}
+
+/// When the entry's request ended, if its timings were recorded
+fn end(entry: &Entry) -> Option<SystemTime> {
+    let start = started(entry.started_date_time.as_deref()?)?;
+    let time = entry.time.filter(|time| time.is_finite() && *time >= 0.0)?;
+    Some(start + Duration::from_secs_f64(time / 1000.0))
+}
+
+/// Parses an ISO 8601 time as HAR files write it, in UTC (`Z`, as Chrome writes it) or with an
+/// offset (`+02:00`, as Firefox does)
+fn started(time: &str) -> Option<SystemTime> {
+    let split = time
+        .len()
+        .checked_sub(6)
+        .filter(|at| time.is_char_boundary(*at))
+        .map(|at| time.split_at(at));
+    let (local, offset) = match split {
+        Some((local, offset)) if offset.starts_with(['+', '-']) && offset.as_bytes()[3] == b':' => {
+            let hours: u64 = offset[1..3].parse().ok()?;
+            let minutes: u64 = offset[4..].parse().ok()?;
+            (
+                local,
+                Some((
+                    offset.starts_with('-'),
+                    Duration::from_secs(hours * 3600 + minutes * 60),
+                )),
+            )
+        }
+        _ => (time.strip_suffix('Z').unwrap_or(time), None),
+    };
+    let local = humantime::parse_rfc3339_weak(local).ok()?;
+    Some(match offset {
+        // Local time is ahead of UTC by a positive offset
+        Some((false, offset)) => local - offset,
+        Some((true, offset)) => local + offset,
+        None => local,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn reads_the_requests_to_the_first_origin() {
+        let path = env::temp_dir().join(format!("recording-{}.har", std::process::id()));
+        fs::write(
+            &path,
+            r#"{"log": {"entries": [
+                {"request": {"method": "GET", "url": "data:text/plain,x"}},
+                {"request": {"method": "post", "url": "https://x.test/login?next=%2F",
+                    "headers": [{"name": ":authority", "value": "x.test"},
+                                {"name": "Content-Length", "value": "3"},
+                                {"name": "Content-Type", "value": "text/plain"}],
+                    "postData": {"text": "a=1"}}},
+                {"request": {"method": "GET", "url": "https://cdn.test/app.js"}},
+                {"request": {"method": "GET", "url": "https://x.test/items"},
+                    "startedDateTime": "2024-05-01T10:00:02Z"},
+                {"request": {"method": "GET", "url": "https://x.test/items/1"},
+                    "startedDateTime": "2024-05-01T12:00:03.250+02:00", "time": 100},
+                {"request": {"method": "GET", "url": "https://x.test/items/2"},
+                    "startedDateTime": "2024-05-01T10:00:04.350Z", "time": 50},
+                {"request": {"method": "GET", "url": "https://x.test/items/3"},
+                    "startedDateTime": "2024-05-01T10:00:04.360Z"}
+            ]}}"#,
+        )
+        .unwrap();
+        let recording = read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(recording.origin, "https://x.test");
+        assert_eq!(recording.requests.len(), 5);
+        let login = &recording.requests[0];
+        assert_eq!(login.method, Method::POST);
+        assert_eq!(login.path, "/login?next=%2F");
+        assert_eq!(
+            login.headers,
+            [(
+                HeaderName::from_static("content-type"),
+                "text/plain".to_string()
+            )]
+        );
+        assert_eq!(login.body.as_deref(), Some("a=1"));
+        assert_eq!(recording.requests[1].path, "/items");
+        let gaps: Vec<_> = recording
+            .requests
+            .iter()
+            .map(|request| request.gap)
+            .collect();
+        // Untimed requests have no gap, and the last one started before the previous ended
+        assert_eq!(
+            gaps,
+            [
+                None,
+                None,
+                None,
+                Some(Duration::from_secs(1)),
+                Some(Duration::ZERO)
+            ]
+        );
+    }
+
+    fn entry(started: &str, time: f64) -> Entry {
+        Entry {
+            request: RequestFile {
+                method: "GET".to_string(),
+                url: "https://x.test/".to_string(),
+                headers: vec![],
+                post_data: None,
+            },
+            started_date_time: Some(started.to_string()),
+            time: Some(time),
+        }
+    }
+
+    #[test]
+    fn parses_start_times_with_offsets() {
+        let utc = started("2024-05-01T10:00:00.500Z").unwrap();
+        assert_eq!(started("2024-05-01T12:00:00.500+02:00"), Some(utc));
+        assert_eq!(started("2024-05-01T07:30:00.500-02:30"), Some(utc));
+        assert_eq!(started("2024-05-01T10:00:00.500"), Some(utc));
+        for time in [
+            "",
+            "+02:00",
+            "2024-05-01",
+            "2024-05-01T10:00:00+0é:00",
+            "2024-05-01T10:00:00é",
+        ] {
+            assert_eq!(started(time), None, "{}", time);
+        }
+    }
+
+    #[test]
+    fn ends_after_the_recorded_time() {
+        let start = started("2024-05-01T10:00:00Z").unwrap();
+        assert_eq!(
+            end(&entry("2024-05-01T10:00:00Z", 1500.0)),
+            Some(start + Duration::from_millis(1500))
+        );
+        for time in [-1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(end(&entry("2024-05-01T10:00:00Z", time)), None, "{}", time);
+        }
+    }
+}
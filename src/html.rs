@@ -0,0 +1,197 @@
+use std::fmt::Write;
+
+use crate::{
+    report::{self, Sections},
+    LoadResult,
+};
+
+const TEMPLATE: &str = include_str!("templates/report.html");
+const HISTOGRAM_BINS: usize = 20;
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 220.0;
+/// Room below the bars for axis labels
+const LABEL_HEIGHT: f64 = 20.0;
+
+/// Renders a self-contained HTML report, charts included as inline SVG
+pub fn render(url: &str, result: &LoadResult, sections: &Sections, test_duration: f32) -> String {
+    let summary = report::summarize(result, sections, test_duration);
+    let mut rows = vec![
+        (
+            "Duration (s)".to_string(),
+            format!("{:.2}", summary.duration),
+        ),
+        ("Successful".to_string(), summary.successful.to_string()),
+        ("Failed".to_string(), summary.failed.to_string()),
+        (
+            "Requests per second".to_string(),
+            format!("{:.2}", summary.rps),
+        ),
+        (
+            "Received (bytes, MB/s)".to_string(),
+            format!(
+                "{}, {:.2}",
+                summary.transfer.bytes, summary.transfer.mb_per_sec
+            ),
+        ),
+    ];
+    if let Some(warmup) = summary.warmup_requests {
+        rows.push((
+            "Warm-up requests (not counted)".to_string(),
+            warmup.to_string(),
+        ));
+    }
+    for p in &sections.percentiles {
+        rows.push((
+            format!("P{} TTFB (s)", p),
+            format!("{:.4}", summary.percentiles[&report::percentile_name(*p)]),
+        ));
+    }
+    rows.push((
+        "Total time (s) (min, max, mean, median, std dev, variance)".to_string(),
+        format!(
+            "{:.4}, {:.4}, {:.4}, {:.4}, {:.4}, {:.4}",
+            summary.total_time.min,
+            summary.total_time.max,
+            summary.total_time.mean,
+            summary.total_time.median,
+            summary.total_time.stddev,
+            summary.total_time.variance
+        ),
+    ));
+    rows.push((
+        "TTFB (s) (min, max, mean, median, std dev, variance)".to_string(),
+        format!(
+            "{:.4}, {:.4}, {:.4}, {:.4}, {:.4}, {:.4}",
+            summary.ttfb.min,
+            summary.ttfb.max,
+            summary.ttfb.mean,
+            summary.ttfb.median,
+            summary.ttfb.stddev,
+            summary.ttfb.variance
+        ),
+    ));
+    rows.push((
+        "TTLB (s) (min, max, mean, median, std dev, variance)".to_string(),
+        format!(
+            "{:.4}, {:.4}, {:.4}, {:.4}, {:.4}, {:.4}",
+            summary.ttlb.min,
+            summary.ttlb.max,
+            summary.ttlb.mean,
+            summary.ttlb.median,
+            summary.ttlb.stddev,
+            summary.ttlb.variance
+        ),
+    ));
+    let mut table = String::from("<table>\n");
+    for (name, value) in rows {
+        let _ = writeln!(table, "<tr><th>{}</th><td>{}</td></tr>", name, value);
+    }
+    table.push_str("</table>");
+
+    let statuses: Vec<(String, f64, bool)> = summary
+        .statuses
+        .iter()
+        .map(|(status, count)| (status.to_string(), *count as f64, *status >= 400))
+        .collect();
+
+    TEMPLATE
+        .replace("{{url}}", &escape(url))
+        .replace(
+            "{{generated}}",
+            &humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string(),
+        )
+        .replace("{{summary}}", &table)
+        .replace("{{latency}}", &bar_chart(&latency_histogram(result), "s"))
+        .replace(
+            "{{rps}}",
+            &bar_chart(&rps_over_time(result, sections, test_duration), "rps"),
+        )
+        .replace("{{statuses}}", &bar_chart(&statuses, "responses"))
+}
+
+/// Successful requests per bin of total request time
+fn latency_histogram(result: &LoadResult) -> Vec<(String, f64, bool)> {
+    result
+        .latencies
+        .total_time
+        .bins(HISTOGRAM_BINS)
+        .into_iter()
+        .map(|(midpoint, count)| (format!("{:.3}", midpoint), count as f64, false))
+        .collect()
+}
+
+/// Successful requests per second in each time bucket of the test
+fn rps_over_time(
+    result: &LoadResult,
+    sections: &Sections,
+    test_duration: f32,
+) -> Vec<(String, f64, bool)> {
+    let width = report::bucket_width(sections.resolution, test_duration);
+    let buckets = result.timeline.buckets(width, test_duration);
+    let width = width.as_secs_f32();
+    buckets
+        .into_iter()
+        .enumerate()
+        .map(|(i, bucket)| {
+            (
+                format!("{}s", (i + 1) as f32 * width),
+                bucket.successful as f64 / width as f64,
+                false,
+            )
+        })
+        .collect()
+}
+
+/// Vertical bar chart of `(label, value, failed)` bars, labelling about ten of them
+fn bar_chart(bars: &[(String, f64, bool)], unit: &str) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+        CHART_WIDTH,
+        CHART_HEIGHT + LABEL_HEIGHT
+    );
+    if bars.is_empty() {
+        let _ = write!(
+            svg,
+            "<text class=\"axis\" x=\"10\" y=\"20\">No data</text></svg>"
+        );
+        return svg;
+    }
+
+    let max = bars.iter().map(|(_, v, _)| *v).fold(0.0, f64::max).max(1.0);
+    let slot = CHART_WIDTH / bars.len() as f64;
+    let label_every = bars.len().div_ceil(10);
+    for (i, (label, value, failed)) in bars.iter().enumerate() {
+        let height = value / max * (CHART_HEIGHT - 10.0);
+        let x = i as f64 * slot;
+        let _ = write!(
+            svg,
+            "<rect class=\"bar{}\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\"><title>{}: {:.2} {}</title></rect>",
+            if *failed { " failed" } else { "" },
+            x + slot * 0.1,
+            CHART_HEIGHT - height,
+            slot * 0.8,
+            height,
+            escape(label),
+            value,
+            unit
+        );
+        if i % label_every == 0 {
+            let _ = write!(
+                svg,
+                "<text class=\"axis\" x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+                x + slot * 0.1,
+                CHART_HEIGHT + 14.0,
+                escape(label)
+            );
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
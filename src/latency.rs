@@ -0,0 +1,413 @@
+use std::time::Duration;
+
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Stats;
+
+/// Longest latency recorded exactly, slower requests are clamped to it
+const MAX_MICROS: u64 = 3_600_000_000;
+
+/// Fastest body transfer rate recorded exactly, in bytes per second
+const MAX_BYTES_PER_SEC: u64 = 1 << 40;
+
+/// Exact count, min, max, mean and variance of a stream of values, kept with Welford's
+/// online algorithm in f64 so millions of values don't lose precision
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Moments {
+    count: u64,
+    mean: f64,
+    m2: f64,
+    min: f32,
+    max: f32,
+}
+
+impl Default for Moments {
+    fn default() -> Self {
+        Moments {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: f32::MAX,
+            max: f32::MIN,
+        }
+    }
+}
+
+impl Moments {
+    pub fn record(&mut self, x: f32) {
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+        self.count += 1;
+        let delta = x as f64 - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x as f64 - self.mean);
+    }
+
+    /// Combines two streams as if every value had been recorded here (Chan et al.)
+    pub fn merge(&mut self, other: &Moments) {
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.m2 +=
+            other.m2 + delta * delta * (self.count as f64 * other.count as f64) / count as f64;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.count = count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// f32::MAX with nothing recorded, like an empty fold
+    pub fn min(&self) -> f32 {
+        self.min
+    }
+
+    /// f32::MIN with nothing recorded
+    pub fn max(&self) -> f32 {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Population variance, 0 with nothing recorded
+    pub fn variance(&self) -> f64 {
+        if self.count > 0 {
+            self.m2 / self.count as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Latencies in microseconds, with 3 significant digits, alongside their exact moments.
+/// Memory stays fixed however many requests are recorded, and high percentiles stay accurate.
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    histogram: Histogram<u64>,
+    moments: Moments,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            histogram: Histogram::new_with_bounds(1, MAX_MICROS, 3).unwrap(),
+            moments: Moments::default(),
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, secs: f32) {
+        let micros = (secs as f64 * 1e6).round() as u64;
+        self.histogram
+            .saturating_record(micros.clamp(1, MAX_MICROS));
+        self.moments.record(secs);
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        // Both share the same bounds, so adding can't fail
+        self.histogram.add(&other.histogram).unwrap();
+        self.moments.merge(&other.moments);
+    }
+
+    /// Latency in seconds below which `percentile` percent of the recorded ones fall, 0 with
+    /// nothing recorded
+    pub fn percentile(&self, percentile: f32) -> f32 {
+        if self.histogram.is_empty() {
+            return 0.0;
+        }
+        self.histogram.value_at_quantile(percentile as f64 / 100.0) as f32 / 1e6
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.histogram.is_empty()
+    }
+
+    pub fn max(&self) -> f32 {
+        self.histogram.max() as f32 / 1e6
+    }
+
+    /// Exact moments of the recorded latencies, in seconds
+    pub fn moments(&self) -> &Moments {
+        &self.moments
+    }
+
+    /// Counts in `bins` equal-width bins between the fastest and slowest latency, each given
+    /// by its midpoint in seconds
+    pub fn bins(&self, bins: usize) -> Vec<(f32, u64)> {
+        if self.histogram.is_empty() {
+            return vec![];
+        }
+        let (min, max) = (self.moments.min, self.moments.max);
+        let width = ((max - min) / bins as f32).max(f32::EPSILON);
+        let mut counts = vec![0u64; bins];
+        for value in self.histogram.iter_recorded() {
+            let secs = (value.value_iterated_to() as f32 / 1e6).clamp(min, max);
+            counts[(((secs - min) / width) as usize).min(bins - 1)] += value.count_at_value();
+        }
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| (min + width * (i as f32 + 0.5), count))
+            .collect()
+    }
+}
+
+/// Saved as the recorded (value, count) pairs, which are few however many requests there were
+#[derive(Serialize, Deserialize)]
+struct SavedHistogram {
+    counts: Vec<(u64, u64)>,
+    moments: Moments,
+}
+
+impl Serialize for LatencyHistogram {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SavedHistogram {
+            counts: self
+                .histogram
+                .iter_recorded()
+                .map(|v| (v.value_iterated_to(), v.count_at_value()))
+                .collect(),
+            moments: self.moments,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LatencyHistogram {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = SavedHistogram::deserialize(deserializer)?;
+        let mut histogram = LatencyHistogram::default();
+        for (value, count) in saved.counts {
+            histogram
+                .histogram
+                .saturating_record_n(value.clamp(1, MAX_MICROS), count);
+        }
+        histogram.moments = saved.moments;
+        Ok(histogram)
+    }
+}
+
+/// Time to first byte counted from when each request was meant to go out rather than from
+/// when it did, which is what users on the test's schedule would have seen (coordinated
+/// omission correction, as in wrk2), along with how late requests went out
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Corrected {
+    pub ttfb: LatencyHistogram,
+    pub send_delay: LatencyHistogram,
+}
+
+impl Corrected {
+    pub fn record(&mut self, ttfb: f32, send_delay: Duration) {
+        self.ttfb.record(ttfb + send_delay.as_secs_f32());
+        self.send_delay.record(send_delay.as_secs_f32());
+    }
+
+    pub fn merge(&mut self, other: &Corrected) {
+        self.ttfb.merge(&other.ttfb);
+        self.send_delay.merge(&other.send_delay);
+    }
+}
+
+/// Histograms of the successful requests' timings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Latencies {
+    pub ttfb: LatencyHistogram,
+    pub ttlb: LatencyHistogram,
+    pub total_time: LatencyHistogram,
+    /// Time spent reading the body after the first byte
+    pub body: LatencyHistogram,
+}
+
+impl Latencies {
+    pub fn record(&mut self, stats: &Stats) {
+        self.ttfb.record(stats.ttfb);
+        self.ttlb.record(stats.ttlb);
+        self.total_time.record(stats.total_time);
+        self.body.record(stats.ttlb - stats.ttfb);
+    }
+
+    pub fn merge(&mut self, other: &Latencies) {
+        self.ttfb.merge(&other.ttfb);
+        self.ttlb.merge(&other.ttlb);
+        self.total_time.merge(&other.total_time);
+        self.body.merge(&other.body);
+    }
+}
+
+/// Rate at which each successful response's body arrived after its first byte, in bytes per
+/// second, for --download
+#[derive(Debug, Clone)]
+pub struct Throughput {
+    histogram: Histogram<u64>,
+    moments: Moments,
+}
+
+impl Default for Throughput {
+    fn default() -> Self {
+        Throughput {
+            histogram: Histogram::new_with_bounds(1, MAX_BYTES_PER_SEC, 3).unwrap(),
+            moments: Moments::default(),
+        }
+    }
+}
+
+impl Throughput {
+    pub fn record(&mut self, stats: &Stats) {
+        let transfer_time = stats.ttlb - stats.ttfb;
+        let rate = if transfer_time > 0.0 {
+            stats.bytes as f64 / transfer_time as f64
+        } else {
+            0.0
+        };
+        self.histogram
+            .saturating_record((rate.round() as u64).clamp(1, MAX_BYTES_PER_SEC));
+        self.moments.record(rate as f32);
+    }
+
+    pub fn merge(&mut self, other: &Throughput) {
+        self.histogram.add(&other.histogram).unwrap();
+        self.moments.merge(&other.moments);
+    }
+
+    pub fn percentile(&self, percentile: f32) -> f32 {
+        if self.histogram.is_empty() {
+            return 0.0;
+        }
+        self.histogram.value_at_quantile(percentile as f64 / 100.0) as f32
+    }
+
+    pub fn moments(&self) -> &Moments {
+        &self.moments
+    }
+}
+
+impl Serialize for Throughput {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SavedHistogram {
+            counts: self
+                .histogram
+                .iter_recorded()
+                .map(|v| (v.value_iterated_to(), v.count_at_value()))
+                .collect(),
+            moments: self.moments,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Throughput {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = SavedHistogram::deserialize(deserializer)?;
+        let mut throughput = Throughput::default();
+        for (value, count) in saved.counts {
+            throughput
+                .histogram
+                .saturating_record_n(value.clamp(1, MAX_BYTES_PER_SEC), count);
+        }
+        throughput.moments = saved.moments;
+        Ok(throughput)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moments(values: &[f32]) -> Moments {
+        let mut moments = Moments::default();
+        for &value in values {
+            moments.record(value);
+        }
+        moments
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{} is not {}", a, b);
+    }
+
+    #[test]
+    fn keeps_exact_moments() {
+        let moments = moments(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(moments.count(), 8);
+        assert_eq!((moments.min(), moments.max()), (2.0, 9.0));
+        assert_close(moments.mean(), 5.0);
+        assert_close(moments.variance(), 4.0);
+
+        let empty = Moments::default();
+        assert_eq!(empty.count(), 0);
+        assert_eq!(empty.variance(), 0.0);
+    }
+
+    #[test]
+    fn counts_late_sends_towards_corrected_latency() {
+        let mut corrected = Corrected::default();
+        for _ in 0..99 {
+            corrected.record(0.01, Duration::ZERO);
+        }
+        // The generator stalled for a second before sending the last one
+        corrected.record(0.01, Duration::from_secs(1));
+        assert!((corrected.ttfb.percentile(50.0) - 0.01).abs() < 0.0001);
+        assert!((corrected.ttfb.max() - 1.01).abs() < 0.001);
+        assert_eq!(corrected.send_delay.moments().count(), 100);
+    }
+
+    #[test]
+    fn merges_like_one_stream() {
+        let values = [0.25, 1.5, 0.75, 3.0, 0.5, 2.25, 0.125];
+        let whole = moments(&values);
+        for split in 0..=values.len() {
+            let mut merged = moments(&values[..split]);
+            merged.merge(&moments(&values[split..]));
+            assert_eq!(merged.count(), whole.count());
+            assert_eq!((merged.min(), merged.max()), (whole.min(), whole.max()));
+            assert_close(merged.mean(), whole.mean());
+            assert_close(merged.variance(), whole.variance());
+        }
+
+        let mut empty = Moments::default();
+        empty.merge(&Moments::default());
+        assert_eq!(empty.count(), 0);
+        assert_eq!((empty.min(), empty.max()), (f32::MAX, f32::MIN));
+    }
+
+    #[test]
+    fn clamps_latencies_to_the_histogram() {
+        let mut histogram = LatencyHistogram::default();
+        assert!(histogram.is_empty());
+        assert_eq!(histogram.percentile(99.0), 0.0);
+        assert!(histogram.bins(10).is_empty());
+
+        histogram.record(0.0);
+        histogram.record(1e9);
+        assert_eq!(histogram.percentile(0.0), 1e-6);
+        assert!(histogram.max() <= MAX_MICROS as f32 / 1e6 * 1.001);
+        assert_eq!(histogram.moments().max(), 1e9);
+    }
+
+    #[test]
+    fn merges_histograms() {
+        let (mut ours, mut theirs) = (LatencyHistogram::default(), LatencyHistogram::default());
+        for i in 1..=50 {
+            ours.record(i as f32 / 1000.0);
+            theirs.record((i + 50) as f32 / 1000.0);
+        }
+        ours.merge(&LatencyHistogram::default());
+        assert_eq!(ours.moments().count(), 50);
+        ours.merge(&theirs);
+        assert_eq!(ours.moments().count(), 100);
+        assert!((ours.percentile(50.0) - 0.05).abs() < 0.001);
+        assert!((ours.max() - 0.1).abs() < 0.001);
+        let bins = ours.bins(4);
+        assert_eq!(bins.len(), 4);
+        assert_eq!(bins.iter().map(|(_, count)| count).sum::<u64>(), 100);
+    }
+}
@@ -0,0 +1,52 @@
+use std::{fs::File, path::Path, sync::Mutex};
+
+use clap::ValueEnum;
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    fmt,
+    prelude::*,
+    Layer, Registry,
+};
+
+use crate::secrets::Redacting;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines
+    Text,
+    /// One JSON object per line, for shipping to Loki/ELK
+    Json,
+}
+
+/// Installs the global subscriber for the tool's own logs. Logs go to stderr unless a file is
+/// given, in which case they are appended to it without colors. Secrets are redacted.
+/// Dependencies (hyper, reqwest) are capped at `warn` so their connection chatter doesn't drown
+/// the tool's logs.
+pub fn init(level: LevelFilter, format: LogFormat, file: Option<&Path>) -> std::io::Result<()> {
+    let filter = Targets::new()
+        .with_default(level.min(LevelFilter::WARN))
+        .with_target(env!("CARGO_CRATE_NAME"), level);
+
+    let layer = fmt::layer().with_target(false);
+    let layer: Box<dyn Layer<Registry> + Send + Sync> = match (format, file) {
+        (LogFormat::Text, None) => layer.with_writer(Redacting(std::io::stderr)).boxed(),
+        (LogFormat::Text, Some(path)) => layer
+            .with_ansi(false)
+            .with_writer(Redacting(Mutex::new(open(path)?)))
+            .boxed(),
+        (LogFormat::Json, None) => layer.json().with_writer(Redacting(std::io::stderr)).boxed(),
+        (LogFormat::Json, Some(path)) => layer
+            .json()
+            .with_writer(Redacting(Mutex::new(open(path)?)))
+            .boxed(),
+    };
+
+    tracing_subscriber::registry()
+        .with(layer.with_filter(filter))
+        .init();
+    Ok(())
+}
+
+fn open(path: &Path) -> std::io::Result<File> {
+    File::options().create(true).append(true).open(path)
+}
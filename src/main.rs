@@ -1,253 +1,4084 @@
-use std::time::{Duration, Instant};
+mod assertions;
+mod checkpoint;
+mod client_ip;
+mod clock;
+mod compression;
+mod config;
+mod control;
+mod convert;
+mod cookies;
+mod curl;
+mod data;
+mod distributed;
+mod doctor;
+mod errors;
+mod events;
+mod fleet;
+mod graphql;
+mod grpc;
+mod har;
+mod html;
+mod latency;
+mod logging;
+mod otel;
+mod phases;
+mod prometheus;
+mod proxy_protocol;
+mod raw;
+mod record;
+mod report;
+mod resolve;
+mod samples;
+mod scenario;
+mod script;
+mod search;
+mod secrets;
+mod sinks;
+mod spike;
+mod sse;
+mod stages;
+mod stop;
+mod targets;
+mod tcp;
+mod template;
+mod think;
+mod thresholds;
+mod timeline;
+mod tls;
+mod trend;
+mod tui;
+mod unix_socket;
+mod ws;
 
-use clap::Parser;
-use futures::future::join_all;
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::{IpAddr, SocketAddr},
+    ops::RangeInclusive,
+    path::PathBuf,
+    pin::Pin,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant, SystemTime},
+};
+
+use assertions::{Assertion, Assertions};
+use base64::Engine;
+use bytes::Bytes;
+use checkpoint::Checkpoint;
+use clap::{Parser, Subcommand};
+use client_ip::ClientIps;
+use compression::{Counter, Decoder, Encoding};
+use control::{Control, RateLimit};
+use cookies::Cookies;
+use data::{DataFeed, Sharing};
+use errors::ErrorCategory;
+use events::{Event, EventBus};
+use futures::{stream::FuturesUnordered, Future, FutureExt, StreamExt};
 use indicatif::{ProgressBar, ProgressStyle};
-use reqwest::{self, StatusCode};
-use tokio::{self};
+use latency::{Corrected, Latencies, LatencyHistogram, Throughput};
+use logging::LogFormat;
+use otel::{Otel, Span};
+use phases::{ConnectionPhases, SharedPhases};
+use proxy_protocol::ProxyVersion;
+use rand::Rng;
+use report::{OutputFormat, Sections};
+use reqwest::{
+    self,
+    header::{
+        HeaderMap, HeaderName, HeaderValue, ACCEPT_ENCODING, ACCEPT_RANGES, AUTHORIZATION,
+        CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, COOKIE, FORWARDED, HOST,
+        RANGE, RETRY_AFTER, USER_AGENT,
+    },
+    Method, StatusCode, Url, Version,
+};
+use resolve::{Family, Override, Resolver};
+use samples::SampleFile;
+use scenario::Scenario;
+use script::Script;
+use search::Search;
+use secrets::Secret;
+use serde::{Deserialize, Serialize};
+use sinks::Sinks;
+use spike::{Spike, SpikePlan, Spikes};
+use stages::{Schedule, Stage, Stages};
+use stop::{Abort, StopCondition, StopSignal};
+use targets::{Target, Targets};
+use template::Template;
+use think::ThinkTime;
+use thresholds::Threshold;
+use timeline::Timeline;
+use tls::{Fingerprint, TlsOptions, TlsVersion};
+use tokio::{self, sync::broadcast};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::filter::LevelFilter;
+use trend::{Series, Trend};
 
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+#[command(args_override_self = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a load test, same as passing the options without a subcommand
+    Run(Box<Args>),
+    /// Turn a HAR file, a Postman collection or curl commands into a --scenario file, to edit
+    /// and keep under version control
+    Convert {
+        /// File to convert
+        input: PathBuf,
+
+        /// Format of the input, guessed from its extension (.har, .json, .sh, .curl or .txt)
+        /// when missing
+        #[arg(long, value_enum)]
+        from: Option<convert::Format>,
+
+        /// YAML or TOML file to write, by its extension; YAML goes to stdout without one
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Write the recorded Authorization and Cookie headers as they are, rather than as
+        /// placeholders for --secret to fill in
+        #[arg(long, default_value_t = false)]
+        keep_credentials: bool,
+    },
+    /// Run a local HTTP(S) proxy recording the requests a browser or app sends through it,
+    /// and write them as a --scenario file on Ctrl-C
+    Record {
+        /// YAML or TOML file to write the scenario to, by its extension
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Address of the proxy to point the browser or app at
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+
+        /// CA certificate that signs a certificate for each HTTPS host, for the browser or
+        /// app to trust; created with its key on the first recording
+        #[arg(long, default_value = "load-tester-ca.pem")]
+        ca_cert: PathBuf,
+
+        /// Private key of --ca-cert
+        #[arg(long, default_value = "load-tester-ca.key")]
+        ca_key: PathBuf,
+
+        /// Only record the requests to this origin, e.g. https://shop.example.com, rather
+        /// than to the first request's
+        #[arg(long)]
+        origin: Option<String>,
+
+        /// Accept any certificate from the servers the requests are forwarded to
+        #[arg(short = 'k', long, default_value_t = false)]
+        insecure: bool,
+
+        /// Write the recorded Authorization and Cookie headers as they are, rather than as
+        /// placeholders for --secret to fill in
+        #[arg(long, default_value_t = false)]
+        keep_credentials: bool,
+    },
+    /// Print the report stored in a checkpoint file
+    Report {
+        /// Checkpoint written by --checkpoint
+        checkpoint: PathBuf,
+    },
+    /// Check this host's limits (open files, ports, conntrack, memory) against a planned test
+    Doctor {
+        /// Concurrency the test will run with
+        #[arg(short, long, default_value_t = 1)]
+        concurrency: u64,
+    },
+    /// Deploy workers to several hosts over SSH, run the test on all of them at once and
+    /// print a combined report
+    Fleet {
+        /// Comma-separated SSH destinations, e.g. user@eu-1,user@us-1
+        #[arg(long, value_delimiter = ',', required = true)]
+        hosts: Vec<String>,
+
+        /// Binary to deploy, defaults to this one (it must run on the workers)
+        #[arg(long)]
+        binary: Option<PathBuf>,
+
+        /// How long to give every worker to get ready before the synchronized start
+        #[arg(long, value_parser = humantime::parse_duration, default_value = "10s")]
+        lead_time: Duration,
+
+        /// Options for `run` on every worker, after --
+        #[arg(last = true, required = true)]
+        args: Vec<String>,
+    },
+    /// Wait for tests from a coordinator started with `run --workers` and run them on this
+    /// host. Listening beyond loopback takes --token, and tests whose options read or write
+    /// files on the worker are refused.
+    Serve {
+        /// Address to accept tests on
+        #[arg(long, default_value = "127.0.0.1:7070")]
+        listen: SocketAddr,
+
+        /// Only accept tests from coordinators passing the same --workers-token
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// Hold WebSocket connections open, sending messages at a steady rate, and report connect
+    /// time, message round trips and dropped connections
+    Ws {
+        /// ws:// or wss:// URL to connect to
+        #[arg(short, long)]
+        url: String,
+
+        /// Number of connections to hold open at once
+        #[arg(short, long, default_value_t = 1)]
+        connections: usize,
+
+        /// Messages per second each connection sends
+        #[arg(long, value_parser = parse_rate, default_value = "1")]
+        rate: f64,
+
+        /// Text message to send, each answer the server sends timing the oldest unanswered one
+        #[arg(long, default_value = "ping")]
+        message: String,
+
+        /// How long to hold the connections open
+        #[arg(long, value_parser = humantime::parse_duration, default_value = "10s")]
+        duration: Duration,
+
+        /// Accept any certificate from a wss:// server
+        #[arg(short = 'k', long, default_value_t = false)]
+        insecure: bool,
+    },
+    /// Open TCP connections, optionally writing a payload on each, and report connect time
+    /// and throughput
+    Tcp {
+        /// host:port to connect to
+        #[arg(short, long)]
+        addr: String,
+
+        /// Text to write on every connection
+        #[arg(long, conflicts_with = "payload_file")]
+        payload: Option<String>,
+
+        /// File holding the bytes to write on every connection, read once up front
+        #[arg(long)]
+        payload_file: Option<PathBuf>,
+
+        /// Number of connections to make
+        #[arg(short, long, default_value_t = 10)]
+        number: u64,
+
+        /// Number of connections open at once
+        #[arg(short, long, default_value_t = 1)]
+        concurrency: usize,
+
+        /// Keep connecting until this much time has passed, e.g. 30s, instead of making
+        /// --number connections
+        #[arg(long, value_parser = humantime::parse_duration, conflicts_with = "number")]
+        duration: Option<Duration>,
+
+        /// How long the server may stay quiet before a connection is closed; 0 closes it
+        /// without reading
+        #[arg(long, value_parser = humantime::parse_duration, default_value = "1s")]
+        read_timeout: Duration,
+    },
+    /// Hold Server-Sent Events streams open and report time to first event, time between
+    /// events and premature disconnects
+    Sse {
+        /// URL of the event stream
+        #[arg(short, long)]
+        url: String,
+
+        /// Number of streams to hold open at once
+        #[arg(short, long, default_value_t = 1)]
+        connections: usize,
+
+        /// How long to hold the streams open
+        #[arg(long, value_parser = humantime::parse_duration, default_value = "10s")]
+        duration: Duration,
+
+        /// Header to send with every request, as "Name: value" (repeatable)
+        #[arg(short = 'H', long = "header", value_parser = parse_header)]
+        headers: Vec<(HeaderName, HeaderValue)>,
+
+        /// Accept any certificate from an HTTPS server
+        #[arg(short = 'k', long, default_value_t = false)]
+        insecure: bool,
+    },
+    /// Make unary gRPC calls of one method and report their latency and status codes
+    Grpc {
+        /// Server to call, e.g. http://localhost:50051 or https://api.example.com
+        #[arg(short, long)]
+        url: String,
+
+        /// Method to call, as package.Service/Method
+        #[arg(short, long)]
+        method: String,
+
+        /// .proto file defining the method; without one the server's reflection service
+        /// describes it
+        #[arg(long)]
+        proto: Option<PathBuf>,
+
+        /// Directory to resolve the imports of --proto from, besides its own (repeatable)
+        #[arg(short = 'I', long = "import-path")]
+        import_paths: Vec<PathBuf>,
+
+        /// Request message as JSON
+        #[arg(short, long, default_value = "{}")]
+        data: String,
+
+        /// Number of calls to make
+        #[arg(short, long, default_value_t = 10)]
+        number: u64,
+
+        /// Number of concurrent calls
+        #[arg(short, long, default_value_t = 1)]
+        concurrency: usize,
+
+        /// Keep calling until this much time has passed, e.g. 30s, instead of making --number
+        /// calls
+        #[arg(long, value_parser = humantime::parse_duration, conflicts_with = "number")]
+        duration: Option<Duration>,
+    },
+}
+
+#[derive(clap::Args, Debug)]
 struct Args {
-    /// URL
-    #[arg(short, long)]
-    url: String,
+    /// TOML or YAML test plan whose keys are long option names; options on the command line
+    /// override it
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// URL, repeatable with a weight as <URL>@<WEIGHT>; later ones, and the paths of
+    /// --scenario steps, may be relative to the first
+    #[arg(
+        short,
+        long,
+        required_unless_present_any = ["resume", "urls_file", "har", "from_curl"],
+        value_parser = targets::parse_target
+    )]
+    url: Vec<Target>,
+
+    /// File with one `[METHOD] <URL>[@<WEIGHT>]` per line, added after any --url
+    #[arg(long)]
+    urls_file: Option<PathBuf>,
+
+    /// How each request picks its URL when there are several
+    #[arg(long, value_enum, default_value_t = Spread::RoundRobin)]
+    urls_order: Spread,
+
+    /// YAML or TOML file of requests each concurrent user sends in turn, one per batch,
+    /// instead of GETs of --url
+    #[arg(long, conflicts_with = "download", group = "scenario_or_har")]
+    scenario: Option<PathBuf>,
+
+    /// HAR file from browser devtools whose requests every concurrent user replays in turn,
+    /// like the steps of a --scenario, against its first request's origin or --url
+    #[arg(long, conflicts_with = "download", group = "scenario_or_har")]
+    har: Option<PathBuf>,
+
+    /// Replay the requests of --har in an order shuffled once up front rather than as recorded
+    #[arg(long, requires = "har")]
+    har_shuffle: bool,
+
+    /// curl command line whose URL, method, headers and body every request repeats, e.g. one
+    /// copied from browser devtools; -H adds headers to it or replaces those it sets
+    #[arg(
+        long,
+        value_name = "COMMAND",
+        conflicts_with_all = ["url", "urls_file", "method", "body", "body_file", "graphql", "scenario", "har"]
+    )]
+    from_curl: Option<String>,
+
+    /// Value of ${KEY} in --config and --scenario files, taking precedence over the environment
+    /// variable KEY (repeatable)
+    #[arg(long, value_name = "KEY=VALUE", value_parser = config::parse_env)]
+    env: Vec<(String, String)>,
+
+    /// Value for {{NAME}} placeholders read from an environment variable or a file, as
+    /// NAME=env:VAR or NAME=file:PATH, and kept out of what the tool prints and writes
+    /// (repeatable)
+    #[arg(long, value_parser = secrets::parse_secret)]
+    secret: Vec<Secret>,
+
+    /// Append the values each --scenario iteration extracted to this CSV file, one column per
+    /// variable, for a later run's --data, e.g. to read or clean up what this one created
+    #[arg(long, requires = "scenario")]
+    save_extracted: Option<PathBuf>,
+
+    /// CSV file whose header names placeholders, e.g. {{username}}, that its rows fill in
+    /// in the URL, -H values and --body or the --scenario steps, one row per request (per pass
+    /// through the steps with --scenario)
+    #[arg(long)]
+    data: Option<PathBuf>,
+
+    /// Draw the rows of --data at random rather than in turn
+    #[arg(long, requires = "data", conflicts_with_all = ["data_checkout", "data_consume"])]
+    data_random: bool,
+
+    /// Check each row of --data out to one request, or one --scenario iteration, at a time,
+    /// so concurrent users never share a row. There must be a row per concurrent user.
+    #[arg(long, requires = "data")]
+    data_checkout: bool,
+
+    /// Use each row of --data once, checking it out and never giving it back, and stop the
+    /// test when every row was used
+    #[arg(long, requires = "data")]
+    data_consume: bool,
 
     /// Number of times to make request
     #[arg(short, long, default_value_t = 10)]
-    number: u8,
+    number: u64,
+
+    /// Requests to start per second, regardless of how fast responses come back with the
+    /// open model
+    #[arg(long, value_parser = parse_rate)]
+    rate: Option<f64>,
+
+    /// Workload model: closed has --concurrency users each waiting for its response before
+    /// sending again, so slow responses lower the load; open starts requests at --rate
+    /// whatever is outstanding, dropping arrivals once --max-in-flight are [default: open
+    /// with --rate, closed otherwise]
+    #[arg(long, value_enum)]
+    model: Option<Model>,
+
+    /// Most requests the open model has outstanding at once, arrivals beyond it being dropped
+    /// and counted [default: --concurrency]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_in_flight: Option<u64>,
+
+    /// Load stages as <DURATION>:<TARGET>, e.g. 1m:50,2m:200,1m:0, each moving linearly from
+    /// the last target (or zero) to its own: concurrency with the closed model, the arrival
+    /// rate with the open one. Stats are reported per stage as well as overall.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = stages::parse_stage,
+        conflicts_with_all = ["duration", "number", "rate", "ramp_up", "vus", "resume"]
+    )]
+    stages: Vec<Stage>,
+
+    /// Search for the most load the server sustains instead of running a fixed one: start at
+    /// one user, or at --rate with the open model, double it every --auto-step while every
+    /// --auto-limit holds, then bisect between the highest load that held and the lowest that
+    /// didn't. The closed model tries no more than --concurrency users.
+    #[arg(
+        long,
+        conflicts_with_all = ["number", "stages", "vus", "ramp_up", "adaptive", "resume", "workers"]
+    )]
+    auto: bool,
+
+    /// How long --auto runs each load before judging it
+    #[arg(long, requires = "auto", value_parser = humantime::parse_duration, default_value = "10s")]
+    auto_step: Duration,
+
+    /// Limit a load must stay within for --auto to count it as sustained, in --threshold
+    /// syntax (repeatable)
+    #[arg(
+        long,
+        requires = "auto",
+        value_parser = thresholds::parse_threshold,
+        default_values = ["p99<1s", "error_rate<1%"]
+    )]
+    auto_limit: Vec<Threshold>,
+
+    /// Spike test as <BASELINE>:<SPIKE>:<DURATION>:<REPEAT>, e.g. 50:500:30s:3: the load
+    /// jumps from the baseline to the spike for the duration, the given number of times, with
+    /// --spike-gap at the baseline around each. The report gives how latency recovered after
+    /// each spike. Loads are arrival rates with the open model, which is the default,
+    /// concurrency with the closed one.
+    #[arg(
+        long,
+        value_parser = spike::parse_spike,
+        conflicts_with_all = ["duration", "number", "rate", "ramp_up", "stages", "auto", "vus", "resume", "workers"]
+    )]
+    spike: Option<Spike>,
+
+    /// Time at the --spike baseline before each spike and after the last, which latency has
+    /// to recover in
+    #[arg(long, requires = "spike", value_parser = humantime::parse_duration, default_value = "1m")]
+    spike_gap: Duration,
+
+    /// Run this many virtual users instead of batches of --concurrency requests, each sending
+    /// its requests one after the other with its own cookie jar and scenario variables over
+    /// the shared connection pool
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["concurrency", "number", "rate", "model", "max_in_flight", "ramp_up", "resume"]
+    )]
+    vus: Option<u64>,
+
+    /// Iterations each of the --vus runs, an iteration being a pass through the --scenario
+    /// steps or a single request without one [default: 1 without --duration]
+    #[arg(
+        long,
+        requires = "vus",
+        conflicts_with = "duration",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    iterations: Option<u64>,
+
+    /// Pause each of the --vus takes between its requests, kept out of the timings: fixed
+    /// (500ms), uniform over a range (200ms-800ms) or exponential around a mean (exp:500ms)
+    #[arg(long, requires = "vus", value_parser = think::parse_think_time)]
+    think_time: Option<ThinkTime>,
+
+    /// Grow concurrency (or --rate) linearly from 1 to its target over this window, e.g. 30s
+    #[arg(long, value_parser = humantime::parse_duration)]
+    ramp_up: Option<Duration>,
+
+    /// Keep sending batches until this much time has passed, e.g. 30s, instead of making
+    /// --number requests
+    #[arg(long, value_parser = humantime::parse_duration, conflicts_with = "number")]
+    duration: Option<Duration>,
+
+    /// Send traffic that isn't recorded before the test, for N requests or a duration like
+    /// 5s, so the server's caches, JIT and connection pools are warm when measuring starts
+    #[arg(long, value_parser = parse_warmup)]
+    warmup: Option<Warmup>,
 
     /// Number of concurrent requests
     #[arg(short, long, default_value_t = 1)]
-    concurrency: u8,
+    concurrency: usize,
+
+    /// HTTP method: GET, POST, PUT, PATCH, DELETE, HEAD or OPTIONS
+    #[arg(short, long, value_parser = parse_method, default_value = "GET")]
+    method: Method,
+
+    /// Header to send with every request, as "Name: value" (repeatable)
+    #[arg(short = 'H', long = "header", value_parser = parse_header)]
+    headers: Vec<(HeaderName, HeaderValue)>,
+
+    /// Send HTTP Basic credentials, as user:password
+    #[arg(long, value_parser = parse_basic_auth, conflicts_with = "bearer")]
+    basic: Option<HeaderValue>,
+
+    /// Send a bearer token in the Authorization header
+    #[arg(long, value_parser = parse_bearer_auth)]
+    bearer: Option<HeaderValue>,
+
+    /// Keep a cookie jar per concurrent user and send back the cookies the server sets, for
+    /// session-based auth; every one of the --vus always has one
+    #[arg(long)]
+    cookies: bool,
+
+    /// Cookie to send with every request, as name=value (repeatable)
+    #[arg(long, value_parser = cookies::parse_cookie)]
+    cookie: Vec<(String, String)>,
+
+    /// Request body to send with every request
+    #[arg(long, conflicts_with = "body_file")]
+    body: Option<String>,
+
+    /// File holding the request body to send with every request, read once up front
+    #[arg(long)]
+    body_file: Option<PathBuf>,
+
+    /// POST the GraphQL operation of --query, with --variables, as JSON and fail responses
+    /// whose body has an `errors` array, whatever their status
+    #[arg(
+        long,
+        requires = "query",
+        conflicts_with_all = ["method", "body", "body_file", "scenario", "download"]
+    )]
+    graphql: bool,
+
+    /// File holding the GraphQL query or mutation to send with --graphql
+    #[arg(long, requires = "graphql")]
+    query: Option<PathBuf>,
+
+    /// JSON file holding the variables of the --query
+    #[arg(long, requires = "query")]
+    variables: Option<PathBuf>,
+
+    /// Discard response bodies while streaming and report throughput (MB/s)
+    #[arg(short, long, default_value_t = false)]
+    download: bool,
+
+    /// Issue randomized Range requests of the given size, e.g. random:1MB
+    #[arg(short, long, value_parser = parse_range_spec)]
+    range: Option<u64>,
+
+    /// Statuses that count as successful, as codes, ranges or classes, e.g. 2xx,404 or
+    /// 200-204 [default: 2xx]
+    #[arg(long, value_delimiter = ',', value_parser = parse_expected_status)]
+    expect_status: Vec<RangeInclusive<u16>>,
+
+    /// Fail successful responses whose status isn't one of these, e.g. 200,201
+    #[arg(long, value_delimiter = ',', value_parser = assertions::parse_status)]
+    assert_status: Vec<StatusCode>,
+
+    /// Fail responses without this header, or whose value doesn't match, as Name[: <REGEX>]
+    #[arg(long, value_parser = assertions::parse_header_assertion)]
+    assert_header: Vec<Assertion>,
+
+    /// Fail responses whose body doesn't contain this text
+    #[arg(long, conflicts_with = "download")]
+    assert_body_contains: Vec<String>,
+
+    /// Fail responses whose body doesn't match this regex
+    #[arg(long, value_parser = assertions::parse_regex, conflicts_with = "download")]
+    assert_body_regex: Vec<regex::Regex>,
+
+    /// Rhai script whose request(ctx) builds every request and check(response) validates
+    /// every response, with state kept per concurrent user
+    #[arg(long)]
+    script: Option<PathBuf>,
+
+    /// Slow down when the server signals overload (503/429) and recover gradually
+    #[arg(short, long, default_value_t = false)]
+    adaptive: bool,
+
+    /// Number of times to retry a request that failed with a transport error or 5XX
+    #[arg(long, default_value_t = 0)]
+    retries: u8,
+
+    /// Base delay between retries, doubled on every attempt and jittered, e.g. 250ms
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "100ms")]
+    retry_backoff: Duration,
+
+    /// Most retries as a fraction of the requests sent so far
+    #[arg(long, default_value_t = 0.1)]
+    retry_budget: f32,
+
+    /// Fail requests whose server certificate SHA-256 fingerprint doesn't match (repeatable)
+    #[arg(long = "pin-sha256", value_parser = tls::parse_pin)]
+    pin_sha256: Vec<Fingerprint>,
+
+    /// TLS server name to send, while the Host header and connection target come from the URL
+    #[arg(long)]
+    sni: Option<String>,
+
+    /// Host header to send instead of the URL's authority, e.g. to reach one instance behind a
+    /// load balancer by IP; add --sni with the same name to send it in the TLS handshake too
+    #[arg(long, value_parser = parse_host)]
+    host: Option<HeaderValue>,
+
+    /// User-Agent to send instead of rust-load-tester/<version>
+    #[arg(long, value_parser = parse_user_agent)]
+    user_agent: Option<HeaderValue>,
+
+    /// Send a unique X-Request-Id with every request, also written to --raw-out, so server
+    /// logs can be matched with the results
+    #[arg(long)]
+    request_id: bool,
+
+    /// Send a W3C traceparent header with every request, so its server-side trace can be found
+    #[arg(long)]
+    otel: bool,
+
+    /// Export a client span of every request attempt to this OTLP/HTTP traces endpoint, e.g.
+    /// http://localhost:4318/v1/traces (implies --otel)
+    #[arg(long, value_parser = Url::parse)]
+    otel_endpoint: Option<Url>,
+
+    /// Encodings to accept, e.g. gzip,br (also deflate), or none to ask for uncompressed
+    /// bodies. They're decompressed once read, counting toward the total time but not the TTLB
+    #[arg(long, value_delimiter = ',', value_parser = compression::parse_encoding)]
+    compression: Vec<Encoding>,
+
+    /// Minimum TLS version to offer
+    #[arg(long, value_enum, default_value_t = TlsVersion::Tls12)]
+    tls_min: TlsVersion,
+
+    /// Maximum TLS version to offer
+    #[arg(long, value_enum, default_value_t = TlsVersion::Tls13)]
+    tls_max: TlsVersion,
+
+    /// Comma-separated cipher suites to offer, in order of preference
+    #[arg(long, value_delimiter = ',', value_parser = tls::parse_cipher)]
+    ciphers: Vec<rustls::SupportedCipherSuite>,
+
+    /// Comma-separated ALPN protocols to offer, in order of preference
+    #[arg(long, value_delimiter = ',', default_values_t = ["h2".to_string(), "http/1.1".to_string()])]
+    alpn: Vec<String>,
+
+    /// Only speak HTTP/2: offer just h2 over TLS and use prior knowledge over plain HTTP
+    #[arg(long, default_value_t = false, conflicts_with_all = ["http1", "alpn"])]
+    http2: bool,
+
+    /// Speak HTTP/3 over QUIC, for https URLs only
+    #[arg(long, default_value_t = false, conflicts_with_all = ["http1", "http2", "alpn"])]
+    http3: bool,
+
+    /// Only speak HTTP/1.1, offering just http/1.1 over TLS
+    #[arg(long, default_value_t = false, conflicts_with = "alpn")]
+    http1: bool,
+
+    /// Also trust the CA certificates in this PEM bundle, e.g. an internal root
+    #[arg(long)]
+    cacert: Option<PathBuf>,
+
+    /// Client certificate for mutual TLS: a PEM chain, or a PKCS#12 bundle holding the key too
+    #[arg(long)]
+    cert: Option<PathBuf>,
+
+    /// PEM private key for --cert, unless the certificate file holds it
+    #[arg(long, requires = "cert")]
+    key: Option<PathBuf>,
+
+    /// Password of a PKCS#12 --cert bundle
+    #[arg(long, requires = "cert", default_value = "")]
+    cert_password: String,
+
+    /// Skip server certificate verification, e.g. for staging servers with self-signed certs
+    #[arg(short = 'k', long, default_value_t = false)]
+    insecure: bool,
+
+    /// Append TLS session secrets to the file named by SSLKEYLOGFILE (for Wireshark)
+    #[arg(long, default_value_t = false)]
+    keylog: bool,
+
+    /// Write every engine event (scheduled, connect, headers, body done, errors, summaries) to a file
+    #[arg(long)]
+    events_file: Option<PathBuf>,
+
+    /// Level of the tool's own logs: off, error, warn, info, debug or trace
+    #[arg(long, default_value_t = LevelFilter::WARN)]
+    log_level: LevelFilter,
+
+    /// Append the tool's own logs to a file instead of stderr
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+
+    /// Format of the tool's own logs
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Also report TTFB percentiles corrected for coordinated omission, wrk2 style: each
+    /// request timed from when it was meant to go out, on the --rate schedule or as soon as its
+    /// user was free, rather than from when the generator got round to sending it
+    #[arg(long)]
+    correct_omission: bool,
+
+    /// Report the TTFB percentiles per window of this length as well, e.g. 1h for a test
+    /// soaking for hours, and flag those that grew by more than --drift-threshold from the
+    /// first window to the last
+    #[arg(long, value_parser = humantime::parse_duration)]
+    trend_window: Option<Duration>,
+
+    /// Growth of a percentile between the first and the last --trend-window flagged as drift
+    #[arg(long, requires = "trend_window", value_parser = parse_percent, default_value = "20%")]
+    drift_threshold: f64,
+
+    /// Periodically write aggregated results to this file, readable with `report`
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// How often to write the checkpoint, e.g. 30s or 5m
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "30s")]
+    checkpoint_interval: Duration,
+
+    /// Continue the test stored in a checkpoint, merging its results into this run's report
+    #[arg(long)]
+    resume: Option<PathBuf>,
+
+    /// Also append every successful request to this file, for huge runs that need raw samples
+    #[arg(long)]
+    samples_file: Option<PathBuf>,
+
+    /// Width of interval summaries and time-series buckets, e.g. 1s or 10s
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "1s")]
+    resolution: Duration,
+
+    /// Print the RPS, errors and p95 of every interval while the test runs, e.g. 5s
+    #[arg(long, value_parser = humantime::parse_duration)]
+    interval: Option<Duration>,
+
+    /// Wait until this wall-clock time before starting, e.g. 2024-01-01T12:00:00Z, so tests on
+    /// several hosts begin together
+    #[arg(long, value_parser = humantime::parse_rfc3339_weak)]
+    start_at: Option<SystemTime>,
+
+    /// Refuse to use --start-at when the local clock differs from the target's by more than this
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "2s")]
+    max_clock_skew: Duration,
+
+    /// Comma-separated workers started with `serve`, e.g. 10.0.0.1:7070,10.0.0.2:7070, to run
+    /// the test on all of them at once and print a combined report
+    #[arg(long, value_delimiter = ',')]
+    workers: Vec<String>,
+
+    /// Token the workers were started with
+    #[arg(long)]
+    workers_token: Option<String>,
+
+    /// Spread requests across the pods behind a Kubernetes Service, e.g. api.default, using the
+    /// DNS records of its headless Service, and report latency per pod
+    #[arg(long)]
+    k8s_service: Option<String>,
+
+    /// Spread connections across every address the host resolves to instead of the one the OS
+    /// picks (also picks how --k8s-service chooses pods)
+    #[arg(long, value_enum)]
+    spread: Option<Spread>,
+
+    /// Prepend a PROXY protocol header to every connection, for services behind a
+    /// PROXY-protocol-speaking load balancer
+    #[arg(long, value_enum)]
+    proxy_protocol: Option<ProxyVersion>,
+
+    /// Client address announced in the PROXY header, defaults to the real one
+    #[arg(long, requires = "proxy_protocol")]
+    proxy_client_ip: Option<IpAddr>,
+
+    /// Connect to this Unix domain socket instead of the URL's host, e.g. a service's local
+    /// listener behind nginx; the URL still gives the Host header and path
+    #[arg(long, conflicts_with_all = ["proxy_protocol", "sni", "spread", "k8s_service", "http3", "phases"])]
+    unix_socket: Option<PathBuf>,
+
+    /// Send X-Forwarded-For and Forwarded headers with a synthetic client address per
+    /// concurrent user: random or cidr:<network>/<prefix>, e.g. cidr:10.0.0.0/16
+    #[arg(long, value_parser = client_ip::parse_client_ips)]
+    spoof_client_ip: Option<ClientIps>,
+
+    /// Pause before each --scenario step for its recorded `pause`, as `convert` writes it from
+    /// a HAR file's timings, or before each --har request, so every user replays the recorded
+    /// pacing rather than at full speed. The pauses are left out of the timings.
+    #[arg(long, requires = "scenario_or_har")]
+    preserve_timing: bool,
+
+    /// Replay the pauses of --preserve-timing this many times faster, e.g. 2 for twice the
+    /// recorded traffic or 0.5 for half
+    #[arg(long, value_parser = parse_speed, default_value = "1", requires = "preserve_timing")]
+    speed: f64,
+
+    /// Connect to these addresses instead of what DNS returns for host:port, as
+    /// host:port:addr[,addr] (repeatable), keeping the host name for SNI and the Host header
+    #[arg(long, value_parser = resolve::parse_override)]
+    resolve: Vec<Override>,
+
+    /// Only connect over IPv4
+    #[arg(short = '4', long, conflicts_with = "ipv6")]
+    ipv4: bool,
+
+    /// Only connect over IPv6
+    #[arg(short = '6', long)]
+    ipv6: bool,
+
+    /// Stop the test cleanly once this file exists
+    #[arg(long)]
+    stop_when_file: Option<PathBuf>,
+
+    /// Stop the test cleanly once this URL returns a status, given as <URL>[=<STATUS>]
+    /// (default 200)
+    #[arg(long, value_parser = stop::parse_stop_url)]
+    stop_when_url: Option<(String, StatusCode)>,
+
+    /// Stop the test with a partial report once this many requests have failed
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    abort_on_errors: Option<u64>,
+
+    /// Stop the test with a partial report once this share of the last 100 requests has
+    /// failed, above 0% up to 100%, e.g. 10%, judged from the 20th request on
+    #[arg(long, value_parser = parse_error_rate)]
+    abort_on_error_rate: Option<f64>,
+
+    /// Serve a control API on this address while the test runs (/status, /stop, /rate),
+    /// e.g. 127.0.0.1:9090
+    #[arg(long)]
+    control_addr: Option<SocketAddr>,
+
+    /// Serve live Prometheus metrics at /metrics on this address while the test runs, e.g.
+    /// 0.0.0.0:9091
+    #[arg(long)]
+    prometheus: Option<SocketAddr>,
+
+    /// Stream the metrics of every --resolution interval to this StatsD daemon while the
+    /// test runs, e.g. localhost:8125
+    #[arg(long)]
+    statsd: Option<String>,
+
+    /// Post the metrics of every --resolution interval in the InfluxDB line protocol to this
+    /// write URL while the test runs, with $INFLUX_TOKEN as the token when set
+    #[arg(long, value_parser = Url::parse)]
+    influx: Option<Url>,
+
+    /// Format of the final report
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// Write the JSON report to this file instead of stdout
+    #[arg(long)]
+    output_file: Option<PathBuf>,
+
+    /// TTFB percentiles to report, e.g. 50,75,90,95,99,99.9
+    #[arg(long, value_delimiter = ',', value_parser = parse_percentile, default_value = "50,90,95,99")]
+    percentiles: Vec<f32>,
+
+    /// Check on the final stats, e.g. p95<200ms, error_rate<1%, rps>=100 or failed<10; the
+    /// test exits with code 2 if any misses (repeatable)
+    #[arg(long, value_parser = thresholds::parse_threshold)]
+    threshold: Vec<Threshold>,
+
+    /// Write one CSV row per request (timestamp, status, timings, retries, error category,
+    /// HTTP version, request ID, trace ID)
+    #[arg(long)]
+    raw_out: Option<PathBuf>,
+
+    /// Also write a self-contained HTML report with charts to this file
+    #[arg(long = "report")]
+    html_report: Option<PathBuf>,
+
+    /// Show a live dashboard (RPS, in flight, error rate, p95/p99) instead of the spinner
+    #[arg(long, default_value_t = false)]
+    tui: bool,
+
+    /// Maximum number of idle connections kept open per host for reuse [default: unlimited]
+    #[arg(long)]
+    pool_size: Option<usize>,
+
+    /// Open a fresh connection for every request instead of reusing pooled ones
+    #[arg(long, default_value_t = false, conflicts_with = "pool_size")]
+    no_keepalive: bool,
+
+    /// Time the DNS lookup, TCP connect and TLS handshake of every new connection by relaying
+    /// connections through a local proxy, which adds a loopback hop to every request
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["proxy_protocol", "sni", "spread", "k8s_service", "http3"]
+    )]
+    phases: bool,
+
+    /// Raise the soft limit on open files to the hard limit before the test starts
+    #[arg(long, default_value_t = false)]
+    raise_limits: bool,
+}
+
+/// How each request picks the backend address it connects to, or the URL it goes to
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Spread {
+    /// Each address in turn
+    #[default]
+    RoundRobin,
+    /// A uniformly random address
+    Random,
+}
+
+impl std::fmt::Display for Spread {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Spread::RoundRobin => write!(f, "round-robin"),
+            Spread::Random => write!(f, "random"),
+        }
+    }
+}
+
+/// How the load relates to the server's response times
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Model {
+    /// A fixed number of users, each sending its next request once the last one is answered
+    Closed,
+    /// Requests arriving on a schedule, whether earlier ones were answered or not
+    Open,
+}
+
+/// HTTP version the clients are restricted to
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Protocol {
+    /// Whatever ALPN settles on, HTTP/1.1 without TLS
+    Negotiated,
+    Http1,
+    Http2,
+    Http3,
+}
+
+/// Per-request settings shared by every call of the test
+struct RequestOptions {
+    events: EventBus,
+    clients: Clients,
+    targets: Targets,
+    protocol: Protocol,
+    method: Method,
+    headers: HeaderMap,
+    /// Statuses that count as successful
+    expected_statuses: Vec<RangeInclusive<u16>>,
+    assertions: Assertions,
+    /// Shared by every request, cloning only bumps a reference count
+    body: Option<Bytes>,
+    download: bool,
+    range: Option<RangeOptions>,
+    retry: RetryPolicy,
+    /// Rows filling in the placeholders of `urls` and the scenario
+    data: Option<DataFeed>,
+    /// The URL of each target to fill in with a row of --data for every request
+    urls: Option<Vec<Template>>,
+    /// Headers and body filled in along with `urls`, those without placeholders sent as is
+    header_templates: Vec<(HeaderName, Template)>,
+    body_template: Option<Template>,
+    scenario: Option<Scenario>,
+    pins: Vec<Fingerprint>,
+    sni: Option<SniOverride>,
+    tls_config: rustls::ClientConfig,
+    backends: Option<Backends>,
+    /// Whether the first address the host resolves to is IPv6, known only when it has
+    /// addresses of both families, so Happy Eyeballs fallbacks can be spotted
+    prefers_ipv6: Option<bool>,
+    proxy_relay: Option<ProxyRelay>,
+    /// Synthetic address of each concurrent user, empty unless spoofing
+    client_ips: Vec<IpAddr>,
+    /// How many times faster the recorded pauses of the --scenario steps are replayed
+    replay_speed: Option<f64>,
+    cookies: Cookies,
+    /// Encodings offered in Accept-Encoding and decoded after the body is read
+    compression: Vec<Encoding>,
+    /// Random prefix of this run's X-Request-Ids, set with --request-id
+    request_id_prefix: Option<String>,
+    /// Trace context sent with every request, with --otel
+    otel: Option<Otel>,
+    script: Option<Script>,
+    raw_out: Option<raw::RawWriter>,
+}
+
+/// Local relay that adds a PROXY protocol header to connections on their way to the host.
+/// Clients reach it as an HTTP proxy since reqwest offers no hook into its connections.
+struct ProxyRelay {
+    /// Host of the URL, used as the server name when probing TLS through the relay
+    host: String,
+    addr: SocketAddr,
+}
+
+impl ProxyRelay {
+    fn proxy(&self) -> reqwest::Result<reqwest::Proxy> {
+        reqwest::Proxy::all(format!("http://{}", self.addr))
+    }
+}
+
+/// Addresses requests are pinned to in turn, instead of whatever the resolver returns first
+struct Backends {
+    /// Host of the URL, resolved to the chosen address for each request
+    host: String,
+    addrs: Vec<SocketAddr>,
+    spread: Spread,
+    next: AtomicUsize,
+}
+
+impl Backends {
+    fn new(host: String, mut addrs: Vec<SocketAddr>, spread: Spread) -> Self {
+        addrs.sort();
+        addrs.dedup();
+        Backends {
+            host,
+            addrs,
+            spread,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    fn next(&self) -> SocketAddr {
+        let index = match self.spread {
+            Spread::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            Spread::Random => rand::thread_rng().gen(),
+        };
+        self.addrs[index % self.addrs.len()]
+    }
+}
+
+/// HTTP clients shared by all workers so connections are pooled and reused. Requests pinned
+/// to a backend address get that backend's client, everything else the shared one.
+struct Clients {
+    shared: reqwest::Client,
+    backends: HashMap<SocketAddr, reqwest::Client>,
+}
+
+impl Clients {
+    /// `idle_per_host` of zero closes every connection once its request is done
+    fn build(
+        base: impl Fn() -> reqwest::ClientBuilder,
+        pins: bool,
+        sni: Option<&SniOverride>,
+        backends: Option<&Backends>,
+        proxy_relay: Option<&ProxyRelay>,
+        idle_per_host: usize,
+        protocol: Protocol,
+    ) -> reqwest::Result<Self> {
+        let builder = |resolve: Option<(&str, SocketAddr)>| {
+            let mut builder = base().tls_info(pins).pool_max_idle_per_host(idle_per_host);
+            builder = match protocol {
+                Protocol::Negotiated => builder,
+                Protocol::Http1 => builder.http1_only(),
+                Protocol::Http2 => builder.http2_prior_knowledge(),
+                Protocol::Http3 => builder.http3_prior_knowledge(),
+            };
+            match (resolve, sni) {
+                (Some((host, addr)), _) => builder = builder.resolve(host, addr),
+                (None, Some(sni)) => builder = builder.resolve_to_addrs(&sni.name, &sni.addrs),
+                (None, None) => {}
+            }
+            if let Some(relay) = proxy_relay {
+                builder = builder.proxy(relay.proxy()?);
+            }
+            builder.build()
+        };
+        let mut clients = HashMap::new();
+        if let Some(backends) = backends {
+            let host = sni.map_or(backends.host.as_str(), |sni| sni.name.as_str());
+            for &addr in &backends.addrs {
+                clients.insert(addr, builder(Some((host, addr)))?);
+            }
+        }
+        Ok(Clients {
+            shared: builder(None)?,
+            backends: clients,
+        })
+    }
+
+    /// reqwest's HTTP/3 pool fails requests made while its first connection is still being
+    /// set up, so every client connects once before the test starts
+    async fn warm_up_http3(&self, url: &str) {
+        for client in std::iter::once(&self.shared).chain(self.backends.values()) {
+            if let Err(e) = client.get(url).version(Version::HTTP_3).send().await {
+                debug!("HTTP/3 warm-up request failed: {}", e);
+            }
+        }
+    }
+
+    fn get(&self, backend: Option<SocketAddr>) -> &reqwest::Client {
+        backend
+            .and_then(|addr| self.backends.get(&addr))
+            .unwrap_or(&self.shared)
+    }
+}
+
+/// Outcomes of the requests sent to one backend address or target URL
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackendStats {
+    pub successful: u64,
+    pub failed: u64,
+    /// Sum of the total request time of successful requests
+    pub total_time: f32,
+    pub max_total_time: f32,
 }
 
-#[derive(Debug)]
-struct Stats {
+impl Stats {
+    /// The response got through every check a request makes
+    fn succeeded(&self) -> bool {
+        self.expected
+            && self.pin_matched
+            && self.range_satisfied
+            && self.decoded_bytes.is_some()
+            && self.failed_check.is_none()
+            && self.failed_assertion.is_none()
+            && self.script_failure.is_none()
+    }
+}
+
+impl BackendStats {
+    fn record(&mut self, outcome: &Result<Stats, reqwest::Error>) {
+        match outcome {
+            Ok(resp) if resp.succeeded() => {
+                self.successful += 1;
+                self.total_time += resp.total_time;
+                self.max_total_time = self.max_total_time.max(resp.total_time);
+            }
+            _ => self.failed += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &BackendStats) {
+        self.successful = self.successful.saturating_add(other.successful);
+        self.failed = self.failed.saturating_add(other.failed);
+        self.total_time += other.total_time;
+        self.max_total_time = self.max_total_time.max(other.max_total_time);
+    }
+}
+
+/// Network-layer outcomes, kept apart from request errors. Attempts count requests sent,
+/// with keep-alive many of them share one connection.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConnectionStats {
+    pub attempts: u64,
+    /// Attempts that never got a connection established
+    pub failed: u64,
+    /// Connections that ended up on the other address family than the preferred one
+    pub fallbacks: u64,
+    /// Requests that succeeded on a retry after a failed connect
+    pub recovered: u64,
+    /// Responses per address family of the server, unknown through a local relay
+    #[serde(default)]
+    pub ipv4: u64,
+    #[serde(default)]
+    pub ipv6: u64,
+}
+
+/// Connects to the URL's host while presenting a different TLS server name
+struct SniOverride {
+    /// The URL's original host
+    host: String,
+    name: String,
+    /// Addresses of the URL's original host
+    addrs: Vec<SocketAddr>,
+}
+
+impl SniOverride {
+    /// `url` with its host replaced by the SNI name, its path and query kept, along with its
+    /// original authority to send as the Host header. None for a URL to another host.
+    fn apply(&self, url: &str) -> Option<(Url, String)> {
+        let mut url = Url::parse(url).ok()?;
+        if url.host_str() != Some(self.host.as_str()) {
+            return None;
+        }
+        let host_header = match url.port() {
+            Some(port) => format!("{}:{}", self.host, port),
+            None => self.host.clone(),
+        };
+        url.set_host(Some(&self.name)).ok()?;
+        Some((url, host_header))
+    }
+}
+
+impl RequestOptions {
+    /// What the request numbered `id` sends for `user` to the target at `target`, once the
+    /// --script had its say. Nothing when there is no row of --data for it.
+    fn outgoing(&self, target: usize, user: usize, id: u64) -> Option<Outgoing> {
+        let mut outgoing = self.filled_in(target, user)?;
+        let Some(script) = &self.script else {
+            return Some(outgoing);
+        };
+        match script.request(user, id, &outgoing.url, &outgoing.method) {
+            Ok(scripted) => {
+                if let Some(url) = scripted.url {
+                    outgoing.url = url;
+                }
+                if let Some(method) = scripted.method {
+                    outgoing.method = method;
+                }
+                if let Some(body) = scripted.body {
+                    outgoing.body = Some(Bytes::from(body));
+                }
+                // Replaces the headers of the same name
+                outgoing.headers.extend(scripted.headers);
+            }
+            Err(e) => {
+                info!(id, "script failed: {}", e);
+                outgoing.script_error = Some(e);
+            }
+        }
+        Some(outgoing)
+    }
+
+    /// What a request of `user` to the target at `target` sends, with the placeholders filled
+    /// in from --data and as the --scenario builds it
+    fn filled_in(&self, target: usize, user: usize) -> Option<Outgoing> {
+        let picked = self.targets.get(target);
+        let mut outgoing = Outgoing {
+            url: picked.url.clone(),
+            method: picked.method.clone().unwrap_or_else(|| self.method.clone()),
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+            step: None,
+            row: None,
+            pause: None,
+            script_error: None,
+        };
+        if let Some(scenario) = &self.scenario {
+            return match scenario.next(user, self.data.as_ref()) {
+                Ok(request) => {
+                    outgoing.url = request.url.to_string();
+                    outgoing.method = request.method;
+                    outgoing.headers.extend(request.headers);
+                    outgoing.body = request.body.map(Bytes::from).or(outgoing.body);
+                    outgoing.step = Some(request.step);
+                    outgoing.pause = self
+                        .replay_speed
+                        .zip(request.pause)
+                        .map(|(speed, pause)| pause.div_f64(speed));
+                    Some(outgoing)
+                }
+                Err(e) => {
+                    if !self.data.as_ref().is_some_and(DataFeed::used_up) {
+                        warn!("not sending a scenario step: {}", secrets::redact(&e));
+                    }
+                    None
+                }
+            };
+        }
+        let Some(template) = self.urls.as_ref().map(|urls| &urls[target]) else {
+            return Some(outgoing);
+        };
+        let row = match &self.data {
+            Some(data) if data.checks_out() => {
+                let (held, row) = data.checkout()?;
+                outgoing.row = Some(held);
+                Some(row)
+            }
+            Some(data) => Some(data.draw()),
+            None => None,
+        };
+        let lookup = |name: &str| row.as_ref()?.get(name).map(str::to_string);
+        let render = |outgoing: &mut Outgoing| -> Result<(), String> {
+            outgoing.url = template.render(lookup)?;
+            for (name, value) in &self.header_templates {
+                let value = value.render(lookup)?;
+                let value = HeaderValue::from_str(&value)
+                    .map_err(|_| format!("invalid value for header {}", name))?;
+                outgoing.headers.insert(name.clone(), value);
+            }
+            if let Some(body) = &self.body_template {
+                outgoing.body = Some(Bytes::from(body.render(lookup)?));
+            }
+            Ok(())
+        };
+        if let Err(e) = render(&mut outgoing) {
+            warn!("not sending a request: {}", secrets::redact(&e));
+            self.give_back(&outgoing);
+            return None;
+        }
+        Some(outgoing)
+    }
+
+    /// Returns the row of --data the request held
+    fn give_back(&self, outgoing: &Outgoing) {
+        if let (Some(data), Some(row)) = (&self.data, outgoing.row) {
+            data.give_back(row);
+        }
+    }
+
+    /// X-Request-Id of the request numbered `id`, shared by its retries
+    fn request_id(&self, id: u64) -> Option<String> {
+        self.request_id_prefix
+            .as_ref()
+            .map(|prefix| format!("{}-{}", prefix, id))
+    }
+}
+
+/// One request of the test, sent as is by each of its attempts
+struct Outgoing {
+    url: String,
+    method: Method,
+    headers: HeaderMap,
+    body: Option<Bytes>,
+    /// Index of the --scenario step it sends
+    step: Option<usize>,
+    /// Row of --data checked out for the request, given back once it's done
+    row: Option<usize>,
+    /// Recorded pause to replay before sending the request
+    pause: Option<Duration>,
+    /// Why the script couldn't build the request, which is then sent unchanged and counted
+    /// as failed
+    script_error: Option<String>,
+}
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+const TRACEPARENT: HeaderName = HeaderName::from_static("traceparent");
+
+const DEFAULT_USER_AGENT: &str = concat!("rust-load-tester/", env!("CARGO_PKG_VERSION"));
+
+/// Longest delay between retries, unless --retry-backoff itself is longer
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// Exponential backoff with full jitter, bounded by a budget shared by the whole test
+struct RetryPolicy {
+    max_retries: u8,
+    base_delay: Duration,
+    /// Retries allowed per request sent so far
+    budget: f64,
+    /// Requests sent so far, retries left out
+    sent: AtomicU64,
+    retried: AtomicU64,
+}
+
+impl RetryPolicy {
+    fn new(max_retries: u8, base_delay: Duration, budget: f64) -> Self {
+        RetryPolicy {
+            max_retries,
+            base_delay,
+            budget,
+            sent: AtomicU64::new(0),
+            retried: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts a request's first attempt towards the budget
+    fn record_sent(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Takes a retry out of the budget unless that would make more retries than the budget's
+    /// share of the requests sent so far, however long the test runs
+    fn take_budget(&self) -> bool {
+        let allowed = (self.sent.load(Ordering::Relaxed) as f64 * self.budget).ceil() as u64;
+        self.retried
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |retried| {
+                (retried < allowed).then_some(retried + 1)
+            })
+            .is_ok()
+    }
+
+    /// Picks a delay uniformly between zero and the capped exponential backoff for `attempt`
+    fn delay(&self, attempt: u8) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1 << attempt.min(16))
+            .min(RETRY_MAX_DELAY.max(self.base_delay));
+        ceiling.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+struct RangeOptions {
+    /// Number of bytes requested per call
+    size: u64,
+    /// Total size of the object the ranges are picked from
+    object_size: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stats {
     ttlb: f32,
     ttfb: f32,
     total_time: f32,
     status: StatusCode,
+    /// Body size as received
+    bytes: u64,
+    /// Body size after decompression, None when it failed to decompress
+    decoded_bytes: Option<u64>,
+    finished_at: Instant,
+    range_satisfied: bool,
+    pin_matched: bool,
+    retry_after: Option<Duration>,
+    /// Why the response is a failure despite its status, e.g. a value missing to extract
+    failed_check: Option<String>,
+    version: Version,
+    /// Address of the backend that answered
+    remote_addr: Option<SocketAddr>,
+    /// Index of the first assertion the response failed
+    failed_assertion: Option<usize>,
+    /// Why the script rejected the request or its response
+    script_failure: Option<String>,
+    /// The status is one of the successful ones
+    expected: bool,
 }
 struct LoadResult {
-    number_of_successful_calls: u8,
-    number_of_failed_calls: u8,
-    stats: Vec<Stats>,
+    number_of_successful_calls: u64,
+    number_of_failed_calls: u64,
+    number_of_range_violations: u64,
+    number_of_overload_signals: u64,
+    number_of_pin_mismatches: u64,
+    /// Largest Retry-After hint seen in the most recent batch
+    retry_after: Option<Duration>,
+    number_of_retries: u64,
+    /// Requests that needed at least one retry, only their last attempt is in the latency stats
+    number_of_retried_requests: u64,
+    /// Retried requests whose last attempt succeeded
+    number_of_recovered_requests: u64,
+    /// Time spent sleeping between retries, kept out of the latency stats
+    retry_delay: Duration,
+    /// Time spent waiting between batches because of overload signals
+    backoff_delay: Duration,
+    /// Iterations the virtual users ended, getting through every step or failing one, with --vus
+    number_of_iterations: u64,
+    /// Arrivals the open model didn't send because --max-in-flight requests were outstanding
+    number_of_dropped_arrivals: u64,
+    backends: BTreeMap<SocketAddr, BackendStats>,
+    /// Outcomes per URL when the test has several
+    targets: BTreeMap<String, BackendStats>,
+    /// 2XX responses that failed an assertion, per assertion
+    assertion_failures: BTreeMap<String, u64>,
+    /// Requests that got no response, per cause
+    errors: BTreeMap<ErrorCategory, u64>,
+    /// Body bytes of every response as received, failed ones included
+    bytes_received: u64,
+    /// The same bodies after decompression, with --compression
+    bytes_decoded: u64,
+    /// Responses whose body failed to decompress, counted as failed
+    decode_failures: u64,
+    connections: ConnectionStats,
+    /// Responses per status code, failed ones included
+    statuses: BTreeMap<u16, u64>,
+    /// Successful responses per HTTP version they were answered with
+    versions: BTreeMap<String, u64>,
+    /// Body bytes of the successful responses
+    bytes_successful: u64,
+    /// Body transfer rates of the successful responses, for --download
+    throughput: Throughput,
+    timeline: Timeline,
+    /// Timings of the successful requests, which the percentiles are read from
+    latencies: Latencies,
+    /// Time to first byte of the successful requests since the last --interval line
+    interval: LatencyHistogram,
+    /// Setup timings of the connections opened, with --phases
+    phases: Option<ConnectionPhases>,
+    /// Raw successful requests, with --samples-file
+    samples: Option<SampleFile>,
+    /// Outcomes per load stage, with --stages
+    stages: Option<Stages>,
+    /// The search for the most load the server sustains, with --auto
+    search: Option<Search>,
+    /// Latency around each spike, with --spike
+    spikes: Option<Spikes>,
+    /// TTFB percentiles per window, with --trend-window
+    trend: Option<Series>,
+    /// TTFB from when requests were meant to go out, with --correct-omission
+    corrected: Option<Corrected>,
+    /// Limits on failures that stop the test, with --abort-on-errors or --abort-on-error-rate
+    abort: Option<Abort>,
+}
+
+/// Outcome of the last attempt of a request along with the retries it took to get there
+struct Attempt {
+    outcome: Result<Stats, reqwest::Error>,
+    retries: u8,
+    retry_delay: Duration,
+    /// Address the last attempt was pinned to
+    backend: Option<SocketAddr>,
+    /// Attempts that failed to connect
+    connect_failures: u8,
+    /// Index of the URL the request went to
+    target: usize,
+    request_id: Option<String>,
+    trace_id: Option<String>,
+    /// How much later than meant the request went out, on the --rate schedule or after its
+    /// user became free
+    send_delay: Duration,
+}
+
+/// Linear ramp from 1 to a target value over a window starting when the test does
+struct Ramp {
+    start: Instant,
+    window: Option<Duration>,
+}
+
+impl Ramp {
+    /// Current value on the way to `target`. Zero stays zero so an unset rate stays off.
+    fn scale(&self, target: f64) -> f64 {
+        let progress = match self.window {
+            Some(window) if !window.is_zero() => {
+                (self.start.elapsed().as_secs_f64() / window.as_secs_f64()).min(1.0)
+            }
+            _ => 1.0,
+        };
+        if target <= 1.0 {
+            target
+        } else {
+            1.0 + (target - 1.0) * progress
+        }
+    }
+}
+
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Delays the next batch while the server signals overload, halving the offered
+/// rate on every overloaded batch and easing back once batches come back clean
+#[derive(Default)]
+struct AdaptiveBackoff {
+    delay: Duration,
+}
+
+impl AdaptiveBackoff {
+    fn next_delay(&mut self, overloaded: bool, retry_after: Option<Duration>) -> Duration {
+        if overloaded {
+            self.delay = (self.delay * 2).clamp(MIN_BACKOFF, MAX_BACKOFF);
+            if let Some(hint) = retry_after {
+                self.delay = self.delay.max(hint.min(MAX_BACKOFF));
+            }
+        } else {
+            self.delay = self.delay.mul_f32(0.75);
+            if self.delay < MIN_BACKOFF {
+                self.delay = Duration::ZERO;
+            }
+        }
+        self.delay
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), reqwest::Error> {
-    let args: Args = Args::parse();
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let argv = config::expand_args(std::env::args_os().collect())?;
+    let Cli { command, args } = Cli::parse_from(argv.clone());
+    let (args, command) = match command {
+        Some(Command::Run(args)) => (*args, None),
+        command => (args, command),
+    };
+    logging::init(args.log_level, args.log_format, args.log_file.as_deref())?;
+
+    let result = match command {
+        Some(Command::Convert {
+            input,
+            from,
+            output,
+            keep_credentials,
+        }) => convert::run(convert::Conversion {
+            input,
+            from,
+            output,
+            keep_credentials,
+        }),
+        Some(Command::Record {
+            output,
+            listen,
+            ca_cert,
+            ca_key,
+            origin,
+            insecure,
+            keep_credentials,
+        }) => {
+            record::run(record::Recorder {
+                listen,
+                output,
+                ca_cert,
+                ca_key,
+                origin,
+                tls_config: tls::client_config(&TlsOptions {
+                    min_version: TlsVersion::Tls12,
+                    max_version: TlsVersion::Tls13,
+                    ciphers: vec![],
+                    alpn: vec![],
+                    keylog: false,
+                    insecure,
+                    identity: None,
+                    extra_roots: vec![],
+                })?,
+                keep_credentials,
+            })
+            .await
+        }
+        Some(Command::Report { checkpoint }) => print_checkpoint(&checkpoint),
+        Some(Command::Doctor { concurrency }) => {
+            if !doctor::run(concurrency) {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Fleet {
+            hosts,
+            binary,
+            lead_time,
+            args,
+        }) => {
+            let binary = match binary {
+                Some(binary) => binary,
+                None => std::env::current_exe()?,
+            };
+            fleet::run(fleet::Fleet {
+                hosts,
+                binary,
+                lead_time,
+                args,
+            })
+            .await
+        }
+        Some(Command::Serve { listen, token }) => distributed::serve(listen, token).await,
+        Some(Command::Tcp {
+            addr,
+            payload,
+            payload_file,
+            number,
+            concurrency,
+            duration,
+            read_timeout,
+        }) => {
+            let payload = match (payload, payload_file) {
+                (Some(payload), _) => Some(payload.into_bytes()),
+                (None, Some(path)) => Some(std::fs::read(&path).map_err(|e| {
+                    format!("failed to read payload file {}: {}", path.display(), e)
+                })?),
+                (None, None) => None,
+            };
+            tcp::run(tcp::TcpTest {
+                addr,
+                payload,
+                number,
+                concurrency,
+                duration,
+                read_timeout,
+            })
+            .await
+        }
+        Some(Command::Sse {
+            url,
+            connections,
+            duration,
+            headers,
+            insecure,
+        }) => {
+            sse::run(sse::SseTest {
+                url,
+                connections,
+                duration,
+                headers: headers.into_iter().collect(),
+                tls_config: tls::client_config(&TlsOptions {
+                    min_version: TlsVersion::Tls12,
+                    max_version: TlsVersion::Tls13,
+                    ciphers: vec![],
+                    alpn: vec![],
+                    keylog: false,
+                    insecure,
+                    identity: None,
+                    extra_roots: vec![],
+                })?,
+            })
+            .await
+        }
+        Some(Command::Grpc {
+            url,
+            method,
+            proto,
+            import_paths,
+            data,
+            number,
+            concurrency,
+            duration,
+        }) => {
+            grpc::run(grpc::GrpcTest {
+                url,
+                method,
+                proto,
+                import_paths,
+                data,
+                number,
+                concurrency,
+                duration,
+            })
+            .await
+        }
+        Some(Command::Ws {
+            url,
+            connections,
+            rate,
+            message,
+            duration,
+            insecure,
+        }) => {
+            ws::run(ws::WsTest {
+                url,
+                connections,
+                rate,
+                message,
+                duration,
+                tls_config: tls::client_config(&TlsOptions {
+                    min_version: TlsVersion::Tls12,
+                    max_version: TlsVersion::Tls13,
+                    ciphers: vec![],
+                    alpn: vec![],
+                    keylog: false,
+                    insecure,
+                    identity: None,
+                    extra_roots: vec![],
+                })?,
+            })
+            .await
+        }
+        _ if !args.workers.is_empty() => {
+            distributed::coordinate(
+                args.workers,
+                args.workers_token,
+                distributed::worker_args(&argv),
+            )
+            .await
+        }
+        _ => run(args).await,
+    };
+    if let Err(e) = &result {
+        error!("test aborted: {}", e);
+    }
+    result
+}
+
+fn print_checkpoint(path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    let checkpoint = Checkpoint::read(path)?;
+    let test_start = Instant::now();
+    println!(
+        "Checkpoint of {} after {}s ({} of {} requests completed)",
+        checkpoint.url,
+        checkpoint.elapsed,
+        checkpoint.successful.saturating_add(checkpoint.failed),
+        checkpoint.requested
+    );
+    report::print_report(
+        &checkpoint.restore(test_start)?,
+        &checkpoint.sections,
+        checkpoint.elapsed,
+    );
+    Ok(())
+}
+
+async fn run(mut args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(vus) = args.vus {
+        args.concurrency = vus as usize;
+    }
+    // Closed-model stages need a user for the most concurrency they call for
+    if args.model != Some(Model::Open) {
+        if let Some(most) = args
+            .stages
+            .iter()
+            .map(|stage| stage.target)
+            .reduce(f64::max)
+        {
+            args.concurrency = (most.ceil() as usize).max(1);
+        }
+    }
+    // Spikes are arrival rates unless the closed model is asked for
+    if let Some(spike) = &args.spike {
+        match args.model {
+            Some(Model::Closed) => {
+                args.concurrency = (spike.baseline.max(spike.spike).ceil() as usize).max(1)
+            }
+            _ => args.model = Some(Model::Open),
+        }
+    }
+    if args.raise_limits {
+        let limit = doctor::raise_open_files_limit()?;
+        info!(limit, "raised the open files limit");
+    }
+    let resumed = match &args.resume {
+        Some(path) => Some(Checkpoint::read(path)?),
+        None => None,
+    };
+    let imported = match &args.from_curl {
+        Some(command) => Some(curl::request(command).map_err(|e| format!("--from-curl: {}", e))?),
+        None => None,
+    };
+    let mut urls = args.url.clone();
+    if let Some(request) = &imported {
+        urls.push(Target {
+            url: request.url.clone(),
+            weight: 1,
+            method: None,
+        });
+    }
+    if let Some(path) = &args.urls_file {
+        urls.extend(targets::read_targets(path)?);
+    }
+    let recording = match &args.har {
+        Some(path) => Some(har::read(path)?),
+        None => None,
+    };
+    if let (true, Some(recording)) = (urls.is_empty(), &recording) {
+        urls.push(Target {
+            url: recording.origin.clone(),
+            weight: 1,
+            method: None,
+        });
+    }
+    let targets = match (urls.is_empty(), &resumed) {
+        (true, Some(checkpoint)) => Targets::new(
+            vec![Target {
+                url: checkpoint.url.clone(),
+                weight: 1,
+                method: None,
+            }],
+            args.urls_order,
+        )?,
+        _ => Targets::new(urls, args.urls_order)?,
+    };
+    let url_to_test_against = targets.primary().to_string();
+    if targets.len() > 1 && (args.sni.is_some() || args.range.is_some()) {
+        return Err("--sni and --range only work with a single --url".into());
+    }
+    let family = match (args.ipv4, args.ipv6) {
+        (true, _) => Some(Family::V4),
+        (_, true) => Some(Family::V6),
+        _ => None,
+    };
+    let resolver = Resolver::new(
+        args.resolve.clone(),
+        family,
+        &(0..targets.len())
+            .map(|i| targets.get(i).url.as_str())
+            .collect::<Vec<_>>(),
+    )?;
+    // A resumed test keeps the size it was started with and only makes the requests it has left
+    let duration = args
+        .duration
+        .or(resumed.as_ref().and_then(|c| c.duration))
+        .or((!args.stages.is_empty()).then(|| args.stages.iter().map(|s| s.duration).sum()))
+        .or(args.spike.map(|spike| spike.test_duration(args.spike_gap)));
+    // A timed test has no request limit of its own
+    let requested = match (duration, &resumed) {
+        // Virtual users stop after their iterations, however many requests those take
+        _ if args.vus.is_some() => u64::MAX,
+        // A search stops once it has found the most load the server sustains
+        _ if args.auto => u64::MAX,
+        (Some(_), _) => u64::MAX,
+        (None, Some(checkpoint)) => checkpoint.requested,
+        (None, None) => args.number,
+    };
+    let completed = resumed.as_ref().map_or(0, |c| c.successful + c.failed);
+    let remaining = requested.saturating_sub(completed);
+    let checkpoint_path = args.checkpoint.clone().or(args.resume.clone());
+    let protocol = match (args.http1, args.http2, args.http3) {
+        (true, _, _) => Protocol::Http1,
+        (_, true, _) => Protocol::Http2,
+        (_, _, true) => Protocol::Http3,
+        _ => Protocol::Negotiated,
+    };
+    let tls_config = tls::client_config(&TlsOptions {
+        min_version: args.tls_min,
+        max_version: args.tls_max,
+        ciphers: args.ciphers,
+        alpn: match protocol {
+            Protocol::Negotiated => args.alpn,
+            Protocol::Http1 => vec!["http/1.1".to_string()],
+            Protocol::Http2 => vec!["h2".to_string()],
+            Protocol::Http3 => vec!["h3".to_string()],
+        },
+        keylog: args.keylog,
+        insecure: args.insecure,
+        identity: match &args.cert {
+            Some(cert) => Some(tls::load_identity(
+                cert,
+                args.key.as_deref(),
+                &args.cert_password,
+            )?),
+            None => None,
+        },
+        extra_roots: match &args.cacert {
+            Some(path) => tls::load_ca_bundle(path)?,
+            None => vec![],
+        },
+    })?;
+    if args.insecure {
+        warn!("server certificates are not being verified (--insecure)");
+    }
+    if args.keylog {
+        warn!("TLS session secrets are being written to $SSLKEYLOGFILE");
+    }
+
+    let phases = args.phases.then(SharedPhases::default);
+    let proxy_relay = match (args.proxy_protocol, &phases) {
+        (None, Some(phases)) => {
+            let url = Url::parse(&url_to_test_against)?;
+            let host = url.host_str().ok_or("URL has no host")?.to_string();
+            let port = url.port_or_known_default().ok_or("URL has no port")?;
+            let addr =
+                phases::spawn_proxy((host.clone(), port), resolver.clone(), phases.clone()).await?;
+            info!(proxy = %addr, "timing connections through a local proxy");
+            Some(ProxyRelay { host, addr })
+        }
+        (Some(version), _) => {
+            if args.sni.is_some() || args.spread.is_some() || args.k8s_service.is_some() {
+                return Err(
+                    "--proxy-protocol can't be combined with --sni, --spread or --k8s-service"
+                        .into(),
+                );
+            }
+            let (host, addrs) = resolve_host(&url_to_test_against, &resolver).await?;
+            let upstream = *addrs
+                .first()
+                .ok_or_else(|| format!("{} did not resolve", host))?;
+            if let Some(ip) = args.proxy_client_ip {
+                if ip.is_ipv6() != upstream.is_ipv6() {
+                    return Err(format!(
+                        "--proxy-client-ip {} is not of the same address family as {}",
+                        ip, upstream
+                    )
+                    .into());
+                }
+            }
+            let addr = proxy_protocol::spawn_relay(upstream, version, args.proxy_client_ip).await?;
+            info!(%upstream, relay = %addr, "relaying connections with PROXY protocol headers");
+            Some(ProxyRelay { host, addr })
+        }
+        (None, None) => match &args.unix_socket {
+            Some(path) => {
+                let url = Url::parse(&url_to_test_against)?;
+                let host = url.host_str().ok_or("URL has no host")?.to_string();
+                let addr = unix_socket::spawn_relay(path).await?;
+                info!(socket = %path.display(), relay = %addr, "relaying connections to a Unix socket");
+                Some(ProxyRelay { host, addr })
+            }
+            None => None,
+        },
+    };
+
+    let range = match args.range {
+        Some(size) => {
+            let object_size = fetch_object_size(
+                &url_to_test_against,
+                &tls_config,
+                &resolver,
+                proxy_relay.as_ref(),
+            )
+            .await?;
+            if size > object_size {
+                return Err(format!(
+                    "range size {} exceeds the object size {}",
+                    size, object_size
+                )
+                .into());
+            }
+            Some(RangeOptions { size, object_size })
+        }
+        None => None,
+    };
+    let sni = match &args.sni {
+        Some(name) => Some(resolve_sni_override(&url_to_test_against, name, &resolver).await?),
+        None => None,
+    };
+
+    let spread = args.spread.unwrap_or_default();
+    let backends = match (&args.k8s_service, args.spread) {
+        (Some(service), _) => {
+            Some(resolve_k8s_service(&url_to_test_against, service, spread, &resolver).await?)
+        }
+        (None, Some(spread)) => {
+            let (host, addrs) = resolve_host(&url_to_test_against, &resolver).await?;
+            let backends = Backends::new(host, addrs, spread);
+            info!(addresses = backends.addrs.len(), %spread, "spreading connections");
+            Some(backends)
+        }
+        (None, None) => None,
+    };
+
+    let prefers_ipv6 = if sni.is_none() && backends.is_none() && proxy_relay.is_none() {
+        let (_, addrs) = resolve_host(&url_to_test_against, &resolver).await?;
+        let has_both =
+            addrs.iter().any(SocketAddr::is_ipv4) && addrs.iter().any(SocketAddr::is_ipv6);
+        has_both.then(|| addrs[0].is_ipv6())
+    } else {
+        None
+    };
+
+    if protocol == Protocol::Http3 {
+        if !url_to_test_against.starts_with("https://") {
+            return Err("--http3 needs an https URL".into());
+        }
+        if proxy_relay.is_some() {
+            return Err("--http3 can't be combined with --proxy-protocol".into());
+        }
+    }
+    let quic = if protocol == Protocol::Http3 {
+        let (name, addrs) = match &sni {
+            Some(sni) => (sni.name.clone(), sni.addrs.clone()),
+            None => resolve_host(&url_to_test_against, &resolver).await?,
+        };
+        Some(tls::probe_quic(tls_config.clone(), &name, &addrs).await?)
+    } else {
+        None
+    };
+    let negotiated = if url_to_test_against.starts_with("https://") && quic.is_none() {
+        let (name, addrs) = match (&sni, &proxy_relay) {
+            (Some(sni), _) => (sni.name.clone(), sni.addrs.clone()),
+            (None, Some(relay)) => (relay.host.clone(), vec![relay.addr]),
+            (None, None) => resolve_host(&url_to_test_against, &resolver).await?,
+        };
+        Some(tls::probe(tls_config.clone(), &name, &addrs).await?)
+    } else {
+        None
+    };
+
+    let body = match (args.body, &args.body_file, &args.query) {
+        (_, _, Some(query)) => Some(Bytes::from(graphql::payload(
+            query,
+            args.variables.as_deref(),
+        )?)),
+        (Some(body), _, _) => Some(Bytes::from(body)),
+        (None, Some(path), _) => {
+            Some(Bytes::from(std::fs::read(path).map_err(|e| {
+                format!("failed to read body file {}: {}", path.display(), e)
+            })?))
+        }
+        (None, None, None) => imported
+            .as_ref()
+            .and_then(|request| request.body.clone())
+            .map(Bytes::from),
+    };
+
+    if args.output_file.is_some() && matches!(args.output, OutputFormat::Text) {
+        return Err("--output-file needs --output json".into());
+    }
+    if args.resolution.is_zero() {
+        return Err("resolution must be greater than zero".into());
+    }
+    if args.interval.is_some_and(|interval| interval.is_zero()) {
+        return Err("interval must be greater than zero".into());
+    }
+    if !(0.0..=1.0).contains(&args.retry_budget) {
+        return Err("retry budget must be between 0 and 1".into());
+    }
+    let model = args.model.unwrap_or(match args.rate {
+        Some(_) => Model::Open,
+        None => Model::Closed,
+    });
+    if model == Model::Open && args.rate.is_none() && args.stages.is_empty() && args.spike.is_none()
+    {
+        return Err("--model open needs the --rate, --stages or --spike requests arrive at".into());
+    }
+    let max_in_flight = match (model, args.max_in_flight) {
+        (Model::Closed, Some(_)) => {
+            return Err("--max-in-flight caps the open model, --concurrency the closed one".into())
+        }
+        (_, Some(max)) => max as usize,
+        (_, None) => args.concurrency,
+    };
+    if args.auto && model == Model::Closed && args.concurrency < 2 {
+        return Err(
+            "--auto searches up to --concurrency users with the closed model, set it above 1"
+                .into(),
+        );
+    }
+    secrets::install(&args.secret);
+    let data = match &args.data {
+        Some(path) => {
+            let sharing = if args.data_consume {
+                Sharing::Consume
+            } else if args.data_checkout {
+                Sharing::Checkout
+            } else if args.data_random {
+                Sharing::Random
+            } else {
+                Sharing::Shared
+            };
+            let data = DataFeed::load(path, sharing)?;
+            if data.checks_out() && data.len() < args.concurrency {
+                return Err(format!(
+                    "--data has {} rows to check out, fewer than the {} concurrent users",
+                    data.len(),
+                    args.concurrency
+                )
+                .into());
+            }
+            Some(data)
+        }
+        None => None,
+    };
+    let scenario = match (&args.scenario, recording) {
+        _ if targets.len() > 1 && (args.scenario.is_some() || args.har.is_some()) => {
+            return Err(
+                "--scenario and --har take a single --url, which their requests are relative to"
+                    .into(),
+            )
+        }
+        (_, Some(recording)) => Some(Scenario::from_recording(
+            recording,
+            &url_to_test_against,
+            args.concurrency,
+            data.as_ref(),
+            args.har_shuffle,
+        )?),
+        (Some(path), None) => {
+            let mut scenario = Scenario::load(
+                path,
+                &url_to_test_against,
+                args.concurrency,
+                data.as_ref(),
+                &args.env,
+            )?;
+            if let Some(path) = &args.save_extracted {
+                scenario.save_extracted(path)?;
+            }
+            Some(scenario)
+        }
+        (None, None) => None,
+    };
+    // A scenario fills in its own steps
+    let urls = if scenario.is_none() {
+        let urls = (0..targets.len())
+            .map(|target| {
+                let url = Template::parse(&targets.get(target).url)?;
+                url.check(data.as_ref(), &[])?;
+                Ok(url)
+            })
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(|e| format!("--url: {}", e))?;
+        Some(urls)
+    } else {
+        None
+    };
+    let mut headers = HeaderMap::new();
+    if let Some(request) = &imported {
+        for (name, value) in &request.headers {
+            let (name, value) = parse_header(&format!("{}: {}", name, value))
+                .map_err(|e| format!("--from-curl: {}", e))?;
+            headers.append(name, value);
+        }
+    }
+    for (name, _) in &args.headers {
+        headers.remove(name);
+    }
+    headers.extend(args.headers);
+    if let Some(auth) = args.basic.or(args.bearer) {
+        headers.insert(AUTHORIZATION, auth);
+    }
+    secrets::hide_headers(&headers);
+    if args.graphql {
+        headers
+            .entry(CONTENT_TYPE)
+            .or_insert(HeaderValue::from_static("application/json"));
+    }
+    if let Some(host) = args.host {
+        headers.insert(HOST, host);
+    }
+    if let Some(accept) = compression::accept_encoding(&args.compression) {
+        headers.insert(ACCEPT_ENCODING, accept);
+    }
+    match args.user_agent {
+        Some(user_agent) => {
+            headers.insert(USER_AGENT, user_agent);
+        }
+        None => {
+            headers
+                .entry(USER_AGENT)
+                .or_insert(HeaderValue::from_static(DEFAULT_USER_AGENT));
+        }
+    }
+    // Filled in with the URL, unless a scenario fills in its own steps
+    let mut header_templates = vec![];
+    let mut body_template = None;
+    if urls.is_some() {
+        for (name, value) in &headers {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            let template = Template::parse(value).map_err(|e| format!("-H {}: {}", name, e))?;
+            if template.has_placeholders() {
+                template
+                    .check(data.as_ref(), &[])
+                    .map_err(|e| format!("-H {}: {}", name, e))?;
+                header_templates.push((name.clone(), template));
+            }
+        }
+        if let Some(text) = body
+            .as_deref()
+            .and_then(|body| std::str::from_utf8(body).ok())
+        {
+            let template = Template::parse(text).map_err(|e| format!("--body: {}", e))?;
+            if template.has_placeholders() {
+                template
+                    .check(data.as_ref(), &[])
+                    .map_err(|e| format!("--body: {}", e))?;
+                body_template = Some(template);
+            }
+        }
+    }
+    // Requests are sent as is when there is nothing to fill in
+    let urls = urls.filter(|urls| {
+        data.is_some()
+            || !header_templates.is_empty()
+            || body_template.is_some()
+            || urls.iter().any(Template::has_placeholders)
+    });
+    let clients = Clients::build(
+        || resolver.apply(reqwest::Client::builder().use_preconfigured_tls(tls_config.clone())),
+        !args.pin_sha256.is_empty(),
+        sni.as_ref(),
+        backends.as_ref(),
+        proxy_relay.as_ref(),
+        match (args.no_keepalive, args.pool_size) {
+            (true, _) => 0,
+            (false, size) => size.unwrap_or(usize::MAX),
+        },
+        protocol,
+    )?;
+    let mut assertions = vec![];
+    if !args.assert_status.is_empty() {
+        assertions.push(Assertion::Status(args.assert_status));
+    }
+    assertions.extend(args.assert_header);
+    assertions.extend(
+        args.assert_body_contains
+            .into_iter()
+            .map(Assertion::BodyContains),
+    );
+    assertions.extend(
+        args.assert_body_regex
+            .into_iter()
+            .map(Assertion::BodyMatches),
+    );
+    if args.graphql {
+        assertions.push(Assertion::NoGraphqlErrors);
+    }
+    let assertions = Assertions::new(assertions);
+    let span_exporter = match &args.otel_endpoint {
+        Some(endpoint) => Some((
+            endpoint.clone(),
+            reqwest::Client::builder()
+                .use_preconfigured_tls(tls_config.clone())
+                .build()?,
+        )),
+        None => None,
+    };
+    let options = RequestOptions {
+        events: EventBus::new(),
+        clients,
+        targets,
+        protocol,
+        method: match &imported {
+            _ if args.graphql => Method::POST,
+            Some(request) => {
+                parse_method(&request.method).map_err(|e| format!("--from-curl: {}", e))?
+            }
+            None => args.method,
+        },
+        headers,
+        expected_statuses: if args.expect_status.is_empty() {
+            vec![200..=299]
+        } else {
+            args.expect_status
+        },
+        assertions,
+        body,
+        download: args.download,
+        range,
+        retry: RetryPolicy::new(args.retries, args.retry_backoff, args.retry_budget as f64),
+        data,
+        urls,
+        header_templates,
+        body_template,
+        scenario,
+        pins: args.pin_sha256,
+        sni,
+        tls_config,
+        backends,
+        prefers_ipv6,
+        proxy_relay,
+        client_ips: match args.spoof_client_ip {
+            Some(ips) => (0..args.concurrency).map(|_| ips.generate()).collect(),
+            None => vec![],
+        },
+        replay_speed: args.preserve_timing.then_some(args.speed),
+        compression: args.compression.clone(),
+        request_id_prefix: args
+            .request_id
+            .then(|| format!("{:08x}", rand::random::<u32>())),
+        otel: (args.otel || args.otel_endpoint.is_some()).then(|| Otel::new(span_exporter)),
+        script: match &args.script {
+            Some(path) => Some(Script::load(path, args.concurrency)?),
+            None => None,
+        },
+        cookies: Cookies::new(
+            &args.cookie,
+            // Virtual users stand for people, whose sessions don't mix
+            if args.cookies || args.vus.is_some() {
+                args.concurrency
+            } else {
+                0
+            },
+        ),
+        raw_out: match &args.raw_out {
+            Some(path) => Some(raw::RawWriter::create(path)?),
+            None => None,
+        },
+    };
+
+    if protocol == Protocol::Http3 {
+        let url = match options
+            .sni
+            .as_ref()
+            .and_then(|sni| sni.apply(&url_to_test_against))
+        {
+            Some((url, _)) => url.to_string(),
+            None => url_to_test_against.clone(),
+        };
+        options.clients.warm_up_http3(&url).await;
+    }
+
+    // Warm up before waiting for --start-at so hosts of a fleet still begin measuring together
+    let warmup_requests = match args.warmup {
+        Some(warmup) => {
+            info!("warming up");
+            Some(warm_up(warmup, args.concurrency, &options).await)
+        }
+        None => None,
+    };
+
+    if let Some(start_at) = args.start_at {
+        let mut builder = resolver
+            .apply(reqwest::Client::builder().use_preconfigured_tls(options.tls_config.clone()));
+        if let Some(relay) = &options.proxy_relay {
+            builder = builder.proxy(relay.proxy()?);
+        }
+        let client = builder.build()?;
+        let skew = clock::measure_skew(&client, &url_to_test_against).await?;
+        info!(
+            skew_seconds = skew,
+            "measured clock skew against the target"
+        );
+        if skew.abs() > args.max_clock_skew.as_secs_f64() {
+            return Err(format!(
+                "local clock is {:.2}s {} the target's, more than --max-clock-skew allows",
+                skew.abs(),
+                if skew > 0.0 { "ahead of" } else { "behind" }
+            )
+            .into());
+        }
+
+        info!(
+            "waiting until {} to start (clock skew {:.2}s)",
+            humantime::format_rfc3339_seconds(start_at),
+            skew
+        );
+        clock::wait_until(start_at).await?;
+    }
 
     let spinner = ProgressBar::new_spinner();
     spinner.set_style(ProgressStyle::default_spinner());
 
-    let url_to_test_against = args.url;
-    let number_of_batches = args.number / args.concurrency;
-    let remainder = args.number % args.concurrency;
+    // Resumed tests continue the clock from where the checkpoint left off
+    let prior_elapsed = resumed
+        .as_ref()
+        .map_or(Duration::ZERO, |c| Duration::from_secs_f32(c.elapsed));
+    let test_start = Instant::now()
+        .checked_sub(prior_elapsed)
+        .unwrap_or_else(Instant::now);
+
+    let mut final_result = match &resumed {
+        Some(checkpoint) => checkpoint.restore(test_start)?,
+        None => LoadResult {
+            number_of_failed_calls: 0,
+            number_of_successful_calls: 0,
+            number_of_range_violations: 0,
+            number_of_overload_signals: 0,
+            number_of_pin_mismatches: 0,
+            retry_after: None,
+            number_of_retries: 0,
+            number_of_retried_requests: 0,
+            number_of_recovered_requests: 0,
+            retry_delay: Duration::ZERO,
+            backoff_delay: Duration::ZERO,
+            number_of_iterations: 0,
+            number_of_dropped_arrivals: 0,
+            backends: BTreeMap::new(),
+            targets: BTreeMap::new(),
+            assertion_failures: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            bytes_received: 0,
+            bytes_decoded: 0,
+            decode_failures: 0,
+            connections: ConnectionStats::default(),
+            statuses: BTreeMap::new(),
+            versions: BTreeMap::new(),
+            bytes_successful: 0,
+            throughput: Throughput::default(),
+            timeline: Timeline::new(test_start, args.resolution),
+            latencies: Latencies::default(),
+            interval: LatencyHistogram::default(),
+            phases: None,
+            samples: match &args.samples_file {
+                Some(path) => Some(SampleFile::create(path, test_start)?),
+                None => None,
+            },
+            stages: (!args.stages.is_empty()).then(|| Stages::new(test_start, args.stages.clone())),
+            search: args.auto.then(|| {
+                Search::new(
+                    test_start,
+                    match model {
+                        Model::Open => args.rate.unwrap_or_default(),
+                        Model::Closed => 1.0,
+                    },
+                    (model == Model::Closed).then_some(args.concurrency as f64),
+                    model == Model::Closed,
+                    args.auto_step,
+                    args.auto_limit.clone(),
+                )
+            }),
+            spikes: args
+                .spike
+                .map(|spike| Spikes::new(test_start, spike, args.spike_gap)),
+            trend: args
+                .trend_window
+                .map(|window| Series::new(test_start, window, args.percentiles.clone())),
+            corrected: args.correct_omission.then(Corrected::default),
+            abort: None,
+        },
+    };
 
-    let mut final_result = LoadResult {
-        number_of_failed_calls: 0,
-        number_of_successful_calls: 0,
-        stats: vec![],
+    let stop = StopSignal::new();
+    final_result.abort = Abort::new(args.abort_on_errors, args.abort_on_error_rate, stop.clone());
+    let stop_conditions = args
+        .stop_when_file
+        .map(StopCondition::File)
+        .into_iter()
+        .chain(
+            args.stop_when_url
+                .map(|(url, status)| StopCondition::Url { url, status }),
+        )
+        .collect();
+    stop::watch_conditions(stop_conditions, options.tls_config.clone(), stop.clone())?;
+    stop::stop_on_interrupt(stop.clone());
+    let rate = RateLimit::default();
+    if let Some(rps) = args.rate {
+        rate.set(rps);
+    }
+    if let Some(addr) = args.control_addr {
+        let control = Control {
+            requested,
+            test_start,
+            stop: stop.clone(),
+            rate: rate.clone(),
+        };
+        let addr = control::serve(addr, control, options.events.subscribe()).await?;
+        info!("control API listening on http://{}", addr);
+    }
+    if let Some(addr) = args.prometheus {
+        let addr = prometheus::serve(addr, test_start, options.events.subscribe()).await?;
+        info!("Prometheus metrics on http://{}/metrics", addr);
+    }
+    let sinks = if args.statsd.is_some() || args.influx.is_some() {
+        let client = reqwest::Client::builder()
+            .use_preconfigured_tls(options.tls_config.clone())
+            .build()?;
+        let sinks = Sinks {
+            statsd: args.statsd.clone(),
+            influx: args.influx.clone(),
+        };
+        Some(sinks::spawn(sinks, args.resolution, client, options.events.subscribe()).await?)
+    } else {
+        None
     };
 
     spinner.enable_steady_tick(Duration::from_millis(100));
     spinner.set_message("Processing...");
-    let test_start = Instant::now();
+    let progress = if args.tui {
+        tokio::spawn(tui::dashboard(options.events.subscribe(), spinner.clone()))
+    } else {
+        tokio::spawn(report_progress(options.events.subscribe(), spinner.clone()))
+    };
+    let event_log = args.events_file.map(|path| {
+        let events = options.events.subscribe();
+        tokio::spawn(async move { events::write_events(events, &path, test_start).await })
+    });
+    let mut last_summary = (
+        Instant::now(),
+        final_result.number_of_successful_calls,
+        final_result.number_of_failed_calls,
+    );
+
+    let mut last_interval = (
+        Instant::now(),
+        final_result.number_of_successful_calls,
+        final_result.number_of_failed_calls,
+    );
+
+    let mut left = remaining;
 
-    for _ in 0..number_of_batches {
-        final_result = process_batch(&url_to_test_against, args.concurrency, final_result).await;
+    if resumed.is_some() {
+        info!(completed, remaining, "resuming test from checkpoint");
     }
-    // Process the remainder
-    if remainder > 0 {
-        final_result = process_batch(&url_to_test_against, remainder, final_result).await;
+    info!(
+        url = %url_to_test_against,
+        requests = remaining,
+        concurrency = args.concurrency,
+        "test started"
+    );
+    let sections = Sections {
+        range: options.range.is_some(),
+        pins: !options.pins.is_empty(),
+        retries: args.retries > 0,
+        adaptive: args.adaptive,
+        download: args.download,
+        negotiated,
+        quic,
+        resolution: args.resolution,
+        percentiles: args.percentiles.clone(),
+        backends: options.backends.is_some(),
+        spread: options.backends.as_ref().map(|b| b.spread),
+        versions: protocol != Protocol::Negotiated,
+        insecure: args.insecure,
+        assertions: !options.assertions.is_empty() || options.script.is_some(),
+        warmup_requests,
+        family: resolver.family(),
+        compression: args.compression.iter().any(|&e| e != Encoding::Identity),
+        open_model: model == Model::Open,
+        vus: args.vus,
+        stages: args.stages.clone(),
+        trend: args.trend_window.map(|window| Trend {
+            window,
+            drift: args.drift_threshold,
+        }),
+    };
+    let mut last_checkpoint = Instant::now();
+
+    let mut backoff = AdaptiveBackoff::default();
+    let mut batch = 0;
+    // Leave out the connections of the TLS probe and other checks made before the test
+    if let Some(phases) = &phases {
+        *phases.lock().unwrap() = ConnectionPhases::default();
     }
+    // When each user of a batch last got its response
+    let mut free_at = vec![None; args.concurrency];
+    let mut pacer = Pacer::new(
+        args.concurrency,
+        max_in_flight,
+        model == Model::Open,
+        stop.clone(),
+    );
+    let mut users = args.vus.map(|vus| {
+        let iterations = args.iterations.or(duration.is_none().then_some(1));
+        VirtualUsers::new(vus as usize, iterations, args.think_time, stop.clone())
+    });
+    let deadline = duration.map(|duration| test_start + duration);
+    let ramp = Ramp {
+        start: Instant::now(),
+        window: args.ramp_up,
+    };
+    let schedule = final_result
+        .stages
+        .as_ref()
+        .map(|stages| stages.schedule().clone());
+    let spike_plan = final_result
+        .spikes
+        .as_ref()
+        .map(|spikes| spikes.plan().clone());
+    // Load the stages or spikes call for now
+    let planned = || {
+        schedule
+            .as_ref()
+            .map(Schedule::target)
+            .or_else(|| spike_plan.as_ref().map(SpikePlan::target))
+    };
+    // Arrivals per second the test calls for now, zero to send batches instead
+    let arrival_rate = || match planned() {
+        Some(target) if model == Model::Open => target,
+        Some(_) => 0.0,
+        None => ramp.scale(rate.get()),
+    };
+    while left > 0 {
+        if stop.reason().is_some() || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break;
+        }
+        let now = Instant::now();
+        if final_result
+            .search
+            .as_ref()
+            .is_some_and(|search| search.step_over(now))
+        {
+            // The requests a load started count towards it, not towards the next
+            pacer.drain(&options, &mut final_result).await;
+            if let Some(search) = &mut final_result.search {
+                if !search.advance(Instant::now()) {
+                    break;
+                }
+                if model == Model::Open {
+                    rate.set(search.load());
+                }
+            }
+        }
+        // With a rate set, the batch only groups bookkeeping, its requests are paced
+        let rps = arrival_rate();
+        let size = match planned() {
+            // A second's worth of arrivals, so batches keep up with the stages or spikes
+            Some(_) if model == Model::Open => rps.ceil(),
+            Some(target) => target.ceil(),
+            None => match &final_result.search {
+                Some(search) if search.users() => search.load(),
+                _ => ramp.scale(args.concurrency as f64).round(),
+            },
+        };
+        let count = left.min(size as u64) as usize;
+        if count == 0 {
+            // A stage ramping up from nothing or down to it, or a baseline of nothing
+            tokio::time::sleep(RATE_POLL).await;
+            continue;
+        }
+        left -= count as u64;
+        let overload_signals_before = final_result.number_of_overload_signals;
+        final_result.retry_after = None;
+        debug!(batch, count, "batch started");
+        let batch_start = Instant::now();
+        if let Some(users) = &mut users {
+            users
+                .run(count, deadline, &options, &mut final_result)
+                .await;
+        } else if rps > 0.0 {
+            pacer
+                .start(count, &arrival_rate, deadline, &options, &mut final_result)
+                .await;
+        } else {
+            pacer.drain(&options, &mut final_result).await;
+            final_result = process_batch(count, &options, final_result, &stop, &mut free_at).await;
+        }
+        if options.data.as_ref().is_some_and(DataFeed::used_up) {
+            spinner.println("Stopping, every row of --data was used");
+            break;
+        }
+        if users.as_ref().is_some_and(VirtualUsers::finished) {
+            break;
+        }
+        debug!(batch, elapsed = ?batch_start.elapsed(), "batch finished");
+        batch += 1;
 
-    spinner.finish_with_message("Done!");
-    let CalculatedStats {
-        total_time: (total_min, total_max, total_mean),
-        ttfb: (ttfb_min, ttfb_max, ttfb_mean),
-        ttlb: (ttlb_min, ttlb_max, ttlb_mean),
-    } = calculate_stats(&final_result);
+        let (summary_start, successful_before, failed_before) = last_summary;
+        if summary_start.elapsed() >= args.resolution {
+            options.events.publish(Event::IntervalSummary {
+                elapsed: summary_start.elapsed(),
+                successful: final_result.number_of_successful_calls - successful_before,
+                failed: final_result.number_of_failed_calls - failed_before,
+            });
+            last_summary = (
+                Instant::now(),
+                final_result.number_of_successful_calls,
+                final_result.number_of_failed_calls,
+            );
+        }
 
-    let test_end = Instant::now();
+        let (interval_start, successful_before, failed_before) = last_interval;
+        if args
+            .interval
+            .is_some_and(|interval| interval_start.elapsed() >= interval)
+        {
+            let line = report::interval_line(
+                test_start.elapsed(),
+                interval_start.elapsed(),
+                final_result.number_of_successful_calls - successful_before,
+                final_result.number_of_failed_calls - failed_before,
+                &final_result.interval,
+            );
+            // Kept off stdout, which may carry the JSON report
+            spinner.suspend(|| eprintln!("{}", line));
+            final_result.interval = LatencyHistogram::default();
+            last_interval = (
+                Instant::now(),
+                final_result.number_of_successful_calls,
+                final_result.number_of_failed_calls,
+            );
+        }
 
-    let test_duration = test_end.duration_since(test_start).as_secs_f32();
+        if let Some(path) = &checkpoint_path {
+            if last_checkpoint.elapsed() >= args.checkpoint_interval {
+                write_checkpoint(
+                    path,
+                    &url_to_test_against,
+                    requested,
+                    duration,
+                    &sections,
+                    &mut final_result,
+                    test_start,
+                );
+                last_checkpoint = Instant::now();
+            }
+        }
 
-    let rps = final_result.number_of_successful_calls as f32 / test_duration;
+        if args.adaptive && left > 0 {
+            let overloaded = final_result.number_of_overload_signals > overload_signals_before;
+            let delay = backoff.next_delay(overloaded, final_result.retry_after);
+            if !delay.is_zero() {
+                info!(?delay, overloaded, "backing off before next batch");
+                final_result.backoff_delay += delay;
+                spinner.set_message(format!("Backing off for {:?}...", delay));
+                tokio::time::sleep(delay).await;
+                spinner.set_message("Processing...");
+            }
+        }
+    }
 
-    // Usage
-    let mut latencies = final_result
-        .stats
-        .iter()
-        .map(|s| s.ttfb)
-        .collect::<Vec<f32>>();
-    latencies.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    pacer.drain(&options, &mut final_result).await;
+    if let Some(users) = &mut users {
+        users.drain(&options, &mut final_result).await;
+    }
+    options.events.publish(Event::Finished {
+        elapsed: test_start.elapsed(),
+    });
+    info!(elapsed = ?test_start.elapsed(), "test finished");
+    if let Some(event_log) = event_log {
+        event_log.await??;
+    }
+    if let Some(sinks) = sinks {
+        sinks.await?;
+    }
+    if let Some(otel) = &options.otel {
+        otel.finish().await;
+    }
+    progress.abort();
+    match stop.reason() {
+        Some(reason) => spinner.finish_with_message(format!("Stopped early: {}", reason)),
+        None => spinner.finish_with_message("Done!"),
+    }
+    let test_duration = test_start.elapsed().as_secs_f32();
+    final_result.phases = phases.map(|phases| phases.lock().unwrap().clone());
+    if let Some(samples) = &mut final_result.samples {
+        samples.flush()?;
+    }
+    if let Some(raw_out) = &options.raw_out {
+        raw_out.flush()?;
+    }
+    if let Some(path) = &checkpoint_path {
+        write_checkpoint(
+            path,
+            &url_to_test_against,
+            requested,
+            duration,
+            &sections,
+            &mut final_result,
+            test_start,
+        );
+    }
+    if let Some(path) = &args.html_report {
+        let html = html::render(
+            &url_to_test_against,
+            &final_result,
+            &sections,
+            test_duration,
+        );
+        std::fs::write(path, html)?;
+    }
+    report::write_report(
+        &final_result,
+        &sections,
+        test_duration,
+        args.output,
+        args.output_file.as_deref(),
+    )?;
+    if !args.threshold.is_empty() {
+        let verdicts = thresholds::evaluate(
+            &args.threshold,
+            &thresholds::Observed::of(&final_result, test_duration),
+        );
+        let json_on_stdout =
+            matches!(args.output, OutputFormat::Json) && args.output_file.is_none();
+        thresholds::print_verdicts(&verdicts, json_on_stdout);
+        if verdicts.iter().any(|verdict| !verdict.passed) {
+            std::process::exit(thresholds::THRESHOLD_FAILED_EXIT_CODE);
+        }
+    }
+    if final_result.abort.as_ref().is_some_and(Abort::tripped) {
+        std::process::exit(stop::ABORTED_EXIT_CODE);
+    }
 
-    let p95 = calculate_percentiles(&latencies, 95.0);
-    let p99 = calculate_percentiles(&latencies, 99.0);
+    Ok(())
+}
 
-    println!("Results:");
-    println!(
-        "Total Requests (2XX).......................: {}",
-        final_result.number_of_successful_calls
-    );
-    println!(
-        "Failed Requests (5XX).......................: {}",
-        final_result.number_of_failed_calls
-    );
-    println!("Request Per Sec (RPS).......................: {}", rps);
-    println!();
-    println!();
-    println!("P95.......................: {}", p95);
-    println!("P99.......................: {}", p99);
-    println!();
-    println!();
-    println!(
-        "Total Request Time (s) (Min, Max, Mean).....: {}, {}, {},",
-        total_min, total_max, total_mean
-    );
-    println!(
-        "Time to First Byte (s) (Min, Max, Mean).....: {}, {}, {},",
-        ttfb_min, ttfb_max, ttfb_mean
-    );
-    println!(
-        "Time to Last Byte (s) (Min, Max, Mean).....: {}, {}, {},",
-        ttlb_min, ttlb_max, ttlb_mean
-    );
+/// Failing to write a checkpoint shouldn't abort the test it is meant to protect
+fn write_checkpoint(
+    path: &std::path::Path,
+    url: &str,
+    requested: u64,
+    duration: Option<Duration>,
+    sections: &Sections,
+    result: &mut LoadResult,
+    test_start: Instant,
+) {
+    if let Some(samples) = &mut result.samples {
+        if let Err(e) = samples.flush() {
+            warn!("failed to flush samples before checkpointing: {}", e);
+        }
+    }
+    let checkpoint = Checkpoint {
+        duration,
+        ..Checkpoint::capture(url, requested, sections.clone(), result, test_start)
+    };
+    match checkpoint.write(path) {
+        Ok(()) => debug!(path = %path.display(), "checkpoint written"),
+        Err(e) => warn!(path = %path.display(), "failed to write checkpoint: {}", e),
+    }
+}
 
-    Ok(())
+/// Shows how many requests have completed next to the spinner
+async fn report_progress(mut events: broadcast::Receiver<Event>, spinner: ProgressBar) {
+    let (mut completed, mut failed) = (0u64, 0u64);
+    loop {
+        match events.recv().await {
+            Ok(Event::BodyDone { .. }) => completed += 1,
+            Ok(Event::Error { .. }) => failed += 1,
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+        spinner.set_message(format!(
+            "Processing... {} responses, {} errors",
+            completed, failed
+        ));
+    }
 }
 
-async fn make_request(url: &str) -> Result<Stats, reqwest::Error> {
+async fn make_request(
+    outgoing: &Outgoing,
+    user: usize,
+    options: &RequestOptions,
+    id: u64,
+    attempt: u8,
+    backend: Option<SocketAddr>,
+) -> Result<Stats, reqwest::Error> {
+    let client = options.clients.get(backend);
+
+    let sni = options
+        .sni
+        .as_ref()
+        .and_then(|sni| sni.apply(&outgoing.url));
+    let mut request = match sni {
+        Some((url, host_header)) => client
+            .request(outgoing.method.clone(), url)
+            .header(HOST, host_header),
+        None => client.request(outgoing.method.clone(), &outgoing.url),
+    }
+    // A Host from --host or -H replaces the URL's authority
+    .headers(outgoing.headers.clone());
+    if let Some(body) = &outgoing.body {
+        request = request.body(body.clone());
+    }
+    if options.protocol == Protocol::Http3 {
+        // reqwest only routes requests asking for HTTP/3 to its QUIC client
+        request = request.version(Version::HTTP_3);
+    }
+    if let Some(&ip) = options.client_ips.get(user) {
+        request = request
+            .header(X_FORWARDED_FOR, ip.to_string())
+            .header(FORWARDED, client_ip::forwarded_for(ip));
+    }
+    if let Some(request_id) = options.request_id(id) {
+        request = request.header(X_REQUEST_ID, request_id);
+    }
+    if let Some(otel) = &options.otel {
+        request = request.header(TRACEPARENT, otel.traceparent(id, attempt));
+    }
+    if let Some(cookies) = options.cookies.header(user, &outgoing.url) {
+        request = request.header(COOKIE, cookies);
+    }
+    let mut requested_start = None;
+    if let Some(range) = &options.range {
+        let start = rand::thread_rng().gen_range(0..=range.object_size - range.size);
+        let end = start + range.size - 1;
+        request = request.header(RANGE, format!("bytes={}-{}", start, end));
+        requested_start = Some(start);
+    }
+
     let start = Instant::now();
+    options.events.publish(Event::RequestScheduled {
+        id,
+        attempt,
+        at: start,
+    });
 
     // Start the request
-    let res = reqwest::get(url).await?;
+    let mut res = request.send().await?;
+    options.cookies.store(user, &outgoing.url, res.headers());
     let status = res.status();
+    let version = res.version();
+    let remote_addr = res.remote_addr();
+    if let Some(addr) = remote_addr {
+        options.events.publish(Event::Connect { id, addr });
+    }
+    let retry_after = res
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
+    let pin_matched = options.pins.is_empty() || tls::certificate_matches(&res, &options.pins);
+    let checked_headers = options.script.as_ref().map(|_| res.headers().clone());
+    let mut failed_assertion = options.assertions.check_head(status, res.headers());
+    let range_satisfied = match requested_start {
+        Some(requested) => {
+            status == StatusCode::PARTIAL_CONTENT && content_range_start(&res) == Some(requested)
+        }
+        None => true,
+    };
 
     // Time to first byte (TTFB)
     let ttfb = start.elapsed().as_secs_f32();
+    options.events.publish(Event::Headers { id, status, ttfb });
 
-    // Read the whole body
-    let _ = res.bytes().await?;
+    let expected = options
+        .expected_statuses
+        .iter()
+        .any(|range| range.contains(&status.as_u16()));
+    let encoding =
+        compression::negotiated(&options.compression, res.headers().get(CONTENT_ENCODING));
+    let mut bytes = 0;
+    let mut body = None;
+    let mut decoded_bytes = None;
+    let mut failed_check = None;
+    let mut decoded_body = None;
+    if options.download {
+        // Stream the body chunk by chunk, only counting bytes so large downloads aren't buffered
+        let mut decoder = Some(Decoder::new(encoding, Counter::default()));
+        while let Some(chunk) = res.chunk().await? {
+            bytes += chunk.len() as u64;
+            if decoder.as_mut().is_some_and(|d| d.write(&chunk).is_err()) {
+                decoder = None;
+            }
+        }
+        decoded_bytes = decoder
+            .and_then(|d| d.finish().ok())
+            .map(|counter| counter.0);
+    } else {
+        // Read the whole body
+        let headers = res.headers().clone();
+        let raw = res.bytes().await?;
+        bytes = raw.len() as u64;
+        body = Some((headers, raw));
+    }
     // Measure the time immediately after the body is fully read
     let body_end = Instant::now();
 
     // Time to last byte (TTLB)
     let ttlb = body_end.duration_since(start).as_secs_f32();
 
+    // Decompression counts toward the total time only
+    if let Some((headers, raw)) = body {
+        match compression::decode(encoding, raw) {
+            Ok(decoded) => {
+                decoded_bytes = Some(decoded.len() as u64);
+                if let (Some(scenario), Some(step)) = (&options.scenario, outgoing.step) {
+                    if expected {
+                        failed_check = scenario.extract(user, step, &headers, &decoded);
+                    }
+                }
+                failed_assertion =
+                    failed_assertion.or_else(|| options.assertions.check_body(&decoded));
+                decoded_body = Some(decoded);
+            }
+            Err(e) => info!(id, "failed to decode the {:?} body: {}", encoding, e),
+        }
+    }
+
     let total_time = Instant::now().duration_since(start).as_secs_f32();
 
-    Ok(Stats {
+    let mut script_failure = outgoing.script_error.clone();
+    if let (Some(script), Some(headers)) = (&options.script, &checked_headers) {
+        script_failure = script_failure.or_else(|| {
+            script.check(
+                user,
+                status,
+                headers,
+                decoded_body.as_deref().unwrap_or_default(),
+            )
+        });
+    }
+
+    let stats = Stats {
         ttlb,
         ttfb,
         total_time,
         status,
-    })
+        bytes,
+        decoded_bytes,
+        finished_at: body_end,
+        range_satisfied,
+        pin_matched,
+        retry_after,
+        failed_check,
+        version,
+        remote_addr,
+        failed_assertion,
+        script_failure,
+        expected,
+    };
+    options.events.publish(Event::BodyDone {
+        id,
+        bytes,
+        ttlb,
+        succeeded: stats.succeeded(),
+    });
+    Ok(stats)
+}
+
+/// Sends the next request of `user`, retrying it as the policy allows. Nothing when there is
+/// no row of --data to fill it in. `user` is the request's slot in its batch, which also picks
+/// its spoofed client address.
+async fn make_request_with_retries(
+    user: usize,
+    send_delay: Duration,
+    options: &RequestOptions,
+) -> Option<Attempt> {
+    let (target, _) = options.targets.pick();
+    let id = options.events.next_request_id();
+    // A retry sends the same request again
+    let outgoing = options.outgoing(target, user, id)?;
+    if let Some(pause) = outgoing.pause {
+        tokio::time::sleep(pause).await;
+    }
+    options.retry.record_sent();
+    let mut retries = 0;
+    let mut retry_delay = Duration::ZERO;
+    let mut connect_failures = 0;
+    loop {
+        let backend = options.backends.as_ref().map(Backends::next);
+        let started_at = SystemTime::now();
+        let outcome = make_request(&outgoing, user, options, id, retries, backend).await;
+        if let Some(otel) = &options.otel {
+            otel.record(Span {
+                id,
+                attempt: retries,
+                method: outgoing.method.to_string(),
+                url: outgoing.url.clone(),
+                start: started_at,
+                end: SystemTime::now(),
+                status: outcome.as_ref().ok().map(|stats| stats.status.as_u16()),
+                error: outcome.as_ref().err().map(|e| e.to_string()),
+            });
+        }
+        if let Err(e) = &outcome {
+            if e.is_connect() {
+                connect_failures += 1;
+            }
+            info!(id, attempt = retries, "request failed: {}", e);
+            options.events.publish(Event::Error {
+                id,
+                message: e.to_string(),
+            });
+        }
+        let retryable = match &outcome {
+            Ok(stats) => stats.status.is_server_error(),
+            Err(_) => true,
+        };
+        let mut give_up = !retryable || retries >= options.retry.max_retries;
+        if !give_up && !options.retry.take_budget() {
+            info!(id, "retry budget exhausted, giving up");
+            give_up = true;
+        }
+        if give_up {
+            let succeeded = outcome.as_ref().is_ok_and(Stats::succeeded);
+            if let Some(scenario) = options.scenario.as_ref().filter(|_| !succeeded) {
+                // Its iteration won't get through every step
+                scenario.restart(user);
+            }
+            options.give_back(&outgoing);
+            return Some(Attempt {
+                outcome,
+                retries,
+                retry_delay,
+                backend,
+                connect_failures,
+                target,
+                request_id: options.request_id(id),
+                trace_id: options.otel.as_ref().map(|otel| otel.trace_id(id)),
+                send_delay,
+            });
+        }
+
+        let delay = options.retry.delay(retries);
+        info!(id, attempt = retries + 1, ?delay, "retrying request");
+        tokio::time::sleep(delay).await;
+        retry_delay += delay;
+        retries += 1;
+    }
 }
 
-async fn process_batch(url: &str, count: u8, mut result: LoadResult) -> LoadResult {
-    let mut futures = Vec::new();
-    for _ in 0..count {
-        futures.push(make_request(url));
+/// Sends batches of `concurrency` requests until the warm-up is over, throwing their outcomes
+/// away. Returns how many were sent.
+async fn warm_up(warmup: Warmup, concurrency: usize, options: &RequestOptions) -> u64 {
+    let (mut remaining, deadline) = match warmup {
+        Warmup::Requests(count) => (count, None),
+        Warmup::Duration(duration) => (u64::MAX, Some(Instant::now() + duration)),
+    };
+    let mut sent = 0;
+    while remaining > 0 && deadline.is_none_or(|deadline| Instant::now() < deadline) {
+        let count = remaining.min(concurrency as u64);
+        let mut in_flight: FuturesUnordered<_> = (0..count as usize)
+            .map(|user| make_request_with_retries(user, Duration::ZERO, options))
+            .collect();
+        while in_flight.next().await.is_some() {}
+        remaining -= count;
+        sent += count;
+    }
+    info!(requests = sent, "warm-up done");
+    sent
+}
+
+/// Requests of virtual users, None for one the test ended before its think time did
+type UserRequests<'a> =
+    FuturesUnordered<Pin<Box<dyn Future<Output = Option<(usize, Option<Attempt>)>> + 'a>>>;
+
+/// Virtual users, each sending its next request as soon as its last one is answered rather
+/// than waiting for a batch, until it has run its iterations or the test ends
+struct VirtualUsers<'a> {
+    in_flight: UserRequests<'a>,
+    users: usize,
+    /// Iterations each user has left to start, None to keep going until the test ends
+    iterations_left: Option<Vec<u64>>,
+    think_time: Option<ThinkTime>,
+    started: bool,
+    /// No new request is sent, the test having ended
+    draining: bool,
+    stop: StopSignal,
+}
+
+impl<'a> VirtualUsers<'a> {
+    fn new(
+        users: usize,
+        iterations: Option<u64>,
+        think_time: Option<ThinkTime>,
+        stop: StopSignal,
+    ) -> Self {
+        VirtualUsers {
+            in_flight: FuturesUnordered::new(),
+            users,
+            iterations_left: iterations.map(|iterations| vec![iterations; users]),
+            think_time,
+            started: false,
+            draining: false,
+            stop,
+        }
+    }
+
+    /// Every user has run its iterations
+    fn finished(&self) -> bool {
+        self.started && self.in_flight.is_empty()
+    }
+
+    /// Records `count` answered requests, each user sending its next one in its place
+    async fn run(
+        &mut self,
+        count: usize,
+        deadline: Option<Instant>,
+        options: &'a RequestOptions,
+        result: &mut LoadResult,
+    ) {
+        if !std::mem::replace(&mut self.started, true) {
+            for user in 0..self.users {
+                self.send(user, Duration::ZERO, deadline, options);
+            }
+        }
+        self.collect(count, deadline, options, result).await;
+    }
+
+    /// Waits for the requests in flight without sending any more
+    async fn drain(&mut self, options: &'a RequestOptions, result: &mut LoadResult) {
+        self.draining = true;
+        self.collect(usize::MAX, None, options, result).await;
     }
 
-    let calls: Vec<Result<Stats, reqwest::Error>> = join_all(futures).await;
-    for call in calls {
-        match call {
-            Ok(resp) if resp.status.is_success() => {
-                result.number_of_successful_calls += 1;
-                result.stats.push(resp);
+    async fn collect(
+        &mut self,
+        count: usize,
+        deadline: Option<Instant>,
+        options: &'a RequestOptions,
+        result: &mut LoadResult,
+    ) {
+        let stop = self.stop.clone();
+        let drain_timeout = stop.drain_timeout();
+        tokio::pin!(drain_timeout);
+        for _ in 0..count {
+            tokio::select! {
+                answered = self.in_flight.next() => match answered {
+                    Some(Some((user, attempt))) => {
+                        if let Some(attempt) = attempt {
+                            record_attempt(result, attempt, options);
+                        }
+                        if starts_over(user, options) {
+                            result.number_of_iterations += 1;
+                        }
+                        let pause = self.think_time.map_or(Duration::ZERO, |t| t.sample());
+                        self.send(user, pause, deadline, options);
+                    }
+                    Some(None) => {}
+                    None => return,
+                },
+                _ = &mut drain_timeout => {
+                    abandon(self.in_flight.len());
+                    self.in_flight.clear();
+                    return;
+                }
             }
+        }
+    }
+
+    /// Sends the user's next request after `pause`, unless the test ended or the user ran its
+    /// iterations
+    fn send(
+        &mut self,
+        user: usize,
+        pause: Duration,
+        deadline: Option<Instant>,
+        options: &'a RequestOptions,
+    ) {
+        if self.draining
+            || self.stop.reason().is_some()
+            || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return;
+        }
+        if let Some(left) = &mut self.iterations_left {
+            if starts_over(user, options) {
+                if left[user] == 0 {
+                    return;
+                }
+                left[user] -= 1;
+            }
+        }
+        let stop = self.stop.clone();
+        let until = deadline.map_or(Instant::now() + pause, |deadline| {
+            deadline.min(Instant::now() + pause)
+        });
+        self.in_flight.push(Box::pin(async move {
+            if !pause.is_zero() {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(until.into()) => {}
+                    _ = stop.stopped() => return None,
+                }
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return None;
+                }
+            }
+            let send_delay = Instant::now().saturating_duration_since(until);
+            Some((
+                user,
+                make_request_with_retries(user, send_delay, options).await,
+            ))
+        }));
+    }
+}
+
+/// Whether the user's next request starts a new iteration, the last one having ended
+fn starts_over(user: usize, options: &RequestOptions) -> bool {
+    options
+        .scenario
+        .as_ref()
+        .is_none_or(|scenario| scenario.starts_over(user))
+}
+
+async fn process_batch(
+    count: usize,
+    options: &RequestOptions,
+    mut result: LoadResult,
+    stop: &StopSignal,
+    free_at: &mut [Option<Instant>],
+) -> LoadResult {
+    let now = Instant::now();
+    let mut in_flight: FuturesUnordered<_> = (0..count)
+        .map(|user| {
+            // A user means to send again as soon as its last request came back, not once the
+            // whole batch did
+            let send_delay = free_at[user].map_or(Duration::ZERO, |at| now - at);
+            make_request_with_retries(user, send_delay, options).map(move |attempt| (user, attempt))
+        })
+        .collect();
 
-            Ok(_) => result.number_of_failed_calls += 1,
-            Err(_) => result.number_of_failed_calls += 1,
+    let drain_timeout = stop.drain_timeout();
+    tokio::pin!(drain_timeout);
+    loop {
+        tokio::select! {
+            answered = in_flight.next() => match answered {
+                Some((user, attempt)) => {
+                    free_at[user] = Some(Instant::now());
+                    if let Some(attempt) = attempt {
+                        record_attempt(&mut result, attempt, options);
+                    }
+                }
+                None => break,
+            },
+            _ = &mut drain_timeout => {
+                abandon(in_flight.len());
+                break;
+            }
         }
     }
 
     result
 }
 
-#[derive(Debug)]
-struct CalculatedStats {
-    ttfb: (f32, f32, f32),
-    ttlb: (f32, f32, f32),
-    total_time: (f32, f32, f32),
+/// Gives up on requests still in flight after the test stopped, leaving them out of the report
+fn abandon(in_flight: usize) {
+    warn!(
+        in_flight,
+        "gave up waiting for requests in flight, leaving them out of the report"
+    );
+}
+
+/// Folds the outcome of one request into the aggregated result
+fn record_attempt(result: &mut LoadResult, attempt: Attempt, options: &RequestOptions) {
+    let failed_before = result.number_of_failed_calls;
+    if let Some(raw_out) = &options.raw_out {
+        if let Err(e) = raw_out.write(&attempt) {
+            warn!("failed to write raw results: {}", e);
+        }
+    }
+    result.number_of_retries += u64::from(attempt.retries);
+    if attempt.retries > 0 {
+        result.number_of_retried_requests += 1;
+    }
+    result.retry_delay += attempt.retry_delay;
+    result.connections.attempts += attempt.retries as u64 + 1;
+    result.connections.failed += attempt.connect_failures as u64;
+    if let Ok(resp) = &attempt.outcome {
+        *result.statuses.entry(resp.status.as_u16()).or_default() += 1;
+        result.bytes_received += resp.bytes;
+        result.bytes_decoded += resp.decoded_bytes.unwrap_or_default();
+        if attempt.connect_failures > 0 {
+            result.connections.recovered += 1;
+        }
+        let fell_back = options
+            .prefers_ipv6
+            .zip(resp.remote_addr)
+            .is_some_and(|(ipv6, addr)| addr.is_ipv6() != ipv6);
+        if fell_back {
+            result.connections.fallbacks += 1;
+        }
+        match resp.remote_addr.filter(|_| options.proxy_relay.is_none()) {
+            Some(SocketAddr::V4(_)) => result.connections.ipv4 += 1,
+            Some(SocketAddr::V6(_)) => result.connections.ipv6 += 1,
+            None => {}
+        }
+    }
+    // Connection failures can only be attributed when the request was pinned to an address
+    let addr = attempt.backend.or_else(|| {
+        attempt
+            .outcome
+            .as_ref()
+            .ok()
+            .and_then(|resp| resp.remote_addr)
+    });
+    if let Some(addr) = addr {
+        result
+            .backends
+            .entry(addr)
+            .or_default()
+            .record(&attempt.outcome);
+    }
+    if options.targets.len() > 1 {
+        result
+            .targets
+            .entry(options.targets.get(attempt.target).label())
+            .or_default()
+            .record(&attempt.outcome);
+    }
+    let finished_at = attempt
+        .outcome
+        .as_ref()
+        .map_or_else(|_| Instant::now(), |resp| resp.finished_at);
+    if let Some(stages) = &mut result.stages {
+        stages.record(finished_at, &attempt.outcome);
+    }
+    if let Some(search) = &mut result.search {
+        search.record(&attempt.outcome);
+    }
+    if let Some(spikes) = &mut result.spikes {
+        spikes.record(finished_at, &attempt.outcome);
+    }
+    match attempt.outcome {
+        Ok(resp) if !resp.pin_matched => {
+            result.number_of_failed_calls += 1;
+            result.number_of_pin_mismatches += 1;
+        }
+        Ok(resp) if is_overload_signal(resp.status) => {
+            result.number_of_failed_calls += 1;
+            result.number_of_overload_signals += 1;
+            result.retry_after = result.retry_after.max(resp.retry_after);
+        }
+        Ok(resp) if !resp.range_satisfied => {
+            result.number_of_failed_calls += 1;
+            result.number_of_range_violations += 1;
+        }
+        Ok(resp) if resp.decoded_bytes.is_none() => {
+            result.number_of_failed_calls += 1;
+            result.decode_failures += 1;
+        }
+        Ok(resp)
+            if resp.expected
+                && (resp.failed_assertion.is_some() || resp.script_failure.is_some()) =>
+        {
+            result.number_of_failed_calls += 1;
+            let assertion = match (resp.failed_assertion, &resp.script_failure) {
+                (Some(index), _) => options.assertions.get(index).to_string(),
+                (None, Some(failure)) => format!("script: {}", failure),
+                (None, None) => unreachable!(),
+            };
+            *result.assertion_failures.entry(assertion).or_default() += 1;
+        }
+        Ok(resp) if resp.failed_check.is_some() => result.number_of_failed_calls += 1,
+        Ok(resp) if resp.expected => {
+            result.number_of_successful_calls += 1;
+            if attempt.retries > 0 {
+                result.number_of_recovered_requests += 1;
+            }
+            result.latencies.record(&resp);
+            result.interval.record(resp.ttfb);
+            if let Some(trend) = &mut result.trend {
+                trend.record(resp.finished_at, resp.ttfb);
+            }
+            if let Some(corrected) = &mut result.corrected {
+                corrected.record(resp.ttfb, attempt.send_delay);
+            }
+            result.throughput.record(&resp);
+            result.timeline.record(resp.finished_at, resp.bytes);
+            result.bytes_successful += resp.bytes;
+            if let Some(samples) = &mut result.samples {
+                samples.push(&resp);
+            }
+            *result
+                .versions
+                .entry(format!("{:?}", resp.version))
+                .or_default() += 1;
+        }
+
+        Ok(_) => result.number_of_failed_calls += 1,
+        Err(e) => {
+            result.number_of_failed_calls += 1;
+            *result.errors.entry(errors::categorize(&e)).or_default() += 1;
+        }
+    }
+    if let Some(abort) = &mut result.abort {
+        abort.record(result.number_of_failed_calls > failed_before);
+    }
+}
+
+/// How often a test whose stages call for no load checks whether they still do, and the
+/// pacer whether the rate changed while it waits for the next arrival
+const RATE_POLL: Duration = Duration::from_millis(100);
+
+/// How far behind schedule the pacer may fall before it stops catching up
+const MAX_PACING_LAG: Duration = Duration::from_millis(100);
+
+type InFlight<'a> = FuturesUnordered<Pin<Box<dyn Future<Output = Option<Attempt>> + 'a>>>;
+
+/// Starts requests at a fixed arrival rate rather than in batches. At most `max_in_flight`
+/// are outstanding at once: in the open model an arrival finding them all taken is dropped,
+/// in the closed one it waits for a free slot, so slow responses hold back the next request.
+struct Pacer<'a> {
+    in_flight: InFlight<'a>,
+    /// Concurrent users whose clients and rows the requests take in turn
+    users: usize,
+    max_in_flight: usize,
+    open: bool,
+    /// When the last arrival was due, None to start the next one at once
+    last_at: Option<Instant>,
+    /// When the last arrival was meant to start on an unbroken schedule, which unlike `last_at`
+    /// doesn't skip ahead once the pacer falls far behind
+    intended_at: Option<Instant>,
+    started: usize,
+    /// Set once the in-flight cap held back a request, so the warning is only logged once
+    saturated: bool,
+    stop: StopSignal,
 }
 
-fn calculate_stats(result: &LoadResult) -> CalculatedStats {
-    let mut ttfb_min = f32::MAX;
-    let mut ttfb_max = f32::MIN;
-    let ttfb_mean = calculate_mean(&result.stats, |x| x.ttfb);
+impl<'a> Pacer<'a> {
+    fn new(users: usize, max_in_flight: usize, open: bool, stop: StopSignal) -> Self {
+        Pacer {
+            in_flight: FuturesUnordered::new(),
+            users,
+            max_in_flight,
+            open,
+            last_at: None,
+            intended_at: None,
+            started: 0,
+            saturated: false,
+            stop,
+        }
+    }
+
+    /// Starts `count` requests spaced `1 / rps()` seconds apart, recording the ones that
+    /// finish in the meantime, dropped arrivals counting towards `count`. Nothing new is
+    /// started once `deadline` passes or the test stops.
+    async fn start(
+        &mut self,
+        count: usize,
+        rps: &dyn Fn() -> f64,
+        deadline: Option<Instant>,
+        options: &'a RequestOptions,
+        result: &mut LoadResult,
+    ) {
+        for _ in 0..count {
+            let due = loop {
+                let due = self.next_at(rps());
+                if self.in_flight.len() >= self.max_in_flight && !self.open {
+                    if !self.saturated {
+                        warn!("rate can't be sustained with this concurrency, raise --concurrency");
+                        self.saturated = true;
+                    }
+                } else if Instant::now() >= due {
+                    break due;
+                }
+                if self.stop.reason().is_some()
+                    || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+                {
+                    return;
+                }
+                // Woken now and then as well, to follow a rate that changes
+                let wake_at = due.min(Instant::now() + RATE_POLL);
+                let wake_at = deadline.map_or(wake_at, |deadline| deadline.min(wake_at));
+                tokio::select! {
+                    _ = tokio::time::sleep_until(wake_at.into()),
+                        if self.open || self.in_flight.len() < self.max_in_flight => {}
+                    Some(attempt) = self.in_flight.next(), if !self.in_flight.is_empty() => {
+                        if let Some(attempt) = attempt {
+                            record_attempt(result, attempt, options);
+                        }
+                    }
+                    _ = self.stop.stopped() => {}
+                }
+            };
+
+            if self.stop.reason().is_some()
+                || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return;
+            }
+            let spacing = Duration::try_from_secs_f64(1.0 / rps()).ok();
+            let intended = match (self.intended_at, spacing) {
+                (Some(at), Some(spacing)) => at.checked_add(spacing).map_or(due, |at| at.min(due)),
+                _ => due,
+            };
+            self.intended_at = Some(intended);
+            if self.in_flight.len() >= self.max_in_flight {
+                // Only the open model gets here, its arrivals don't wait for the server
+                if !self.saturated {
+                    warn!("--max-in-flight requests are outstanding, dropping arrivals");
+                    self.saturated = true;
+                }
+                result.number_of_dropped_arrivals += 1;
+                if let Some(search) = &mut result.search {
+                    search.record_dropped();
+                }
+            } else {
+                let user = self.started % self.users;
+                let send_delay = Instant::now().saturating_duration_since(intended);
+                self.in_flight.push(Box::pin(make_request_with_retries(
+                    user, send_delay, options,
+                )));
+                self.started += 1;
+            }
+            self.last_at = Some(due);
+        }
+    }
+
+    /// When the next arrival is due at `rps` arrivals per second
+    fn next_at(&self, rps: f64) -> Instant {
+        let now = Instant::now();
+        let Some(last) = self.last_at else {
+            return now;
+        };
+        let spacing = Duration::try_from_secs_f64(1.0 / rps).ok();
+        match spacing.and_then(|spacing| last.checked_add(spacing)) {
+            // Catch up on small delays, but don't burst after falling far behind
+            Some(at) if at + MAX_PACING_LAG < now => now,
+            Some(at) => at,
+            // No rate to speak of for now
+            None => now + RATE_POLL,
+        }
+    }
 
-    for stat in &result.stats {
-        ttfb_min = ttfb_min.min(stat.ttfb);
-        ttfb_max = ttfb_max.max(stat.ttfb);
+    /// Waits for every outstanding request
+    async fn drain(&mut self, options: &RequestOptions, result: &mut LoadResult) {
+        let stop = self.stop.clone();
+        let drain_timeout = stop.drain_timeout();
+        tokio::pin!(drain_timeout);
+        loop {
+            tokio::select! {
+                attempt = self.in_flight.next() => match attempt {
+                    Some(Some(attempt)) => record_attempt(result, attempt, options),
+                    Some(None) => {}
+                    None => break,
+                },
+                _ = &mut drain_timeout => {
+                    abandon(self.in_flight.len());
+                    self.in_flight.clear();
+                    break;
+                }
+            }
+        }
+        self.last_at = None;
+        self.intended_at = None;
     }
+}
 
-    let mut ttlb_min = f32::MAX;
-    let mut ttlb_max = f32::MIN;
-    let ttlb_mean = calculate_mean(&result.stats, |x| x.ttlb);
+fn is_overload_signal(status: StatusCode) -> bool {
+    status == StatusCode::SERVICE_UNAVAILABLE || status == StatusCode::TOO_MANY_REQUESTS
+}
 
-    for stat in &result.stats {
-        ttlb_min = ttlb_min.min(stat.ttlb);
-        ttlb_max = ttlb_max.max(stat.ttlb);
+fn parse_rate(rate: &str) -> Result<f64, String> {
+    match rate.parse::<f64>() {
+        Ok(rps) if rps.is_finite() && rps > 0.0 => Ok(rps),
+        _ => Err(format!(
+            "invalid rate `{}`, expected a positive number",
+            rate
+        )),
     }
+}
 
-    let mut total_min = f32::MAX;
-    let mut total_max = f32::MIN;
-    let total_mean = calculate_mean(&result.stats, |x| x.total_time);
+fn parse_percent(percent: &str) -> Result<f64, String> {
+    percent
+        .trim()
+        .strip_suffix('%')
+        .unwrap_or(percent)
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|p| p.is_finite() && *p >= 0.0)
+        .ok_or_else(|| format!("invalid percentage `{}`, expected e.g. 20%", percent))
+}
 
-    for stat in &result.stats {
-        total_min = total_min.min(stat.total_time);
-        total_max = total_max.max(stat.total_time);
+/// A percentage of requests, more than 0% and at most 100%
+fn parse_error_rate(rate: &str) -> Result<f64, String> {
+    match parse_percent(rate)? {
+        p if p > 0.0 && p <= 100.0 => Ok(p),
+        _ => Err(format!(
+            "invalid error rate `{}`, expected above 0% up to 100%",
+            rate
+        )),
     }
+}
 
-    CalculatedStats {
-        ttfb: (
-            truncate_to_two_decimals(ttfb_min),
-            truncate_to_two_decimals(ttfb_max),
-            truncate_to_two_decimals(ttfb_mean.unwrap_or_default()),
-        ),
-        ttlb: (
-            truncate_to_two_decimals(ttlb_min),
-            truncate_to_two_decimals(ttlb_max),
-            truncate_to_two_decimals(ttlb_mean.unwrap_or_default()),
-        ),
-        total_time: (
-            truncate_to_two_decimals(total_min),
-            truncate_to_two_decimals(total_max),
-            truncate_to_two_decimals(total_mean.unwrap_or_default()),
-        ),
+/// How long to warm up for
+#[derive(Debug, Clone, Copy)]
+enum Warmup {
+    Requests(u64),
+    Duration(Duration),
+}
+
+/// Parses a request count (100) or a duration (5s)
+fn parse_warmup(warmup: &str) -> Result<Warmup, String> {
+    if let Ok(count) = warmup.parse::<u64>() {
+        return Ok(Warmup::Requests(count));
+    }
+    humantime::parse_duration(warmup)
+        .map(Warmup::Duration)
+        .map_err(|_| {
+            format!(
+                "invalid warm-up `{}`, expected a request count or a duration like 5s",
+                warmup
+            )
+        })
+}
+
+fn parse_percentile(percentile: &str) -> Result<f32, String> {
+    percentile
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|p| (0.0..=100.0).contains(p))
+        .ok_or_else(|| format!("invalid percentile `{}`, expected 0 to 100", percentile))
+}
+
+/// Parses a status code (404), a range (200-204) or a class (2xx)
+fn parse_expected_status(spec: &str) -> Result<RangeInclusive<u16>, String> {
+    let invalid = || {
+        format!(
+            "invalid status `{}`, expected e.g. 404, 200-204 or 2xx",
+            spec
+        )
+    };
+    let code = |s: &str| {
+        s.trim()
+            .parse::<u16>()
+            .ok()
+            .filter(|code| (100..=599).contains(code))
+            .ok_or_else(invalid)
+    };
+    let spec_lower = spec.trim().to_ascii_lowercase();
+    if let Some(class) = spec_lower.strip_suffix("xx") {
+        let class = code(&format!("{}00", class))?;
+        return Ok(class..=class + 99);
+    }
+    match spec_lower.split_once('-') {
+        Some((low, high)) => {
+            let (low, high) = (code(low)?, code(high)?);
+            if low > high {
+                return Err(invalid());
+            }
+            Ok(low..=high)
+        }
+        None => code(&spec_lower).map(|code| code..=code),
+    }
+}
+
+fn parse_method(method: &str) -> Result<Method, String> {
+    let method = method.to_ascii_uppercase();
+    match method.as_str() {
+        "GET" | "POST" | "PUT" | "PATCH" | "DELETE" | "HEAD" | "OPTIONS" => {
+            Ok(Method::from_bytes(method.as_bytes()).unwrap())
+        }
+        _ => Err(format!(
+            "unsupported method `{}`, expected GET, POST, PUT, PATCH, DELETE, HEAD or OPTIONS",
+            method
+        )),
+    }
+}
+
+/// Parses `Name: value`
+fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| format!("invalid header `{}`, expected \"Name: value\"", header))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| format!("invalid header name `{}`", name.trim()))?;
+    let value = HeaderValue::from_str(value.trim())
+        .map_err(|_| format!("invalid value for header `{}`", name))?;
+    Ok((name, value))
+}
+
+/// Encodes `user:password` as a Basic Authorization value, the password may be empty
+fn parse_basic_auth(credentials: &str) -> Result<HeaderValue, String> {
+    if !credentials.contains(':') {
+        return Err(format!(
+            "invalid credentials `{}`, expected user:password",
+            credentials
+        ));
+    }
+    let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+    let mut value = HeaderValue::from_str(&format!("Basic {}", encoded))
+        .map_err(|_| "invalid credentials".to_string())?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+fn parse_bearer_auth(token: &str) -> Result<HeaderValue, String> {
+    let mut value = HeaderValue::from_str(&format!("Bearer {}", token.trim()))
+        .map_err(|_| "invalid bearer token".to_string())?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+fn parse_user_agent(user_agent: &str) -> Result<HeaderValue, String> {
+    HeaderValue::from_str(user_agent).map_err(|_| format!("invalid user agent `{}`", user_agent))
+}
+
+/// Parses `host[:port]`
+fn parse_host(host: &str) -> Result<HeaderValue, String> {
+    let invalid = || format!("invalid host `{}`, expected e.g. api.example.com", host);
+    let url = Url::parse(&format!("http://{}/", host)).map_err(|_| invalid())?;
+    if url.path() != "/" || !url.username().is_empty() || url.query().is_some() {
+        return Err(invalid());
     }
+    HeaderValue::from_str(host).map_err(|_| invalid())
 }
 
-fn calculate_mean<F>(numbers: &[Stats], value_extractor: F) -> Option<f32>
-where
-    F: FnMut(&Stats) -> f32,
-{
-    let sum: f32 = numbers.iter().map(value_extractor).sum();
-    let count = numbers.len();
+/// Parses `random:<size>` where size accepts B, KB, MB and GB suffixes
+fn parse_range_spec(spec: &str) -> Result<u64, String> {
+    let size = spec
+        .strip_prefix("random:")
+        .ok_or_else(|| format!("unsupported range spec `{}`, expected random:<size>", spec))?;
+    let size = parse_size(size)?;
+    if size == 0 {
+        return Err("range size must be greater than zero".to_string());
+    }
+    Ok(size)
+}
+
+fn parse_speed(speed: &str) -> Result<f64, String> {
+    match speed.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        _ => Err(format!(
+            "invalid speed `{}`, expected a positive number",
+            speed
+        )),
+    }
+}
 
-    if count > 0 {
-        Some(sum / count as f32)
+fn parse_size(size: &str) -> Result<u64, String> {
+    let upper = size.trim().to_ascii_uppercase();
+    let (digits, multiplier) = if let Some(n) = upper.strip_suffix("GB") {
+        (n, 1024 * 1024 * 1024)
+    } else if let Some(n) = upper.strip_suffix("MB") {
+        (n, 1024 * 1024)
+    } else if let Some(n) = upper.strip_suffix("KB") {
+        (n, 1024)
+    } else if let Some(n) = upper.strip_suffix('B') {
+        (n, 1)
     } else {
-        None
+        (upper.as_str(), 1)
+    };
+    let n = digits
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| format!("invalid size `{}`", size))?;
+    n.checked_mul(multiplier)
+        .ok_or_else(|| format!("size `{}` too large", size))
+}
+
+/// Resolves the URL's host to the name and addresses a TLS handshake would go to
+async fn resolve_host(
+    url: &str,
+    resolver: &Resolver,
+) -> Result<(String, Vec<SocketAddr>), Box<dyn std::error::Error>> {
+    let url = Url::parse(url)?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| format!("{} has no host", url))?
+        .trim_matches(['[', ']'])
+        .to_string();
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| format!("{} has no port", url))?;
+    let addrs = resolver
+        .lookup(&host, port)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok((host, addrs))
+}
+
+async fn resolve_sni_override(
+    url: &str,
+    name: &str,
+    resolver: &Resolver,
+) -> Result<SniOverride, Box<dyn std::error::Error>> {
+    let host = Url::parse(url)?
+        .host_str()
+        .ok_or_else(|| format!("{} has no host", url))?
+        .to_string();
+    let (_, addrs) = resolve_host(url, resolver).await?;
+    // Fails early on a name no URL can take as its host
+    Url::parse(url)?.set_host(Some(name))?;
+
+    Ok(SniOverride {
+        host,
+        name: name.to_string(),
+        addrs,
+    })
+}
+
+/// Finds the pod addresses behind a Kubernetes Service through cluster DNS, which answers
+/// with one record per ready pod for headless Services. `service` is `<name>.<namespace>`
+/// or a fully qualified name; the port comes from the URL.
+async fn resolve_k8s_service(
+    url: &str,
+    service: &str,
+    spread: Spread,
+    resolver: &Resolver,
+) -> Result<Backends, Box<dyn std::error::Error>> {
+    let parsed = Url::parse(url)?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| format!("{} has no host", url))?
+        .to_string();
+    let port = parsed
+        .port_or_known_default()
+        .ok_or_else(|| format!("{} has no port", url))?;
+    let name = if service.contains(".svc") {
+        service.to_string()
+    } else {
+        format!("{}.svc.cluster.local", service)
+    };
+
+    let addrs = resolver.lookup(&name, port).await?;
+    let backends = Backends::new(host, addrs, spread);
+    if backends.addrs.is_empty() {
+        return Err(format!("{} has no endpoints", name).into());
+    }
+    if backends.addrs.len() == 1 {
+        warn!(
+            service = name,
+            "only one address found, the Service is probably not headless"
+        );
+    }
+    info!(
+        service = name,
+        pods = backends.addrs.len(),
+        "resolved Service endpoints"
+    );
+    Ok(backends)
+}
+
+/// Looks up the object size with a HEAD request so ranges can be picked inside it
+async fn fetch_object_size(
+    url: &str,
+    tls_config: &rustls::ClientConfig,
+    resolver: &Resolver,
+    proxy_relay: Option<&ProxyRelay>,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut builder =
+        resolver.apply(reqwest::Client::builder().use_preconfigured_tls(tls_config.clone()));
+    if let Some(relay) = proxy_relay {
+        builder = builder.proxy(relay.proxy()?);
+    }
+    let res = builder.build()?.head(url).send().await?;
+    if res
+        .headers()
+        .get(ACCEPT_RANGES)
+        .is_some_and(|v| v.as_bytes() == b"none")
+    {
+        return Err(format!("{} does not accept range requests", url).into());
     }
+    res.headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .ok_or_else(|| format!("{} did not report a Content-Length", url).into())
 }
 
-fn truncate_to_two_decimals(num: f32) -> f32 {
-    (num * 100.0).trunc() / 100.0
+/// Extracts the first byte position from a `Content-Range: bytes <start>-<end>/<size>` header
+fn content_range_start(res: &reqwest::Response) -> Option<u64> {
+    let value = res.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = value.strip_prefix("bytes ")?;
+    let (start, _) = range.split_once('-')?;
+    start.parse().ok()
 }
 
-fn calculate_percentiles(latencies: &Vec<f32>, percentile: f32) -> f32 {
-    let len = latencies.len();
-    if len == 0 {
-        return 0.0;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_speeds_and_rates() {
+        assert_eq!(parse_speed("0.5").unwrap(), 0.5);
+        assert_eq!(parse_speed("1e1").unwrap(), 10.0);
+        assert_eq!(parse_rate("1e3").unwrap(), 1000.0);
+        for value in ["", "0", "-1", "inf", "NaN", "fast"] {
+            assert!(parse_speed(value).is_err(), "{}", value);
+            assert!(parse_rate(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn retries_within_the_budget_of_requests_sent() {
+        let retry = RetryPolicy::new(3, Duration::ZERO, 0.1);
+        retry.record_sent();
+        assert!(retry.take_budget());
+        assert!(!retry.take_budget());
+        for _ in 0..19 {
+            retry.record_sent();
+        }
+        assert!(retry.take_budget());
+        assert!(!retry.take_budget());
+    }
+
+    #[test]
+    fn parses_error_rates() {
+        assert_eq!(parse_error_rate("10%").unwrap(), 10.0);
+        assert_eq!(parse_error_rate(" 100 ").unwrap(), 100.0);
+        assert_eq!(parse_error_rate("0.5%").unwrap(), 0.5);
+        for rate in ["", "0%", "0", "100.1%", "250%", "-5%", "high"] {
+            assert!(parse_error_rate(rate).is_err(), "{}", rate);
+        }
+    }
+
+    #[test]
+    fn sends_every_scenario_step_to_the_sni_name() {
+        let sni = SniOverride {
+            host: "10.0.0.5".to_string(),
+            name: "api.example.com".to_string(),
+            addrs: vec![],
+        };
+        let steps = [
+            "https://10.0.0.5:8443/login",
+            "https://10.0.0.5:8443/orders?page=2",
+        ];
+        let sent: Vec<(String, String)> = steps
+            .iter()
+            .map(|step| {
+                let (url, host) = sni.apply(step).unwrap();
+                (url.to_string(), host)
+            })
+            .collect();
+        assert_eq!(
+            sent,
+            [
+                (
+                    "https://api.example.com:8443/login".to_string(),
+                    "10.0.0.5:8443".to_string()
+                ),
+                (
+                    "https://api.example.com:8443/orders?page=2".to_string(),
+                    "10.0.0.5:8443".to_string()
+                ),
+            ]
+        );
+        assert!(sni.apply("https://other.example.com/").is_none());
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size(" 2kb ").unwrap(), 2048);
+        assert_eq!(parse_size("3GB").unwrap(), 3 << 30);
+        assert_eq!(
+            parse_range_spec("random:99999999999999GB").unwrap_err(),
+            "size `99999999999999GB` too large"
+        );
+        for size in ["", "MB", "-1KB", "1TB"] {
+            assert!(parse_size(size).is_err(), "{}", size);
+        }
+    }
+
+    #[test]
+    fn parses_expected_statuses() {
+        assert_eq!(parse_expected_status("404").unwrap(), 404..=404);
+        assert_eq!(parse_expected_status(" 200 - 204 ").unwrap(), 200..=204);
+        assert_eq!(parse_expected_status("2XX").unwrap(), 200..=299);
+        assert_eq!(parse_expected_status("5xx").unwrap(), 500..=599);
+        for spec in [
+            "", "99", "600", "6xx", "xx", "0xx", "204-200", "200-", "-200", "65536", "2xx-3xx",
+            "２00",
+        ] {
+            assert!(parse_expected_status(spec).is_err(), "{}", spec);
+        }
     }
-    let index = (percentile / 100.0 * (len as f32 - 1.0)).round() as usize;
-    *latencies.get(index).unwrap_or(&0.0)
 }
@@ -0,0 +1,187 @@
+use std::{
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{header::CONTENT_TYPE, Url};
+use serde_json::{json, Value};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::{debug, warn};
+
+/// Spans posted to the collector at once
+const BATCH_SIZE: usize = 512;
+
+/// One attempt of a request, as the client saw it
+pub struct Span {
+    pub id: u64,
+    pub attempt: u8,
+    pub method: String,
+    pub url: String,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// W3C trace context of the test's requests. Every request gets its own trace, shared by its
+/// retries, and every attempt its own span, so they can be found in server-side traces.
+pub struct Otel {
+    /// Random high half of every trace id, the request number is the low half
+    run: u64,
+    /// Spans rendered as OTLP JSON, followed by None when the test is over
+    exporter: Option<mpsc::UnboundedSender<Option<Value>>>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Otel {
+    /// Exports spans to the endpoint over OTLP/HTTP with JSON encoding when given
+    pub fn new(exporter: Option<(Url, reqwest::Client)>) -> Self {
+        let (exporter, task) = match exporter {
+            Some((endpoint, client)) => {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                let task = tokio::spawn(export(endpoint, client, receiver));
+                (Some(sender), Some(task))
+            }
+            None => (None, None),
+        };
+        Otel {
+            run: rand::random::<u64>() | 1,
+            exporter,
+            task: Mutex::new(task),
+        }
+    }
+
+    /// Trace id of the request numbered `id`. The low half looks random, as samplers that
+    /// keep a share of traces by their id expect.
+    pub fn trace_id(&self, id: u64) -> String {
+        format!(
+            "{:016x}{:016x}",
+            self.run,
+            mix(self.run.rotate_left(32) ^ id)
+        )
+    }
+
+    /// Span id of an attempt, derived from the run so it doesn't need to be passed around
+    fn span_id(&self, id: u64, attempt: u8) -> String {
+        format!("{:016x}", mix(self.run ^ (id << 8 | attempt as u64)).max(1))
+    }
+
+    /// traceparent header of an attempt, sampled so servers record it
+    pub fn traceparent(&self, id: u64, attempt: u8) -> String {
+        format!("00-{}-{}-01", self.trace_id(id), self.span_id(id, attempt))
+    }
+
+    pub fn record(&self, span: Span) {
+        if let Some(exporter) = &self.exporter {
+            let _ = exporter.send(Some(self.to_json(&span)));
+        }
+    }
+
+    /// Sends the spans still queued and waits for the exporter to finish
+    pub async fn finish(&self) {
+        if let Some(exporter) = &self.exporter {
+            let _ = exporter.send(None);
+        }
+        let task = self.task.lock().unwrap().take();
+        if let Some(task) = task {
+            let _ = task.await;
+        }
+    }
+
+    fn to_json(&self, span: &Span) -> Value {
+        let nanos = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+                .to_string()
+        };
+        let mut attributes = vec![
+            json!({ "key": "http.request.method", "value": { "stringValue": span.method } }),
+            json!({ "key": "url.full", "value": { "stringValue": span.url } }),
+            json!({ "key": "http.request.resend_count", "value": { "intValue": span.attempt.to_string() } }),
+        ];
+        if let Some(status) = span.status {
+            attributes.push(json!({
+                "key": "http.response.status_code",
+                "value": { "intValue": status.to_string() }
+            }));
+        }
+        // Error status for failed requests and 5XX responses, like HTTP client spans do
+        let status = match (&span.error, span.status) {
+            (Some(error), _) => json!({ "code": 2, "message": error }),
+            (None, Some(status)) if status >= 500 => json!({ "code": 2 }),
+            _ => json!({}),
+        };
+        json!({
+            "traceId": self.trace_id(span.id),
+            "spanId": self.span_id(span.id, span.attempt),
+            "name": span.method,
+            // SPAN_KIND_CLIENT
+            "kind": 3,
+            "startTimeUnixNano": nanos(span.start),
+            "endTimeUnixNano": nanos(span.end),
+            "attributes": attributes,
+            "status": status,
+        })
+    }
+}
+
+/// splitmix64 finalizer, a bijection spreading consecutive numbers across the whole range
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+async fn export(
+    endpoint: Url,
+    client: reqwest::Client,
+    mut spans: mpsc::UnboundedReceiver<Option<Value>>,
+) {
+    let mut batch = vec![];
+    let mut finished = false;
+    while !finished {
+        match spans.recv().await {
+            Some(Some(span)) => batch.push(span),
+            Some(None) | None => finished = true,
+        }
+        while batch.len() < BATCH_SIZE {
+            match spans.try_recv() {
+                Ok(Some(span)) => batch.push(span),
+                Ok(None) => {
+                    finished = true;
+                    break;
+                }
+                Err(_) => break,
+            }
+        }
+        if batch.is_empty() {
+            continue;
+        }
+        let body = json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [{
+                        "key": "service.name",
+                        "value": { "stringValue": "rust-load-tester" }
+                    }]
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "rust-load-tester", "version": env!("CARGO_PKG_VERSION") },
+                    "spans": std::mem::take(&mut batch),
+                }]
+            }]
+        });
+        let sent = client
+            .post(endpoint.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .and_then(|res| res.error_for_status());
+        match sent {
+            Ok(_) => debug!("exported spans"),
+            Err(e) => warn!("failed to export spans: {}", e),
+        }
+    }
+}
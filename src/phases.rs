@@ -0,0 +1,259 @@
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, warn};
+
+use crate::{latency::LatencyHistogram, resolve::Resolver};
+
+/// TLS record type of encrypted application data
+const APPLICATION_DATA: u8 = 0x17;
+/// TLS record type of handshake messages
+const HANDSHAKE: u8 = 0x16;
+
+/// Setup timings of the connections opened during the test
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionPhases {
+    pub connections: u64,
+    /// Only connections to a host name, not an IP address, look it up
+    pub dns: LatencyHistogram,
+    pub connect: LatencyHistogram,
+    pub tls: LatencyHistogram,
+}
+
+pub type SharedPhases = Arc<Mutex<ConnectionPhases>>;
+
+/// Listens on a local port and acts as an HTTP proxy that does the DNS lookup and TCP connect
+/// of every connection itself, timing both, then times the TLS handshake going through it.
+/// CONNECT tunnels and absolute-form plain HTTP requests go where they ask, raw TLS goes to
+/// `default_upstream`. Hosts with a --resolve override skip the lookup.
+pub async fn spawn_proxy(
+    default_upstream: (String, u16),
+    resolver: Resolver,
+    phases: SharedPhases,
+) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let default_upstream = Arc::new(default_upstream);
+    tokio::spawn(async move {
+        loop {
+            let (inbound, _) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("connection timing proxy stopped accepting: {}", e);
+                    return;
+                }
+            };
+            let phases = phases.clone();
+            let default_upstream = default_upstream.clone();
+            let resolver = resolver.clone();
+            tokio::spawn(async move {
+                if let Err(e) = relay(inbound, &default_upstream, &resolver, &phases).await {
+                    debug!("timed connection ended: {}", e);
+                }
+            });
+        }
+    });
+    Ok(addr)
+}
+
+async fn relay(
+    mut inbound: TcpStream,
+    default_upstream: &(String, u16),
+    resolver: &Resolver,
+    phases: &Mutex<ConnectionPhases>,
+) -> io::Result<()> {
+    let opening = read_opening(&mut inbound).await?;
+    let (host, port, early_data, tls) = match opening {
+        Opening::Connect { host, port, rest } => {
+            inbound
+                .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+                .await?;
+            (host, port, rest, true)
+        }
+        Opening::Http { host, port, head } => (host, port, head, false),
+        Opening::Tls(hello) => (default_upstream.0.clone(), default_upstream.1, hello, true),
+    };
+
+    let lookup_start = Instant::now();
+    let (addrs, is_name) = match resolver.overridden(&host, port) {
+        Some(addrs) => (addrs, false),
+        None => (
+            resolver.lookup(&host, port).await?,
+            host.parse::<std::net::IpAddr>().is_err(),
+        ),
+    };
+    let dns = lookup_start.elapsed();
+
+    let connect_start = Instant::now();
+    let mut last_error =
+        io::Error::new(io::ErrorKind::NotFound, format!("{} did not resolve", host));
+    let mut outbound = None;
+    for addr in addrs {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => {
+                outbound = Some(stream);
+                break;
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    let outbound = outbound.ok_or(last_error)?;
+    let connect = connect_start.elapsed();
+    {
+        let mut phases = phases.lock().unwrap();
+        phases.connections += 1;
+        if is_name {
+            phases.dns.record(dns.as_secs_f32());
+        }
+        phases.connect.record(connect.as_secs_f32());
+    }
+
+    let (mut from_client, mut to_client) = inbound.into_split();
+    let (mut from_server, mut to_server) = outbound.into_split();
+    let downstream =
+        tokio::spawn(async move { tokio::io::copy(&mut from_server, &mut to_client).await });
+
+    let handshake_start = Instant::now();
+    let mut scanner = tls.then(RecordScanner::default);
+    if let Some(scanner) = &mut scanner {
+        scanner.feed(&early_data);
+    }
+    to_server.write_all(&early_data).await?;
+    let mut chunk = [0; 16 * 1024];
+    loop {
+        let read = from_client.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        // The client's first encrypted record follows its Finished message, so the handshake
+        // is done as far as the client is concerned
+        if scanner
+            .as_mut()
+            .is_some_and(|scanner| scanner.feed(&chunk[..read]))
+        {
+            phases
+                .lock()
+                .unwrap()
+                .tls
+                .record(handshake_start.elapsed().as_secs_f32());
+            scanner = None;
+        }
+        to_server.write_all(&chunk[..read]).await?;
+    }
+    to_server.shutdown().await?;
+    downstream.await??;
+    Ok(())
+}
+
+enum Opening {
+    Connect {
+        host: String,
+        port: u16,
+        rest: Vec<u8>,
+    },
+    /// A plain HTTP request in absolute form, forwarded whole
+    Http {
+        host: String,
+        port: u16,
+        head: Vec<u8>,
+    },
+    Tls(Vec<u8>),
+}
+
+/// Reads until the request head is complete, or the first byte shows a TLS handshake
+async fn read_opening(inbound: &mut TcpStream) -> io::Result<Opening> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let read = inbound.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if buffer[0] == HANDSHAKE {
+            return Ok(Opening::Tls(buffer));
+        }
+        let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") else {
+            continue;
+        };
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "unexpected proxy request");
+        let line = std::str::from_utf8(&buffer[..end])
+            .ok()
+            .and_then(|head| head.lines().next())
+            .ok_or_else(invalid)?;
+        let mut parts = line.split(' ');
+        let (method, target) = (
+            parts.next().ok_or_else(invalid)?,
+            parts.next().ok_or_else(invalid)?,
+        );
+        return if method == "CONNECT" {
+            let (host, port) = split_authority(target, 443).ok_or_else(invalid)?;
+            Ok(Opening::Connect {
+                host,
+                port,
+                rest: buffer.split_off(end + 4),
+            })
+        } else {
+            let authority = target
+                .strip_prefix("http://")
+                .and_then(|rest| rest.split('/').next())
+                .ok_or_else(invalid)?;
+            let (host, port) = split_authority(authority, 80).ok_or_else(invalid)?;
+            Ok(Opening::Http {
+                host,
+                port,
+                head: buffer,
+            })
+        };
+    }
+}
+
+/// Splits `host[:port]`, with IPv6 addresses in brackets
+fn split_authority(authority: &str, default_port: u16) -> Option<(String, u16)> {
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (host, port.parse().ok()?),
+        _ => (authority, default_port),
+    };
+    Some((host.trim_matches(['[', ']']).to_string(), port))
+}
+
+/// Walks TLS record headers across reads to spot the first application data record
+#[derive(Default)]
+struct RecordScanner {
+    header: Vec<u8>,
+    /// Bytes left of the record being skipped
+    remaining: usize,
+}
+
+impl RecordScanner {
+    fn feed(&mut self, mut data: &[u8]) -> bool {
+        while !data.is_empty() {
+            if self.remaining > 0 {
+                let skipped = self.remaining.min(data.len());
+                self.remaining -= skipped;
+                data = &data[skipped..];
+                continue;
+            }
+            let needed = 5 - self.header.len();
+            let taken = needed.min(data.len());
+            self.header.extend_from_slice(&data[..taken]);
+            data = &data[taken..];
+            if self.header.len() == 5 {
+                if self.header[0] == APPLICATION_DATA {
+                    return true;
+                }
+                self.remaining = u16::from_be_bytes([self.header[3], self.header[4]]) as usize;
+                self.header.clear();
+            }
+        }
+        false
+    }
+}
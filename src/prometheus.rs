@@ -0,0 +1,208 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+};
+use tracing::{debug, info};
+
+use crate::events::Event;
+
+/// Upper bounds of the latency histogram buckets in seconds, Prometheus' defaults
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Cumulative histogram in the shape Prometheus expects
+#[derive(Default)]
+struct Histogram {
+    /// Observations at or below each of BUCKETS
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, secs: f32) {
+        let secs = secs as f64;
+        for (bound, count) in BUCKETS.iter().zip(&mut self.buckets) {
+            if secs <= *bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (bound, count) in BUCKETS.iter().zip(&self.buckets) {
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum);
+        let _ = writeln!(out, "{}_count {}", name, self.count);
+    }
+}
+
+/// Live counters folded from the engine's events
+#[derive(Default)]
+struct Metrics {
+    started: u64,
+    responses: BTreeMap<u16, u64>,
+    errors: u64,
+    bytes: u64,
+    ttfb: Histogram,
+    ttlb: Histogram,
+}
+
+impl Metrics {
+    fn render(&self, elapsed: f64) -> String {
+        let mut out = String::new();
+        let mut single = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        };
+        single(
+            "load_tester_elapsed_seconds",
+            "gauge",
+            "Seconds since the test started",
+            elapsed.to_string(),
+        );
+        single(
+            "load_tester_requests_started_total",
+            "counter",
+            "Requests sent, retries included",
+            self.started.to_string(),
+        );
+        single(
+            "load_tester_errors_total",
+            "counter",
+            "Requests that got no complete response",
+            self.errors.to_string(),
+        );
+        single(
+            "load_tester_received_bytes_total",
+            "counter",
+            "Body bytes received",
+            self.bytes.to_string(),
+        );
+        let _ = writeln!(
+            out,
+            "# HELP load_tester_responses_total Responses received, by status code"
+        );
+        let _ = writeln!(out, "# TYPE load_tester_responses_total counter");
+        for (status, count) in &self.responses {
+            let _ = writeln!(
+                out,
+                "load_tester_responses_total{{status=\"{}\"}} {}",
+                status, count
+            );
+        }
+        self.ttfb.render(
+            &mut out,
+            "load_tester_ttfb_seconds",
+            "Time to first byte of every response",
+        );
+        self.ttlb.render(
+            &mut out,
+            "load_tester_ttlb_seconds",
+            "Time to last byte of every response",
+        );
+        out
+    }
+}
+
+/// Serves the test's live metrics in the Prometheus text format at `GET /metrics` on `addr`
+/// for the rest of the process, so they can be scraped alongside the target's own
+pub async fn serve(
+    addr: SocketAddr,
+    test_start: Instant,
+    mut events: broadcast::Receiver<Event>,
+) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+    info!(addr = %local_addr, "Prometheus endpoint listening");
+
+    let metrics = Arc::new(Mutex::new(Metrics::default()));
+    let folded = metrics.clone();
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            let mut metrics = folded.lock().unwrap();
+            match event {
+                Event::RequestScheduled { .. } => metrics.started += 1,
+                Event::Headers { status, ttfb, .. } => {
+                    *metrics.responses.entry(status.as_u16()).or_default() += 1;
+                    metrics.ttfb.observe(ttfb);
+                }
+                Event::BodyDone { bytes, ttlb, .. } => {
+                    metrics.bytes += bytes;
+                    metrics.ttlb.observe(ttlb);
+                }
+                Event::Error { .. } => metrics.errors += 1,
+                Event::Connect { .. } | Event::IntervalSummary { .. } | Event::Finished { .. } => {}
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let metrics = metrics.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream, test_start, &metrics).await {
+                    debug!("Prometheus connection failed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(local_addr)
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    test_start: Instant,
+    metrics: &Mutex<Metrics>,
+) -> std::io::Result<()> {
+    let mut buffer = [0; 1024];
+    let read = stream.read(&mut buffer).await?;
+    let request = String::from_utf8_lossy(&buffer[..read]);
+    let mut parts = request.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let path = target.split_once('?').map_or(target, |(path, _)| path);
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            metrics
+                .lock()
+                .unwrap()
+                .render(test_start.elapsed().as_secs_f64()),
+        ),
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -0,0 +1,135 @@
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, warn};
+
+/// Version of the PROXY protocol header sent ahead of every connection
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum ProxyVersion {
+    /// Human-readable text header
+    V1,
+    /// Binary header
+    V2,
+}
+
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Builds the header announcing a connection from `source` to `destination`. Both must be
+/// of the same address family.
+pub fn header(version: ProxyVersion, source: SocketAddr, destination: SocketAddr) -> Vec<u8> {
+    match version {
+        ProxyVersion::V1 => format!(
+            "PROXY {} {} {} {} {}\r\n",
+            if source.is_ipv4() { "TCP4" } else { "TCP6" },
+            source.ip(),
+            destination.ip(),
+            source.port(),
+            destination.port()
+        )
+        .into_bytes(),
+        ProxyVersion::V2 => {
+            let mut header = V2_SIGNATURE.to_vec();
+            // Version 2, PROXY command
+            header.push(0x21);
+            let addrs = match (source.ip(), destination.ip()) {
+                (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                    // TCP over IPv4
+                    header.push(0x11);
+                    [src.octets().to_vec(), dst.octets().to_vec()].concat()
+                }
+                (src, dst) => {
+                    // TCP over IPv6
+                    header.push(0x21);
+                    [to_ipv6(src).octets(), to_ipv6(dst).octets()].concat()
+                }
+            };
+            header.extend(((addrs.len() + 4) as u16).to_be_bytes());
+            header.extend(addrs);
+            header.extend(source.port().to_be_bytes());
+            header.extend(destination.port().to_be_bytes());
+            header
+        }
+    }
+}
+
+fn to_ipv6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+/// Listens on a local port and relays every connection to `upstream`, prefixed with a PROXY
+/// header claiming to come from `client_ip` (or the relay's own address). The relay acts as
+/// an HTTP proxy: CONNECT tunnels are answered locally, anything else (absolute-form plain
+/// HTTP requests, raw TLS) is forwarded as is.
+pub async fn spawn_relay(
+    upstream: SocketAddr,
+    version: ProxyVersion,
+    client_ip: Option<IpAddr>,
+) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        loop {
+            let (mut inbound, _) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("PROXY protocol relay stopped accepting: {}", e);
+                    return;
+                }
+            };
+            tokio::spawn(async move {
+                let result = async {
+                    let early_data = accept_tunnel(&mut inbound).await?;
+                    let mut outbound = TcpStream::connect(upstream).await?;
+                    let local = outbound.local_addr()?;
+                    let source = SocketAddr::new(client_ip.unwrap_or(local.ip()), local.port());
+                    outbound
+                        .write_all(&header(version, source, upstream))
+                        .await?;
+                    outbound.write_all(&early_data).await?;
+                    tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await
+                }
+                .await;
+                if let Err(e) = result {
+                    debug!("PROXY protocol relay connection ended: {}", e);
+                }
+            });
+        }
+    });
+    Ok(addr)
+}
+
+/// Answers a CONNECT request if the client opened with one, returning the bytes read past it
+/// that belong to the upstream
+pub async fn accept_tunnel(inbound: &mut TcpStream) -> io::Result<Vec<u8>> {
+    const CONNECT: &[u8] = b"CONNECT ";
+
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let read = inbound.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(buffer);
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+
+        let prefix = buffer.len().min(CONNECT.len());
+        if buffer[..prefix] != CONNECT[..prefix] {
+            return Ok(buffer);
+        }
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            inbound
+                .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+                .await?;
+            return Ok(buffer.split_off(end + 4));
+        }
+    }
+}
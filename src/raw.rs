@@ -0,0 +1,94 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::Mutex,
+    time::{Instant, SystemTime},
+};
+
+use crate::{errors, Attempt, Stats};
+
+/// CSV file with one row per request, for analysis outside the tool
+pub struct RawWriter {
+    file: Mutex<BufWriter<File>>,
+    /// Ties the monotonic clock to wall-clock time for the timestamps
+    anchor: (Instant, SystemTime),
+}
+
+impl RawWriter {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(
+            file,
+            "timestamp,status,ttfb,ttlb,total,retries,error,version,request_id,trace_id"
+        )?;
+        Ok(RawWriter {
+            file: Mutex::new(file),
+            anchor: (Instant::now(), SystemTime::now()),
+        })
+    }
+
+    pub fn write(&self, attempt: &Attempt) -> io::Result<()> {
+        let finished_at = match &attempt.outcome {
+            Ok(stats) => stats.finished_at,
+            Err(_) => Instant::now(),
+        };
+        let (anchor_instant, anchor_time) = self.anchor;
+        let timestamp = match finished_at.checked_duration_since(anchor_instant) {
+            Some(after) => anchor_time + after,
+            None => anchor_time - anchor_instant.duration_since(finished_at),
+        };
+
+        let request_id = attempt.request_id.as_deref().unwrap_or_default();
+        let trace_id = attempt.trace_id.as_deref().unwrap_or_default();
+        let mut file = self.file.lock().unwrap();
+        match &attempt.outcome {
+            Ok(stats) => writeln!(
+                file,
+                "{},{},{},{},{},{},{},{:?},{},{}",
+                humantime::format_rfc3339_millis(timestamp),
+                stats.status.as_u16(),
+                stats.ttfb,
+                stats.ttlb,
+                stats.total_time,
+                attempt.retries,
+                response_category(stats),
+                stats.version,
+                request_id,
+                trace_id
+            ),
+            Err(e) => writeln!(
+                file,
+                "{},,,,,{},{},,{},{}",
+                humantime::format_rfc3339_millis(timestamp),
+                attempt.retries,
+                errors::categorize(e).name(),
+                request_id,
+                trace_id
+            ),
+        }
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        self.file.lock().unwrap().flush()
+    }
+}
+
+/// Why a response counts as failed, empty for successful ones
+fn response_category(stats: &Stats) -> &'static str {
+    if !stats.pin_matched {
+        "pin_mismatch"
+    } else if !stats.range_satisfied {
+        "range_violation"
+    } else if stats.decoded_bytes.is_none() {
+        "decode_error"
+    } else if stats.expected {
+        ""
+    } else if stats.status.is_client_error() {
+        "http_4xx"
+    } else if stats.status.is_server_error() {
+        "http_5xx"
+    } else {
+        "http_other"
+    }
+}
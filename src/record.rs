@@ -0,0 +1,476 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    error::Error,
+    fs,
+    io::Write,
+    net::{IpAddr, SocketAddr},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::Engine;
+use hyper::{
+    header::{HeaderName, HeaderValue},
+    server::conn::Http,
+    service::service_fn,
+    Body, Method, Request, Response, StatusCode,
+};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose, SanType,
+};
+use reqwest::Url;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tracing::{info, warn};
+
+use crate::{convert, har};
+
+/// Where the recording proxy listens, what it records and where it writes the scenario
+pub struct Recorder {
+    pub listen: SocketAddr,
+    pub output: PathBuf,
+    /// CA certificate the browser or app trusts for HTTPS, created with its key if missing
+    pub ca_cert: PathBuf,
+    pub ca_key: PathBuf,
+    /// Origin whose requests are recorded, the first request's when missing
+    pub origin: Option<String>,
+    /// Used to forward the requests to the servers, accepting any certificate with --insecure
+    pub tls_config: rustls::ClientConfig,
+    /// Write the recorded Authorization and Cookie values instead of placeholders
+    pub keep_credentials: bool,
+}
+
+/// Name of the CA, which signs a certificate for every host browsed over HTTPS
+const CA_NAME: &str = "load-tester recording CA";
+
+/// How long the CA created on the first recording is valid for
+const CA_VALIDITY: Duration = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+/// How long the certificate of a host is valid for, within what browsers accept
+const HOST_VALIDITY: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Headers of the connection to the proxy, not forwarded or recorded
+const HOP_HEADERS: [&str; 8] = [
+    "connection",
+    "proxy-connection",
+    "proxy-authorization",
+    "keep-alive",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+struct Proxy {
+    ca: Certificate,
+    /// The CA certificate as the browser trusts it, sent after each host's certificate
+    ca_der: Vec<u8>,
+    /// TLS configs of the hosts browsed so far, each with a certificate the CA signed
+    hosts: Mutex<HashMap<String, Arc<rustls::ServerConfig>>>,
+    client: reqwest::Client,
+    origin: Mutex<Option<String>>,
+    recorded: Mutex<Vec<convert::Request>>,
+    /// Requests to other origins, forwarded but not recorded
+    skipped: AtomicUsize,
+}
+
+/// Runs an HTTP proxy that forwards what a browser or app sends through it, decrypting HTTPS
+/// with certificates signed by the recording CA, until Ctrl-C. Then writes the requests to
+/// the recorded origin as the steps of a scenario.
+pub async fn run(recorder: Recorder) -> Result<(), Box<dyn Error>> {
+    let (ca, ca_der) = load_ca(&recorder.ca_cert, &recorder.ca_key)?;
+    let client = reqwest::Client::builder()
+        .use_preconfigured_tls(recorder.tls_config)
+        .redirect(reqwest::redirect::Policy::none())
+        .no_proxy()
+        .build()?;
+    let origin = recorder
+        .origin
+        .map(|origin| Url::parse(&origin).map(|url| url.origin().ascii_serialization()))
+        .transpose()?;
+    let proxy = Arc::new(Proxy {
+        ca,
+        ca_der,
+        hosts: Mutex::new(HashMap::new()),
+        client,
+        origin: Mutex::new(origin),
+        recorded: Mutex::new(vec![]),
+        skipped: AtomicUsize::new(0),
+    });
+    let listener = TcpListener::bind(recorder.listen)
+        .await
+        .map_err(|e| format!("failed to listen on {}: {}", recorder.listen, e))?;
+    eprintln!(
+        "Recording through the proxy at http://{}. For HTTPS, trust {} in the browser or app.",
+        recorder.listen,
+        recorder.ca_cert.display()
+    );
+    eprintln!(
+        "Press Ctrl-C to stop and write {}",
+        recorder.output.display()
+    );
+
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        warn!("failed to accept a connection: {}", e);
+                        continue;
+                    }
+                };
+                let proxy = proxy.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |request| {
+                        let proxy = proxy.clone();
+                        async move { Ok::<_, Infallible>(proxy.handle(request).await) }
+                    });
+                    // Clients drop connections to the proxy as they please
+                    let _ = Http::new()
+                        .http1_only(true)
+                        .serve_connection(stream, service)
+                        .with_upgrades()
+                        .await;
+                });
+            }
+            _ = &mut interrupted => break,
+        }
+    }
+
+    let skipped = proxy.skipped.load(Ordering::Relaxed);
+    if skipped > 0 {
+        warn!(
+            "skipping {} requests to other origins than {}",
+            skipped,
+            proxy.origin.lock().unwrap().as_deref().unwrap_or_default()
+        );
+    }
+    let requests = std::mem::take(&mut *proxy.recorded.lock().unwrap());
+    if requests.is_empty() {
+        return Err("nothing was recorded".into());
+    }
+    convert::write(requests, Some(&recorder.output), recorder.keep_credentials)
+}
+
+impl Proxy {
+    /// Answers a request to the proxy, opening a tunnel to decrypt for a CONNECT
+    async fn handle(self: Arc<Self>, request: Request<Body>) -> Response<Body> {
+        if request.method() == Method::CONNECT {
+            let Some(authority) = request
+                .uri()
+                .authority()
+                .map(|authority| authority.to_string())
+            else {
+                return error(StatusCode::BAD_REQUEST, "CONNECT without a host");
+            };
+            tokio::spawn(async move {
+                if let Ok(upgraded) = hyper::upgrade::on(request).await {
+                    self.intercept(upgraded, &authority).await;
+                }
+            });
+            return Response::new(Body::empty());
+        }
+        if request.uri().scheme().is_none() {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "this is a recording proxy, send requests to it as a proxy",
+            );
+        }
+        let url = request.uri().to_string();
+        self.answer(request, &url).await
+    }
+
+    /// Forwards a request to `url`, answering with the server's response or why there is none
+    async fn answer(&self, request: Request<Body>, url: &str) -> Response<Body> {
+        match self.forward(request, url).await {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("failed to forward {}: {}", url, e);
+                error(StatusCode::BAD_GATEWAY, &e.to_string())
+            }
+        }
+    }
+
+    /// Decrypts the tunnel a CONNECT opened, presenting a certificate for its host, and
+    /// answers the requests inside
+    async fn intercept(self: Arc<Self>, io: hyper::upgrade::Upgraded, authority: &str) {
+        let host = authority
+            .rsplit_once(':')
+            .map_or(authority, |(host, _)| host)
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let config = match self.host_config(host) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("failed to make a certificate for {}: {}", host, e);
+                return;
+            }
+        };
+        let stream = match TlsAcceptor::from(config).accept(io).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!(
+                    "TLS handshake for {} failed, does the client trust the recording CA? {}",
+                    host, e
+                );
+                return;
+            }
+        };
+        let origin: Arc<str> = match Url::parse(&format!("https://{}/", authority)) {
+            Ok(url) => url.origin().ascii_serialization().into(),
+            Err(e) => {
+                warn!("invalid CONNECT host {}: {}", authority, e);
+                return;
+            }
+        };
+        // Requests in the tunnel only carry a path
+        let service = service_fn(move |request: Request<Body>| {
+            let proxy = self.clone();
+            let path = request
+                .uri()
+                .path_and_query()
+                .map_or("/", |path| path.as_str());
+            let url = format!("{}{}", origin, path);
+            async move { Ok::<_, Infallible>(proxy.answer(request, &url).await) }
+        });
+        let _ = Http::new()
+            .http1_only(true)
+            .serve_connection(stream, service)
+            .await;
+    }
+
+    /// Records the request if it is to the recorded origin, and sends it on to the server
+    async fn forward(
+        &self,
+        request: Request<Body>,
+        url: &str,
+    ) -> Result<Response<Body>, Box<dyn Error + Send + Sync>> {
+        let (parts, body) = request.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (name, value) in &parts.headers {
+            if name != hyper::header::HOST && !HOP_HEADERS.contains(&name.as_str()) {
+                headers.append(name.clone(), value.clone());
+            }
+        }
+        self.record(&parts.method, url, &headers, &body);
+
+        let response = self
+            .client
+            .request(parts.method, url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?;
+        let mut forwarded = Response::builder().status(response.status());
+        for (name, value) in response.headers() {
+            if !HOP_HEADERS.contains(&name.as_str()) {
+                forwarded = forwarded.header(name, value);
+            }
+        }
+        Ok(forwarded.body(Body::from(response.bytes().await?))?)
+    }
+
+    fn record(
+        &self,
+        method: &Method,
+        url: &str,
+        headers: &reqwest::header::HeaderMap,
+        body: &[u8],
+    ) {
+        let Ok(parsed) = Url::parse(url) else {
+            return;
+        };
+        let origin = parsed.origin().ascii_serialization();
+        if *self.origin.lock().unwrap().get_or_insert(origin.clone()) != origin {
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let headers = headers
+            .iter()
+            .filter(|(name, _)| !har::SKIPPED_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = match std::str::from_utf8(body) {
+            _ if body.is_empty() => None,
+            Ok(body) => Some(body.to_string()),
+            Err(_) => {
+                warn!(
+                    "recording {} {} without its body, which isn't text",
+                    method, url
+                );
+                None
+            }
+        };
+        self.recorded.lock().unwrap().push(convert::Request {
+            name: Some(format!("{} {}", method, parsed.path())),
+            method: method.to_string(),
+            url: url.to_string(),
+            headers,
+            body,
+            templated: false,
+            gap: None,
+        });
+    }
+
+    /// The TLS config presenting a certificate for `host`, signed by the CA
+    fn host_config(&self, host: &str) -> Result<Arc<rustls::ServerConfig>, Box<dyn Error>> {
+        if let Some(config) = self.hosts.lock().unwrap().get(host) {
+            return Ok(config.clone());
+        }
+        let mut params = CertificateParams::default();
+        params.distinguished_name = DistinguishedName::new();
+        params.distinguished_name.push(DnType::CommonName, host);
+        params.subject_alt_names = vec![match host.parse::<IpAddr>() {
+            Ok(ip) => SanType::IpAddress(ip),
+            Err(_) => SanType::DnsName(host.to_string()),
+        }];
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        params.use_authority_key_identifier_extension = true;
+        set_validity(&mut params, HOST_VALIDITY);
+        let certificate = Certificate::from_params(params)?;
+        let der = certificate.serialize_der_with_signer(&self.ca)?;
+        let mut config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![
+                    rustls::Certificate(der),
+                    rustls::Certificate(self.ca_der.clone()),
+                ],
+                rustls::PrivateKey(certificate.serialize_private_key_der()),
+            )?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let config = Arc::new(config);
+        self.hosts
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), config.clone());
+        Ok(config)
+    }
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    let mut response = Response::new(Body::from(message.to_string()));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        HeaderName::from_static("content-type"),
+        HeaderValue::from_static("text/plain"),
+    );
+    response
+}
+
+/// Makes the certificate valid for `length` from a day ago, in case the client's clock is
+/// behind
+fn set_validity(params: &mut CertificateParams, length: Duration) {
+    let day = Duration::from_secs(24 * 60 * 60);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let epoch = rcgen::date_time_ymd(1970, 1, 1);
+    params.not_before = epoch + now.saturating_sub(day);
+    params.not_after = epoch + now + length;
+}
+
+/// The CA's parameters, which sign with `key_pair` and name the same issuer every time
+fn ca_params(key_pair: Option<KeyPair>) -> CertificateParams {
+    let mut params = CertificateParams::default();
+    params.distinguished_name = DistinguishedName::new();
+    params.distinguished_name.push(DnType::CommonName, CA_NAME);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    params.key_pair = key_pair;
+    params
+}
+
+/// The CA to sign hosts' certificates with, and its certificate. They are read from the
+/// files when both exist, so the client trusts the CA once, and created in them otherwise.
+fn load_ca(cert_path: &Path, key_path: &Path) -> Result<(Certificate, Vec<u8>), Box<dyn Error>> {
+    match (cert_path.exists(), key_path.exists()) {
+        (true, true) => {
+            let read = || -> Result<(Certificate, Vec<u8>), Box<dyn Error>> {
+                let key = KeyPair::from_pem(&fs::read_to_string(key_path)?)?;
+                let der = rustls_pemfile::certs(&mut fs::read(cert_path)?.as_slice())?
+                    .into_iter()
+                    .next()
+                    .ok_or("no certificate in it")?;
+                Ok((Certificate::from_params(ca_params(Some(key)))?, der))
+            };
+            read().map_err(|e| {
+                format!(
+                    "failed to read the recording CA {}: {}",
+                    cert_path.display(),
+                    e
+                )
+                .into()
+            })
+        }
+        (false, false) => {
+            let mut params = ca_params(None);
+            set_validity(&mut params, CA_VALIDITY);
+            let ca = Certificate::from_params(params)?;
+            // Serialized once, since every serialization is signed anew
+            let der = ca.serialize_der()?;
+            let write = || -> Result<(), Box<dyn Error>> {
+                fs::write(cert_path, pem("CERTIFICATE", &der))?;
+                fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(key_path)?
+                    .write_all(ca.serialize_private_key_pem().as_bytes())?;
+                Ok(())
+            };
+            write().map_err(|e| {
+                format!(
+                    "failed to write the recording CA {}: {}",
+                    cert_path.display(),
+                    e
+                )
+            })?;
+            info!(
+                "created the recording CA {} and its key {}",
+                cert_path.display(),
+                key_path.display()
+            );
+            Ok((ca, der))
+        }
+        (true, false) => Err(format!(
+            "{} has no key, expected {}",
+            cert_path.display(),
+            key_path.display()
+        )
+        .into()),
+        (false, true) => Err(format!(
+            "{} has no certificate, expected {}",
+            key_path.display(),
+            cert_path.display()
+        )
+        .into()),
+    }
+}
+
+fn pem(label: &str, der: &[u8]) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(der);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(&String::from_utf8_lossy(line));
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
@@ -0,0 +1,1207 @@
+use std::{collections::BTreeMap, net::SocketAddr, path::Path, time::Duration};
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::ErrorCategory,
+    latency::LatencyHistogram,
+    resolve::Family,
+    search::{Search, Step},
+    spike::Recovery,
+    stages::{Stage, Stages},
+    tls::{Negotiated, QuicHandshake},
+    trend::{self, Drift, Trend},
+    BackendStats, ConnectionStats, LoadResult, Spread,
+};
+
+/// Most time buckets a report prints; longer runs are downsampled into wider buckets
+const MAX_BUCKETS: u32 = 120;
+
+/// Optional parts of the report, switched on by the flags a test ran with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sections {
+    pub range: bool,
+    pub pins: bool,
+    pub retries: bool,
+    pub adaptive: bool,
+    pub download: bool,
+    pub negotiated: Option<Negotiated>,
+    /// Handshake timings when the test ran over HTTP/3
+    #[serde(default)]
+    pub quic: Option<QuicHandshake>,
+    /// Width of the time buckets in time-series output
+    #[serde(default = "default_resolution")]
+    pub resolution: Duration,
+    /// TTFB percentiles to report
+    #[serde(default = "default_percentiles")]
+    pub percentiles: Vec<f32>,
+    /// Requests were spread across several backend addresses, so the per-backend breakdown
+    /// is printed even if only one of them answered
+    #[serde(default)]
+    pub backends: bool,
+    /// Strategy used to pick a backend for each request
+    #[serde(default)]
+    pub spread: Option<Spread>,
+    /// The test forced an HTTP version, so the versions that answered are printed even
+    /// without TLS
+    #[serde(default)]
+    pub versions: bool,
+    /// Server certificates weren't verified
+    #[serde(default)]
+    pub insecure: bool,
+    /// Responses were checked against assertions
+    #[serde(default)]
+    pub assertions: bool,
+    /// Requests sent before the test to warm the server up, left out of the results
+    #[serde(default)]
+    pub warmup_requests: Option<u64>,
+    /// Address family forced with -4 or -6
+    #[serde(default)]
+    pub family: Option<Family>,
+    /// Compressed responses were accepted and decoded
+    #[serde(default)]
+    pub compression: bool,
+    /// Requests arrived on a schedule, with arrivals dropped once too many were in flight
+    #[serde(default)]
+    pub open_model: bool,
+    /// Virtual users that ran the test, with --vus
+    #[serde(default)]
+    pub vus: Option<u64>,
+    /// Load stages the test went through, with --stages
+    #[serde(default)]
+    pub stages: Vec<Stage>,
+    /// Windows latency was compared across, with --trend-window
+    #[serde(default)]
+    pub trend: Option<Trend>,
+}
+
+/// Format of the final report
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// Machine-readable version of the report
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    /// Server certificates weren't verified, the target may not be who it claims to be
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub insecure: bool,
+    /// Seconds the test ran for
+    pub duration: f32,
+    pub successful: u64,
+    pub failed: u64,
+    pub rps: f32,
+    /// Requests sent to warm the server up, not counted above
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warmup_requests: Option<u64>,
+    /// Percentiles of the time to first byte, in seconds
+    pub percentiles: BTreeMap<String, f32>,
+    /// The same percentiles counted from when each request was meant to go out, with
+    /// --correct-omission
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub corrected_percentiles: Option<BTreeMap<String, f32>>,
+    /// Seconds requests went out later than meant, with --correct-omission
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_delay: Option<Latency>,
+    pub total_time: Latency,
+    pub ttfb: Latency,
+    pub ttlb: Latency,
+    /// Responses per status code, failed ones included
+    pub statuses: BTreeMap<u16, u64>,
+    /// Requests that got no response, per cause
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<ErrorCategory, u64>,
+    pub transfer: Transfer,
+    /// Where the time of new connections and of requests went, with --phases
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phases: Option<Phases>,
+    /// Successful responses per HTTP version
+    pub versions: BTreeMap<String, u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range_violations: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pin_mismatches: Option<u64>,
+    /// 2XX responses that failed an assertion, per assertion
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assertion_failures: Option<BTreeMap<String, u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retried_requests: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovered_requests: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overload_signals: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dropped_arrivals: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vus: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iterations: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stages: Vec<StageSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search: Option<SearchSummary>,
+    /// How latency went through each --spike and recovered after it
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub spikes: Vec<Recovery>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trend: Option<TrendSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downloaded_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub negotiated: Option<Negotiated>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quic: Option<QuicHandshake>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub backends: BTreeMap<SocketAddr, BackendStats>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub targets: BTreeMap<String, BackendStats>,
+    pub connections: ConnectionStats,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family: Option<Family>,
+}
+
+/// Outcomes of the requests that finished during one load stage
+#[derive(Debug, Serialize)]
+pub struct StageSummary {
+    /// Seconds the stage was planned to last
+    pub duration: f32,
+    pub target: f64,
+    pub successful: u64,
+    pub failed: u64,
+    pub rps: f32,
+    /// Percentiles of the total request time, in seconds
+    pub percentiles: BTreeMap<String, f32>,
+}
+
+impl StageSummary {
+    /// One per stage, their RPS over the part of each the test got through
+    fn of(stages: &Stages, percentiles: &[f32], test_duration: f32) -> Vec<StageSummary> {
+        let mut started = 0.0;
+        stages
+            .plan()
+            .iter()
+            .zip(stages.stats())
+            .map(|(stage, stats)| {
+                let duration = stage.duration.as_secs_f32();
+                let ran = (test_duration - started).clamp(0.0, duration);
+                started += duration;
+                StageSummary {
+                    duration,
+                    target: stage.target,
+                    successful: stats.successful,
+                    failed: stats.failed,
+                    rps: if ran > 0.0 {
+                        stats.successful as f32 / ran
+                    } else {
+                        0.0
+                    },
+                    percentiles: percentiles
+                        .iter()
+                        .map(|p| (percentile_name(*p), stats.latency.percentile(*p)))
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Loads an --auto search tried, in order, and the most the server sustained
+#[derive(Debug, Serialize)]
+pub struct SearchSummary {
+    /// Limits every load had to stay within
+    pub limits: Vec<String>,
+    /// What the loads are, "concurrency" or "rate"
+    pub load: &'static str,
+    pub steps: Vec<Step>,
+    /// RPS of the load that held with the most, None if none did
+    pub max_rps: Option<f64>,
+    pub max_load: Option<f64>,
+}
+
+impl SearchSummary {
+    fn of(search: &Search) -> SearchSummary {
+        let maximum = search.maximum();
+        SearchSummary {
+            limits: search.limits().iter().map(ToString::to_string).collect(),
+            load: if search.users() {
+                "concurrency"
+            } else {
+                "rate"
+            },
+            steps: search.steps().to_vec(),
+            max_rps: maximum.map(|step| step.rps),
+            max_load: maximum.map(|step| step.load),
+        }
+    }
+}
+
+/// Latency per --trend-window and the percentiles that drifted over the test
+#[derive(Debug, Serialize)]
+pub struct TrendSummary {
+    /// Seconds each window lasts
+    pub window: f32,
+    pub windows: Vec<WindowSummary>,
+    /// Percentiles that grew by more than the drift threshold from the first window to the last
+    pub drifts: Vec<Drift>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WindowSummary {
+    pub successful: u64,
+    /// Percentiles of the time to first byte, in seconds, empty if nothing finished
+    pub percentiles: BTreeMap<String, f32>,
+}
+
+impl TrendSummary {
+    fn of(result: &LoadResult, trend: &Trend, percentiles: &[f32]) -> Option<TrendSummary> {
+        let slices = result.trend.as_ref()?.slices();
+        Some(TrendSummary {
+            window: trend.window.as_secs_f32(),
+            windows: slices
+                .iter()
+                .map(|slice| match slice {
+                    Some(slice) => WindowSummary {
+                        successful: slice.successful,
+                        percentiles: percentiles
+                            .iter()
+                            .map(|p| percentile_name(*p))
+                            .zip(slice.values.iter().copied())
+                            .collect(),
+                    },
+                    None => WindowSummary {
+                        successful: 0,
+                        percentiles: BTreeMap::new(),
+                    },
+                })
+                .collect(),
+            drifts: trend::drifts(&slices, percentiles, trend.drift),
+        })
+    }
+}
+
+/// Response bodies received over the whole test, failed responses included
+#[derive(Debug, Serialize)]
+pub struct Transfer {
+    /// As received, compressed or not
+    pub bytes: u64,
+    pub mean_body_bytes: f64,
+    /// Aggregate over the test duration
+    pub mb_per_sec: f32,
+    /// After decompression, with --compression
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decompressed_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decode_failures: Option<u64>,
+}
+
+impl Transfer {
+    fn new(result: &LoadResult, sections: &Sections, test_duration: f32) -> Self {
+        let responses: u64 = result.statuses.values().sum();
+        Transfer {
+            bytes: result.bytes_received,
+            mean_body_bytes: if responses > 0 {
+                result.bytes_received as f64 / responses as f64
+            } else {
+                0.0
+            },
+            mb_per_sec: if test_duration > 0.0 {
+                bytes_to_mb(result.bytes_received) / test_duration
+            } else {
+                0.0
+            },
+            decompressed_bytes: sections.compression.then_some(result.bytes_decoded),
+            decode_failures: sections.compression.then_some(result.decode_failures),
+        }
+    }
+}
+
+/// Median, 95th percentile and max in seconds
+#[derive(Debug, Serialize)]
+pub struct PhaseTiming {
+    pub p50: f32,
+    pub p95: f32,
+    pub max: f32,
+}
+
+impl From<&LatencyHistogram> for PhaseTiming {
+    fn from(histogram: &LatencyHistogram) -> Self {
+        PhaseTiming {
+            p50: histogram.percentile(50.0),
+            p95: histogram.percentile(95.0),
+            max: histogram.max(),
+        }
+    }
+}
+
+/// Connection setup phases of the connections opened, then the phases of every request
+#[derive(Debug, Serialize)]
+pub struct Phases {
+    pub connections: u64,
+    pub dns: PhaseTiming,
+    pub connect: PhaseTiming,
+    /// Missing when the test made no TLS connections
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<PhaseTiming>,
+    pub ttfb: PhaseTiming,
+    pub body: PhaseTiming,
+}
+
+impl Phases {
+    fn new(result: &LoadResult) -> Option<Self> {
+        let phases = result.phases.as_ref()?;
+        Some(Phases {
+            connections: phases.connections,
+            dns: (&phases.dns).into(),
+            connect: (&phases.connect).into(),
+            tls: (!phases.tls.is_empty()).then(|| (&phases.tls).into()),
+            ttfb: (&result.latencies.ttfb).into(),
+            body: (&result.latencies.body).into(),
+        })
+    }
+}
+
+/// Min, max, mean, median and standard deviation in seconds, variance in seconds squared
+#[derive(Debug, Serialize)]
+pub struct Latency {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub median: f32,
+    pub stddev: f32,
+    pub variance: f32,
+}
+
+pub fn summarize(result: &LoadResult, sections: &Sections, test_duration: f32) -> Summary {
+    let CalculatedStats {
+        total_time,
+        ttfb,
+        ttlb,
+    } = calculate_stats(result, |x| x);
+
+    let percentiles = sections
+        .percentiles
+        .iter()
+        .map(|p| (percentile_name(*p), result.latencies.ttfb.percentile(*p)))
+        .collect();
+
+    Summary {
+        insecure: sections.insecure,
+        duration: test_duration,
+        successful: result.number_of_successful_calls,
+        failed: result.number_of_failed_calls,
+        rps: result.number_of_successful_calls as f32 / test_duration,
+        warmup_requests: sections.warmup_requests,
+        percentiles,
+        corrected_percentiles: result.corrected.as_ref().map(|corrected| {
+            sections
+                .percentiles
+                .iter()
+                .map(|p| (percentile_name(*p), corrected.ttfb.percentile(*p)))
+                .collect()
+        }),
+        send_delay: result
+            .corrected
+            .as_ref()
+            .map(|corrected| describe(&corrected.send_delay, |x| x)),
+        total_time,
+        ttfb,
+        ttlb,
+        statuses: result.statuses.clone(),
+        errors: result.errors.clone(),
+        transfer: Transfer::new(result, sections, test_duration),
+        phases: Phases::new(result),
+        versions: result.versions.clone(),
+        range_violations: sections.range.then_some(result.number_of_range_violations),
+        pin_mismatches: sections.pins.then_some(result.number_of_pin_mismatches),
+        assertion_failures: sections
+            .assertions
+            .then(|| result.assertion_failures.clone()),
+        retries: sections.retries.then_some(result.number_of_retries),
+        retried_requests: sections
+            .retries
+            .then_some(result.number_of_retried_requests),
+        recovered_requests: sections
+            .retries
+            .then_some(result.number_of_recovered_requests),
+        overload_signals: sections
+            .adaptive
+            .then_some(result.number_of_overload_signals),
+        dropped_arrivals: sections
+            .open_model
+            .then_some(result.number_of_dropped_arrivals),
+        vus: sections.vus,
+        iterations: sections.vus.map(|_| result.number_of_iterations),
+        stages: result.stages.as_ref().map_or_else(Vec::new, |stages| {
+            StageSummary::of(stages, &sections.percentiles, test_duration)
+        }),
+        search: result.search.as_ref().map(SearchSummary::of),
+        trend: sections
+            .trend
+            .and_then(|trend| TrendSummary::of(result, &trend, &sections.percentiles)),
+        spikes: result
+            .spikes
+            .as_ref()
+            .map_or_else(Vec::new, |spikes| spikes.recoveries()),
+        downloaded_bytes: sections.download.then_some(result.bytes_successful),
+        negotiated: sections.negotiated.clone(),
+        quic: sections.quic.clone(),
+        backends: result.backends.clone(),
+        targets: result.targets.clone(),
+        connections: result.connections.clone(),
+        family: sections.family,
+    }
+}
+
+/// Prints the report in `format`, or writes it to `file` when given
+pub fn write_report(
+    result: &LoadResult,
+    sections: &Sections,
+    test_duration: f32,
+    format: OutputFormat,
+    file: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Text => print_report(result, sections, test_duration),
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(&summarize(result, sections, test_duration))?;
+            match file {
+                Some(path) => std::fs::write(path, json + "\n")?,
+                None => println!("{}", json),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn default_resolution() -> Duration {
+    Duration::from_secs(1)
+}
+
+/// What reports from before --percentiles showed
+fn default_percentiles() -> Vec<f32> {
+    vec![95.0, 99.0]
+}
+
+/// Key of a percentile in the JSON report, e.g. p95 or p99.9
+pub fn percentile_name(percentile: f32) -> String {
+    format!("p{}", percentile)
+}
+
+/// Widens `resolution` to a whole multiple of itself when the run is too long to fit in
+/// MAX_BUCKETS, so multi-day runs stay readable
+pub fn bucket_width(resolution: Duration, test_duration: f32) -> Duration {
+    let buckets = (test_duration / resolution.as_secs_f32()).ceil() as u32;
+    resolution * buckets.div_ceil(MAX_BUCKETS).max(1)
+}
+
+pub fn print_report(result: &LoadResult, sections: &Sections, test_duration: f32) {
+    let CalculatedStats {
+        total_time,
+        ttfb,
+        ttlb,
+    } = calculate_stats(result, truncate_to_two_decimals);
+
+    let rps = result.number_of_successful_calls as f32 / test_duration;
+
+    if sections.insecure {
+        println!("WARNING: server certificates were NOT verified (--insecure), the responses may not have come from the intended server");
+    }
+    println!("Results:");
+    println!(
+        "Successful Requests........................: {}",
+        result.number_of_successful_calls
+    );
+    println!(
+        "Failed Requests............................: {}",
+        result.number_of_failed_calls
+    );
+    println!("Request Per Sec (RPS).......................: {}", rps);
+    if let Some(warmup) = sections.warmup_requests {
+        println!("Warm-up Requests (Not Counted)..............: {}", warmup);
+    }
+    println!();
+    println!();
+    for p in &sections.percentiles {
+        println!(
+            "{:.<26}: {}",
+            format!("P{}", p),
+            result.latencies.ttfb.percentile(*p)
+        );
+    }
+    if let Some(corrected) = &result.corrected {
+        for p in &sections.percentiles {
+            println!(
+                "{:.<26}: {}",
+                format!("Corrected P{}", p),
+                corrected.ttfb.percentile(*p)
+            );
+        }
+    }
+    println!();
+    println!();
+    println!(
+        "Total Request Time (s) (Min, Max, Mean, Median, Std Dev, Variance).....: {}, {}, {}, {}, {}, {},",
+        total_time.min,
+total_time.max,
+total_time.mean,
+total_time.median,
+total_time.stddev,
+total_time.variance
+    );
+    println!(
+        "Time to First Byte (s) (Min, Max, Mean, Median, Std Dev, Variance).....: {}, {}, {}, {}, {}, {},",
+        ttfb.min,
+ttfb.max,
+ttfb.mean,
+ttfb.median,
+ttfb.stddev,
+ttfb.variance
+    );
+    println!(
+        "Time to Last Byte (s) (Min, Max, Mean, Median, Std Dev, Variance).....: {}, {}, {}, {}, {}, {},",
+        ttlb.min,
+ttlb.max,
+ttlb.mean,
+ttlb.median,
+ttlb.stddev,
+ttlb.variance
+    );
+    if let Some(corrected) = &result.corrected {
+        let delay = describe(&corrected.send_delay, truncate_to_two_decimals);
+        println!(
+            "Send Delay (s) (Min, Max, Mean, Median, Std Dev, Variance).....: {}, {}, {}, {}, {}, {},",
+            delay.min,
+delay.max,
+delay.mean,
+delay.median,
+delay.stddev,
+delay.variance
+        );
+    }
+
+    let transfer = Transfer::new(result, sections, test_duration);
+    println!();
+    println!();
+    println!(
+        "Bytes Received (All Responses)..............: {}",
+        transfer.bytes
+    );
+    println!(
+        "Mean Body Size (bytes)......................: {}",
+        transfer.mean_body_bytes.round()
+    );
+    println!(
+        "Throughput (MB/s)...........................: {}",
+        truncate_to_two_decimals(transfer.mb_per_sec)
+    );
+    if let Some(decompressed) = transfer.decompressed_bytes {
+        println!(
+            "Bytes After Decompression...................: {}",
+            decompressed
+        );
+    }
+    if let Some(failures) = transfer.decode_failures.filter(|&f| f > 0) {
+        println!("Decompression Failures......................: {}", failures);
+    }
+
+    if let Some(phases) = Phases::new(result) {
+        println!();
+        println!();
+        println!(
+            "Connection Phases (s) (P50, P95, Max), {} New Connections:",
+            phases.connections
+        );
+        print_phase("DNS Lookup", &phases.dns);
+        print_phase("TCP Connect", &phases.connect);
+        if let Some(tls) = &phases.tls {
+            print_phase("TLS Handshake", tls);
+        }
+        println!("Request Phases (s) (P50, P95, Max):");
+        print_phase("Time to First Byte", &phases.ttfb);
+        print_phase("Body Transfer", &phases.body);
+    }
+
+    if !result.statuses.is_empty() {
+        println!();
+        println!();
+        println!("Status Codes (Responses):");
+        let labels: Vec<(String, u64)> = result
+            .statuses
+            .iter()
+            .map(|(status, count)| {
+                let reason = StatusCode::from_u16(*status)
+                    .ok()
+                    .and_then(|status| status.canonical_reason())
+                    .unwrap_or("");
+                (format!("{} {}", status, reason), *count)
+            })
+            .collect();
+        let width = labels
+            .iter()
+            .map(|(label, _)| label.len())
+            .max()
+            .unwrap_or(0);
+        for (label, count) in labels {
+            println!("  {:.<width$}: {}", label, count, width = width + 5);
+        }
+    }
+
+    if !result.errors.is_empty() {
+        println!();
+        println!();
+        println!("Errors (No Response):");
+        for (category, count) in &result.errors {
+            println!("  {:.<29}: {}", category.to_string(), count);
+        }
+    }
+
+    if sections.range {
+        println!();
+        println!();
+        println!(
+            "Range Violations (non-206).................: {}",
+            result.number_of_range_violations
+        );
+    }
+
+    let versions: Vec<String> = result
+        .versions
+        .iter()
+        .map(|(version, count)| format!("{} ({})", version, count))
+        .collect();
+    if let Some(negotiated) = &sections.negotiated {
+        println!();
+        println!();
+        println!(
+            "Negotiated TLS Version......................: {}",
+            negotiated.version
+        );
+        println!(
+            "Negotiated Cipher Suite.....................: {}",
+            negotiated.cipher
+        );
+        println!(
+            "Negotiated ALPN.............................: {}",
+            negotiated.alpn.as_deref().unwrap_or("none")
+        );
+        println!(
+            "HTTP Versions (2XX).........................: {}",
+            versions.join(", ")
+        );
+    }
+
+    if let Some(quic) = &sections.quic {
+        println!();
+        println!();
+        println!(
+            "QUIC Handshake (s)..........................: {}",
+            quic.full
+        );
+        println!(
+            "QUIC Resumed Handshake (s)..................: {}",
+            quic.resumed
+        );
+        println!(
+            "QUIC 0-RTT Accepted.........................: {}",
+            if quic.zero_rtt { "yes" } else { "no" }
+        );
+        println!(
+            "Negotiated ALPN.............................: {}",
+            quic.alpn.as_deref().unwrap_or("none")
+        );
+        println!(
+            "HTTP Versions (2XX).........................: {}",
+            versions.join(", ")
+        );
+    }
+
+    if sections.versions && sections.negotiated.is_none() && sections.quic.is_none() {
+        println!();
+        println!();
+        println!(
+            "HTTP Versions (2XX).........................: {}",
+            versions.join(", ")
+        );
+    }
+
+    if sections.pins {
+        println!();
+        println!();
+        println!(
+            "Certificate Pin Mismatches..................: {}",
+            result.number_of_pin_mismatches
+        );
+    }
+
+    if sections.assertions {
+        println!();
+        println!();
+        println!(
+            "Assertion Failures (2XX)....................: {}",
+            result.assertion_failures.values().sum::<u64>()
+        );
+        for (assertion, count) in &result.assertion_failures {
+            println!("  {}: {}", assertion, count);
+        }
+    }
+
+    if sections.retries {
+        println!();
+        println!();
+        println!(
+            "Retries.....................................: {}",
+            result.number_of_retries
+        );
+        println!(
+            "Requests Retried............................: {}",
+            result.number_of_retried_requests
+        );
+        println!(
+            "Succeeded After Retry.......................: {}",
+            result.number_of_recovered_requests
+        );
+        println!(
+            "Retry Delay (s).............................: {}",
+            truncate_to_two_decimals(result.retry_delay.as_secs_f32())
+        );
+    }
+
+    if let Some(vus) = sections.vus {
+        println!();
+        println!();
+        println!("Virtual Users...............................: {}", vus);
+        println!(
+            "Iterations Run..............................: {}",
+            result.number_of_iterations
+        );
+    }
+
+    if sections.open_model {
+        println!();
+        println!();
+        println!(
+            "Dropped Arrivals (Open Model)...............: {}",
+            result.number_of_dropped_arrivals
+        );
+    }
+
+    if sections.adaptive {
+        let attempts =
+            result.number_of_successful_calls as f32 + result.number_of_failed_calls as f32;
+        println!();
+        println!();
+        println!(
+            "Overload Signals (503/429).................: {}",
+            result.number_of_overload_signals
+        );
+        println!(
+            "Time Spent Backing Off (s).................: {}",
+            truncate_to_two_decimals(result.backoff_delay.as_secs_f32())
+        );
+        println!(
+            "Offered Load (RPS).........................: {}",
+            attempts / test_duration
+        );
+        println!("Goodput (RPS)...............................: {}", rps);
+    }
+
+    let connections = &result.connections;
+    if sections.family.is_some() || (connections.ipv4 > 0 && connections.ipv6 > 0) {
+        println!();
+        println!();
+        if let Some(family) = sections.family {
+            println!("Address Family (Forced).....................: {}", family);
+        }
+        println!(
+            "Responses over IPv4.........................: {}",
+            connections.ipv4
+        );
+        println!(
+            "Responses over IPv6.........................: {}",
+            connections.ipv6
+        );
+    }
+
+    // Only worth a section when the network layer misbehaved
+    if connections.failed > 0 || connections.fallbacks > 0 {
+        println!();
+        println!();
+        println!(
+            "Connection Attempts.........................: {}",
+            connections.attempts
+        );
+        println!(
+            "Failed Connects.............................: {}",
+            connections.failed
+        );
+        println!(
+            "Address Family Fallbacks....................: {}",
+            connections.fallbacks
+        );
+        println!(
+            "Succeeded After Failed Connect..............: {}",
+            connections.recovered
+        );
+    }
+
+    if sections.backends || result.backends.len() > 1 {
+        println!();
+        println!();
+        if let Some(spread) = sections.spread {
+            println!("Backend Selection...........................: {}", spread);
+        }
+        println!("Per Backend (OK, Failed, Mean (s), Max (s)):");
+        for (addr, backend) in &result.backends {
+            print_outcomes(&addr.to_string(), 24, backend);
+        }
+    }
+
+    if let Some(stages) = &result.stages {
+        println!();
+        println!();
+        let names: Vec<String> = sections
+            .percentiles
+            .iter()
+            .map(|p| percentile_name(*p))
+            .collect();
+        println!(
+            "Per Stage (OK, Failed, RPS, Total Request Time (s) {}):",
+            names.join(", ")
+        );
+        let summaries = StageSummary::of(stages, &sections.percentiles, test_duration);
+        let labels: Vec<String> = summaries
+            .iter()
+            .enumerate()
+            .map(|(i, stage)| {
+                format!(
+                    "{}. {} to {}",
+                    i + 1,
+                    humantime::format_duration(Duration::from_secs_f32(stage.duration)),
+                    stage.target
+                )
+            })
+            .collect();
+        let width = labels.iter().map(String::len).max().unwrap_or(0);
+        for (label, stage) in labels.iter().zip(&summaries) {
+            let percentiles: Vec<String> = names
+                .iter()
+                .map(|name| stage.percentiles[name].to_string())
+                .collect();
+            println!(
+                "  {:<width$}.....: {}, {}, {}, {}",
+                label,
+                stage.successful,
+                stage.failed,
+                truncate_to_two_decimals(stage.rps),
+                percentiles.join(", "),
+                width = width
+            );
+        }
+    }
+
+    if let Some(search) = &result.search {
+        let summary = SearchSummary::of(search);
+        // Rates land between whole numbers once bisected
+        let load = |load: f64| format!("{}", (load * 100.0).round() / 100.0);
+        println!();
+        println!();
+        println!(
+            "Search Limits...............................: {}",
+            summary.limits.join(", ")
+        );
+        println!(
+            "Load Search by {} (OK, Failed, RPS, Limits Missed):",
+            if search.users() {
+                "Concurrency"
+            } else {
+                "Arrival Rate"
+            }
+        );
+        let labels: Vec<String> = summary.steps.iter().map(|step| load(step.load)).collect();
+        let width = labels.iter().map(String::len).max().unwrap_or(0);
+        for (label, step) in labels.iter().zip(&summary.steps) {
+            let mut line = format!(
+                "  {:<width$}.....: {}, {}, {}",
+                label,
+                step.successful,
+                step.failed,
+                truncate_to_two_decimals(step.rps as f32),
+                width = width
+            );
+            if !step.held() {
+                line += &format!(", {}", step.missed.join(", "));
+            }
+            println!("{}", line);
+        }
+        match (summary.max_rps, summary.max_load) {
+            (Some(rps), Some(at)) => println!(
+                "Sustained Maximum (RPS).....................: {} at {} {}",
+                truncate_to_two_decimals(rps as f32),
+                summary.load,
+                load(at)
+            ),
+            _ => println!("Sustained Maximum (RPS).....................: none, no load held"),
+        }
+    }
+
+    if let Some(spikes) = &result.spikes {
+        let spike = spikes.spike();
+        let recoveries = spikes.recoveries();
+        println!();
+        println!();
+        println!(
+            "Spikes, {} to {} for {} (p95 TTFB (s) Before, During, Worst After, Time to Recover (s)):",
+            spike.baseline,
+            spike.spike,
+            humantime::format_duration(spike.duration)
+        );
+        let to_recover = |recovery: &Recovery| match recovery.time_to_recover {
+            Some(secs) => secs.to_string(),
+            None => "not recovered".to_string(),
+        };
+        for (i, recovery) in recoveries.iter().enumerate() {
+            println!(
+                "  {}.....: {}, {}, {}, {}",
+                i + 1,
+                recovery.baseline_p95,
+                recovery.spike_p95,
+                recovery.peak_p95,
+                to_recover(recovery)
+            );
+        }
+        // The slowest recovery, a spike that never recovered being the slowest of all
+        if let Some(slowest) = recoveries.iter().max_by(|a, b| {
+            let secs = |recovery: &Recovery| recovery.time_to_recover.unwrap_or(f32::INFINITY);
+            secs(a).total_cmp(&secs(b))
+        }) {
+            println!(
+                "Longest Time to Recover (s).................: {}",
+                to_recover(slowest)
+            );
+        }
+    }
+
+    if let Some(trend) = sections
+        .trend
+        .and_then(|trend| TrendSummary::of(result, &trend, &sections.percentiles))
+    {
+        let window = Duration::from_secs_f32(trend.window);
+        let label = |index: usize| {
+            format!(
+                "{}-{}",
+                humantime::format_duration(window * index as u32),
+                humantime::format_duration(window * (index as u32 + 1))
+            )
+        };
+        let names: Vec<String> = sections
+            .percentiles
+            .iter()
+            .map(|p| percentile_name(*p))
+            .collect();
+        println!();
+        println!();
+        println!(
+            "Trend per {} (OK, Time to First Byte (s) {}):",
+            humantime::format_duration(window),
+            names.join(", ")
+        );
+        let labels: Vec<String> = (0..trend.windows.len()).map(label).collect();
+        let width = labels.iter().map(String::len).max().unwrap_or(0);
+        for (label, window) in labels.iter().zip(&trend.windows) {
+            let percentiles: Vec<String> = names
+                .iter()
+                .filter_map(|name| window.percentiles.get(name))
+                .map(ToString::to_string)
+                .collect();
+            println!(
+                "  {:<width$}.....: {}{}",
+                label,
+                window.successful,
+                percentiles
+                    .iter()
+                    .map(|p| format!(", {}", p))
+                    .collect::<String>(),
+                width = width
+            );
+        }
+        let threshold = sections.trend.map_or(0.0, |trend| trend.drift);
+        if trend.drifts.is_empty() {
+            println!(
+                "Latency Drift...............................: none over {}%",
+                threshold
+            );
+        } else {
+            println!("Latency Drift (Over {}%):", threshold);
+            for drift in &trend.drifts {
+                println!(
+                    "  {} grew {:.0}% between {} and {} ({} to {})",
+                    percentile_name(drift.percentile),
+                    drift.growth,
+                    label(drift.first_slice),
+                    label(drift.last_slice),
+                    drift.first,
+                    drift.last
+                );
+            }
+        }
+    }
+
+    if !result.targets.is_empty() {
+        println!();
+        println!();
+        println!("Per Target (OK, Failed, Mean (s), Max (s)):");
+        let width = result.targets.keys().map(String::len).max().unwrap_or(0);
+        for (url, target) in &result.targets {
+            print_outcomes(url, width, target);
+        }
+    }
+
+    if sections.download {
+        print_throughput(result, test_duration, sections.resolution);
+    }
+}
+
+#[derive(Debug)]
+struct CalculatedStats {
+    ttfb: Latency,
+    ttlb: Latency,
+    total_time: Latency,
+}
+
+/// `round` is applied to every value, e.g. to truncate them for printing
+fn calculate_stats(result: &LoadResult, round: fn(f32) -> f32) -> CalculatedStats {
+    CalculatedStats {
+        ttfb: describe(&result.latencies.ttfb, round),
+        ttlb: describe(&result.latencies.ttlb, round),
+        total_time: describe(&result.latencies.total_time, round),
+    }
+}
+
+/// Exact min, max, mean and variance of one timing over every request, the median comes from
+/// its histogram
+fn describe(histogram: &LatencyHistogram, round: fn(f32) -> f32) -> Latency {
+    let moments = histogram.moments();
+    let variance = moments.variance();
+    Latency {
+        min: round(moments.min()),
+        max: round(moments.max()),
+        mean: round(moments.mean() as f32),
+        median: round(histogram.percentile(50.0)),
+        stddev: round(variance.sqrt() as f32),
+        variance: round(variance as f32),
+    }
+}
+
+fn truncate_to_two_decimals(num: f32) -> f32 {
+    (num * 100.0).trunc() / 100.0
+}
+
+/// One line of --interval output: throughput, failures and p95 time to first byte of the
+/// `width` of the test that ended `elapsed` into it
+pub fn interval_line(
+    elapsed: Duration,
+    width: Duration,
+    successful: u64,
+    failed: u64,
+    ttfb: &LatencyHistogram,
+) -> String {
+    format!(
+        "Interval Ending {:>6}s....................: {} RPS, {} Errors, P95 {}",
+        truncate_to_two_decimals(elapsed.as_secs_f32()),
+        truncate_to_two_decimals(successful as f32 / width.as_secs_f32()),
+        failed,
+        ttfb.percentile(95.0)
+    )
+}
+
+fn bytes_to_mb(bytes: u64) -> f32 {
+    bytes as f32 / (1024.0 * 1024.0)
+}
+
+fn print_throughput(result: &LoadResult, test_duration: f32, resolution: Duration) {
+    let total_bytes = result.bytes_successful;
+    let aggregate = if test_duration > 0.0 {
+        bytes_to_mb(total_bytes) / test_duration
+    } else {
+        0.0
+    };
+
+    // Throughput each connection achieved while transferring its body
+    let rate = |bytes_per_sec: f32| bytes_per_sec / (1024.0 * 1024.0);
+    let moments = result.throughput.moments();
+    let (min, max, mean) = if moments.count() > 0 {
+        (
+            rate(moments.min()),
+            rate(moments.max()),
+            rate(moments.mean() as f32),
+        )
+    } else {
+        (0.0, 0.0, 0.0)
+    };
+    let p95 = rate(result.throughput.percentile(95.0));
+
+    // Bytes completed in each time bucket of the test
+    let width = bucket_width(resolution, test_duration);
+    let buckets = result.timeline.buckets(width, test_duration);
+    let width = width.as_secs_f32();
+
+    println!();
+    println!();
+    println!(
+        "Total Downloaded (MB).......................: {}",
+        truncate_to_two_decimals(bytes_to_mb(total_bytes))
+    );
+    println!(
+        "Aggregate Throughput (MB/s).................: {}",
+        truncate_to_two_decimals(aggregate)
+    );
+    println!(
+        "Connection Throughput (MB/s) (Min, Max, Mean): {}, {}, {},",
+        truncate_to_two_decimals(min),
+        truncate_to_two_decimals(max),
+        truncate_to_two_decimals(mean)
+    );
+    println!(
+        "Connection Throughput P95 (MB/s)............: {}",
+        truncate_to_two_decimals(p95)
+    );
+    println!();
+    println!("Throughput Over Time (MB/s):");
+    for (i, bucket) in buckets.iter().enumerate() {
+        println!(
+            "  {:>6}s.....................: {}",
+            truncate_to_two_decimals((i + 1) as f32 * width),
+            truncate_to_two_decimals(bytes_to_mb(bucket.bytes) / width)
+        );
+    }
+}
+
+/// One row of a per-backend or per-target table, `label` padded to `width`
+fn print_outcomes(label: &str, width: usize, stats: &BackendStats) {
+    let mean = if stats.successful > 0 {
+        stats.total_time / stats.successful as f32
+    } else {
+        0.0
+    };
+    println!(
+        "  {:<width$}.....: {}, {}, {}, {}",
+        label,
+        stats.successful,
+        stats.failed,
+        truncate_to_two_decimals(mean),
+        truncate_to_two_decimals(stats.max_total_time),
+        width = width
+    );
+}
+
+fn print_phase(name: &str, timing: &PhaseTiming) {
+    println!(
+        "  {:.<29}: {}, {}, {}",
+        name, timing.p50, timing.p95, timing.max
+    );
+}
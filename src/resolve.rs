@@ -0,0 +1,195 @@
+use std::{
+    fmt, io,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    ClientBuilder, Url,
+};
+
+/// Addresses a host:port connects to instead of what DNS says, like curl's --resolve
+#[derive(Debug, Clone)]
+pub struct Override {
+    host: String,
+    port: u16,
+    addrs: Vec<IpAddr>,
+}
+
+/// Parses `host:port:addr[,addr...]`, with IPv6 addresses optionally in brackets
+pub fn parse_override(spec: &str) -> Result<Override, String> {
+    let invalid = || format!("invalid --resolve `{}`, expected host:port:addr", spec);
+    let mut parts = spec.splitn(3, ':');
+    let (Some(host), Some(port), Some(addrs)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid());
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    let port = port
+        .parse::<u16>()
+        .map_err(|_| format!("invalid port `{}` in --resolve `{}`", port, spec))?;
+    let addrs = addrs
+        .split(',')
+        .map(|addr| {
+            addr.trim_matches(['[', ']'])
+                .parse::<IpAddr>()
+                .map_err(|_| format!("invalid address `{}` in --resolve `{}`", addr, spec))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Override {
+        host: host.to_ascii_lowercase(),
+        port,
+        addrs,
+    })
+}
+
+/// Address family every connection is limited to, with -4 or -6
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Family {
+    V4,
+    V6,
+}
+
+impl Family {
+    pub fn of(addr: &SocketAddr) -> Self {
+        Family::of_ip(&addr.ip())
+    }
+
+    fn of_ip(ip: &IpAddr) -> Self {
+        if ip.is_ipv4() {
+            Family::V4
+        } else {
+            Family::V6
+        }
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Family::V4 => "IPv4",
+            Family::V6 => "IPv6",
+        })
+    }
+}
+
+/// How the test's host names turn into addresses: --resolve overrides first, then the
+/// system resolver, keeping only addresses of the family forced with -4 or -6
+#[derive(Debug, Clone, Default)]
+pub struct Resolver {
+    overrides: Arc<Vec<Override>>,
+    family: Option<Family>,
+}
+
+impl Resolver {
+    /// reqwest overrides a host name for every port, so each host may only appear once, and
+    /// for a port one of the `urls` uses
+    pub fn new(
+        overrides: Vec<Override>,
+        family: Option<Family>,
+        urls: &[&str],
+    ) -> Result<Self, String> {
+        for (i, entry) in overrides.iter().enumerate() {
+            if overrides[..i].iter().any(|other| other.host == entry.host) {
+                return Err(format!("--resolve is given twice for {}", entry.host));
+            }
+            let used = urls
+                .iter()
+                .filter_map(|url| Url::parse(url).ok())
+                .any(|url| {
+                    url.host_str()
+                        .is_some_and(|host| host.eq_ignore_ascii_case(&entry.host))
+                        && url.port_or_known_default() == Some(entry.port)
+                });
+            if !used {
+                return Err(format!(
+                    "--resolve {}:{} matches none of the URLs",
+                    entry.host, entry.port
+                ));
+            }
+            let of_family = |ip: &IpAddr| family.is_none_or(|family| family == Family::of_ip(ip));
+            if !entry.addrs.iter().any(of_family) {
+                return Err(format!(
+                    "--resolve {}:{} has no {} address",
+                    entry.host,
+                    entry.port,
+                    family.unwrap()
+                ));
+            }
+        }
+        Ok(Resolver {
+            overrides: Arc::new(overrides),
+            family,
+        })
+    }
+
+    pub fn family(&self) -> Option<Family> {
+        self.family
+    }
+
+    fn keeps(&self, addr: &SocketAddr) -> bool {
+        self.family.is_none_or(|family| Family::of(addr) == family)
+    }
+
+    /// Overridden addresses of `host:port`, if any
+    pub fn overridden(&self, host: &str, port: u16) -> Option<Vec<SocketAddr>> {
+        self.overrides
+            .iter()
+            .find(|entry| entry.port == port && entry.host.eq_ignore_ascii_case(host))
+            .map(|entry| self.addrs(entry))
+    }
+
+    /// Addresses of `host:port`, failing when none are left of the forced family
+    pub async fn lookup(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let addrs: Vec<_> = match self.overridden(host, port) {
+            Some(addrs) => addrs,
+            None => tokio::net::lookup_host((host, port))
+                .await?
+                .filter(|addr| self.keeps(addr))
+                .collect(),
+        };
+        match self.family {
+            Some(family) if addrs.is_empty() => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has no {} address", host, family),
+            )),
+            _ => Ok(addrs),
+        }
+    }
+
+    fn addrs(&self, entry: &Override) -> Vec<SocketAddr> {
+        entry
+            .addrs
+            .iter()
+            .map(|&ip| SocketAddr::new(ip, entry.port))
+            .filter(|addr| self.keeps(addr))
+            .collect()
+    }
+
+    /// Makes the client connect to the overridden addresses, still sending the host name in
+    /// the SNI and Host header, and to addresses of the forced family only
+    pub fn apply(&self, mut builder: ClientBuilder) -> ClientBuilder {
+        for entry in self.overrides.iter() {
+            builder = builder.resolve_to_addrs(&entry.host, &self.addrs(entry));
+        }
+        if self.family.is_some() {
+            builder = builder.dns_resolver(Arc::new(self.clone()));
+        }
+        builder
+    }
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            // The connector puts the URL's port on whatever comes back
+            let addrs = resolver.lookup(name.as_str(), 0).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use reqwest::Version;
+
+use crate::Stats;
+
+/// Size of one encoded sample: ttfb, ttlb, total_time and finished_at as f32, bytes as u64,
+/// status as u16, the HTTP version as u8, padded to 32 bytes
+const RECORD_SIZE: usize = 32;
+
+/// Raw successful requests, appended to a file for huge runs that need them kept. The report
+/// is built from aggregates, so the file is only ever written as the test goes, keeping memory
+/// flat.
+pub struct SampleFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    /// Instant `finished_at` offsets are relative to
+    base: Instant,
+    /// First write error, reported on the next flush so a full disk doesn't go unnoticed
+    error: Option<io::Error>,
+}
+
+impl SampleFile {
+    /// Starts an empty file, truncating `path`
+    pub fn create(path: &Path, base: Instant) -> io::Result<Self> {
+        File::create(path)?;
+        Self::reopen(path, base)
+    }
+
+    /// Appends to `path`, keeping whatever samples it already holds
+    pub fn reopen(path: &Path, base: Instant) -> io::Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(SampleFile {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+            base,
+            error: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn push(&mut self, stats: &Stats) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(e) = self.writer.write_all(&encode(stats, self.base)) {
+            self.error = Some(e);
+        }
+    }
+
+    /// Writes out every pushed sample, surfacing any earlier write error
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+fn encode(stats: &Stats, base: Instant) -> [u8; RECORD_SIZE] {
+    let finished_at = stats
+        .finished_at
+        .checked_duration_since(base)
+        .unwrap_or_default()
+        .as_secs_f32();
+
+    let mut record = [0; RECORD_SIZE];
+    record[0..4].copy_from_slice(&stats.ttfb.to_le_bytes());
+    record[4..8].copy_from_slice(&stats.ttlb.to_le_bytes());
+    record[8..12].copy_from_slice(&stats.total_time.to_le_bytes());
+    record[12..16].copy_from_slice(&finished_at.to_le_bytes());
+    record[16..24].copy_from_slice(&stats.bytes.to_le_bytes());
+    record[24..26].copy_from_slice(&stats.status.as_u16().to_le_bytes());
+    record[26] = encode_version(stats.version);
+    record
+}
+
+fn encode_version(version: Version) -> u8 {
+    match version {
+        Version::HTTP_09 => 0,
+        Version::HTTP_10 => 1,
+        Version::HTTP_2 => 3,
+        Version::HTTP_3 => 4,
+        _ => 2,
+    }
+}
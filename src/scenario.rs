@@ -0,0 +1,750 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    error::Error,
+    fs::{File, OpenOptions},
+    path::Path,
+    sync::Mutex,
+    time::Duration,
+};
+
+use rand::seq::SliceRandom;
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, COOKIE},
+    Method, Url,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::{config, data::DataFeed, har::Recording, secrets, template::Template};
+
+/// Steps as written in the scenario file, which `convert` writes too
+#[derive(Deserialize, Serialize)]
+pub struct ScenarioFile {
+    pub steps: Vec<StepFile>,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct StepFile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Variables to capture from the response, by name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub extract: HashMap<String, ExtractorFile>,
+    /// Recorded pause before the step, e.g. 1s 250ms, which --preserve-timing replays
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pause: Option<String>,
+}
+
+/// One of its fields, written as a table rather than an enum so YAML needs no tags
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExtractorFile {
+    /// Dotted path into a JSON body, e.g. data.items.0.id
+    json: Option<String>,
+    header: Option<String>,
+    /// Regex over the body, capturing its first group or else the whole match
+    regex: Option<String>,
+}
+
+/// Where a variable's value is read from in a response
+enum Extractor {
+    Json(Vec<String>),
+    Header(HeaderName),
+    Regex(Regex),
+}
+
+impl Extractor {
+    fn extract(&self, headers: &HeaderMap, body: &[u8]) -> Option<String> {
+        match self {
+            Extractor::Json(path) => {
+                let mut value = &serde_json::from_slice::<Value>(body).ok()?;
+                for key in path {
+                    value = match value {
+                        Value::Array(items) => items.get(key.parse::<usize>().ok()?)?,
+                        value => value.get(key)?,
+                    };
+                }
+                match value {
+                    Value::String(s) => Some(s.clone()),
+                    Value::Null => None,
+                    value => Some(value.to_string()),
+                }
+            }
+            Extractor::Header(name) => headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            Extractor::Regex(pattern) => {
+                let body = String::from_utf8_lossy(body);
+                let captures = pattern.captures(&body)?;
+                captures
+                    .get(1)
+                    .or_else(|| captures.get(0))
+                    .map(|m| m.as_str().to_string())
+            }
+        }
+    }
+}
+
+struct Step {
+    name: String,
+    method: Method,
+    url: Template,
+    headers: Vec<(HeaderName, Template)>,
+    body: Option<Template>,
+    extract: Vec<(String, Extractor)>,
+    pause: Option<Duration>,
+}
+
+/// Where a virtual user is in the scenario
+#[derive(Default)]
+struct User {
+    next_step: usize,
+    /// Values of the row of --data the user's iteration draws, and those it extracted
+    variables: HashMap<String, String>,
+    /// Row of a checked out --data the user holds until it starts over
+    row: Option<usize>,
+}
+
+/// A request of a scenario step, filled in with the virtual user's row of --data
+pub struct StepRequest {
+    pub step: usize,
+    pub url: Url,
+    pub method: Method,
+    pub headers: HeaderMap,
+    pub body: Option<String>,
+    pub pause: Option<Duration>,
+}
+
+/// Steps every virtual user runs in order, over and over, each request of the test being
+/// the user's next step. With --data every pass through the steps draws a row, whose
+/// columns fill in the `{{name}}` placeholders of the steps.
+pub struct Scenario {
+    steps: Vec<Step>,
+    base: Url,
+    users: Vec<Mutex<User>>,
+    saved: Option<Saved>,
+}
+
+/// CSV file that the values each iteration extracted are appended to, for --data to read
+/// in a later run
+struct Saved {
+    columns: Vec<String>,
+    writer: Mutex<csv::Writer<File>>,
+}
+
+impl Scenario {
+    /// Reads a YAML or TOML scenario whose paths are relative to `base`, for up to `users`
+    /// virtual users at once, its placeholders filled in from the columns of `data`. `${KEY}`
+    /// in its strings is filled in from `env` or the environment.
+    pub fn load(
+        path: &Path,
+        base: &str,
+        users: usize,
+        data: Option<&DataFeed>,
+        env: &[(String, String)],
+    ) -> Result<Self, Box<dyn Error>> {
+        let load = || -> Result<Scenario, Box<dyn Error>> {
+            let mut file = config::read_file(path)?;
+            config::interpolate_value(&mut file, env)?;
+            Scenario::new(serde_json::from_value(file)?, base, users, data)
+        };
+        load().map_err(|e| format!("failed to load scenario {}: {}", path.display(), e).into())
+    }
+
+    /// Replays the requests of a HAR recording as steps, in the order they were made or
+    /// `shuffled` once up front, with their paths relative to `base`
+    pub fn from_recording(
+        recording: Recording,
+        base: &str,
+        users: usize,
+        data: Option<&DataFeed>,
+        shuffled: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut steps: Vec<StepFile> = recording
+            .requests
+            .into_iter()
+            .map(|request| {
+                let mut headers = BTreeMap::<String, String>::new();
+                for (name, value) in request.headers {
+                    let separator = if name == COOKIE { "; " } else { ", " };
+                    headers
+                        .entry(name.to_string())
+                        .and_modify(|joined| {
+                            joined.push_str(separator);
+                            joined.push_str(&value);
+                        })
+                        .or_insert(value);
+                }
+                StepFile {
+                    name: Some(format!("{} {}", request.method, request.path)),
+                    method: Some(request.method.to_string()),
+                    url: request.path,
+                    body: request.body,
+                    headers,
+                    pause: request
+                        .gap
+                        .map(|gap| humantime::format_duration(gap).to_string()),
+                    ..Default::default()
+                }
+            })
+            .collect();
+        if shuffled {
+            steps.shuffle(&mut rand::thread_rng());
+        }
+        Scenario::new(ScenarioFile { steps }, base, users, data)
+            .map_err(|e| format!("failed to replay the recording: {}", e).into())
+    }
+
+    fn new(
+        file: ScenarioFile,
+        base: &str,
+        users: usize,
+        data: Option<&DataFeed>,
+    ) -> Result<Self, Box<dyn Error>> {
+        if file.steps.is_empty() {
+            return Err("the scenario has no steps".into());
+        }
+        // A step's placeholders may take what the steps before it extracted
+        let mut extracted = vec![];
+        let mut steps = vec![];
+        for (i, step) in file.steps.into_iter().enumerate() {
+            let name = step
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("step {}", i + 1));
+            let step =
+                compile(step, &name, data, &extracted).map_err(|e| format!("{}: {}", name, e))?;
+            extracted.extend(step.extract.iter().map(|(variable, _)| variable.clone()));
+            steps.push(step);
+        }
+        Ok(Scenario {
+            steps,
+            base: Url::parse(base)?,
+            users: (0..users.max(1)).map(|_| Mutex::default()).collect(),
+            saved: None,
+        })
+    }
+
+    /// Appends the values of every iteration that got through all the steps to the CSV file
+    /// at `path`, one column per extracted variable, writing the header if the file is new
+    pub fn save_extracted(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let columns: Vec<String> = self
+            .steps
+            .iter()
+            .flat_map(|step| step.extract.iter().map(|(name, _)| name.clone()))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        if columns.is_empty() {
+            return Err("the scenario extracts nothing to save".into());
+        }
+        let open = || -> Result<csv::Writer<File>, Box<dyn Error>> {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            let is_new = file.metadata()?.len() == 0;
+            if !is_new {
+                let header = csv::Reader::from_path(path)?.headers()?.clone();
+                if !header.iter().eq(columns.iter()) {
+                    return Err(format!(
+                        "it has the columns {}, but the scenario extracts {}",
+                        header.iter().collect::<Vec<_>>().join(","),
+                        columns.join(",")
+                    )
+                    .into());
+                }
+            }
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(file);
+            if is_new {
+                writer.write_record(&columns)?;
+                writer.flush()?;
+            }
+            Ok(writer)
+        };
+        let writer = open().map_err(|e| {
+            format!(
+                "failed to save extracted values to {}: {}",
+                path.display(),
+                e
+            )
+        })?;
+        self.saved = Some(Saved {
+            columns,
+            writer: Mutex::new(writer),
+        });
+        Ok(())
+    }
+
+    /// The next step of a virtual user, moving it along. Each time the user starts over it
+    /// draws a row of `data`, or checks one out in place of the one it held, and tries again
+    /// on its next request when no row is free.
+    pub fn next(&self, user: usize, data: Option<&DataFeed>) -> Result<StepRequest, String> {
+        let mut state = self.user(user).lock().unwrap();
+        let index = state.next_step;
+        if index == 0 {
+            state.variables.clear();
+            if let Some(data) = data {
+                if let Some(held) = state.row.take() {
+                    data.give_back(held);
+                }
+                let row = if data.checks_out() {
+                    let Some((held, row)) = data.checkout() else {
+                        return Err(data.no_free_row().to_string());
+                    };
+                    state.row = Some(held);
+                    row
+                } else {
+                    data.draw()
+                };
+                state.variables.extend(
+                    row.pairs()
+                        .map(|(name, value)| (name.to_string(), value.to_string())),
+                );
+            }
+        }
+        state.next_step = (index + 1) % self.steps.len();
+        let step = &self.steps[index];
+        let lookup = |name: &str| state.variables.get(name).cloned();
+        let render = || -> Result<StepRequest, String> {
+            let url = step.url.render(lookup)?;
+            let url = self
+                .base
+                .join(&url)
+                .map_err(|e| format!("invalid URL `{}`: {}", url, e))?;
+            let mut headers = HeaderMap::new();
+            for (name, value) in &step.headers {
+                let value = value.render(lookup)?;
+                let value = HeaderValue::from_str(&value)
+                    .map_err(|_| format!("invalid value for header {}", name))?;
+                headers.append(name.clone(), value);
+            }
+            Ok(StepRequest {
+                step: index,
+                url,
+                method: step.method.clone(),
+                headers,
+                body: step
+                    .body
+                    .as_ref()
+                    .map(|body| body.render(lookup))
+                    .transpose()?,
+                pause: step.pause,
+            })
+        };
+        render().map_err(|e| {
+            state.next_step = 0;
+            format!("{}: {}", step.name, e)
+        })
+    }
+
+    /// Captures the step's variables from its response, starting the user over and saying
+    /// why when one is missing. The values of an iteration's last step are saved.
+    pub fn extract(
+        &self,
+        user: usize,
+        step: usize,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Option<String> {
+        let step_def = &self.steps[step];
+        let mut state = self.user(user).lock().unwrap();
+        for (name, extractor) in &step_def.extract {
+            match extractor.extract(headers, body) {
+                Some(value) => {
+                    state.variables.insert(name.clone(), value);
+                }
+                None => {
+                    state.next_step = 0;
+                    return Some(format!(
+                        "{}: nothing to extract {} from",
+                        step_def.name, name
+                    ));
+                }
+            }
+        }
+        if let Some(saved) = self.saved.as_ref().filter(|_| step == self.steps.len() - 1) {
+            let row = saved
+                .columns
+                .iter()
+                .map(|name| state.variables.get(name).map_or("", String::as_str))
+                .map(|value| secrets::redact(value).into_owned());
+            let mut writer = saved.writer.lock().unwrap();
+            // Flushed row by row so an interrupted test keeps what it created
+            if let Err(e) = writer.write_record(row).and_then(|_| Ok(writer.flush()?)) {
+                warn!("failed to save extracted values: {}", e);
+            }
+        }
+        None
+    }
+
+    /// Sends the user back to the first step, after a step failed
+    pub fn restart(&self, user: usize) {
+        self.user(user).lock().unwrap().next_step = 0;
+    }
+
+    /// Whether the user's next request is the first step, its last iteration having ended
+    pub fn starts_over(&self, user: usize) -> bool {
+        self.user(user).lock().unwrap().next_step == 0
+    }
+
+    fn user(&self, user: usize) -> &Mutex<User> {
+        &self.users[user % self.users.len()]
+    }
+}
+
+fn compile(
+    step: StepFile,
+    name: &str,
+    data: Option<&DataFeed>,
+    extracted: &[String],
+) -> Result<Step, String> {
+    let method = match step.method {
+        Some(method) => Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+            .map_err(|_| format!("invalid method `{}`", method))?,
+        None => Method::GET,
+    };
+    let mut headers = vec![];
+    for (header, value) in step.headers {
+        let header = HeaderName::from_bytes(header.as_bytes())
+            .map_err(|_| format!("invalid header name `{}`", header))?;
+        headers.push((header, Template::parse(&value)?));
+    }
+    let mut extract = vec![];
+    for (variable, extractor) in step.extract {
+        let extractor = match (extractor.json, extractor.header, extractor.regex) {
+            (Some(path), None, None) => Extractor::Json(
+                path.trim_start_matches('$')
+                    .replace('[', ".")
+                    .replace(']', "")
+                    .split('.')
+                    .filter(|key| !key.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            (None, Some(header), None) => Extractor::Header(
+                HeaderName::from_bytes(header.as_bytes())
+                    .map_err(|_| format!("invalid header name `{}`", header))?,
+            ),
+            (None, None, Some(pattern)) => Extractor::Regex(
+                Regex::new(&pattern).map_err(|e| format!("invalid regex `{}`: {}", pattern, e))?,
+            ),
+            _ => {
+                return Err(format!(
+                    "{} needs exactly one of json, header or regex",
+                    variable
+                ))
+            }
+        };
+        extract.push((variable, extractor));
+    }
+    let step = Step {
+        name: name.to_string(),
+        method,
+        url: Template::parse(&step.url)?,
+        headers,
+        body: step.body.as_deref().map(Template::parse).transpose()?,
+        extract,
+        pause: step
+            .pause
+            .map(|pause| {
+                humantime::parse_duration(&pause)
+                    .map_err(|e| format!("invalid pause `{}`: {}", pause, e))
+            })
+            .transpose()?,
+    };
+    let templates = [&step.url]
+        .into_iter()
+        .chain(step.headers.iter().map(|(_, value)| value))
+        .chain(&step.body);
+    for template in templates {
+        template.check(data, extracted)?;
+    }
+    Ok(step)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::{data::Sharing, har::Recorded};
+
+    fn write(name: &str, text: &str) -> std::path::PathBuf {
+        let path = env::temp_dir().join(format!("scenario-{}-{}", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn load_with(
+        name: &str,
+        text: &str,
+        users: usize,
+        data: Option<&DataFeed>,
+    ) -> Result<Scenario, Box<dyn Error>> {
+        let path = write(name, text);
+        let env = [("STAGE".to_string(), "staging".to_string())];
+        let scenario = Scenario::load(&path, "http://localhost:8080/api/", users, data, &env);
+        fs::remove_file(&path).unwrap();
+        scenario
+    }
+
+    fn load(name: &str, text: &str, users: usize) -> Result<Scenario, Box<dyn Error>> {
+        load_with(name, text, users, None)
+    }
+
+    fn feed(name: &str, sharing: Sharing) -> DataFeed {
+        let path = write(name, "id,token\n1,a\n2,b\n");
+        let feed = DataFeed::load(&path, sharing).unwrap();
+        fs::remove_file(&path).unwrap();
+        feed
+    }
+
+    #[test]
+    fn users_run_the_steps_in_turn() {
+        let scenario = load(
+            "turns.yaml",
+            "steps:\n  - url: /login\n    method: post\n    body: hi\n  - url: items?page=2\n    \
+             pause: 1s 250ms\n",
+            2,
+        )
+        .unwrap();
+        let first = scenario.next(0, None).unwrap();
+        assert_eq!(first.method, Method::POST);
+        assert_eq!(first.url.as_str(), "http://localhost:8080/login");
+        assert_eq!(first.body.as_deref(), Some("hi"));
+        assert_eq!(
+            scenario.next(1, None).unwrap().url.as_str(),
+            "http://localhost:8080/login"
+        );
+        let second = scenario.next(0, None).unwrap();
+        assert_eq!(second.method, Method::GET);
+        assert_eq!(first.pause, None);
+        assert_eq!(second.pause, Some(Duration::from_millis(1250)));
+        assert_eq!(
+            second.url.as_str(),
+            "http://localhost:8080/api/items?page=2"
+        );
+        assert_eq!(
+            scenario.next(0, None).unwrap().url.as_str(),
+            "http://localhost:8080/login"
+        );
+    }
+
+    #[test]
+    fn fills_each_iteration_in_from_a_row() {
+        let data = feed("rows.csv", Sharing::Checkout);
+        let text =
+            "steps:\n  - url: /users/{{id}}\n    headers: {x-token: \"{{token}}\"}\n  - url: /x\n";
+        let scenario = load_with("rows.yaml", text, 2, Some(&data)).unwrap();
+        let first = scenario.next(0, Some(&data)).unwrap();
+        assert_eq!(first.url.as_str(), "http://localhost:8080/users/1");
+        assert_eq!(first.headers["x-token"], "a");
+        let other = scenario.next(1, Some(&data)).unwrap();
+        assert_eq!(other.url.as_str(), "http://localhost:8080/users/2");
+        // Both rows are held until their users start over
+        let third = load_with("rows.yaml", text, 3, Some(&data)).unwrap();
+        assert!(third.next(2, Some(&data)).is_err());
+        scenario.next(0, Some(&data)).unwrap();
+        let again = scenario.next(0, Some(&data)).unwrap();
+        assert_eq!(again.url.as_str(), "http://localhost:8080/users/1");
+
+        let error = load_with(
+            "column.yaml",
+            "steps:\n  - url: /{{name}}\n",
+            1,
+            Some(&data),
+        )
+        .err()
+        .unwrap();
+        assert!(error
+            .to_string()
+            .ends_with("step 1: --data has no name column for {{name}}"));
+        assert!(load("nodata.yaml", "steps:\n  - url: /{{id}}\n", 1).is_err());
+    }
+
+    #[test]
+    fn saves_what_each_iteration_extracted() {
+        let text = "steps:\n  - url: /items\n    method: post\n    extract:\n      id: { json: 'data.items[0].id' }\n      \
+                    at: { header: location }\n  - url: /done\n    extract:\n      word: { regex: 'é(\\w+)' }\n";
+        let mut scenario = load("extract.yaml", text, 1).unwrap();
+        let path = write("extracted.csv", "");
+        fs::remove_file(&path).unwrap();
+        scenario.save_extracted(&path).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("location", HeaderValue::from_static("/items/7"));
+        let body = br#"{"data": {"items": [{"id": 7}]}}"#;
+        assert_eq!(scenario.next(0, None).unwrap().step, 0);
+        assert_eq!(scenario.extract(0, 0, &headers, body), None);
+        assert_eq!(scenario.next(0, None).unwrap().step, 1);
+        let missing = scenario.extract(0, 1, &headers, b"none");
+        assert_eq!(
+            missing.as_deref(),
+            Some("step 2: nothing to extract word from")
+        );
+        // A missing value starts the user over, and only the complete iteration is saved
+        assert_eq!(scenario.next(0, None).unwrap().step, 0);
+        assert_eq!(scenario.extract(0, 0, &headers, body), None);
+        scenario.next(0, None).unwrap();
+        assert_eq!(scenario.extract(0, 1, &headers, "éok".as_bytes()), None);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "at,id,word\n/items/7,7,ok\n"
+        );
+
+        // A later run appends to the file, unless its columns differ
+        let mut again = load("extract.yaml", text, 1).unwrap();
+        again.save_extracted(&path).unwrap();
+        let mut other = load(
+            "other.yaml",
+            "steps:\n  - url: /\n    extract: {x: {header: a}}\n",
+            1,
+        )
+        .unwrap();
+        assert!(other.save_extracted(&path).is_err());
+        fs::remove_file(&path).unwrap();
+        let mut none = load("none.yaml", "steps:\n  - url: /\n", 1).unwrap();
+        assert!(none.save_extracted(&path).is_err());
+        assert!(load(
+            "both.yaml",
+            "steps:\n  - url: /\n    extract: {x: {header: a, regex: b}}\n",
+            1
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn chains_extracted_values_into_later_steps() {
+        let text = "steps:\n  - url: /items\n    method: post\n    extract:\n      id: { json: data.id }\n  \
+                    - url: /items/{{id}}\n    headers: {x-item: \"{{ id }}\"}\n";
+        let scenario = load("chain.yaml", text, 2).unwrap();
+        scenario.next(0, None).unwrap();
+        scenario.next(1, None).unwrap();
+        let created = |id: &str| format!(r#"{{"data": {{"id": "{}"}}}}"#, id);
+        assert_eq!(
+            scenario.extract(0, 0, &HeaderMap::new(), created("a1").as_bytes()),
+            None
+        );
+        assert_eq!(
+            scenario.extract(1, 0, &HeaderMap::new(), created("b2").as_bytes()),
+            None
+        );
+        // Each user fills in what its own responses returned
+        let fetch = scenario.next(1, None).unwrap();
+        assert_eq!(fetch.url.as_str(), "http://localhost:8080/items/b2");
+        assert_eq!(fetch.headers["x-item"], "b2");
+        let fetch = scenario.next(0, None).unwrap();
+        assert_eq!(fetch.url.as_str(), "http://localhost:8080/items/a1");
+
+        // Only earlier steps fill in a placeholder
+        let backwards =
+            "steps:\n  - url: /items/{{id}}\n  - url: /items\n    extract: {id: {json: id}}\n";
+        assert!(load("backwards.yaml", backwards, 1).is_err());
+    }
+
+    #[test]
+    fn replays_a_recording() {
+        let recorded = |method: Method, path: &str, headers: Vec<(HeaderName, &str)>| Recorded {
+            method,
+            path: path.to_string(),
+            headers: headers
+                .into_iter()
+                .map(|(name, value)| (name, value.to_string()))
+                .collect(),
+            body: None,
+            gap: None,
+        };
+        let recording = || Recording {
+            origin: "https://shop.example.com".to_string(),
+            requests: vec![
+                recorded(
+                    Method::GET,
+                    "/cart?x=1",
+                    vec![(COOKIE, "a=1"), (COOKIE, "b=2")],
+                ),
+                Recorded {
+                    body: Some("{}".to_string()),
+                    gap: Some(Duration::from_millis(1250)),
+                    ..recorded(Method::POST, "/checkout", vec![])
+                },
+            ],
+        };
+        let scenario =
+            Scenario::from_recording(recording(), "http://localhost:8080/api/", 1, None, false)
+                .unwrap();
+        let cart = scenario.next(0, None).unwrap();
+        assert_eq!(cart.url.as_str(), "http://localhost:8080/cart?x=1");
+        assert_eq!(cart.headers[COOKIE], "a=1; b=2");
+        let checkout = scenario.next(0, None).unwrap();
+        assert_eq!(checkout.method, Method::POST);
+        assert_eq!(checkout.body.as_deref(), Some("{}"));
+        assert_eq!(checkout.pause, Some(Duration::from_millis(1250)));
+
+        let shuffled =
+            Scenario::from_recording(recording(), "http://localhost:8080/", 1, None, true).unwrap();
+        let mut paths: Vec<_> = (0..2)
+            .map(|_| shuffled.next(0, None).unwrap().url.path().to_string())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, ["/cart", "/checkout"]);
+    }
+
+    #[test]
+    fn reads_toml_with_headers() {
+        let scenario = load(
+            "headers.toml",
+            "[[steps]]\nurl = \"/\"\n[steps.headers]\naccept = \"text/html\"\n",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            scenario.next(0, None).unwrap().headers["accept"],
+            "text/html"
+        );
+        let scenario = load(
+            "env.toml",
+            "[[steps]]\nurl = \"/${STAGE}/${LOAD_TESTER_TEST_UNSET:-x}?p=$${STAGE}\"\n",
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            scenario.next(0, None).unwrap().url.as_str(),
+            "http://localhost:8080/staging/x?p=${STAGE}"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_steps() {
+        let error = load("empty.yaml", "steps: []\n", 1).err().unwrap();
+        assert!(error.to_string().ends_with("the scenario has no steps"));
+        let error = load(
+            "header.yaml",
+            "steps:\n  - url: /\n    headers: {\"a b\": c}\n",
+            1,
+        )
+        .err()
+        .unwrap();
+        assert!(error
+            .to_string()
+            .ends_with("step 1: invalid header name `a b`"));
+        let error = load("pause.yaml", "steps:\n  - url: /\n    pause: \"5\"\n", 1)
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .ends_with("step 1: invalid pause `5`: time unit needed, for example 5sec or 5ms"));
+        assert!(load("unknown.yaml", "steps:\n  - url: /\n    wait: 1\n", 1).is_err());
+        assert!(load("scenario.json", "{}", 1).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use std::{error::Error, path::Path, sync::Mutex};
+
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Method, StatusCode,
+};
+use rhai::{Blob, CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+
+/// A Rhai script shaping the test's requests and checking their responses. It may define
+///
+/// - `fn request(ctx)`, called before every request with `#{ user, id, url, method }`, and
+///   returning a map whose `url`, `method`, `headers` and `body` replace the target's, or `()`
+///   to send it unchanged
+/// - `fn check(response)`, called with `#{ status, headers, body }` after every response, and
+///   returning `false` or a reason to count it as failed
+///
+/// Both run with `this` bound to a map private to the virtual user, kept between its requests,
+/// e.g. for a token obtained by its first one.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    has_request: bool,
+    has_check: bool,
+    /// State of each virtual user
+    users: Vec<Mutex<Dynamic>>,
+}
+
+/// What `request` changed about a request
+#[derive(Debug, Default)]
+pub struct Scripted {
+    pub url: Option<String>,
+    pub method: Option<Method>,
+    /// Added to the test's headers, replacing those of the same name
+    pub headers: HeaderMap,
+    pub body: Option<Vec<u8>>,
+}
+
+impl Script {
+    /// Compiles the script, `users` being the most virtual users the test runs at once
+    pub fn load(path: &Path, users: usize) -> Result<Self, Box<dyn Error>> {
+        let engine = Engine::new();
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| format!("failed to load script {}: {}", path.display(), e))?;
+        let defines = |name: &str| {
+            ast.iter_functions()
+                .any(|f| f.name == name && f.params.len() == 1)
+        };
+        let (has_request, has_check) = (defines("request"), defines("check"));
+        if !has_request && !has_check {
+            return Err(format!(
+                "script {} defines neither request(ctx) nor check(response)",
+                path.display()
+            )
+            .into());
+        }
+        Ok(Script {
+            engine,
+            ast,
+            has_request,
+            has_check,
+            users: (0..users.max(1))
+                .map(|_| Mutex::new(Dynamic::from_map(Map::new())))
+                .collect(),
+        })
+    }
+
+    /// Runs `request` for the request numbered `id` of a virtual user
+    pub fn request(
+        &self,
+        user: usize,
+        id: u64,
+        url: &str,
+        method: &Method,
+    ) -> Result<Scripted, String> {
+        if !self.has_request {
+            return Ok(Scripted::default());
+        }
+        let mut ctx = Map::new();
+        ctx.insert("user".into(), (user as i64).into());
+        ctx.insert("id".into(), (id as i64).into());
+        ctx.insert("url".into(), url.into());
+        ctx.insert("method".into(), method.as_str().into());
+        let returned = self.call("request", user, ctx)?;
+        if returned.is_unit() {
+            return Ok(Scripted::default());
+        }
+        let mut returned = returned
+            .try_cast::<Map>()
+            .ok_or("request() must return a map or ()")?;
+
+        let mut scripted = Scripted::default();
+        if let Some(url) = returned.remove("url") {
+            scripted.url = Some(url.to_string());
+        }
+        if let Some(method) = returned.remove("method") {
+            let method = method.to_string();
+            scripted.method = Some(
+                Method::from_bytes(method.to_uppercase().as_bytes())
+                    .map_err(|_| format!("invalid method `{}`", method))?,
+            );
+        }
+        if let Some(headers) = returned.remove("headers") {
+            let headers = headers
+                .try_cast::<Map>()
+                .ok_or("headers must be a map of names to values")?;
+            for (name, value) in headers {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| format!("invalid header name `{}`", name))?;
+                let value = HeaderValue::from_str(&value.to_string())
+                    .map_err(|_| format!("invalid value for header {}", name))?;
+                scripted.headers.insert(name, value);
+            }
+        }
+        if let Some(body) = returned.remove("body") {
+            scripted.body = Some(if body.is_blob() {
+                body.cast::<Blob>()
+            } else {
+                body.to_string().into_bytes()
+            });
+        }
+        Ok(scripted)
+    }
+
+    /// Runs `check` on a response, returning why it failed if it did. The body is empty when
+    /// it wasn't kept, with --download.
+    pub fn check(
+        &self,
+        user: usize,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Option<String> {
+        if !self.has_check {
+            return None;
+        }
+        let mut response = Map::new();
+        response.insert("status".into(), (status.as_u16() as i64).into());
+        let mut header_map = Map::new();
+        for (name, value) in headers {
+            header_map.insert(
+                name.as_str().into(),
+                String::from_utf8_lossy(value.as_bytes())
+                    .into_owned()
+                    .into(),
+            );
+        }
+        response.insert("headers".into(), header_map.into());
+        response.insert(
+            "body".into(),
+            String::from_utf8_lossy(body).into_owned().into(),
+        );
+        match self.call("check", user, response) {
+            Ok(verdict) => match verdict.as_bool() {
+                Ok(true) => None,
+                Ok(false) => Some("check() returned false".to_string()),
+                Err(_) if verdict.is_unit() => None,
+                Err(_) => Some(verdict.to_string()),
+            },
+            Err(e) => Some(e),
+        }
+    }
+
+    fn call(&self, name: &str, user: usize, arg: Map) -> Result<Dynamic, String> {
+        let mut state = self.users[user % self.users.len()].lock().unwrap();
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut state);
+        self.engine
+            .call_fn_with_options(options, &mut Scope::new(), &self.ast, name, (arg,))
+            .map_err(|e| format!("{}() failed: {}", name, e))
+    }
+}
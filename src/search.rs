@@ -0,0 +1,265 @@
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::{
+    latency::LatencyHistogram,
+    thresholds::{self, Observed, Threshold},
+    Stats,
+};
+
+/// Bisecting stops once the highest load that held is within this fraction of the lowest
+/// that didn't
+const PRECISION: f64 = 0.1;
+
+/// Least load worth trying, one user or one request per second
+const FLOOR: f64 = 1.0;
+
+/// A search for the most load a server sustains: each step runs one load for a while, doubling
+/// it while every limit holds, then bisecting between the highest load that held and the
+/// lowest that didn't
+pub struct Search {
+    limits: Vec<Threshold>,
+    step: Duration,
+    /// Most load to try, the test having no more users than this with the closed model
+    ceiling: Option<f64>,
+    /// Concurrency, which only comes in whole users, rather than an arrival rate
+    users: bool,
+    load: f64,
+    started: Instant,
+    current: StepStats,
+    held: Option<f64>,
+    broke: Option<f64>,
+    steps: Vec<Step>,
+}
+
+/// How one load did
+#[derive(Debug, Clone, Serialize)]
+pub struct Step {
+    pub load: f64,
+    pub successful: u64,
+    pub failed: u64,
+    pub rps: f64,
+    /// Limits the load broke, each with what was measured, e.g. `p99<1s (1.2s)`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missed: Vec<String>,
+}
+
+impl Step {
+    pub fn held(&self) -> bool {
+        self.missed.is_empty()
+    }
+}
+
+/// Outcomes of the requests that finished during the current step
+#[derive(Default)]
+struct StepStats {
+    successful: u64,
+    failed: u64,
+    ttfb: LatencyHistogram,
+}
+
+impl Search {
+    pub fn new(
+        start: Instant,
+        load: f64,
+        ceiling: Option<f64>,
+        users: bool,
+        step: Duration,
+        limits: Vec<Threshold>,
+    ) -> Self {
+        Search {
+            limits,
+            step,
+            ceiling,
+            users,
+            load,
+            started: start,
+            current: StepStats::default(),
+            held: None,
+            broke: None,
+            steps: vec![],
+        }
+    }
+
+    /// Concurrency with the closed model, arrival rate with the open one
+    pub fn load(&self) -> f64 {
+        self.load
+    }
+
+    /// Whether the load is a concurrency rather than an arrival rate
+    pub fn users(&self) -> bool {
+        self.users
+    }
+
+    pub fn limits(&self) -> &[Threshold] {
+        &self.limits
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// The step that held with the most successful requests per second
+    pub fn maximum(&self) -> Option<&Step> {
+        self.steps
+            .iter()
+            .filter(|step| step.held())
+            .max_by(|a, b| a.rps.total_cmp(&b.rps))
+    }
+
+    pub fn record(&mut self, outcome: &Result<Stats, reqwest::Error>) {
+        match outcome {
+            Ok(resp) if resp.succeeded() => {
+                self.current.successful += 1;
+                self.current.ttfb.record(resp.ttfb);
+            }
+            _ => self.current.failed += 1,
+        }
+    }
+
+    /// An arrival the open model dropped is a request the server couldn't take
+    pub fn record_dropped(&mut self) {
+        self.current.failed += 1;
+    }
+
+    /// The current step has run its course and is waiting to be judged
+    pub fn step_over(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.started) >= self.step
+    }
+
+    /// Judges the current step and moves on to the next, returning false when the search is
+    /// over
+    pub fn advance(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.started);
+        let stats = std::mem::take(&mut self.current);
+        let observed = Observed {
+            successful: stats.successful,
+            failed: stats.failed,
+            ttfb: &stats.ttfb,
+            duration: elapsed.as_secs_f32(),
+        };
+        let mut missed: Vec<String> = thresholds::evaluate(&self.limits, &observed)
+            .iter()
+            .filter(|verdict| !verdict.passed)
+            .map(|verdict| format!("{} ({})", verdict.threshold, verdict.actual))
+            .collect();
+        if stats.successful + stats.failed == 0 {
+            missed.push("no responses".to_string());
+        }
+        let step = Step {
+            load: self.load,
+            successful: stats.successful,
+            failed: stats.failed,
+            rps: stats.successful as f64 / elapsed.as_secs_f64(),
+            missed,
+        };
+        if step.held() {
+            self.held = Some(self.load);
+        } else {
+            self.broke = Some(self.load);
+        }
+        self.steps.push(step);
+        self.started = now;
+        match self.next_load() {
+            Some(load) => {
+                self.load = load;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Load the next step tries, None once the search has narrowed it down
+    fn next_load(&self) -> Option<f64> {
+        let next = match (self.held, self.broke) {
+            (Some(held), Some(broke)) if broke - held <= held * PRECISION => return None,
+            (Some(held), Some(broke)) => (held + broke) / 2.0,
+            (Some(held), None) if Some(held) == self.ceiling => return None,
+            (Some(held), None) => self
+                .ceiling
+                .map_or(held * 2.0, |ceiling| (held * 2.0).min(ceiling)),
+            (None, Some(broke)) => broke / 2.0,
+            (None, None) => return Some(self.load),
+        };
+        let next = if self.users { next.floor() } else { next };
+        // No whole user left between the two, or nothing left to halve
+        (next >= FLOOR && Some(next) != self.held).then_some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_then_bisects() {
+        let limits = vec![thresholds::parse_threshold("error_rate<1%").unwrap()];
+        let start = Instant::now();
+        let mut search = Search::new(
+            start,
+            1.0,
+            Some(100.0),
+            true,
+            Duration::from_secs(10),
+            limits,
+        );
+        let mut at = start;
+        let mut tried = vec![];
+        // The server holds up to 37 users
+        loop {
+            tried.push(search.load());
+            if search.load() > 37.0 {
+                search.record(&Err(failure()));
+            } else {
+                search.record(&Ok(success()));
+            }
+            assert!(!search.step_over(at + Duration::from_secs(9)));
+            at += Duration::from_secs(10);
+            assert!(search.step_over(at));
+            if !search.advance(at) {
+                break;
+            }
+        }
+        assert_eq!(
+            tried,
+            [1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 48.0, 40.0, 36.0, 38.0]
+        );
+        assert_eq!(search.maximum().unwrap().load, 36.0);
+        assert!(!search.steps().last().unwrap().held());
+
+        // Every load held, up to the ceiling
+        let mut search = Search::new(start, 5.0, Some(8.0), true, Duration::ZERO, vec![]);
+        search.record(&Ok(success()));
+        assert!(search.advance(start));
+        assert_eq!(search.load(), 8.0);
+        search.record(&Ok(success()));
+        assert!(!search.advance(start));
+        assert_eq!(search.maximum().unwrap().load, 8.0);
+    }
+
+    fn success() -> Stats {
+        Stats {
+            ttfb: 0.01,
+            ttlb: 0.01,
+            total_time: 0.01,
+            finished_at: Instant::now(),
+            bytes: 0,
+            decoded_bytes: Some(0),
+            status: reqwest::StatusCode::OK,
+            version: reqwest::Version::HTTP_11,
+            range_satisfied: true,
+            pin_matched: true,
+            retry_after: None,
+            failed_check: None,
+            remote_addr: None,
+            failed_assertion: None,
+            script_failure: None,
+            expected: true,
+        }
+    }
+
+    fn failure() -> reqwest::Error {
+        reqwest::Client::new().get("not a url").build().unwrap_err()
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt, fs,
+    io::{self, Write},
+    sync::RwLock,
+};
+
+use reqwest::header::{HeaderMap, HeaderName, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION};
+use tracing::warn;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// What a secret is replaced with wherever the tool writes text
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Shorter values would be found all over a report, so they aren't redacted
+const MIN_REDACTED_LEN: usize = 4;
+
+/// Headers whose values are credentials and hidden like secrets
+const SENSITIVE_HEADERS: [HeaderName; 3] = [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE];
+
+/// Named secrets of --secret, which templates fill in like any placeholder
+static NAMED: RwLock<Option<HashMap<String, String>>> = RwLock::new(None);
+
+/// Every value to hide, longest first so a secret containing another is hidden whole
+static HIDDEN: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// A value given with --secret, read when the option is parsed so it never sits on the
+/// command line
+#[derive(Clone)]
+pub struct Secret {
+    pub name: String,
+    value: String,
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({}={})", self.name, REDACTED)
+    }
+}
+
+/// Parses `NAME=env:VAR` or `NAME=file:PATH`. A file's trailing newline isn't part of the
+/// secret.
+pub fn parse_secret(spec: &str) -> Result<Secret, String> {
+    let (name, source) = spec
+        .split_once('=')
+        .filter(|(name, _)| !name.is_empty())
+        .ok_or_else(|| format!("expected NAME=env:VAR or NAME=file:PATH, got `{}`", spec))?;
+    let value = if let Some(var) = source.strip_prefix("env:") {
+        std::env::var(var).map_err(|_| format!("environment variable `{}` is not set", var))?
+    } else if let Some(path) = source.strip_prefix("file:") {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+        contents.trim_end_matches(['\r', '\n']).to_string()
+    } else {
+        return Err(format!(
+            "expected the secret `{}` to come from env:VAR or file:PATH",
+            name
+        ));
+    };
+    Ok(Secret {
+        name: name.to_string(),
+        value,
+    })
+}
+
+/// Makes the secrets available to templates as `{{NAME}}` and hides their values
+pub fn install(secrets: &[Secret]) {
+    let mut named = NAMED.write().unwrap();
+    let named = named.get_or_insert_with(HashMap::new);
+    for secret in secrets {
+        if secret.value.len() < MIN_REDACTED_LEN {
+            warn!(
+                "secret `{}` is too short to be redacted from the output",
+                secret.name
+            );
+        }
+        hide(&secret.value);
+        named.insert(secret.name.clone(), secret.value.clone());
+    }
+}
+
+/// The value of the secret named `name`, for a placeholder nothing else fills in
+pub fn value(name: &str) -> Option<String> {
+    NAMED.read().unwrap().as_ref()?.get(name).cloned()
+}
+
+/// Hides the credentials among `headers`, e.g. from --bearer, --basic or -H 'Cookie: ...'
+pub fn hide_headers(headers: &HeaderMap) {
+    for name in &SENSITIVE_HEADERS {
+        for value in headers.get_all(name) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            hide(value);
+            // The credentials alone, e.g. the token of `Bearer <token>`
+            if let Some((_, credentials)) = value.split_once(' ') {
+                hide(credentials.trim());
+            }
+        }
+    }
+}
+
+/// Adds a value to those redacted from the output, along with its JSON-escaped form
+pub fn hide(value: &str) {
+    if value.len() < MIN_REDACTED_LEN {
+        return;
+    }
+    let escaped = serde_json::to_string(value).unwrap_or_default();
+    let escaped = &escaped[1..escaped.len() - 1];
+    let mut hidden = HIDDEN.write().unwrap();
+    for value in [value, escaped] {
+        if !hidden.iter().any(|hidden| hidden == value) {
+            hidden.push(value.to_string());
+        }
+    }
+    hidden.sort_by_key(|value| std::cmp::Reverse(value.len()));
+}
+
+/// `text` with every hidden value replaced by REDACTED
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let hidden = HIDDEN.read().unwrap();
+    let mut text = Cow::Borrowed(text);
+    for value in hidden.iter() {
+        if text.contains(value.as_str()) {
+            text = Cow::Owned(text.replace(value.as_str(), REDACTED));
+        }
+    }
+    text
+}
+
+/// Log writer redacting each line on its way out. The formatter writes an event in one go,
+/// so a secret is never split across writes.
+pub struct Redacting<W>(pub W);
+
+impl<W: Write> Write for Redacting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match std::str::from_utf8(buf) {
+            Ok(text) => self.0.write_all(redact(text).as_bytes())?,
+            Err(_) => self.0.write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = Redacting<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Redacting(self.0.make_writer())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The secrets are global, so each test hides values of its own
+
+    #[test]
+    fn reads_secrets_from_files() {
+        let path = std::env::temp_dir().join(format!("load-tester-{}-secret", std::process::id()));
+        fs::write(&path, "tökén\r\n\n").unwrap();
+        let secret = parse_secret(&format!("token=file:{}", path.display())).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(
+            (secret.name.as_str(), secret.value.as_str()),
+            ("token", "tökén")
+        );
+        assert_eq!(format!("{:?}", secret), "Secret(token=[REDACTED])");
+    }
+
+    #[test]
+    fn rejects_malformed_secrets() {
+        for spec in [
+            "",
+            "token",
+            "=env:HOME",
+            "token=HOME",
+            "token=env:LOAD_TESTER_TEST_UNSET",
+            "token=file:/nonexistent/secret",
+        ] {
+            assert!(parse_secret(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn redacts_hidden_values() {
+        hide("s3cr\"et-wholé");
+        hide("s3cr");
+        hide("abc");
+        assert_eq!(
+            redact("a s3cr\"et-wholé b s3cr c abc"),
+            "a [REDACTED] b [REDACTED] c abc"
+        );
+        assert_eq!(redact(r#"{"v":"s3cr\"et-wholé"}"#), r#"{"v":"[REDACTED]"}"#);
+        assert!(matches!(redact("nothing here"), Cow::Borrowed(_)));
+        assert_eq!(redact(""), "");
+    }
+
+    #[test]
+    fn hides_credential_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer tok-4711".parse().unwrap());
+        headers.insert(COOKIE, "sid=c00kie-4711".parse().unwrap());
+        hide_headers(&headers);
+        assert_eq!(
+            redact("tok-4711 / Bearer tok-4711 / sid=c00kie-4711"),
+            "[REDACTED] / [REDACTED] / [REDACTED]"
+        );
+    }
+
+    #[test]
+    fn names_installed_secrets() {
+        install(&[Secret {
+            name: "installed_token".to_string(),
+            value: "v4lue-installed".to_string(),
+        }]);
+        assert_eq!(value("installed_token").as_deref(), Some("v4lue-installed"));
+        assert_eq!(value("never_installed"), None);
+        assert_eq!(redact("v4lue-installed"), REDACTED);
+    }
+}
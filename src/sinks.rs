@@ -0,0 +1,167 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{header::AUTHORIZATION, Url};
+use tokio::{net::UdpSocket, sync::broadcast, task::JoinHandle};
+use tracing::{debug, warn};
+
+use crate::{events::Event, latency::LatencyHistogram};
+
+/// Metrics of one interval of the test, folded from the engine's events
+#[derive(Default)]
+struct Interval {
+    started: u64,
+    responses: BTreeMap<u16, u64>,
+    errors: u64,
+    bytes: u64,
+    ttfb: LatencyHistogram,
+    ttlb: LatencyHistogram,
+}
+
+impl Interval {
+    fn record(&mut self, event: &Event) {
+        match event {
+            Event::RequestScheduled { .. } => self.started += 1,
+            Event::Headers { status, ttfb, .. } => {
+                *self.responses.entry(status.as_u16()).or_default() += 1;
+                self.ttfb.record(*ttfb);
+            }
+            Event::BodyDone { bytes, ttlb, .. } => {
+                self.bytes += bytes;
+                self.ttlb.record(*ttlb);
+            }
+            Event::Error { .. } => self.errors += 1,
+            Event::Connect { .. } | Event::IntervalSummary { .. } | Event::Finished { .. } => {}
+        }
+    }
+
+    /// Counters and millisecond gauges in the StatsD format, one metric per line
+    fn statsd(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "load_tester.requests:{}|c", self.started);
+        let _ = writeln!(out, "load_tester.errors:{}|c", self.errors);
+        let _ = writeln!(out, "load_tester.bytes:{}|c", self.bytes);
+        for (status, count) in &self.responses {
+            let _ = writeln!(out, "load_tester.responses.{}:{}|c", status, count);
+        }
+        for (name, histogram) in [("ttfb", &self.ttfb), ("ttlb", &self.ttlb)] {
+            for p in [50.0, 95.0, 99.0] {
+                let _ = writeln!(
+                    out,
+                    "load_tester.{}.p{}:{:.3}|g",
+                    name,
+                    p,
+                    histogram.percentile(p) * 1000.0
+                );
+            }
+        }
+        out
+    }
+
+    /// Points in the InfluxDB line protocol, stamped with `timestamp` in nanoseconds
+    fn influx(&self, width: Duration, timestamp: u128) -> String {
+        let mut fields = format!(
+            "requests={}i,errors={}i,bytes={}i,rps={}",
+            self.started,
+            self.errors,
+            self.bytes,
+            self.responses.values().sum::<u64>() as f64 / width.as_secs_f64()
+        );
+        for (name, histogram) in [("ttfb", &self.ttfb), ("ttlb", &self.ttlb)] {
+            for p in [50.0, 95.0, 99.0] {
+                let _ = write!(fields, ",{}_p{}={}", name, p, histogram.percentile(p));
+            }
+        }
+        let mut out = format!("load_tester {} {}\n", fields, timestamp);
+        for (status, count) in &self.responses {
+            let _ = writeln!(
+                out,
+                "load_tester_responses,status={} count={}i {}",
+                status, count, timestamp
+            );
+        }
+        out
+    }
+}
+
+/// Where per-interval metrics are streamed to while the test runs
+pub struct Sinks {
+    /// StatsD daemon, e.g. localhost:8125
+    pub statsd: Option<String>,
+    /// InfluxDB write endpoint the line protocol is posted to, e.g.
+    /// http://localhost:8086/api/v2/write?org=o&bucket=b. An INFLUX_TOKEN in the
+    /// environment is sent as its token.
+    pub influx: Option<Url>,
+}
+
+/// Sends the metrics of every `period` to the sinks until the test finishes, the last
+/// partial interval included. A sink that can't be reached is logged and skipped, it never
+/// fails the test.
+pub async fn spawn(
+    sinks: Sinks,
+    period: Duration,
+    client: reqwest::Client,
+    mut events: broadcast::Receiver<Event>,
+) -> std::io::Result<JoinHandle<()>> {
+    let statsd = match &sinks.statsd {
+        Some(addr) => {
+            let socket = UdpSocket::bind("0.0.0.0:0").await?;
+            socket.connect(addr).await?;
+            Some(socket)
+        }
+        None => None,
+    };
+    let token = std::env::var("INFLUX_TOKEN").ok();
+
+    Ok(tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(period);
+        ticks.tick().await;
+        let mut interval = Interval::default();
+        let mut interval_start = tokio::time::Instant::now();
+        loop {
+            let finished = tokio::select! {
+                _ = ticks.tick() => false,
+                event = events.recv() => match event {
+                    Ok(Event::Finished { .. }) | Err(broadcast::error::RecvError::Closed) => true,
+                    Ok(event) => {
+                        interval.record(&event);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        debug!(missed, "metrics sinks fell behind the engine");
+                        continue;
+                    }
+                },
+            };
+
+            let sent = std::mem::take(&mut interval);
+            let width = interval_start.elapsed();
+            interval_start = tokio::time::Instant::now();
+            if let Some(socket) = &statsd {
+                if let Err(e) = socket.send(sent.statsd().as_bytes()).await {
+                    warn!("failed to send metrics to StatsD: {}", e);
+                }
+            }
+            if let Some(url) = &sinks.influx {
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos();
+                let mut request = client.post(url.clone()).body(sent.influx(width, timestamp));
+                if let Some(token) = &token {
+                    request = request.header(AUTHORIZATION, format!("Token {}", token));
+                }
+                match request.send().await.and_then(|res| res.error_for_status()) {
+                    Ok(_) => {}
+                    Err(e) => warn!("failed to write metrics to InfluxDB: {}", e),
+                }
+            }
+            if finished {
+                return;
+            }
+        }
+    }))
+}
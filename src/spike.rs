@@ -0,0 +1,257 @@
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::{latency::LatencyHistogram, trend::Series, Stats};
+
+/// Percentile of the time to first byte recovery is judged on
+const PERCENTILE: f32 = 95.0;
+
+/// How far above its baseline a second's latency may be and still count as recovered
+const RECOVERY_MARGIN: f32 = 0.25;
+
+/// Sudden bursts of load: `baseline` jumping to `spike` for `duration`, `repeat` times
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spike {
+    /// Concurrency with the closed model, arrival rate with the open one, as is `spike`
+    pub baseline: f64,
+    pub spike: f64,
+    pub duration: Duration,
+    pub repeat: u32,
+}
+
+impl Spike {
+    /// How long a test with `gap` at the baseline around each spike runs
+    pub fn test_duration(&self, gap: Duration) -> Duration {
+        gap + (self.duration + gap) * self.repeat
+    }
+}
+
+/// Parses `<baseline>:<spike>:<duration>:<repeat>`, e.g. `50:500:30s:3`
+pub fn parse_spike(spec: &str) -> Result<Spike, String> {
+    let invalid = || {
+        format!(
+            "invalid spike `{}`, expected <baseline>:<spike>:<duration>:<repeat>",
+            spec
+        )
+    };
+    let parts: Vec<&str> = spec.split(':').map(str::trim).collect();
+    let [baseline, spike, duration, repeat] = parts[..] else {
+        return Err(invalid());
+    };
+    let load = |value: &str| {
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|load| load.is_finite() && *load >= 0.0)
+            .ok_or_else(invalid)
+    };
+    let spike = Spike {
+        baseline: load(baseline)?,
+        spike: load(spike)?,
+        duration: humantime::parse_duration(duration).map_err(|_| invalid())?,
+        repeat: repeat.parse().map_err(|_| invalid())?,
+    };
+    if spike.duration.is_zero() || spike.repeat == 0 {
+        return Err(invalid());
+    }
+    Ok(spike)
+}
+
+/// When a spike test is at its baseline and when it spikes, timed from when it started: `gap`
+/// at the baseline before each spike and after the last
+#[derive(Clone)]
+pub struct SpikePlan {
+    start: Instant,
+    spike: Spike,
+    gap: Duration,
+}
+
+impl SpikePlan {
+    /// Load the plan calls for now
+    pub fn target(&self) -> f64 {
+        match self.phase(Instant::now()) % 2 {
+            0 => self.spike.baseline,
+            _ => self.spike.spike,
+        }
+    }
+
+    /// The phase running at `at`, even ones at the baseline and odd ones spikes, the last one
+    /// past the end
+    fn phase(&self, at: Instant) -> usize {
+        let phases = 2 * self.spike.repeat as usize + 1;
+        let mut into = at.saturating_duration_since(self.start);
+        for phase in 0..phases {
+            let length = match phase % 2 {
+                0 => self.gap,
+                _ => self.spike.duration,
+            };
+            if into < length || phase == phases - 1 {
+                return phase;
+            }
+            into -= length;
+        }
+        unreachable!("a spike test has at least one phase")
+    }
+
+    /// How far into the test the spike `index` ends
+    fn spike_end(&self, index: usize) -> Duration {
+        (self.gap + self.spike.duration) * (index as u32 + 1)
+    }
+}
+
+/// A spike test and the latency of the requests that finished in each of its phases and in
+/// each second of it
+pub struct Spikes {
+    plan: SpikePlan,
+    phases: Vec<LatencyHistogram>,
+    /// p95 of each second
+    seconds: Series,
+}
+
+/// How latency went through one spike and came back after it
+#[derive(Debug, Serialize)]
+pub struct Recovery {
+    /// p95 time to first byte in seconds over the baseline before the first spike, which a
+    /// spike's aftermath can't have inflated
+    pub baseline_p95: f32,
+    pub spike_p95: f32,
+    /// Worst p95 of a second in the baseline after the spike
+    pub peak_p95: f32,
+    /// Seconds from the end of the spike to the first second whose p95 was back within a
+    /// quarter of the baseline, None if none was before the next spike or the end
+    pub time_to_recover: Option<f32>,
+}
+
+impl Spikes {
+    pub fn new(start: Instant, spike: Spike, gap: Duration) -> Self {
+        Spikes {
+            plan: SpikePlan { start, spike, gap },
+            phases: vec![LatencyHistogram::default(); 2 * spike.repeat as usize + 1],
+            seconds: Series::new(start, Duration::from_secs(1), vec![PERCENTILE]),
+        }
+    }
+
+    pub fn plan(&self) -> &SpikePlan {
+        &self.plan
+    }
+
+    pub fn spike(&self) -> &Spike {
+        &self.plan.spike
+    }
+
+    /// Counts a successful request towards the phase and the second it finished in
+    pub fn record(&mut self, finished_at: Instant, outcome: &Result<Stats, reqwest::Error>) {
+        let resp = match outcome {
+            Ok(resp) if resp.succeeded() => resp,
+            _ => return,
+        };
+        self.phases[self.plan.phase(finished_at)].record(resp.ttfb);
+        self.seconds.record(finished_at, resp.ttfb);
+    }
+
+    fn second_p95(&self, second: usize) -> Option<f32> {
+        self.seconds.slice(second).map(|slice| slice.values[0])
+    }
+
+    /// How latency recovered after each spike
+    pub fn recoveries(&self) -> Vec<Recovery> {
+        let baseline_p95 = self.phases[0].percentile(PERCENTILE);
+        (0..self.plan.spike.repeat as usize)
+            .map(|index| {
+                let end = self.plan.spike_end(index);
+                let first = end.as_secs() as usize;
+                let last = (end + self.plan.gap).as_secs_f64().ceil() as usize;
+                let after: Vec<(usize, f32)> = (first..last)
+                    .filter_map(|second| self.second_p95(second).map(|p95| (second, p95)))
+                    .collect();
+                let time_to_recover = after
+                    .iter()
+                    .find(|(_, p95)| *p95 <= baseline_p95 * (1.0 + RECOVERY_MARGIN))
+                    .map(|(second, _)| {
+                        Duration::from_secs(*second as u64)
+                            .saturating_sub(end)
+                            .as_secs_f32()
+                    });
+                Recovery {
+                    baseline_p95,
+                    spike_p95: self.phases[2 * index + 1].percentile(PERCENTILE),
+                    peak_p95: after.iter().map(|(_, p95)| *p95).fold(0.0, f32::max),
+                    time_to_recover,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_recovery_after_each_spike() {
+        let secs = Duration::from_secs;
+        assert_eq!(
+            parse_spike("50 : 500:30s:3").unwrap(),
+            Spike {
+                baseline: 50.0,
+                spike: 500.0,
+                duration: secs(30),
+                repeat: 3
+            }
+        );
+        for spec in [
+            "",
+            "50:500",
+            "50:500:30s",
+            "50:500:0s:1",
+            "50:500:30s:0",
+            "a:1:1s:1",
+        ] {
+            assert!(parse_spike(spec).is_err(), "{}", spec);
+        }
+
+        // 10s at the baseline, a 5s spike, 10s at the baseline and another 5s spike
+        let spike = parse_spike("10:100:5s:2").unwrap();
+        let start = Instant::now() - secs(60);
+        let mut spikes = Spikes::new(start, spike, secs(10));
+        assert_eq!(spike.test_duration(secs(10)), secs(40));
+        // Latency is 10ms at the baseline, 500ms in the spikes and takes 3s to come back
+        // after the first one, never after the second
+        for second in 0..40 {
+            let ttfb = match second {
+                10..=17 | 25.. => 0.5,
+                _ => 0.01,
+            };
+            spikes.record(start + secs(second), &Ok(answered(ttfb)));
+        }
+        let recoveries = spikes.recoveries();
+        let ms = |secs: f32| (secs * 1000.0).round();
+        assert_eq!(ms(recoveries[0].baseline_p95), 10.0);
+        assert_eq!(ms(recoveries[0].spike_p95), 500.0);
+        assert_eq!(ms(recoveries[0].peak_p95), 500.0);
+        assert_eq!(recoveries[0].time_to_recover, Some(3.0));
+        assert_eq!(recoveries[1].time_to_recover, None);
+    }
+
+    fn answered(ttfb: f32) -> Stats {
+        Stats {
+            ttfb,
+            ttlb: ttfb,
+            total_time: ttfb,
+            finished_at: Instant::now(),
+            bytes: 0,
+            decoded_bytes: Some(0),
+            status: reqwest::StatusCode::OK,
+            version: reqwest::Version::HTTP_11,
+            range_satisfied: true,
+            pin_matched: true,
+            retry_after: None,
+            failed_check: None,
+            remote_addr: None,
+            failed_assertion: None,
+            script_failure: None,
+            expected: true,
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use futures::future::join_all;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CACHE_CONTROL};
+use tokio::time::timeout_at;
+use tracing::{debug, warn};
+
+use crate::latency::LatencyHistogram;
+
+/// A Server-Sent Events test: streams held open for its duration, timing how long the first
+/// event takes and the gaps between the events after it. A stream the server ends early is
+/// counted as a premature disconnect and opened again.
+pub struct SseTest {
+    pub url: String,
+    pub connections: usize,
+    pub duration: Duration,
+    pub headers: HeaderMap,
+    pub tls_config: rustls::ClientConfig,
+}
+
+/// What the streams of a test went through, merged
+#[derive(Default)]
+struct Summary {
+    opened: u64,
+    failed_connects: u64,
+    /// Streams that ended or broke before the test did
+    disconnects: u64,
+    events: u64,
+    first_event: LatencyHistogram,
+    between_events: LatencyHistogram,
+}
+
+impl Summary {
+    fn merge(&mut self, other: Summary) {
+        self.opened = self.opened.saturating_add(other.opened);
+        self.failed_connects = self.failed_connects.saturating_add(other.failed_connects);
+        self.disconnects = self.disconnects.saturating_add(other.disconnects);
+        self.events = self.events.saturating_add(other.events);
+        self.first_event.merge(&other.first_event);
+        self.between_events.merge(&other.between_events);
+    }
+}
+
+pub async fn run(mut test: SseTest) -> Result<(), Box<dyn Error>> {
+    if test.connections == 0 {
+        return Err("there must be at least one connection".into());
+    }
+    test.headers
+        .insert(ACCEPT, HeaderValue::from_static("text/event-stream"));
+    test.headers
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    let client = reqwest::Client::builder()
+        .use_preconfigured_tls(test.tls_config.clone())
+        .build()?;
+    let deadline = tokio::time::Instant::now() + test.duration;
+    let started = Instant::now();
+    let streams =
+        (0..test.connections).map(|connection| stream(&test, &client, connection, deadline));
+    let mut summary = Summary::default();
+    for stream in join_all(streams).await {
+        summary.merge(stream);
+    }
+    print_summary(&test, &summary, started.elapsed());
+    Ok(())
+}
+
+/// Keeps one stream open until `deadline`, opening it again whenever the server ends it
+async fn stream(
+    test: &SseTest,
+    client: &reqwest::Client,
+    connection: usize,
+    deadline: tokio::time::Instant,
+) -> Summary {
+    let mut summary = Summary::default();
+    while tokio::time::Instant::now() < deadline {
+        let start = Instant::now();
+        let request = client.get(&test.url).headers(test.headers.clone()).send();
+        let refused = match timeout_at(deadline, request).await {
+            Err(_) => break,
+            Ok(Ok(response)) if response.status().is_success() => Ok(response),
+            Ok(Ok(response)) => Err(format!("stream refused with {}", response.status())),
+            Ok(Err(e)) => Err(format!("failed to connect: {}", e)),
+        };
+        let mut response = match refused {
+            Ok(response) => response,
+            Err(e) => {
+                warn!(connection, "{}", e);
+                summary.failed_connects += 1;
+                // Don't hammer a server that refuses streams
+                let retry_at = tokio::time::Instant::now() + Duration::from_secs(1);
+                tokio::time::sleep_until(retry_at.min(deadline)).await;
+                continue;
+            }
+        };
+        summary.opened += 1;
+        let mut parser = EventParser::default();
+        let mut last_event = None;
+        loop {
+            match timeout_at(deadline, response.chunk()).await {
+                Err(_) => return summary,
+                Ok(Ok(Some(chunk))) => {
+                    for _ in 0..parser.feed(&chunk) {
+                        let now = Instant::now();
+                        match last_event {
+                            None => summary
+                                .first_event
+                                .record(now.duration_since(start).as_secs_f32()),
+                            Some(last) => summary
+                                .between_events
+                                .record(now.duration_since(last).as_secs_f32()),
+                        }
+                        last_event = Some(now);
+                        summary.events += 1;
+                    }
+                }
+                Ok(Ok(None)) => {
+                    debug!(connection, "stream ended by the server");
+                    summary.disconnects += 1;
+                    break;
+                }
+                Ok(Err(e)) => {
+                    debug!(connection, "stream broke: {}", e);
+                    summary.disconnects += 1;
+                    break;
+                }
+            }
+        }
+    }
+    summary
+}
+
+/// Splits a stream into events, each ended by a blank line after at least one data field.
+/// Comments and events without data, which browsers don't dispatch either, aren't counted.
+#[derive(Default)]
+struct EventParser {
+    /// Start of a line not ended yet
+    line: Vec<u8>,
+    has_data: bool,
+    /// The last chunk ended in a CR, so a LF starting the next one ends no line
+    after_cr: bool,
+}
+
+impl EventParser {
+    /// Takes the next bytes of the stream, returning how many events they completed
+    fn feed(&mut self, bytes: &[u8]) -> usize {
+        let mut events = 0;
+        for &byte in bytes {
+            let after_cr = std::mem::take(&mut self.after_cr);
+            match byte {
+                b'\n' if after_cr => {}
+                b'\r' | b'\n' => {
+                    self.after_cr = byte == b'\r';
+                    let line = std::mem::take(&mut self.line);
+                    if line.is_empty() {
+                        if std::mem::take(&mut self.has_data) {
+                            events += 1;
+                        }
+                    } else if line == b"data" || line.starts_with(b"data:") {
+                        self.has_data = true;
+                    }
+                }
+                byte => self.line.push(byte),
+            }
+        }
+        events
+    }
+}
+
+fn print_summary(test: &SseTest, summary: &Summary, elapsed: Duration) {
+    println!(
+        "Server-Sent Events test of {}: {} connections for {:.1}s",
+        test.url,
+        test.connections,
+        elapsed.as_secs_f32()
+    );
+    println!("Results:");
+    for (label, count) in [
+        ("Streams Opened", summary.opened),
+        ("Failed Connects", summary.failed_connects),
+        ("Premature Disconnects", summary.disconnects),
+        ("Events Received", summary.events),
+    ] {
+        println!("{:.<44}: {}", label, count);
+    }
+    println!();
+    println!();
+    for (label, histogram) in [
+        (
+            "Time to First Event (ms) (P50, P95, P99, Max)",
+            &summary.first_event,
+        ),
+        (
+            "Time Between Events (ms) (P50, P95, P99, Max)",
+            &summary.between_events,
+        ),
+    ] {
+        if histogram.is_empty() {
+            continue;
+        }
+        let ms = |secs: f32| format!("{:.2}", secs * 1000.0);
+        println!(
+            "{:.<44}: {}, {}, {}, {}",
+            label,
+            ms(histogram.percentile(50.0)),
+            ms(histogram.percentile(95.0)),
+            ms(histogram.percentile(99.0)),
+            ms(histogram.max())
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_events_split_across_chunks() {
+        let mut parser = EventParser::default();
+        assert_eq!(parser.feed(b": keep-alive\n\nevent: tick\nda"), 0);
+        assert_eq!(parser.feed(b"ta: 1\n"), 0);
+        assert_eq!(parser.feed(b"\ndata: 2\ndata: 3\n\n"), 2);
+        // Retry hints and ids alone aren't events
+        assert_eq!(parser.feed(b"retry: 1000\n\nid: 4\n\n"), 0);
+        assert_eq!(parser.feed(b"data\r\n\r"), 1);
+        assert_eq!(parser.feed(b"\ndata: 5\r\r"), 1);
+    }
+}
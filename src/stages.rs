@@ -0,0 +1,161 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{latency::LatencyHistogram, Stats};
+
+/// One stage of a test's load, which moves linearly from the previous stage's target, or
+/// from zero for the first, to its own over its duration
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stage {
+    pub duration: Duration,
+    /// Concurrency with the closed model, arrival rate with the open one
+    pub target: f64,
+}
+
+/// Parses `<duration>:<target>`, e.g. `2m:200`
+pub fn parse_stage(stage: &str) -> Result<Stage, String> {
+    let invalid = || format!("invalid stage `{}`, expected <duration>:<target>", stage);
+    let (duration, target) = stage.split_once(':').ok_or_else(invalid)?;
+    let duration = humantime::parse_duration(duration.trim()).map_err(|_| invalid())?;
+    let target: f64 = target.trim().parse().map_err(|_| invalid())?;
+    if duration.is_zero() || !target.is_finite() || target < 0.0 {
+        return Err(invalid());
+    }
+    Ok(Stage { duration, target })
+}
+
+/// The stages of a test, timed from when it started
+#[derive(Clone)]
+pub struct Schedule {
+    start: Instant,
+    plan: Vec<Stage>,
+}
+
+impl Schedule {
+    /// Load the stages call for now
+    pub fn target(&self) -> f64 {
+        let (index, into) = self.position(Instant::now());
+        let stage = &self.plan[index];
+        let from = match index {
+            0 => 0.0,
+            _ => self.plan[index - 1].target,
+        };
+        let progress = (into.as_secs_f64() / stage.duration.as_secs_f64()).min(1.0);
+        from + (stage.target - from) * progress
+    }
+
+    /// The stage running at `at`, the last one past the end, and how far into it the test is
+    fn position(&self, at: Instant) -> (usize, Duration) {
+        let mut into = at.saturating_duration_since(self.start);
+        for (index, stage) in self.plan.iter().enumerate() {
+            if into < stage.duration || index == self.plan.len() - 1 {
+                return (index, into);
+            }
+            into -= stage.duration;
+        }
+        unreachable!("a test has at least one stage")
+    }
+}
+
+/// The stages of a test and the outcomes of the requests that finished in each
+pub struct Stages {
+    schedule: Schedule,
+    stats: Vec<StageStats>,
+}
+
+impl Stages {
+    pub fn new(start: Instant, plan: Vec<Stage>) -> Self {
+        Stages::with_stats(start, plan, vec![])
+    }
+
+    /// Stages whose requests were already recorded, e.g. by the workers of a distributed test
+    pub fn with_stats(start: Instant, plan: Vec<Stage>, mut stats: Vec<StageStats>) -> Self {
+        stats.resize(plan.len(), StageStats::default());
+        Stages {
+            schedule: Schedule { start, plan },
+            stats,
+        }
+    }
+
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+
+    pub fn plan(&self) -> &[Stage] {
+        &self.schedule.plan
+    }
+
+    pub fn stats(&self) -> &[StageStats] {
+        &self.stats
+    }
+
+    /// Counts a request towards the stage it finished in
+    pub fn record(&mut self, finished_at: Instant, outcome: &Result<Stats, reqwest::Error>) {
+        let (index, _) = self.schedule.position(finished_at);
+        self.stats[index].record(outcome);
+    }
+}
+
+/// Outcomes of the requests that finished during one stage
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StageStats {
+    pub successful: u64,
+    pub failed: u64,
+    /// Total request time of the successful requests
+    pub latency: LatencyHistogram,
+}
+
+impl StageStats {
+    fn record(&mut self, outcome: &Result<Stats, reqwest::Error>) {
+        match outcome {
+            Ok(resp) if resp.succeeded() => {
+                self.successful += 1;
+                self.latency.record(resp.total_time);
+            }
+            _ => self.failed += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &StageStats) {
+        self.successful = self.successful.saturating_add(other.successful);
+        self.failed = self.failed.saturating_add(other.failed);
+        self.latency.merge(&other.latency);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramps_between_stages() {
+        let secs = Duration::from_secs;
+        assert_eq!(
+            parse_stage(" 2m : 200").unwrap(),
+            Stage {
+                duration: secs(120),
+                target: 200.0
+            }
+        );
+        for spec in ["", "1m", "1m:", "fast:10", "0s:10", "1m:-1", "1m:inf"] {
+            assert!(parse_stage(spec).is_err(), "{}", spec);
+        }
+
+        let plan = ["1m:50", "2m:200", "30s:0"].map(|stage| parse_stage(stage).unwrap());
+        let start = Instant::now() - secs(90);
+        let mut stages = Stages::new(start, plan.to_vec());
+        // A quarter of the way from 50 to 200
+        let target = stages.schedule().target();
+        assert!((target - 87.5).abs() < 1.0, "{}", target);
+        for at in [0, 59, 60, 185, 600] {
+            stages.record(start + secs(at), &Err(failure()));
+        }
+        let failed: Vec<u64> = stages.stats().iter().map(|stats| stats.failed).collect();
+        assert_eq!(failed, [2, 1, 2]);
+    }
+
+    fn failure() -> reqwest::Error {
+        reqwest::Client::new().get("not a url").build().unwrap_err()
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use reqwest::StatusCode;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+/// How often external stop conditions are checked
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long requests still in flight are waited for once the test stops
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+/// Exit code of a test that --abort-on-errors or --abort-on-error-rate stopped
+pub const ABORTED_EXIT_CODE: i32 = 3;
+/// Latest requests the error rate --abort-on-error-rate watches is taken over
+const ERROR_RATE_WINDOW: usize = 100;
+/// Requests that must have finished before the error rate is judged, so the first failure
+/// alone doesn't count as a 100% error rate
+const ERROR_RATE_MIN_REQUESTS: usize = 20;
+
+/// Ends a test early, remembering why and when. Only the first reason sticks.
+#[derive(Clone)]
+pub struct StopSignal {
+    sender: Arc<watch::Sender<Option<(String, Instant)>>>,
+}
+
+impl StopSignal {
+    pub fn new() -> Self {
+        StopSignal {
+            sender: Arc::new(watch::channel(None).0),
+        }
+    }
+
+    pub fn stop(&self, reason: String) {
+        self.sender.send_if_modified(|current| {
+            if current.is_some() {
+                return false;
+            }
+            info!(reason, "stopping test");
+            *current = Some((reason, Instant::now()));
+            true
+        });
+    }
+
+    pub fn reason(&self) -> Option<String> {
+        self.sender
+            .borrow()
+            .as_ref()
+            .map(|(reason, _)| reason.clone())
+    }
+
+    /// Resolves once the test has been stopped
+    pub async fn stopped(&self) {
+        let _ = self.sender.subscribe().wait_for(Option::is_some).await;
+    }
+
+    /// Resolves DRAIN_TIMEOUT after the test was stopped, bounding how long requests in
+    /// flight are given to finish. The deadline is set when the stop happens, so every wait
+    /// shares it however many there are and whenever they start.
+    pub async fn drain_timeout(&self) {
+        self.stopped().await;
+        let stopped_at = self.sender.borrow().as_ref().map(|(_, at)| *at);
+        if let Some(at) = stopped_at {
+            tokio::time::sleep_until((at + DRAIN_TIMEOUT).into()).await;
+        }
+    }
+}
+
+/// Stops the test as soon as requests fail too much: once `errors` of them have failed, or once
+/// the error rate over the latest ones reaches `error_rate` percent
+pub struct Abort {
+    errors: Option<u64>,
+    error_rate: Option<f64>,
+    failed: u64,
+    /// Whether each of the latest requests failed, oldest first
+    latest: VecDeque<bool>,
+    latest_failed: usize,
+    tripped: bool,
+    signal: StopSignal,
+}
+
+impl Abort {
+    /// None when neither limit is set
+    pub fn new(errors: Option<u64>, error_rate: Option<f64>, signal: StopSignal) -> Option<Self> {
+        (errors.is_some() || error_rate.is_some()).then(|| Abort {
+            errors,
+            error_rate,
+            failed: 0,
+            latest: VecDeque::with_capacity(ERROR_RATE_WINDOW),
+            latest_failed: 0,
+            tripped: false,
+            signal,
+        })
+    }
+
+    /// Counts a finished request, stopping the test when it takes a limit
+    pub fn record(&mut self, failed: bool) {
+        self.failed += failed as u64;
+        if self.latest.len() == ERROR_RATE_WINDOW && self.latest.pop_front() == Some(true) {
+            self.latest_failed -= 1;
+        }
+        self.latest.push_back(failed);
+        self.latest_failed += failed as usize;
+        if self.tripped {
+            return;
+        }
+        if let Some(reason) = self.breach() {
+            self.tripped = true;
+            self.signal.stop(reason);
+        }
+    }
+
+    fn breach(&self) -> Option<String> {
+        if let Some(errors) = self.errors.filter(|errors| self.failed >= *errors) {
+            return Some(format!(
+                "{} requests failed, reaching --abort-on-errors {}",
+                self.failed, errors
+            ));
+        }
+        let rate = self.latest_failed as f64 / self.latest.len() as f64 * 100.0;
+        self.error_rate
+            .filter(|limit| self.latest.len() >= ERROR_RATE_MIN_REQUESTS && rate >= *limit)
+            .map(|limit| {
+                format!(
+                    "{:.1}% of the last {} requests failed, reaching --abort-on-error-rate {}%",
+                    rate,
+                    self.latest.len(),
+                    limit
+                )
+            })
+    }
+
+    /// Whether a limit stopped the test
+    pub fn tripped(&self) -> bool {
+        self.tripped
+    }
+}
+
+/// Stops the test on the first Ctrl-C so a partial report still gets printed, and exits
+/// right away on the second
+pub fn stop_on_interrupt(signal: StopSignal) {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        signal.stop("interrupted".to_string());
+        warn!(
+            "interrupted, waiting up to {:?} for requests in flight, press Ctrl-C again to quit",
+            DRAIN_TIMEOUT
+        );
+        if tokio::signal::ctrl_c().await.is_ok() {
+            warn!("interrupted twice, exiting without a report");
+            std::process::exit(130);
+        }
+    });
+}
+
+/// Something outside the tool that ends the test when it happens
+pub enum StopCondition {
+    /// The file appears
+    File(PathBuf),
+    /// The URL answers with the status
+    Url { url: String, status: StatusCode },
+}
+
+/// Parses `<url>` or `<url>=<status>`, waiting for a 200 when no status is given
+pub fn parse_stop_url(spec: &str) -> Result<(String, StatusCode), String> {
+    let (url, status) = match spec.rsplit_once('=') {
+        Some((url, status)) if status.len() == 3 && status.bytes().all(|b| b.is_ascii_digit()) => {
+            (url, status)
+        }
+        _ => (spec, "200"),
+    };
+    let status = status
+        .parse::<u16>()
+        .ok()
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .ok_or_else(|| format!("invalid status `{}`", status))?;
+    Ok((url.to_string(), status))
+}
+
+/// Polls the conditions in the background until one of them is met or the test stops
+pub fn watch_conditions(
+    conditions: Vec<StopCondition>,
+    tls_config: rustls::ClientConfig,
+    signal: StopSignal,
+) -> reqwest::Result<()> {
+    if conditions.is_empty() {
+        return Ok(());
+    }
+    let client = reqwest::Client::builder()
+        .use_preconfigured_tls(tls_config)
+        .build()?;
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        while signal.reason().is_none() {
+            interval.tick().await;
+            for condition in &conditions {
+                if let Some(reason) = check(condition, &client).await {
+                    signal.stop(reason);
+                    return;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn check(condition: &StopCondition, client: &reqwest::Client) -> Option<String> {
+    match condition {
+        StopCondition::File(path) => path
+            .exists()
+            .then(|| format!("{} appeared", path.display())),
+        StopCondition::Url { url, status } => match client.get(url).send().await {
+            Ok(res) if res.status() == *status => Some(format!("{} returned {}", url, status)),
+            Ok(_) => None,
+            Err(e) => {
+                debug!(url, "stop condition check failed: {}", e);
+                None
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aborts_on_errors() {
+        assert!(Abort::new(None, None, StopSignal::new()).is_none());
+
+        let signal = StopSignal::new();
+        let mut abort = Abort::new(Some(3), None, signal.clone()).unwrap();
+        for failed in [true, false, true] {
+            abort.record(failed);
+        }
+        assert!(!abort.tripped());
+        abort.record(true);
+        assert!(abort.tripped());
+        assert_eq!(
+            signal.reason().unwrap(),
+            "3 requests failed, reaching --abort-on-errors 3"
+        );
+
+        // The first failures alone don't make a rate, and old ones age out of the window
+        let signal = StopSignal::new();
+        let mut abort = Abort::new(None, Some(10.0), signal.clone()).unwrap();
+        abort.record(true);
+        assert!(!abort.tripped());
+        for _ in 0..200 {
+            abort.record(false);
+        }
+        assert!(!abort.tripped());
+        for _ in 0..9 {
+            abort.record(true);
+        }
+        assert!(!abort.tripped());
+        abort.record(true);
+        assert_eq!(
+            signal.reason().unwrap(),
+            "10.0% of the last 100 requests failed, reaching --abort-on-error-rate 10%"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    error::Error,
+    fs,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use rand::Rng;
+use reqwest::{Method, Url};
+
+use crate::Spread;
+
+/// A URL to send requests to, picked in proportion to its weight
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub url: String,
+    pub weight: u32,
+    /// Overrides --method for this URL
+    pub method: Option<Method>,
+}
+
+impl Target {
+    /// How the target is named in the report, with its method when it has its own
+    pub fn label(&self) -> String {
+        match &self.method {
+            Some(method) => format!("{} {}", method, self.url),
+            None => self.url.clone(),
+        }
+    }
+}
+
+/// Parses `<url>` or `<url>@<weight>`, the weight defaulting to 1
+pub fn parse_target(spec: &str) -> Result<Target, String> {
+    let (url, weight) = match spec.rsplit_once('@') {
+        Some((url, weight)) if !weight.is_empty() && weight.bytes().all(|b| b.is_ascii_digit()) => {
+            let weight = weight
+                .parse::<u32>()
+                .ok()
+                .filter(|w| *w > 0)
+                .ok_or_else(|| {
+                    format!("invalid weight `{}`, expected a positive number", weight)
+                })?;
+            (url, weight)
+        }
+        _ => (spec, 1),
+    };
+    Ok(Target {
+        url: url.to_string(),
+        weight,
+        method: None,
+    })
+}
+
+/// Reads one target per line as `[METHOD] <url>[@<weight>]`, skipping blank lines and
+/// `#` comments
+pub fn read_targets(path: &Path) -> Result<Vec<Target>, Box<dyn Error>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let mut targets = vec![];
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let at_line = |e: String| format!("{}:{}: {}", path.display(), number + 1, e);
+        let mut fields = line.split_whitespace();
+        let target = match (fields.next(), fields.next(), fields.next()) {
+            (Some(spec), None, _) => parse_target(spec).map_err(at_line)?,
+            (Some(method), Some(spec), None) => Target {
+                method: Some(crate::parse_method(method).map_err(at_line)?),
+                ..parse_target(spec).map_err(at_line)?
+            },
+            _ => return Err(at_line("expected `[METHOD] <url>`".to_string()).into()),
+        };
+        targets.push(target);
+    }
+    if targets.is_empty() {
+        return Err(format!("{} has no URLs", path.display()).into());
+    }
+    Ok(targets)
+}
+
+/// The URLs of a test. Requests cycle through them, or pick one at random, so that each gets
+/// its share of the weight.
+pub struct Targets {
+    targets: Vec<Target>,
+    total_weight: u64,
+    order: Spread,
+    next: AtomicUsize,
+}
+
+impl Targets {
+    /// Paths are resolved against the first target, which has to be a full URL
+    pub fn new(mut targets: Vec<Target>, order: Spread) -> Result<Self, String> {
+        let first = targets.first().ok_or("--url is required")?;
+        let base =
+            Url::parse(&first.url).map_err(|e| format!("invalid URL `{}`: {}", first.url, e))?;
+        for target in &mut targets[1..] {
+            if Url::parse(&target.url).is_err() {
+                target.url = base
+                    .join(&target.url)
+                    .map_err(|e| format!("invalid URL `{}`: {}", target.url, e))?
+                    .to_string();
+            }
+        }
+        Ok(Targets {
+            total_weight: targets.iter().map(|t| t.weight as u64).sum(),
+            targets,
+            order,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// The first URL, which host-level setup like DNS, SNI and TLS probing works off
+    pub fn primary(&self) -> &str {
+        &self.targets[0].url
+    }
+
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub fn get(&self, index: usize) -> &Target {
+        &self.targets[index]
+    }
+
+    /// Index of the target the next request goes to, and the target itself
+    pub fn pick(&self) -> (usize, &Target) {
+        if self.targets.len() == 1 {
+            return (0, &self.targets[0]);
+        }
+        let mut slot = match self.order {
+            Spread::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) as u64,
+            Spread::Random => rand::thread_rng().gen(),
+        } % self.total_weight;
+        for (index, target) in self.targets.iter().enumerate() {
+            if slot < target.weight as u64 {
+                return (index, target);
+            }
+            slot -= target.weight as u64;
+        }
+        unreachable!("slot is below the total weight")
+    }
+}
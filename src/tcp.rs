@@ -0,0 +1,171 @@
+use std::{
+    error::Error,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use futures::future::join_all;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+use tracing::debug;
+
+use crate::latency::LatencyHistogram;
+
+/// A raw TCP test: connections opened one after the other, --concurrency at a time, each
+/// writing the payload if there is one and reading what the server answers until it closes
+/// the connection or goes quiet
+pub struct TcpTest {
+    /// host:port to connect to
+    pub addr: String,
+    pub payload: Option<Vec<u8>>,
+    pub number: u64,
+    pub concurrency: usize,
+    /// Keep connecting until this much time has passed instead of making `number` connections
+    pub duration: Option<Duration>,
+    /// How long the server may stay quiet before the connection is closed, zero to close it
+    /// without reading
+    pub read_timeout: Duration,
+}
+
+/// What the connections of a test went through
+#[derive(Default)]
+struct Summary {
+    connected: u64,
+    failed_connects: u64,
+    /// Connections that broke while writing or reading
+    broken: u64,
+    connect: LatencyHistogram,
+    /// From connecting to the last byte sent or received
+    session: LatencyHistogram,
+    bytes_sent: u64,
+    bytes_received: u64,
+}
+
+pub async fn run(test: TcpTest) -> Result<(), Box<dyn Error>> {
+    if test.concurrency == 0 {
+        return Err("concurrency must be at least 1".into());
+    }
+    let connections = AtomicU64::new(0);
+    let summary = Mutex::new(Summary::default());
+    let started = Instant::now();
+    let deadline = test.duration.map(|duration| started + duration);
+    let workers = (0..test.concurrency).map(|_| async {
+        loop {
+            let more = match deadline {
+                Some(deadline) => Instant::now() < deadline,
+                None => connections.fetch_add(1, Ordering::Relaxed) < test.number,
+            };
+            if !more {
+                break;
+            }
+            connect(&test, &summary).await;
+        }
+    });
+    join_all(workers).await;
+    print_summary(&test, &summary.into_inner().unwrap(), started.elapsed());
+    Ok(())
+}
+
+/// Makes one connection, recording how it went in `summary`
+async fn connect(test: &TcpTest, summary: &Mutex<Summary>) {
+    let start = Instant::now();
+    let mut stream = match TcpStream::connect(&test.addr).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            debug!("failed to connect to {}: {}", test.addr, e);
+            summary.lock().unwrap().failed_connects += 1;
+            return;
+        }
+    };
+    let connected = start.elapsed();
+    let mut sent = 0;
+    let mut received = 0;
+    // When the last byte went either way, so the quiet wait at the end isn't timed
+    let mut last = Instant::now();
+    let talk = async {
+        if let Some(payload) = &test.payload {
+            stream.write_all(payload).await?;
+            sent = payload.len() as u64;
+            last = Instant::now();
+        }
+        if test.read_timeout.is_zero() {
+            return Ok(());
+        }
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            match timeout(test.read_timeout, stream.read(&mut buf)).await {
+                // The server went quiet, which is how many protocols end an answer
+                Err(_) | Ok(Ok(0)) => return Ok(()),
+                Ok(Ok(n)) => {
+                    received += n as u64;
+                    last = Instant::now();
+                }
+                Ok(Err(e)) => return Err(e),
+            }
+        }
+    };
+    let outcome: std::io::Result<()> = talk.await;
+    let session = last.duration_since(start);
+    let mut summary = summary.lock().unwrap();
+    summary.connected += 1;
+    summary.connect.record(connected.as_secs_f32());
+    summary.bytes_sent += sent;
+    summary.bytes_received += received;
+    match outcome {
+        Ok(()) => summary.session.record(session.as_secs_f32()),
+        Err(e) => {
+            debug!("connection to {} broke: {}", test.addr, e);
+            summary.broken += 1;
+        }
+    }
+}
+
+fn print_summary(test: &TcpTest, summary: &Summary, elapsed: Duration) {
+    let secs = elapsed.as_secs_f32();
+    println!("TCP test of {}", test.addr);
+    println!("Results:");
+    for (label, count) in [
+        ("Connections Made", summary.connected),
+        ("Failed Connects", summary.failed_connects),
+        ("Broken Connections", summary.broken),
+        ("Bytes Sent", summary.bytes_sent),
+        ("Bytes Received", summary.bytes_received),
+    ] {
+        println!("{:.<44}: {}", label, count);
+    }
+    println!(
+        "{:.<44}: {}",
+        "Connections Per Sec",
+        summary.connected as f32 / secs
+    );
+    println!(
+        "{:.<44}: {:.2}",
+        "Throughput (MB/s)",
+        (summary.bytes_sent + summary.bytes_received) as f32 / 1_000_000.0 / secs
+    );
+    println!();
+    println!();
+    for (label, histogram) in [
+        ("Connect Time (ms) (P50, P95, P99, Max)", &summary.connect),
+        ("Session Time (ms) (P50, P95, P99, Max)", &summary.session),
+    ] {
+        if histogram.is_empty() {
+            continue;
+        }
+        let ms = |secs: f32| format!("{:.2}", secs * 1000.0);
+        println!(
+            "{:.<44}: {}, {}, {}, {}",
+            label,
+            ms(histogram.percentile(50.0)),
+            ms(histogram.percentile(95.0)),
+            ms(histogram.percentile(99.0)),
+            ms(histogram.max())
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::{data::DataFeed, secrets};
+
+/// Text with `{{name}}` placeholders, parsed once and filled in for every request, falling
+/// back to the secrets of --secret. The built-in functions `{{uuid}}`, `{{randint MIN MAX}}`,
+/// `{{timestamp}}` and `{{randstr LEN}}` give every request a value of its own.
+#[derive(Debug, Clone)]
+pub struct Template(Vec<Part>);
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    Placeholder(String),
+    Function(Function),
+}
+
+/// Built-in function evaluated anew every time the template is filled in
+#[derive(Debug, Clone, PartialEq)]
+enum Function {
+    /// Random version 4 UUID
+    Uuid,
+    /// Random integer between the two, both included
+    RandInt(i64, i64),
+    /// Seconds since the Unix epoch
+    Timestamp,
+    /// Random alphanumeric string this long
+    RandStr(usize),
+}
+
+impl Function {
+    /// The function a placeholder calls, or None when it names a variable instead
+    fn parse(name: &str) -> Option<Result<Self, String>> {
+        let mut words = name.split_whitespace();
+        let function = words.next()?;
+        let args: Vec<&str> = words.collect();
+        let parsed = match (function, args.as_slice()) {
+            ("uuid", []) => Ok(Function::Uuid),
+            ("timestamp", []) => Ok(Function::Timestamp),
+            ("randint", [min, max]) => match (min.parse::<i64>(), max.parse::<i64>()) {
+                (Ok(min), Ok(max)) if min <= max => Ok(Function::RandInt(min, max)),
+                _ => Err(format!(
+                    "{{{{{}}}}} needs two integers, the first no greater than the second",
+                    name
+                )),
+            },
+            ("randstr", [len]) => len
+                .parse()
+                .map(Function::RandStr)
+                .map_err(|_| format!("{{{{{}}}}} needs a length", name)),
+            ("uuid" | "timestamp", _) => Err(format!("{{{{{}}}}} takes no arguments", name)),
+            ("randint", _) => Err(format!("{{{{{}}}}} needs a minimum and a maximum", name)),
+            ("randstr", _) => Err(format!("{{{{{}}}}} needs a length", name)),
+            _ => return None,
+        };
+        Some(parsed)
+    }
+
+    fn call(&self) -> String {
+        let mut rng = rand::thread_rng();
+        match *self {
+            Function::Uuid => {
+                let bits = rng.gen::<u128>() & !(0xf000 << 64) & !(0xc000 << 48)
+                    | (0x4000 << 64)
+                    | (0x8000 << 48);
+                let hex = format!("{:032x}", bits);
+                format!(
+                    "{}-{}-{}-{}-{}",
+                    &hex[..8],
+                    &hex[8..12],
+                    &hex[12..16],
+                    &hex[16..20],
+                    &hex[20..]
+                )
+            }
+            Function::RandInt(min, max) => rng.gen_range(min..=max).to_string(),
+            Function::Timestamp => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+                .to_string(),
+            Function::RandStr(len) => (0..len).map(|_| rng.sample(Alphanumeric) as char).collect(),
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parts = vec![];
+        let mut rest = text;
+        while let Some(open) = rest.find("{{") {
+            if open > 0 {
+                parts.push(Part::Text(rest[..open].to_string()));
+            }
+            let close = rest[open..]
+                .find("}}")
+                .ok_or_else(|| format!("unclosed {{{{ in `{}`", text))?;
+            let name = rest[open + 2..open + close].trim();
+            if name.is_empty() {
+                return Err(format!("empty placeholder in `{}`", text));
+            }
+            parts.push(match Function::parse(name) {
+                Some(function) => Part::Function(function?),
+                None => Part::Placeholder(name.to_string()),
+            });
+            rest = &rest[open + close + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Template(parts))
+    }
+
+    /// Whether there is anything to fill in at all, placeholders or functions
+    pub fn has_placeholders(&self) -> bool {
+        self.0.iter().any(|part| !matches!(part, Part::Text(_)))
+    }
+
+    /// Names of the placeholders, in order
+    fn placeholders(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().filter_map(|part| match part {
+            Part::Placeholder(name) => Some(name.as_str()),
+            Part::Text(_) | Part::Function(_) => None,
+        })
+    }
+
+    /// Makes sure `data` has a column, --secret a value or an earlier scenario step one of the
+    /// `extracted` variables to fill in every placeholder
+    pub fn check(&self, data: Option<&DataFeed>, extracted: &[String]) -> Result<(), String> {
+        for name in self.placeholders() {
+            if secrets::value(name).is_some() || extracted.iter().any(|e| e == name) {
+                continue;
+            }
+            match data {
+                Some(data) if data.has_column(name) => {}
+                Some(_) => {
+                    return Err(format!(
+                        "--data has no {} column for {{{{{}}}}}",
+                        name, name
+                    ))
+                }
+                None => {
+                    return Err(format!(
+                        "nothing fills in {{{{{}}}}}, pass --data with a {} column or --secret {}=env:<VAR>",
+                        name, name, name
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills in every placeholder with what `lookup` has for its name
+    pub fn render(&self, lookup: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+        let mut out = String::new();
+        for part in &self.0 {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Placeholder(name) => match lookup(name).or_else(|| secrets::value(name)) {
+                    Some(value) => out.push_str(&value),
+                    None => return Err(format!("no value for {{{{{}}}}}", name)),
+                },
+                Part::Function(function) => out.push_str(&function.call()),
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::*;
+
+    #[test]
+    fn fills_in_placeholders() {
+        let template = Template::parse("/users/{{ id }}?q={{q}}{{id}}").unwrap();
+        assert_eq!(
+            template.placeholders().collect::<Vec<_>>(),
+            ["id", "q", "id"]
+        );
+        let lookup = |name: &str| Some(format!("<{}>", name));
+        assert_eq!(template.render(lookup).unwrap(), "/users/<id>?q=<q><id>");
+        assert_eq!(
+            template.render(|name| (name == "id").then(String::new)),
+            Err("no value for {{q}}".to_string())
+        );
+        assert!(template.has_placeholders());
+        let plain = Template::parse("plain").unwrap();
+        assert!(!plain.has_placeholders());
+        assert_eq!(plain.render(|_| None).unwrap(), "plain");
+    }
+
+    #[test]
+    fn calls_functions_anew_every_time() {
+        let template =
+            Template::parse("{{uuid}} {{ randint 1 3 }} {{timestamp}} {{randstr 16}}").unwrap();
+        assert!(template.has_placeholders());
+        assert_eq!(template.placeholders().count(), 0);
+        let first = template.render(|_| None).unwrap();
+        let words: Vec<&str> = first.split(' ').collect();
+        let uuid =
+            Regex::new("^[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-[89ab][0-9a-f]{3}-[0-9a-f]{12}$")
+                .unwrap();
+        assert!(uuid.is_match(words[0]), "{}", words[0]);
+        assert!((1..=3).contains(&words[1].parse::<i64>().unwrap()));
+        assert!(words[2].parse::<u64>().unwrap() > 1_600_000_000);
+        assert_eq!(words[3].len(), 16);
+        assert!(words[3].chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(template.render(|_| None).unwrap(), first);
+        assert_eq!(
+            Template::parse("{{randint 0 0}}")
+                .unwrap()
+                .render(|_| None)
+                .unwrap(),
+            "0"
+        );
+    }
+
+    #[test]
+    fn rejects_broken_placeholders() {
+        assert!(Template::parse("{{randint 5 1}}").is_err());
+        assert!(Template::parse("{{randint 1}}").is_err());
+        assert!(Template::parse("{{randstr x}}").is_err());
+        assert!(Template::parse("{{uuid 4}}").is_err());
+        assert!(Template::parse("/{{id").is_err());
+        assert!(Template::parse("/{{ }}").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/// Pause a virtual user takes between its requests, the way a person reads a page before
+/// clicking on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThinkTime {
+    Fixed(Duration),
+    /// Uniformly between the two
+    Uniform(Duration, Duration),
+    /// Exponentially distributed around this mean, as the gaps between independent arrivals
+    Exponential(Duration),
+}
+
+/// Parses `500ms`, `200ms-800ms` or `exp:500ms`
+pub fn parse_think_time(spec: &str) -> Result<ThinkTime, String> {
+    let duration = |value: &str| {
+        humantime::parse_duration(value.trim())
+            .map_err(|e| format!("invalid think time `{}`: {}", spec, e))
+    };
+    if let Some(mean) = spec.strip_prefix("exp:") {
+        return Ok(ThinkTime::Exponential(duration(mean)?));
+    }
+    match spec.split_once('-') {
+        Some((low, high)) => {
+            let (low, high) = (duration(low)?, duration(high)?);
+            if low > high {
+                return Err(format!(
+                    "invalid think time `{}`, the range is the wrong way round",
+                    spec
+                ));
+            }
+            Ok(ThinkTime::Uniform(low, high))
+        }
+        None => Ok(ThinkTime::Fixed(duration(spec)?)),
+    }
+}
+
+impl ThinkTime {
+    /// How long to pause this time
+    pub fn sample(&self) -> Duration {
+        match *self {
+            ThinkTime::Fixed(pause) => pause,
+            ThinkTime::Uniform(low, high) => rand::thread_rng().gen_range(low..=high),
+            ThinkTime::Exponential(mean) => {
+                // 1 - u is in (0, 1], so its log is finite
+                let u: f64 = rand::thread_rng().gen();
+                mean.mul_f64(-(1.0 - u).ln())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_samples_think_times() {
+        let ms = Duration::from_millis;
+        assert_eq!(
+            parse_think_time("500ms").unwrap(),
+            ThinkTime::Fixed(ms(500))
+        );
+        assert_eq!(
+            parse_think_time("200ms - 1s").unwrap(),
+            ThinkTime::Uniform(ms(200), ms(1000))
+        );
+        assert_eq!(
+            parse_think_time("exp:2s").unwrap(),
+            ThinkTime::Exponential(ms(2000))
+        );
+        for spec in ["", "fast", "1s-200ms", "exp:", "1s-", "uniform:1s"] {
+            assert!(parse_think_time(spec).is_err(), "{}", spec);
+        }
+
+        let uniform = ThinkTime::Uniform(ms(200), ms(800));
+        assert!((0..1000)
+            .map(|_| uniform.sample())
+            .all(|pause| (ms(200)..=ms(800)).contains(&pause)));
+        let exponential = ThinkTime::Exponential(ms(100));
+        let mean = (0..10_000)
+            .map(|_| exponential.sample())
+            .sum::<Duration>()
+            .as_secs_f64()
+            / 10_000.0;
+        assert!((0.09..0.11).contains(&mean), "{}", mean);
+    }
+}
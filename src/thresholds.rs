@@ -0,0 +1,238 @@
+use std::{fmt, time::Duration};
+
+use tracing::{info, warn};
+
+use crate::{latency::LatencyHistogram, LoadResult};
+
+/// Exit code of a test that ran to completion but missed a threshold
+pub const THRESHOLD_FAILED_EXIT_CODE: i32 = 2;
+
+/// What a threshold is checked against
+#[derive(Debug, Clone, Copy)]
+pub enum Metric {
+    /// Percentile of the time to first byte, the same one the report prints
+    Percentile(f32),
+    /// Failed requests as a percentage of all requests
+    ErrorRate,
+    Rps,
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Comparison {
+    Below,
+    AtMost,
+    Above,
+    AtLeast,
+}
+
+/// A pass/fail check on the final stats, e.g. p95<200ms or error_rate<1%
+#[derive(Debug, Clone)]
+pub struct Threshold {
+    spec: String,
+    metric: Metric,
+    comparison: Comparison,
+    /// Seconds for percentiles, a percentage for the error rate
+    limit: f64,
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+/// Parses `<metric><op><value>` with metric p<N>, error_rate, rps or failed and op one of
+/// <, <=, > or >=. Percentiles take a duration, the error rate a percentage.
+pub fn parse_threshold(spec: &str) -> Result<Threshold, String> {
+    let (at, comparison, op_len) = [
+        ("<=", Comparison::AtMost),
+        (">=", Comparison::AtLeast),
+        ("<", Comparison::Below),
+        (">", Comparison::Above),
+    ]
+    .into_iter()
+    .find_map(|(op, comparison)| spec.find(op).map(|at| (at, comparison, op.len())))
+    .ok_or_else(|| format!("`{}` has no comparison, expected e.g. p95<200ms", spec))?;
+    let name = spec[..at].trim();
+    let value = spec[at + op_len..].trim();
+
+    let metric = match name {
+        "error_rate" => Metric::ErrorRate,
+        "rps" => Metric::Rps,
+        "failed" => Metric::Failed,
+        _ => {
+            let percentile = name
+                .strip_prefix('p')
+                .and_then(|p| p.parse::<f32>().ok())
+                .filter(|p| (0.0..=100.0).contains(p))
+                .ok_or_else(|| {
+                    format!(
+                        "unknown metric `{}`, expected p<N>, error_rate, rps or failed",
+                        name
+                    )
+                })?;
+            Metric::Percentile(percentile)
+        }
+    };
+    let limit = match metric {
+        Metric::Percentile(_) => humantime::parse_duration(value)
+            .map_err(|e| format!("invalid duration `{}`: {}", value, e))?
+            .as_secs_f64(),
+        Metric::ErrorRate => value
+            .strip_suffix('%')
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .ok_or_else(|| format!("invalid error rate `{}`, expected a percentage", value))?,
+        Metric::Rps | Metric::Failed => value
+            .parse::<f64>()
+            .map_err(|_| format!("invalid number `{}`", value))?,
+    };
+    Ok(Threshold {
+        spec: spec.to_string(),
+        metric,
+        comparison,
+        limit,
+    })
+}
+
+/// Outcome of one threshold, with the measured value formatted like its limit
+pub struct Verdict<'a> {
+    pub threshold: &'a Threshold,
+    pub actual: String,
+    pub passed: bool,
+}
+
+/// The stats thresholds are checked against, for a whole test or part of one
+pub struct Observed<'a> {
+    pub successful: u64,
+    pub failed: u64,
+    pub ttfb: &'a LatencyHistogram,
+    /// Seconds the requests took to come back in
+    pub duration: f32,
+}
+
+impl<'a> Observed<'a> {
+    pub fn of(result: &'a LoadResult, test_duration: f32) -> Self {
+        Observed {
+            successful: result.number_of_successful_calls,
+            failed: result.number_of_failed_calls,
+            ttfb: &result.latencies.ttfb,
+            duration: test_duration,
+        }
+    }
+}
+
+pub fn evaluate<'a>(thresholds: &'a [Threshold], observed: &Observed) -> Vec<Verdict<'a>> {
+    let total = observed.successful + observed.failed;
+
+    thresholds
+        .iter()
+        .map(|threshold| {
+            let (value, actual) = match threshold.metric {
+                Metric::Percentile(p) => {
+                    let secs = observed.ttfb.percentile(p) as f64;
+                    let micros = Duration::from_micros((secs * 1e6).round() as u64);
+                    (secs, format!("{:?}", micros))
+                }
+                Metric::ErrorRate => {
+                    let rate = if total == 0 {
+                        0.0
+                    } else {
+                        observed.failed as f64 * 100.0 / total as f64
+                    };
+                    (rate, format!("{:.2}%", rate))
+                }
+                Metric::Rps => {
+                    let rps = observed.successful as f64 / observed.duration as f64;
+                    (rps, format!("{:.2}", rps))
+                }
+                Metric::Failed => {
+                    let failed = observed.failed;
+                    (failed as f64, failed.to_string())
+                }
+            };
+            let passed = match threshold.comparison {
+                Comparison::Below => value < threshold.limit,
+                Comparison::AtMost => value <= threshold.limit,
+                Comparison::Above => value > threshold.limit,
+                Comparison::AtLeast => value >= threshold.limit,
+            };
+            Verdict {
+                threshold,
+                actual,
+                passed,
+            }
+        })
+        .collect()
+}
+
+/// Prints every verdict after the report, or logs them when stdout carries the JSON report,
+/// missed ones as warnings
+pub fn print_verdicts(verdicts: &[Verdict], to_log: bool) {
+    if to_log {
+        for verdict in verdicts {
+            let threshold = verdict.threshold.to_string();
+            if verdict.passed {
+                info!(threshold, actual = verdict.actual, "threshold passed");
+            } else {
+                warn!(threshold, actual = verdict.actual, "threshold missed");
+            }
+        }
+        return;
+    }
+    println!();
+    println!();
+    println!("Thresholds:");
+    for verdict in verdicts {
+        println!(
+            "  {:.<24}: {} ({})",
+            verdict.threshold.to_string(),
+            if verdict.passed { "ok" } else { "FAILED" },
+            verdict.actual
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_each_metric() {
+        let threshold = parse_threshold("p99.9 <= 1.5s").unwrap();
+        assert!(matches!(threshold.metric, Metric::Percentile(p) if p == 99.9));
+        assert!(matches!(threshold.comparison, Comparison::AtMost));
+        assert_eq!(threshold.limit, 1.5);
+        assert_eq!(threshold.to_string(), "p99.9 <= 1.5s");
+
+        let threshold = parse_threshold("error_rate<1%").unwrap();
+        assert!(matches!(threshold.metric, Metric::ErrorRate));
+        assert!(matches!(threshold.comparison, Comparison::Below));
+        assert_eq!(threshold.limit, 1.0);
+
+        let threshold = parse_threshold("rps>=250").unwrap();
+        assert!(matches!(threshold.metric, Metric::Rps));
+        assert!(matches!(threshold.comparison, Comparison::AtLeast));
+
+        let threshold = parse_threshold("failed>0").unwrap();
+        assert!(matches!(threshold.metric, Metric::Failed));
+        assert!(matches!(threshold.comparison, Comparison::Above));
+    }
+
+    #[test]
+    fn rejects_malformed_thresholds() {
+        for spec in [
+            "",
+            "p95",
+            "p95≤200ms",
+            "p101<1s",
+            "latency<1s",
+            "é<1",
+            "p95<200",
+            "error_rate<1",
+            "rps>many",
+        ] {
+            assert!(parse_threshold(spec).is_err(), "{}", spec);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Most buckets a timeline keeps; past them it merges its buckets in pairs, so a test running
+/// for days takes no more memory than one running for hours
+const MAX_KEPT: usize = 1 << 14;
+
+/// Successful requests, and their body bytes, that finished in one slice of the test
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Bucket {
+    pub successful: u64,
+    pub bytes: u64,
+}
+
+/// Successful requests per `resolution` slice of the test, by when they finished. It grows
+/// with the length of the test rather than with the number of requests.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timeline {
+    /// Not saved, a resumed test restarts the clock where its checkpoint left off
+    #[serde(skip, default = "Instant::now")]
+    start: Instant,
+    resolution: Duration,
+    buckets: Vec<Bucket>,
+}
+
+impl Timeline {
+    pub fn new(start: Instant, resolution: Duration) -> Self {
+        Timeline {
+            start,
+            resolution,
+            buckets: vec![],
+        }
+    }
+
+    /// The same timeline, counted from `start`
+    pub fn resumed(self, start: Instant) -> Self {
+        Timeline { start, ..self }
+    }
+
+    pub fn record(&mut self, finished_at: Instant, bytes: u64) {
+        let offset = finished_at.saturating_duration_since(self.start);
+        self.add(
+            offset,
+            Bucket {
+                successful: 1,
+                bytes,
+            },
+        );
+    }
+
+    fn add(&mut self, offset: Duration, bucket: Bucket) {
+        let index = loop {
+            let index = (offset.as_secs_f64() / self.resolution.as_secs_f64()) as usize;
+            if index < MAX_KEPT {
+                break index;
+            }
+            self.coarsen();
+        };
+        if self.buckets.len() <= index {
+            self.buckets.resize(index + 1, Bucket::default());
+        }
+        self.buckets[index].successful += bucket.successful;
+        self.buckets[index].bytes += bucket.bytes;
+    }
+
+    /// Doubles the width of the buckets, merging them in pairs
+    fn coarsen(&mut self) {
+        self.resolution *= 2;
+        self.buckets = self
+            .buckets
+            .chunks(2)
+            .map(|pair| Bucket {
+                successful: pair.iter().map(|b| b.successful).sum(),
+                bytes: pair.iter().map(|b| b.bytes).sum(),
+            })
+            .collect();
+    }
+
+    /// Folds in a timeline of a test that started at the same time, e.g. on another worker
+    pub fn merge(&mut self, other: &Timeline) {
+        for (i, bucket) in other.buckets.iter().enumerate() {
+            self.add(other.resolution * i as u32, *bucket);
+        }
+    }
+
+    /// Buckets `width` wide covering `test_duration` seconds. Each recorded slice falls into
+    /// the bucket it starts in, those past the end into the last one.
+    pub fn buckets(&self, width: Duration, test_duration: f32) -> Vec<Bucket> {
+        let width = width.as_secs_f32();
+        let mut buckets = vec![Bucket::default(); (test_duration / width).ceil().max(1.0) as usize];
+        let last = buckets.len() - 1;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            let offset = i as f32 * self.resolution.as_secs_f32();
+            let target = &mut buckets[((offset / width) as usize).min(last)];
+            target.successful += bucket.successful;
+            target.bytes += bucket.bytes;
+        }
+        buckets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_buckets_past_the_most_kept() {
+        let start = Instant::now();
+        let mut timeline = Timeline::new(start, Duration::from_secs(1));
+        timeline.record(start, 10);
+        timeline.record(start + Duration::from_secs(1), 10);
+        // Four times as long as the buckets kept cover at a second each
+        timeline.record(start + Duration::from_secs(4 * MAX_KEPT as u64 - 1), 10);
+        assert_eq!(timeline.resolution, Duration::from_secs(4));
+        assert!(timeline.buckets.len() <= MAX_KEPT);
+        assert_eq!(timeline.buckets[0].successful, 2);
+        let buckets = timeline.buckets(Duration::from_secs(3600), 4.0 * MAX_KEPT as f32);
+        assert_eq!(buckets.iter().map(|b| b.bytes).sum::<u64>(), 30);
+    }
+}
@@ -0,0 +1,435 @@
+use std::{
+    error::Error,
+    io,
+    net::SocketAddr,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use clap::ValueEnum;
+use reqwest::tls::TlsInfo;
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    ClientConfig, RootCertStore, ServerName, SupportedCipherSuite, SupportedProtocolVersion,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{io::AsyncReadExt, net::TcpStream};
+use tokio_rustls::TlsConnector;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum TlsVersion {
+    #[value(name = "1.2")]
+    Tls12,
+    #[value(name = "1.3")]
+    Tls13,
+}
+
+impl TlsVersion {
+    fn protocol(self) -> &'static SupportedProtocolVersion {
+        match self {
+            TlsVersion::Tls12 => &rustls::version::TLS12,
+            TlsVersion::Tls13 => &rustls::version::TLS13,
+        }
+    }
+}
+
+/// Handshake settings used to build the client's TLS configuration
+pub struct TlsOptions {
+    pub min_version: TlsVersion,
+    pub max_version: TlsVersion,
+    /// Cipher suites in order of preference; empty means rustls' defaults
+    pub ciphers: Vec<SupportedCipherSuite>,
+    /// ALPN protocols in order of preference
+    pub alpn: Vec<String>,
+    /// Write session secrets to the file named by SSLKEYLOGFILE
+    pub keylog: bool,
+    /// Accept any server certificate
+    pub insecure: bool,
+    /// Certificate chain and key to present when the server asks for one
+    pub identity: Option<ClientIdentity>,
+    /// Roots trusted on top of the system's, e.g. an internal CA
+    pub extra_roots: Vec<rustls::Certificate>,
+}
+
+/// Client certificate chain and private key for mutual TLS
+pub struct ClientIdentity {
+    pub certs: Vec<rustls::Certificate>,
+    pub key: rustls::PrivateKey,
+}
+
+/// How long the probe waits for a TLS 1.3 server to reject the client certificate, or the
+/// lack of one, which only happens after the client considers the handshake done
+const CLIENT_AUTH_VERDICT_WAIT: Duration = Duration::from_millis(200);
+
+/// Loads every certificate of a PEM bundle, to be trusted as roots
+pub fn load_ca_bundle(path: &Path) -> Result<Vec<rustls::Certificate>, Box<dyn Error>> {
+    let file =
+        std::fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let certs: Vec<rustls::Certificate> = rustls_pemfile::certs(&mut file.as_slice())?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {}", path.display()).into());
+    }
+    Ok(certs)
+}
+
+/// Loads a client identity from a PEM certificate chain and key, or from a PKCS#12 bundle
+/// when the certificate file isn't PEM. The key may sit in the certificate's PEM file.
+pub fn load_identity(
+    cert: &Path,
+    key: Option<&Path>,
+    password: &str,
+) -> Result<ClientIdentity, Box<dyn Error>> {
+    let read = |path: &Path| {
+        std::fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))
+    };
+    let cert_file = read(cert)?;
+    if !cert_file.starts_with(b"-----")
+        && !String::from_utf8_lossy(&cert_file).contains("-----BEGIN")
+    {
+        if key.is_some() {
+            return Err("--key can't be used with a PKCS#12 bundle, which holds the key".into());
+        }
+        return load_pkcs12(cert, &cert_file, password);
+    }
+
+    let certs: Vec<rustls::Certificate> = rustls_pemfile::certs(&mut cert_file.as_slice())?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {}", cert.display()).into());
+    }
+    let (key_path, key_file) = match key {
+        Some(path) => (path, read(path)?),
+        None => (cert, cert_file.clone()),
+    };
+    let key = rustls_pemfile::read_all(&mut key_file.as_slice())?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("no private key found in {}", key_path.display()))?;
+    Ok(ClientIdentity { certs, key })
+}
+
+fn load_pkcs12(
+    path: &Path,
+    bundle: &[u8],
+    password: &str,
+) -> Result<ClientIdentity, Box<dyn Error>> {
+    let store = p12_keystore::KeyStore::from_pkcs12(bundle, password).map_err(|e| {
+        format!(
+            "failed to open {} as PKCS#12 (wrong --cert-password?): {}",
+            path.display(),
+            e
+        )
+    })?;
+    let (_, chain) = store
+        .private_key_chain()
+        .ok_or_else(|| format!("no private key found in {}", path.display()))?;
+    Ok(ClientIdentity {
+        certs: chain
+            .chain()
+            .iter()
+            .map(|cert| rustls::Certificate(cert.as_der().to_vec()))
+            .collect(),
+        key: rustls::PrivateKey(chain.key().to_vec()),
+    })
+}
+
+/// What the server agreed to during the handshake
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Negotiated {
+    pub version: String,
+    pub cipher: String,
+    pub alpn: Option<String>,
+}
+
+/// Timings of QUIC handshakes made outside of the HTTP client, since 0-RTT makes a resumed
+/// connection much cheaper than the first one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuicHandshake {
+    pub alpn: Option<String>,
+    /// Seconds taken by a full handshake
+    pub full: f32,
+    /// Seconds taken by a handshake resuming the first one's session
+    pub resumed: f32,
+    /// The server accepted early data on the resumed handshake
+    pub zero_rtt: bool,
+}
+
+/// Parses a cipher suite by its IANA name, e.g. TLS13_AES_128_GCM_SHA256
+pub fn parse_cipher(name: &str) -> Result<SupportedCipherSuite, String> {
+    rustls::ALL_CIPHER_SUITES
+        .iter()
+        .find(|suite| format!("{:?}", suite.suite()).eq_ignore_ascii_case(name))
+        .copied()
+        .ok_or_else(|| {
+            let supported: Vec<String> = rustls::ALL_CIPHER_SUITES
+                .iter()
+                .map(|suite| format!("{:?}", suite.suite()))
+                .collect();
+            format!(
+                "unsupported cipher suite `{}`, expected one of: {}",
+                name,
+                supported.join(", ")
+            )
+        })
+}
+
+/// Builds the rustls configuration every request client is created from
+pub fn client_config(options: &TlsOptions) -> Result<ClientConfig, Box<dyn Error>> {
+    if options.keylog && std::env::var_os("SSLKEYLOGFILE").is_none() {
+        return Err("--keylog requires the SSLKEYLOGFILE environment variable".into());
+    }
+    if options.min_version > options.max_version {
+        return Err("--tls-min must not be greater than --tls-max".into());
+    }
+    let versions: Vec<&'static SupportedProtocolVersion> = [TlsVersion::Tls12, TlsVersion::Tls13]
+        .into_iter()
+        .filter(|v| (options.min_version..=options.max_version).contains(v))
+        .map(TlsVersion::protocol)
+        .collect();
+    let ciphers = if options.ciphers.is_empty() {
+        rustls::DEFAULT_CIPHER_SUITES
+    } else {
+        &options.ciphers
+    };
+
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_native_certs::load_native_certs()? {
+        // Skip roots rustls can't parse rather than refusing to start
+        let _ = roots.add(&rustls::Certificate(cert.0));
+    }
+    for cert in &options.extra_roots {
+        roots
+            .add(cert)
+            .map_err(|e| format!("unusable CA certificate from --cacert: {}", e))?;
+    }
+
+    let builder = ClientConfig::builder()
+        .with_cipher_suites(ciphers)
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&versions)?
+        .with_root_certificates(roots);
+    let mut config = match &options.identity {
+        Some(identity) => builder
+            .with_client_auth_cert(identity.certs.clone(), identity.key.clone())
+            .map_err(|e| format!("unusable client certificate or key: {}", e))?,
+        None => builder.with_no_client_auth(),
+    };
+    config.alpn_protocols = options.alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+    if options.keylog {
+        config.key_log = Arc::new(rustls::KeyLogFile::new());
+    }
+    if options.insecure {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(AcceptAnyCertificate));
+    }
+    Ok(config)
+}
+
+/// Skips certificate verification for --insecure. Handshake signatures are still checked
+/// against whatever certificate the server presents.
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Performs a single handshake outside of the HTTP client to find out what gets negotiated
+pub async fn probe(
+    config: ClientConfig,
+    server_name: &str,
+    addrs: &[SocketAddr],
+) -> Result<Negotiated, Box<dyn Error>> {
+    let name = ServerName::try_from(server_name)?;
+    let stream = TcpStream::connect(addrs).await?;
+    let client_auth = config.client_auth_cert_resolver.has_certs();
+    let mut tls = TlsConnector::from(Arc::new(config))
+        .connect(name, stream)
+        .await
+        .map_err(|e| describe_handshake_error(e, client_auth))?;
+    let mut buf = [0; 1];
+    if let Ok(Err(e)) = tokio::time::timeout(CLIENT_AUTH_VERDICT_WAIT, tls.read(&mut buf)).await {
+        return Err(describe_handshake_error(e, client_auth).into());
+    }
+
+    let (_, connection) = tls.get_ref();
+    Ok(Negotiated {
+        version: connection
+            .protocol_version()
+            .map(|v| format!("{:?}", v))
+            .unwrap_or_default(),
+        cipher: connection
+            .negotiated_cipher_suite()
+            .map(|s| format!("{:?}", s.suite()))
+            .unwrap_or_default(),
+        alpn: connection
+            .alpn_protocol()
+            .map(|p| String::from_utf8_lossy(p).into_owned()),
+    })
+}
+
+/// Times a full QUIC handshake and then one resuming its session with 0-RTT
+pub async fn probe_quic(
+    mut config: ClientConfig,
+    server_name: &str,
+    addrs: &[SocketAddr],
+) -> Result<QuicHandshake, Box<dyn Error>> {
+    config.enable_early_data = true;
+    let addr = *addrs.first().ok_or("no addresses to connect to")?;
+    let bind = if addr.is_ipv6() {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    };
+    let mut endpoint = quinn::Endpoint::client(bind.parse()?)?;
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(config)));
+
+    let start = Instant::now();
+    let first = endpoint.connect(addr, server_name)?.await?;
+    let full = start.elapsed().as_secs_f32();
+    let alpn = first
+        .handshake_data()
+        .and_then(|data| data.downcast::<quinn::crypto::rustls::HandshakeData>().ok())
+        .and_then(|data| data.protocol)
+        .map(|p| String::from_utf8_lossy(&p).into_owned());
+    // Session tickets arrive after the handshake, give the server a round trip to send one
+    tokio::time::sleep(first.rtt() * 2 + Duration::from_millis(10)).await;
+
+    let start = Instant::now();
+    let (second, zero_rtt) = match endpoint.connect(addr, server_name)?.into_0rtt() {
+        Ok((connection, accepted)) => {
+            let accepted = accepted.await;
+            (connection, accepted)
+        }
+        Err(connecting) => (connecting.await?, false),
+    };
+    let resumed = start.elapsed().as_secs_f32();
+
+    for connection in [first, second] {
+        connection.close(0u32.into(), b"done");
+    }
+    endpoint.wait_idle().await;
+    Ok(QuicHandshake {
+        alpn,
+        full,
+        resumed,
+        zero_rtt,
+    })
+}
+
+/// Points at the client certificate when the server's alert is about it
+fn describe_handshake_error(e: io::Error, client_auth: bool) -> io::Error {
+    use rustls::AlertDescription::*;
+    let alert = match e.get_ref().and_then(|e| e.downcast_ref::<rustls::Error>()) {
+        Some(rustls::Error::AlertReceived(alert)) => *alert,
+        _ => return e,
+    };
+    let hint = match (alert, client_auth) {
+        (CertificateRequired, false) | (HandshakeFailure, false) => {
+            "the server may require a client certificate, pass one with --cert and --key"
+        }
+        (
+            CertificateRequired
+            | BadCertificate
+            | UnsupportedCertificate
+            | CertificateRevoked
+            | CertificateExpired
+            | CertificateUnknown
+            | UnknownCA
+            | AccessDenied,
+            true,
+        ) => "the server rejected the client certificate",
+        _ => return e,
+    };
+    io::Error::new(e.kind(), format!("{}: {}", hint, e))
+}
+
+/// SHA-256 fingerprint of a DER-encoded certificate
+pub type Fingerprint = [u8; 32];
+
+/// Parses a fingerprint written as hex, with or without `:` separators, as printed by
+/// `openssl x509 -noout -fingerprint -sha256`
+pub fn parse_pin(pin: &str) -> Result<Fingerprint, String> {
+    let hex: String = pin.chars().filter(|c| *c != ':').collect();
+    if hex.len() != 64 {
+        return Err(format!(
+            "expected 64 hex characters in `{}`, found {}",
+            pin,
+            hex.len()
+        ));
+    }
+
+    let mut fingerprint = [0; 32];
+    for (i, byte) in fingerprint.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| format!("invalid hex in fingerprint `{}`", pin))?;
+    }
+    Ok(fingerprint)
+}
+
+/// Checks the leaf certificate the server presented against the pinned fingerprints.
+/// Responses without a certificate (plain HTTP) never match.
+pub fn certificate_matches(res: &reqwest::Response, pins: &[Fingerprint]) -> bool {
+    res.extensions()
+        .get::<TlsInfo>()
+        .and_then(|info| info.peer_certificate())
+        .map(|der| {
+            let fingerprint: Fingerprint = Sha256::digest(der).into();
+            pins.contains(&fingerprint)
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pins() {
+        let hex = "00ff".repeat(16);
+        let fingerprint = parse_pin(&hex).unwrap();
+        assert_eq!(fingerprint[..2], [0x00, 0xff]);
+        let colons: Vec<String> = hex
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| String::from_utf8(pair.to_vec()).unwrap().to_uppercase())
+            .collect();
+        assert_eq!(parse_pin(&colons.join(":")).unwrap(), fingerprint);
+    }
+
+    #[test]
+    fn rejects_malformed_pins() {
+        for pin in [
+            String::new(),
+            "00".repeat(31),
+            "00".repeat(33),
+            format!("{}zz", "00".repeat(31)),
+            format!("{}é", "0".repeat(62)),
+            format!("{}٠٠", "00".repeat(31)),
+        ] {
+            assert!(parse_pin(&pin).is_err(), "{}", pin);
+        }
+    }
+}
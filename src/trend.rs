@@ -0,0 +1,225 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::latency::LatencyHistogram;
+
+/// Windows of a long test to compare its latency across, and how much a percentile may grow
+/// from the first window to the last before it is flagged as drifting
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Trend {
+    pub window: Duration,
+    /// Growth in percent
+    pub drift: f64,
+}
+
+/// Percentiles of one slice of a test
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Slice {
+    pub successful: u64,
+    /// One per percentile of the series, in seconds
+    pub values: Vec<f32>,
+}
+
+impl Slice {
+    /// Folds in the same slice recorded elsewhere, e.g. by another worker. Percentiles of two
+    /// sets don't add up, so this weighs each by its requests, which is close for similar sets.
+    pub fn merge(&mut self, other: &Slice) {
+        let successful = self.successful + other.successful;
+        if successful == 0 {
+            return;
+        }
+        let weight = |count: u64| count as f32 / successful as f32;
+        for (ours, theirs) in self.values.iter_mut().zip(&other.values) {
+            *ours = *ours * weight(self.successful) + theirs * weight(other.successful);
+        }
+        self.successful = successful;
+    }
+}
+
+/// Latency percentiles per `width` slice of a test, by when requests finished. A slice is kept
+/// as just its percentiles once it is over, so memory grows by a few bytes per slice however
+/// many requests a test makes.
+#[derive(Debug, Clone)]
+pub struct Series {
+    start: Instant,
+    width: Duration,
+    percentiles: Vec<f32>,
+    /// None for slices nothing finished in
+    slices: Vec<Option<Slice>>,
+    /// The slice requests are finishing in now
+    current: Option<(usize, LatencyHistogram)>,
+}
+
+impl Series {
+    pub fn new(start: Instant, width: Duration, percentiles: Vec<f32>) -> Self {
+        Series::with_slices(start, width, percentiles, vec![])
+    }
+
+    /// A series whose first slices were already recorded, e.g. before a resume
+    pub fn with_slices(
+        start: Instant,
+        width: Duration,
+        percentiles: Vec<f32>,
+        slices: Vec<Option<Slice>>,
+    ) -> Self {
+        Series {
+            start,
+            width,
+            percentiles,
+            slices,
+            current: None,
+        }
+    }
+
+    pub fn record(&mut self, finished_at: Instant, secs: f32) {
+        let offset = finished_at.saturating_duration_since(self.start);
+        let index = (offset.as_secs_f64() / self.width.as_secs_f64()) as usize;
+        match &mut self.current {
+            // Stragglers from a slice that is over count towards the current one
+            Some((current, latency)) if index <= *current => latency.record(secs),
+            _ => {
+                if let Some((over, latency)) = self.current.take() {
+                    let slice = self.summarize(&latency);
+                    if self.slices.len() <= over {
+                        self.slices.resize(over + 1, None);
+                    }
+                    match &mut self.slices[over] {
+                        Some(earlier) => earlier.merge(&slice),
+                        empty => *empty = Some(slice),
+                    }
+                }
+                let mut latency = LatencyHistogram::default();
+                latency.record(secs);
+                self.current = Some((index, latency));
+            }
+        }
+    }
+
+    fn summarize(&self, latency: &LatencyHistogram) -> Slice {
+        Slice {
+            successful: latency.moments().count(),
+            values: self
+                .percentiles
+                .iter()
+                .map(|p| latency.percentile(*p))
+                .collect(),
+        }
+    }
+
+    /// The slice at `index`, the current one included
+    pub fn slice(&self, index: usize) -> Option<Slice> {
+        let mut slice = self.slices.get(index).cloned().flatten();
+        if let Some((current, latency)) = &self.current {
+            if *current == index {
+                let latest = self.summarize(latency);
+                match &mut slice {
+                    Some(earlier) => earlier.merge(&latest),
+                    empty => *empty = Some(latest),
+                }
+            }
+        }
+        slice
+    }
+
+    /// Every slice so far, the current one included
+    pub fn slices(&self) -> Vec<Option<Slice>> {
+        let len = match &self.current {
+            Some((current, _)) => self.slices.len().max(current + 1),
+            None => self.slices.len(),
+        };
+        (0..len).map(|index| self.slice(index)).collect()
+    }
+}
+
+/// A percentile that grew by more than a trend allows between the first slice and the last
+#[derive(Debug, Serialize)]
+pub struct Drift {
+    pub percentile: f32,
+    /// Index of the first slice anything finished in
+    pub first_slice: usize,
+    pub last_slice: usize,
+    /// Seconds, in the first slice
+    pub first: f32,
+    /// Seconds, in the last slice
+    pub last: f32,
+    /// Percent
+    pub growth: f64,
+}
+
+/// The percentiles that grew by more than `threshold` percent from the first slice to the last
+pub fn drifts(slices: &[Option<Slice>], percentiles: &[f32], threshold: f64) -> Vec<Drift> {
+    let mut answered = slices
+        .iter()
+        .enumerate()
+        .filter_map(|(index, slice)| slice.as_ref().map(|slice| (index, slice)));
+    let (Some((first_slice, first)), Some((last_slice, last))) =
+        (answered.next(), answered.next_back())
+    else {
+        return vec![];
+    };
+    percentiles
+        .iter()
+        .zip(first.values.iter().zip(&last.values))
+        .filter(|(_, (first, _))| **first > 0.0)
+        .map(|(percentile, (first, last))| Drift {
+            percentile: *percentile,
+            first_slice,
+            last_slice,
+            first: *first,
+            last: *last,
+            growth: (*last as f64 / *first as f64 - 1.0) * 100.0,
+        })
+        .filter(|drift| drift.growth > threshold)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_latency_drift() {
+        let hour = Duration::from_secs(3600);
+        let start = Instant::now();
+        let mut series = Series::new(start, hour, vec![50.0, 95.0]);
+        // Every request takes 100ms in the first hour, then the slowest get slower each hour
+        for h in 0..6u32 {
+            for i in 0..100u32 {
+                let slow = if i >= 90 {
+                    0.1 * (1.0 + 0.1 * h as f32)
+                } else {
+                    0.1
+                };
+                series.record(start + hour * h + Duration::from_secs(i as u64), slow);
+            }
+        }
+        let slices = series.slices();
+        assert_eq!(slices.len(), 6);
+        assert!(slices
+            .iter()
+            .all(|slice| slice.as_ref().unwrap().successful == 100));
+
+        let drifted = drifts(&slices, &[50.0, 95.0], 20.0);
+        assert_eq!(drifted.len(), 1);
+        assert_eq!(drifted[0].percentile, 95.0);
+        assert_eq!(drifted[0].last_slice, 5);
+        assert!(
+            (drifted[0].growth - 50.0).abs() < 1.0,
+            "{}",
+            drifted[0].growth
+        );
+        assert!(drifts(&slices, &[50.0, 95.0], 60.0).is_empty());
+
+        let mut slice = Slice {
+            successful: 1,
+            values: vec![1.0],
+        };
+        slice.merge(&Slice {
+            successful: 3,
+            values: vec![2.0],
+        });
+        assert_eq!(slice.successful, 4);
+        assert_eq!(slice.values, [1.75]);
+    }
+}
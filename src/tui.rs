@@ -0,0 +1,89 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use indicatif::ProgressBar;
+use tokio::{sync::broadcast, time};
+
+use crate::events::Event;
+
+/// How far back the rates and percentiles on the dashboard look
+const WINDOW: Duration = Duration::from_secs(10);
+const REFRESH: Duration = Duration::from_secs(1);
+
+/// Replaces the spinner message with a live dashboard: RPS, requests in flight, error rate
+/// and rolling p95/p99 TTFB, redrawn every second until the event stream closes
+pub async fn dashboard(mut events: broadcast::Receiver<Event>, spinner: ProgressBar) {
+    let started = Instant::now();
+    let (mut scheduled, mut done, mut responses, mut errors) = (0u64, 0u64, 0u64, 0u64);
+    // (finished at, TTFB, failed) of recent responses and errors
+    let mut recent: VecDeque<(Instant, Option<f32>, bool)> = VecDeque::new();
+    // TTFB of responses whose body is still being read, by request id
+    let mut pending: HashMap<u64, f32> = HashMap::new();
+    let mut refresh = time::interval(REFRESH);
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(Event::RequestScheduled { .. }) => scheduled += 1,
+                Ok(Event::Headers { id, ttfb, .. }) => {
+                    pending.insert(id, ttfb);
+                }
+                Ok(Event::BodyDone { id, succeeded, .. }) => {
+                    done += 1;
+                    responses += 1;
+                    errors += !succeeded as u64;
+                    recent.push_back((Instant::now(), pending.remove(&id), !succeeded));
+                }
+                Ok(Event::Error { id, .. }) => {
+                    pending.remove(&id);
+                    done += 1;
+                    errors += 1;
+                    recent.push_back((Instant::now(), None, true));
+                }
+                Ok(Event::Finished { .. }) | Err(broadcast::error::RecvError::Closed) => return,
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+            },
+            _ = refresh.tick() => {
+                let now = Instant::now();
+                while recent.front().is_some_and(|(at, _, _)| now.duration_since(*at) > WINDOW) {
+                    recent.pop_front();
+                }
+                let window = now.duration_since(started).min(WINDOW).as_secs_f32().max(REFRESH.as_secs_f32());
+                let mut ttfbs: Vec<f32> = recent.iter().filter_map(|(_, ttfb, _)| *ttfb).collect();
+                ttfbs.sort_by(|a, b| a.total_cmp(b));
+                let recent_errors = recent.iter().filter(|(_, _, failed)| *failed).count();
+                let lines = [
+                    (format!("RPS (last {:.0}s)", window), format!("{:.2}", recent.len() as f32 / window)),
+                    ("In flight".to_string(), scheduled.saturating_sub(done).to_string()),
+                    (
+                        format!("Error rate (last {:.0}s)", window),
+                        format!("{:.2}%", recent_errors as f32 * 100.0 / recent.len().max(1) as f32),
+                    ),
+                    (
+                        "P95, P99 TTFB (s)".to_string(),
+                        format!("{:.4}, {:.4}", percentile(&ttfbs, 0.95), percentile(&ttfbs, 0.99)),
+                    ),
+                ];
+                let mut message = format!(
+                    "Elapsed {:.0}s, {} responses, {} errors",
+                    started.elapsed().as_secs_f32(),
+                    responses,
+                    errors
+                );
+                for (label, value) in lines {
+                    message.push_str(&format!("\n{:.<28}: {}", label, value));
+                }
+                spinner.set_message(message);
+            }
+        }
+    }
+}
+
+fn percentile(sorted: &[f32], quantile: f32) -> f32 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (quantile * sorted.len() as f32).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
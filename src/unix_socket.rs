@@ -0,0 +1,54 @@
+use std::{
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, UnixStream},
+};
+use tracing::{debug, warn};
+
+use crate::proxy_protocol::accept_tunnel;
+
+/// Listens on a local port and relays every connection to the Unix domain socket at `path`.
+/// Like the PROXY protocol relay it acts as an HTTP proxy, since reqwest can't connect to a
+/// socket file itself.
+pub async fn spawn_relay(path: &Path) -> io::Result<SocketAddr> {
+    // Fail up front rather than on every request
+    drop(UnixStream::connect(path).await.map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("failed to connect to {}: {}", path.display(), e),
+        )
+    })?);
+    let path: PathBuf = path.into();
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        loop {
+            let (mut inbound, _) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("Unix socket relay stopped accepting: {}", e);
+                    return;
+                }
+            };
+            let path = path.clone();
+            tokio::spawn(async move {
+                let result = async {
+                    let early_data = accept_tunnel(&mut inbound).await?;
+                    let mut outbound = UnixStream::connect(&path).await?;
+                    outbound.write_all(&early_data).await?;
+                    tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await
+                }
+                .await;
+                if let Err(e) = result {
+                    debug!("Unix socket relay connection ended: {}", e);
+                }
+            });
+        }
+    });
+    Ok(addr)
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::{future::join_all, SinkExt, StreamExt};
+use tokio::time::{interval, sleep_until, MissedTickBehavior};
+use tokio_tungstenite::{connect_async_tls_with_config, tungstenite::Message, Connector};
+use tracing::{debug, warn};
+
+use crate::latency::LatencyHistogram;
+
+/// A WebSocket test: connections held open for its duration, each sending messages at a
+/// steady rate and timing how long the server takes to answer each one
+pub struct WsTest {
+    pub url: String,
+    pub connections: usize,
+    /// Messages per second each connection sends
+    pub rate: f64,
+    pub message: String,
+    pub duration: Duration,
+    pub tls_config: rustls::ClientConfig,
+}
+
+/// What the connections of a test went through, merged
+#[derive(Default)]
+struct Summary {
+    connected: u64,
+    failed_connects: u64,
+    /// Connections closed or broken before the test ended
+    dropped: u64,
+    connect: LatencyHistogram,
+    round_trip: LatencyHistogram,
+    sent: u64,
+    received: u64,
+}
+
+impl Summary {
+    fn merge(&mut self, other: Summary) {
+        self.connected = self.connected.saturating_add(other.connected);
+        self.failed_connects = self.failed_connects.saturating_add(other.failed_connects);
+        self.dropped = self.dropped.saturating_add(other.dropped);
+        self.connect.merge(&other.connect);
+        self.round_trip.merge(&other.round_trip);
+        self.sent = self.sent.saturating_add(other.sent);
+        self.received = self.received.saturating_add(other.received);
+    }
+}
+
+pub async fn run(test: WsTest) -> Result<(), Box<dyn Error>> {
+    if !(test.url.starts_with("ws://") || test.url.starts_with("wss://")) {
+        return Err(format!("`{}` is not a ws:// or wss:// URL", test.url).into());
+    }
+    if test.connections == 0 {
+        return Err("there must be at least one connection".into());
+    }
+    let tls_config = Arc::new(test.tls_config.clone());
+    let deadline = tokio::time::Instant::now() + test.duration;
+    let started = Instant::now();
+    let connections = (0..test.connections)
+        .map(|connection| connect_and_send(&test, connection, tls_config.clone(), deadline));
+    let mut summary = Summary::default();
+    for connection in join_all(connections).await {
+        summary.merge(connection);
+    }
+    print_summary(&test, &summary, started.elapsed());
+    Ok(())
+}
+
+/// Opens one connection and sends messages on it until `deadline`
+async fn connect_and_send(
+    test: &WsTest,
+    connection: usize,
+    tls_config: Arc<rustls::ClientConfig>,
+    deadline: tokio::time::Instant,
+) -> Summary {
+    let mut summary = Summary::default();
+    let start = Instant::now();
+    let connector = Some(Connector::Rustls(tls_config));
+    let mut stream = match connect_async_tls_with_config(&test.url, None, false, connector).await {
+        Ok((stream, _)) => stream,
+        Err(e) => {
+            warn!(connection, "failed to connect: {}", e);
+            summary.failed_connects += 1;
+            return summary;
+        }
+    };
+    summary.connected += 1;
+    summary.connect.record(start.elapsed().as_secs_f32());
+
+    let mut ticks = interval(Duration::from_secs_f64(1.0 / test.rate));
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // When each message still waiting for its answer was sent, answers coming in order
+    let mut unanswered = VecDeque::new();
+    loop {
+        tokio::select! {
+            _ = sleep_until(deadline) => {
+                let _ = stream.close(None).await;
+                break;
+            }
+            _ = ticks.tick() => {
+                if let Err(e) = stream.send(Message::Text(test.message.clone())).await {
+                    debug!(connection, "failed to send: {}", e);
+                    summary.dropped += 1;
+                    break;
+                }
+                summary.sent += 1;
+                unanswered.push_back(Instant::now());
+            }
+            message = stream.next() => match message {
+                Some(Ok(Message::Text(_) | Message::Binary(_))) => {
+                    summary.received += 1;
+                    if let Some(sent) = unanswered.pop_front() {
+                        summary.round_trip.record(sent.elapsed().as_secs_f32());
+                    }
+                }
+                // Pings are answered by the stream itself
+                Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => {}
+                Some(Ok(Message::Close(frame))) => {
+                    debug!(connection, "closed by the server: {:?}", frame);
+                    summary.dropped += 1;
+                    break;
+                }
+                Some(Err(e)) => {
+                    debug!(connection, "connection broke: {}", e);
+                    summary.dropped += 1;
+                    break;
+                }
+                None => {
+                    summary.dropped += 1;
+                    break;
+                }
+            }
+        }
+    }
+    summary
+}
+
+fn print_summary(test: &WsTest, summary: &Summary, elapsed: Duration) {
+    println!(
+        "WebSocket test of {}: {} connections for {:.1}s",
+        test.url,
+        test.connections,
+        elapsed.as_secs_f32()
+    );
+    println!("Results:");
+    for (label, count) in [
+        ("Connections Opened", summary.connected),
+        ("Failed Connects", summary.failed_connects),
+        ("Dropped Connections", summary.dropped),
+        ("Messages Sent", summary.sent),
+        ("Messages Received", summary.received),
+    ] {
+        println!("{:.<44}: {}", label, count);
+    }
+    println!();
+    println!();
+    for (label, histogram) in [
+        ("Connect Time (ms) (P50, P95, P99, Max)", &summary.connect),
+        (
+            "Message Round Trip (ms) (P50, P95, P99, Max)",
+            &summary.round_trip,
+        ),
+    ] {
+        if histogram.is_empty() {
+            continue;
+        }
+        let ms = |secs: f32| format!("{:.2}", secs * 1000.0);
+        println!(
+            "{:.<44}: {}, {}, {}, {}",
+            label,
+            ms(histogram.percentile(50.0)),
+            ms(histogram.percentile(95.0)),
+            ms(histogram.percentile(99.0)),
+            ms(histogram.max())
+        );
+    }
+}